@@ -57,6 +57,71 @@ fn trace_and_resolve_separate(b: &mut test::Bencher) {
     });
 }
 
+#[bench]
+#[cfg(feature = "std")]
+fn trace_and_resolve_stack(b: &mut test::Bencher) {
+    #[inline(never)]
+    fn the_function(frames: &mut Vec<backtrace::Frame>) {
+        backtrace::trace(|frame| {
+            frames.push(frame.clone());
+            true
+        });
+        backtrace::resolve_stack(frames, |_, symbol| {
+            test::black_box(symbol);
+        });
+    }
+    let mut frames = Vec::with_capacity(1024);
+    b.iter(|| {
+        the_function(&mut frames);
+        frames.clear();
+    });
+}
+
+#[bench]
+#[cfg(feature = "std")]
+fn resolve_stack_recursive(b: &mut test::Bencher) {
+    #[inline(never)]
+    fn recurse(depth: u32, frames: &mut Vec<backtrace::Frame>) -> u32 {
+        if depth == 0 {
+            backtrace::trace(|frame| {
+                frames.push(frame.clone());
+                true
+            });
+            return 0;
+        }
+        // Called through a pointer so that it isn't turned into a loop.
+        let f: fn(u32, &mut Vec<backtrace::Frame>) -> u32 = test::black_box(recurse);
+        f(depth - 1, frames) + 1
+    }
+    let mut frames = Vec::with_capacity(1024);
+    test::black_box(recurse(100, &mut frames));
+    b.iter(|| {
+        backtrace::resolve_stack(&frames, |_, symbol| {
+            test::black_box(symbol);
+        });
+    });
+}
+
+#[bench]
+#[cfg(feature = "std")]
+fn trace_and_resolve_lines(b: &mut test::Bencher) {
+    #[inline(never)]
+    fn the_function(frames: &mut Vec<backtrace::Frame>) {
+        backtrace::trace(|frame| {
+            frames.push(frame.clone());
+            true
+        });
+        backtrace::resolve_lines(frames, |i, file, line| {
+            test::black_box((i, file, line));
+        });
+    }
+    let mut frames = Vec::with_capacity(1024);
+    b.iter(|| {
+        the_function(&mut frames);
+        frames.clear();
+    });
+}
+
 #[bench]
 #[cfg(feature = "std")]
 fn new_unresolved(b: &mut test::Bencher) {
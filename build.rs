@@ -1,6 +1,7 @@
 extern crate cc;
 
 use std::env;
+use std::process::Command;
 
 fn main() {
     match env::var("CARGO_CFG_TARGET_OS").unwrap_or_default().as_str() {
@@ -8,13 +9,47 @@ fn main() {
         _ => {}
     }
 
-    if cfg!(feature = "llvm-unwind") || cfg!(feature = "nongnu-unwind") {
+    // There's no libunwind to link against on wasm, where the no-op backend
+    // is used instead.
+    let is_wasm = env::var("CARGO_CFG_TARGET_ARCH").unwrap_or_default() == "wasm32";
+    if (cfg!(feature = "llvm-unwind") || cfg!(feature = "nongnu-unwind")) && !is_wasm {
         for i in std::env::var("LIBUNWIND_SEARCH_PATH").ok() {
             println!("cargo:rustc-link-search=native={}", i)
         }
         println!("cargo:rustc-link-lib=unwind")
     }
+    // The remote API is only in the library for the target's architecture.
+    if cfg!(feature = "remote-unwind") && !is_wasm {
+        let arch = env::var("CARGO_CFG_TARGET_ARCH").unwrap_or_default();
+        println!("cargo:rustc-link-lib=unwind-{}", arch)
+    }
+
+    // `asm!` is only stable from Rust 1.59, so older compilers build without
+    // the features needing it, e.g. walking frame pointers.
+    println!("cargo:rustc-check-cfg=cfg(backtrace_no_asm)");
+    match rustc_minor_version() {
+        Some(minor) if minor < 59 => println!("cargo:rustc-cfg=backtrace_no_asm"),
+        _ => {}
+    }
+
+    // `cfg(target_has_atomic)` is only stable from Rust 1.60, and older
+    // compilers don't pass it on to build scripts either, in which case 64-bit
+    // atomics are only assumed on 64-bit targets.
+    println!("cargo:rustc-check-cfg=cfg(backtrace_has_atomic_64)");
+    let has_atomic_64 = match env::var("CARGO_CFG_TARGET_HAS_ATOMIC") {
+        Ok(widths) => widths.split(',').any(|width| width == "64"),
+        Err(_) => env::var("CARGO_CFG_TARGET_POINTER_WIDTH").ok().as_deref() == Some("64"),
+    };
+    if has_atomic_64 {
+        println!("cargo:rustc-cfg=backtrace_has_atomic_64");
+    }
+}
 
+fn rustc_minor_version() -> Option<u32> {
+    let rustc = env::var_os("RUSTC")?;
+    let output = Command::new(rustc).arg("--version").output().ok()?;
+    let version = std::str::from_utf8(&output.stdout).ok()?;
+    version.split('.').nth(1)?.parse().ok()
 }
 
 fn build_android() {
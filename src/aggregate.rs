@@ -0,0 +1,331 @@
+use crate::BacktraceFrame;
+use core::cell::UnsafeCell;
+use core::fmt;
+use core::sync::atomic::{AtomicUsize, Ordering};
+use std::collections::HashMap;
+use std::prelude::v1::*;
+
+/// Merges identical stacks into weighted samples as they're captured.
+///
+/// Sampling profilers tend to see the same few stacks over and over, so
+/// rather than keeping every one of them around until they're exported, this
+/// keeps a single copy of each distinct stack along with the number of times
+/// it was added.
+///
+/// Two stacks are considered identical if their frames have the same
+/// sequence of `symbol_address`es, i.e. they're in the same functions, even
+/// if the `ip`s within those functions differ. The frames kept for a stack
+/// are those of the first sample added with it.
+///
+/// An aggregator still grows with the number of distinct stacks seen, which
+/// for a profiler left running for hours may be more than it can keep. Such
+/// profilers can export it a window at a time with `flush_partial`, which
+/// hands over the stacks added since the last flush and starts afresh, so
+/// only one window's worth is kept. Each window's counts are a delta which
+/// whoever collects them can add to those of the windows before it.
+///
+/// # Required features
+///
+/// This function requires the `std` feature of the `backtrace` crate to be
+/// enabled, and the `std` feature is enabled by default.
+///
+/// # Example
+///
+/// ```
+/// use backtrace::{Backtrace, SampleAggregator};
+///
+/// let mut aggregator = SampleAggregator::new();
+/// for _ in 0..3 {
+///     aggregator.add(Backtrace::new_unresolved());
+/// }
+/// for (frames, count) in aggregator.into_weighted() {
+///     println!("{} samples of a {} frame stack", count, frames.len());
+/// }
+/// ```
+#[derive(Default)]
+pub struct SampleAggregator {
+    // Where each distinct stack lives in `samples`, which is kept in the
+    // order stacks were first seen so output is deterministic.
+    index: HashMap<Vec<usize>, usize>,
+    samples: Vec<(Vec<BacktraceFrame>, u64)>,
+    total: u64,
+}
+
+impl SampleAggregator {
+    /// Creates an aggregator with no samples.
+    pub fn new() -> SampleAggregator {
+        SampleAggregator::default()
+    }
+
+    /// Adds a single sample of the stack `frames`.
+    pub fn add<T: Into<Vec<BacktraceFrame>>>(&mut self, frames: T) {
+        self.add_weighted(frames, 1)
+    }
+
+    /// Adds `count` samples of the stack `frames` at once.
+    pub fn add_weighted<T: Into<Vec<BacktraceFrame>>>(&mut self, frames: T, count: u64) {
+        let frames = frames.into();
+        let key = frames
+            .iter()
+            .map(|frame| frame.symbol_address() as usize)
+            .collect::<Vec<_>>();
+        self.total += count;
+        match self.index.get(&key) {
+            Some(&i) => self.samples[i].1 += count,
+            None => {
+                self.index.insert(key, self.samples.len());
+                self.samples.push((frames, count));
+            }
+        }
+    }
+
+    /// Returns the number of distinct stacks added so far.
+    pub fn len(&self) -> usize {
+        self.samples.len()
+    }
+
+    /// Returns whether no samples have been added.
+    pub fn is_empty(&self) -> bool {
+        self.samples.is_empty()
+    }
+
+    /// Returns the total number of samples added so far, or since the last
+    /// `flush_partial`, counting each duplicate.
+    pub fn total(&self) -> u64 {
+        self.total
+    }
+
+    /// Returns each distinct stack along with how many samples of it were
+    /// added, in the order the stacks were first added.
+    pub fn into_weighted(self) -> Vec<(Vec<BacktraceFrame>, u64)> {
+        self.samples
+    }
+
+    /// Returns each distinct stack added since the last flush along with how
+    /// many samples of it were added, like `into_weighted`, and empties the
+    /// aggregator so that it can carry on with the next window of samples.
+    ///
+    /// A stack seen in more than one window is returned by the flush of each
+    /// of them, with the count of that window's samples of it.
+    pub fn flush_partial(&mut self) -> Vec<(Vec<BacktraceFrame>, u64)> {
+        self.index.clear();
+        self.total = 0;
+        core::mem::take(&mut self.samples)
+    }
+}
+
+// Depths below this each have a bucket of their own. Past it, each power of
+// two is split into `SUB_BUCKETS` buckets, so that a bucket's width is at most
+// an eighth of the depths in it.
+const BITS: u32 = core::mem::size_of::<usize>() as u32 * 8;
+const EXACT_DEPTHS: usize = 16;
+const SUB_BUCKETS: usize = 8;
+const BUCKETS: usize = EXACT_DEPTHS + (BITS - EXACT_DEPTHS.trailing_zeros()) as usize * SUB_BUCKETS;
+
+/// Counts how many frames deep captured stacks are, to tell how big fixed size
+/// buffers of frames need to be.
+///
+/// Functions like `trace_into` capture into a buffer of a fixed size, and
+/// truncate stacks which don't fit, so the size has to be picked up front. A
+/// histogram records the depths of the stacks an application actually
+/// captures, and `percentile` tells how big a buffer would have to be to hold
+/// a given fraction of them in full, e.g. `percentile(99.0)` for all but the
+/// deepest 1%.
+///
+/// Depths are recorded with `record`, or by every `Backtrace` captured while
+/// the histogram is registered with `set_depth_histogram`. Recording only
+/// updates atomic counters, without locking or allocating, so a histogram can
+/// be shared between threads and kept in a `static`.
+///
+/// Depths up to 15 are counted exactly, and deeper ones in buckets at most an
+/// eighth as wide as the depths in them, so that `percentile` is never more
+/// than 12.5% over the true depth.
+///
+/// # Required features
+///
+/// This type requires the `std` feature of the `backtrace` crate to be
+/// enabled, and the `std` feature is enabled by default.
+///
+/// # Example
+///
+/// ```
+/// use backtrace::{Backtrace, DepthHistogram};
+///
+/// static DEPTHS: DepthHistogram = DepthHistogram::new();
+///
+/// backtrace::set_depth_histogram(Some(&DEPTHS));
+/// for _ in 0..100 {
+///     Backtrace::new_unresolved();
+/// }
+/// backtrace::set_depth_histogram(None);
+/// println!("99% of stacks fit in {} frames", DEPTHS.percentile(99.0));
+/// ```
+pub struct DepthHistogram {
+    // Only accessed as `AtomicUsize`s, through `buckets`, since an array of
+    // those can't be built in a `const fn` without `transmute`.
+    buckets: UnsafeCell<[usize; BUCKETS]>,
+    max: AtomicUsize,
+}
+
+// All accesses to `buckets` are atomic.
+unsafe impl Sync for DepthHistogram {}
+
+impl DepthHistogram {
+    /// Creates a histogram with no depths recorded.
+    pub const fn new() -> DepthHistogram {
+        DepthHistogram {
+            buckets: UnsafeCell::new([0; BUCKETS]),
+            max: AtomicUsize::new(0),
+        }
+    }
+
+    /// Records a stack `depth` frames deep.
+    pub fn record(&self, depth: usize) {
+        self.buckets()[bucket(depth)].fetch_add(1, Ordering::Relaxed);
+        let mut max = self.max.load(Ordering::Relaxed);
+        while depth > max {
+            match self
+                .max
+                .compare_exchange_weak(max, depth, Ordering::Relaxed, Ordering::Relaxed)
+            {
+                Ok(_) => break,
+                Err(current) => max = current,
+            }
+        }
+    }
+
+    /// Returns the number of depths recorded.
+    pub fn count(&self) -> usize {
+        self.buckets()
+            .iter()
+            .map(|b| b.load(Ordering::Relaxed))
+            .fold(0, usize::wrapping_add)
+    }
+
+    /// Returns the deepest depth recorded, or 0 if none have been.
+    pub fn max(&self) -> usize {
+        self.max.load(Ordering::Relaxed)
+    }
+
+    /// Returns a depth which at least `p` percent of the depths recorded are
+    /// no deeper than, or 0 if none have been.
+    ///
+    /// `p` is clamped to between 0 and 100. The depth returned is the upper
+    /// end of the bucket the `p`th percentile falls in, so may be up to 12.5%
+    /// over it, but is never over `max`.
+    pub fn percentile(&self, p: f64) -> usize {
+        let counts = self
+            .buckets()
+            .iter()
+            .map(|b| b.load(Ordering::Relaxed))
+            .collect::<Vec<_>>();
+        let count = counts.iter().fold(0, |a: usize, b| a.wrapping_add(*b));
+        if count == 0 {
+            return 0;
+        }
+        // `max` also turns NaN into 0.
+        let p = p.max(0.0).min(100.0);
+        let rank = ((p / 100.0 * count as f64).ceil() as usize).max(1);
+        let mut seen = 0;
+        for (i, &n) in counts.iter().enumerate() {
+            seen += n;
+            if seen >= rank {
+                return bucket_max(i).min(self.max());
+            }
+        }
+        self.max()
+    }
+
+    /// Forgets all the depths recorded so far.
+    pub fn reset(&self) {
+        for bucket in self.buckets().iter() {
+            bucket.store(0, Ordering::Relaxed);
+        }
+        self.max.store(0, Ordering::Relaxed);
+    }
+
+    fn buckets(&self) -> &[AtomicUsize; BUCKETS] {
+        // `AtomicUsize` has the same in-memory representation as `usize`.
+        unsafe { &*(self.buckets.get() as *const [AtomicUsize; BUCKETS]) }
+    }
+}
+
+impl Default for DepthHistogram {
+    fn default() -> DepthHistogram {
+        DepthHistogram::new()
+    }
+}
+
+impl fmt::Debug for DepthHistogram {
+    fn fmt(&self, fmt: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt.debug_struct("DepthHistogram")
+            .field("count", &self.count())
+            .field("max", &self.max())
+            .finish()
+    }
+}
+
+// Returns the index of the bucket `depth` is counted in.
+fn bucket(depth: usize) -> usize {
+    if depth < EXACT_DEPTHS {
+        return depth;
+    }
+    // The power of two `depth` is in, counting from `EXACT_DEPTHS`, and which
+    // eighth of it.
+    let log = (BITS - 1 - depth.leading_zeros()) as usize;
+    let power = log - EXACT_DEPTHS.trailing_zeros() as usize;
+    let sub = (depth >> (log - SUB_BUCKETS.trailing_zeros() as usize)) - SUB_BUCKETS;
+    EXACT_DEPTHS + power * SUB_BUCKETS + sub
+}
+
+// Returns the deepest depth counted in bucket `i`.
+fn bucket_max(i: usize) -> usize {
+    if i < EXACT_DEPTHS {
+        return i;
+    }
+    let power = (i - EXACT_DEPTHS) / SUB_BUCKETS;
+    let sub = (i - EXACT_DEPTHS) % SUB_BUCKETS;
+    let shift =
+        power + EXACT_DEPTHS.trailing_zeros() as usize - SUB_BUCKETS.trailing_zeros() as usize;
+    // The last bucket ends at `usize::MAX`, which this wraps around to.
+    ((SUB_BUCKETS + sub + 1) << shift).wrapping_sub(1)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn buckets_cover_every_depth() {
+        let mut depth = 0;
+        for i in 0..BUCKETS {
+            assert_eq!(bucket(depth), i, "{}", depth);
+            let max = bucket_max(i);
+            assert_eq!(bucket(max), i, "{}", max);
+            // Buckets are at most an eighth as wide as their depths.
+            assert!(max - depth <= depth / 8, "{}..={}", depth, max);
+            depth = max.wrapping_add(1);
+        }
+        assert_eq!(depth, 0);
+    }
+
+    #[test]
+    fn percentiles() {
+        let histogram = DepthHistogram::new();
+        assert_eq!(histogram.percentile(99.0), 0);
+        for depth in 1..=100 {
+            histogram.record(depth);
+        }
+        assert_eq!(histogram.count(), 100);
+        assert_eq!(histogram.max(), 100);
+        assert_eq!(histogram.percentile(0.0), 1);
+        assert_eq!(histogram.percentile(10.0), 10);
+        let p50 = histogram.percentile(50.0);
+        assert!((50..=50 + 50 / 8).contains(&p50), "{}", p50);
+        let p99 = histogram.percentile(99.0);
+        assert!((99..=100).contains(&p99), "{}", p99);
+        assert_eq!(histogram.percentile(100.0), 100);
+        histogram.reset();
+        assert_eq!(histogram.count(), 0);
+    }
+}
@@ -0,0 +1,95 @@
+//! Running a closure on a caller-provided stack.
+//!
+//! The switch is done by a small assembly trampoline which saves the current
+//! stack pointer in a callee-saved register, points the stack pointer at the
+//! new stack, and calls the closure. Its unwind info defines the canonical
+//! frame address in terms of that saved register rather than the stack
+//! pointer, so unwinding out of the closure carries on up the original stack
+//! just as if the closure had been called on it.
+//!
+//! The trampoline symbol is weak so that several copies of this crate linked
+//! into one binary don't clash; they're identical anyway.
+
+use core::ffi::c_void;
+
+extern "C" {
+    fn __backtrace_rs_on_stack(
+        arg: *mut c_void,
+        f: unsafe extern "C" fn(*mut c_void),
+        stack_top: *mut u8,
+    );
+}
+
+#[cfg(target_arch = "x86_64")]
+core::arch::global_asm!(
+    ".text",
+    ".weak __backtrace_rs_on_stack",
+    ".hidden __backtrace_rs_on_stack",
+    ".type __backtrace_rs_on_stack,@function",
+    ".p2align 4",
+    "__backtrace_rs_on_stack:",
+    ".cfi_startproc",
+    "push rbp",
+    ".cfi_def_cfa_offset 16",
+    ".cfi_offset rbp, -16",
+    "push rbx",
+    ".cfi_def_cfa_offset 24",
+    ".cfi_offset rbx, -24",
+    "mov rbx, rsp",
+    ".cfi_def_cfa_register rbx",
+    "mov rsp, rdx",
+    "call rsi",
+    "mov rsp, rbx",
+    ".cfi_def_cfa_register rsp",
+    "pop rbx",
+    ".cfi_def_cfa_offset 16",
+    "pop rbp",
+    ".cfi_def_cfa_offset 8",
+    "ret",
+    ".cfi_endproc",
+    ".size __backtrace_rs_on_stack, .-__backtrace_rs_on_stack",
+);
+
+#[cfg(target_arch = "aarch64")]
+core::arch::global_asm!(
+    ".text",
+    ".weak __backtrace_rs_on_stack",
+    ".hidden __backtrace_rs_on_stack",
+    ".type __backtrace_rs_on_stack,%function",
+    ".p2align 2",
+    "__backtrace_rs_on_stack:",
+    ".cfi_startproc",
+    "stp x29, x30, [sp, #-16]!",
+    ".cfi_def_cfa sp, 16",
+    "mov x29, sp",
+    ".cfi_def_cfa x29, 16",
+    ".cfi_offset x30, -8",
+    ".cfi_offset x29, -16",
+    "mov sp, x2",
+    "blr x1",
+    "mov sp, x29",
+    ".cfi_def_cfa sp, 16",
+    "ldp x29, x30, [sp], #16",
+    ".cfi_def_cfa sp, 0",
+    ".cfi_restore x29",
+    ".cfi_restore x30",
+    "ret",
+    ".cfi_endproc",
+    ".size __backtrace_rs_on_stack, .-__backtrace_rs_on_stack",
+);
+
+/// Calls `f` with the stack pointer at the top of `stack`.
+///
+/// The caller is responsible for `stack` being large enough for `f`, there's
+/// no guard page to catch it overflowing.
+pub unsafe fn on_stack(stack: &mut [u8], mut f: &mut dyn FnMut()) {
+    unsafe extern "C" fn call(arg: *mut c_void) {
+        let f = &mut *(arg as *mut &mut dyn FnMut());
+        f()
+    }
+
+    // Both ABIs want the stack pointer 16-byte aligned at the call.
+    let top = stack.as_mut_ptr() as usize + stack.len();
+    let top = (top & !15) as *mut u8;
+    __backtrace_rs_on_stack(&mut f as *mut _ as *mut c_void, call, top);
+}
@@ -0,0 +1,542 @@
+//! Backtrace support on Apple platforms by reading the compact unwind tables
+//! in `__unwind_info` ourselves, rather than through `_Unwind_Backtrace`.
+//!
+//! The linker boils down how to unwind each function to a 32-bit encoding,
+//! and lays them out in the `__unwind_info` section of `__TEXT` as a
+//! two-level table: a first-level index of pages, each covering a range of
+//! function offsets from the image's header, and second-level pages listing
+//! where each run of functions with the same encoding starts. Regular pages
+//! hold plain pairs of offset and encoding, compressed ones pack a 24-bit
+//! offset from the start of the page together with an 8-bit index into the
+//! encodings shared by the whole image or those of the page.
+//!
+//! On x86_64 an encoding says either that the function keeps a frame record,
+//! which is stepped through just like `frame_pointer` does, or how big its
+//! frame is and where in it the registers it saves are, in which case the
+//! return address is at the top of that frame. That gets the walk through
+//! functions built without frame pointers, as long as they have an encoding.
+//! On aarch64 every function which calls another keeps a frame record, so
+//! only leaf functions are frameless, and those keep the return address in
+//! the link register, which can't be read from anywhere but the innermost
+//! frame. Finding one further out means the stack isn't what it seems, and
+//! the walk stops.
+//!
+//! Functions which can't be described by an encoding are marked as needing the
+//! DWARF CFI in `__eh_frame`, which isn't interpreted here. Those, and code
+//! without an entry at all, are assumed to keep a frame record, which Apple's
+//! toolchains do by default.
+//!
+//! The tables only say where runs of functions start, so the start of the
+//! function a frame is in is still looked up in `LC_FUNCTION_STARTS`, by
+//! leaving `symbol_address` to the lookup in `function_starts`.
+//!
+//! Like in `frame_pointer`, every read from the stack is first checked to be
+//! within the current thread's stack, and every read of the tables to be
+//! within the section, so the walk neither allocates nor takes any locks.
+
+use super::function_starts::{self, Image};
+use super::libunwind::Frame;
+use core::convert::TryFrom;
+use core::mem;
+use core::ops::Range;
+
+const WORD: usize = mem::size_of::<usize>();
+
+const UNWIND_SECTION_VERSION: u32 = 1;
+const UNWIND_SECOND_LEVEL_REGULAR: u32 = 2;
+const UNWIND_SECOND_LEVEL_COMPRESSED: u32 = 3;
+
+#[inline(always)]
+pub unsafe fn trace(cb: &mut dyn FnMut(&super::Frame) -> bool) {
+    let (fp, sp) = match super::frame_pointer::registers() {
+        Some(regs) => regs,
+        None => return,
+    };
+    let stack = match super::frame_pointer::current_thread_stack() {
+        // Nothing below the current stack pointer belongs to a live frame.
+        Some(stack) => stack.start.max(sp)..stack.end,
+        None => return,
+    };
+    // Whatever `trace` is inlined into calls the closure, so it keeps a frame
+    // record, and stepping out of it doesn't need its own ip.
+    let regs = Regs { pc: 0, sp, fp };
+    if let Some(regs) = step_frame_record(&regs, &stack) {
+        walk(regs, &stack, cb);
+    }
+}
+
+// The registers needed to step from one frame to the next.
+#[derive(Clone, Copy)]
+struct Regs {
+    pc: usize,
+    sp: usize,
+    fp: usize,
+}
+
+unsafe fn walk(mut regs: Regs, stack: &Range<usize>, cb: &mut dyn FnMut(&super::Frame) -> bool) {
+    while regs.pc != 0 {
+        let frame = super::Frame {
+            inner: Frame::Cloned {
+                ip: regs.pc as *mut _,
+                sp: regs.sp as *mut _,
+                symbol_address: None,
+                lsda: None,
+                caller_ip: None,
+                proc_info: None,
+                frame_size: None,
+                exact_ip: false,
+                registers: core::ptr::null(),
+            },
+        };
+        if !cb(&frame) {
+            break;
+        }
+
+        let next = match step(&regs, stack) {
+            Some(next) => next,
+            None => break,
+        };
+        // The stack grows down, so anything else is a loop or corruption.
+        if next.sp <= regs.sp {
+            break;
+        }
+        regs = next;
+    }
+}
+
+// How to step out of a function, as told by its encoding.
+#[derive(Debug, PartialEq)]
+enum Rule {
+    // Follow the frame record at the frame pointer.
+    FrameRecord,
+    // The function has a frame of `size` bytes without a frame record, ending
+    // in the return address, with the caller's frame pointer saved `fp_slot`
+    // words below that if it's saved at all.
+    Frameless { size: usize, fp_slot: Option<usize> },
+    // There's no way to find the caller.
+    Stop,
+}
+
+unsafe fn step(regs: &Regs, stack: &Range<usize>) -> Option<Regs> {
+    // A return address may be just past the end of the function, if it ended
+    // in a call to a function which never returns.
+    let pc = regs.pc - 1;
+    let image = function_starts::find_image(pc);
+    let entry = image.as_ref().and_then(|image| {
+        let offset = u32::try_from(pc - image.header).ok()?;
+        lookup(image.unwind_info?, offset)
+    });
+    let rule = match (&image, entry) {
+        (Some(image), Some(entry)) => arch::rule(entry.encoding, |offset| {
+            read_function(image, pc, &entry, offset)
+        }),
+        _ => Rule::FrameRecord,
+    };
+    match rule {
+        Rule::FrameRecord => step_frame_record(regs, stack),
+        Rule::Frameless { size, fp_slot } => {
+            let ret = regs.sp.checked_add(size)?.checked_sub(WORD)?;
+            let fp = match fp_slot {
+                Some(slot) => read_stack(ret.checked_sub(slot * WORD)?, stack)?,
+                None => regs.fp,
+            };
+            Some(Regs {
+                pc: arch::strip_return_address(read_stack(ret, stack)?),
+                sp: ret + WORD,
+                fp,
+            })
+        }
+        Rule::Stop => None,
+    }
+}
+
+unsafe fn step_frame_record(regs: &Regs, stack: &Range<usize>) -> Option<Regs> {
+    let next = read_stack(regs.fp, stack)?;
+    let ret = read_stack(regs.fp.checked_add(WORD)?, stack)?;
+    Some(Regs {
+        pc: arch::strip_return_address(ret),
+        // The caller's stack pointer is just above the frame record.
+        sp: regs.fp + 2 * WORD,
+        fp: next,
+    })
+}
+
+// Reads the word at `addr`, if it's aligned and entirely within `stack`.
+unsafe fn read_stack(addr: usize, stack: &Range<usize>) -> Option<usize> {
+    if addr & (WORD - 1) != 0
+        || addr < stack.start
+        || !matches!(addr.checked_add(WORD), Some(end) if end <= stack.end)
+    {
+        return None;
+    }
+    Some(*(addr as *const usize))
+}
+
+// Reads the 32 bits `offset` bytes into the function `pc` is in, as long as
+// they're within the image's `__TEXT`.
+unsafe fn read_function(image: &Image, pc: usize, entry: &Entry, offset: usize) -> Option<u32> {
+    let start = image
+        .function_start(pc)
+        .unwrap_or(image.header + entry.function_offset as usize);
+    let addr = start.checked_add(offset)?;
+    if addr < image.text.start || addr.checked_add(4)? > image.text.end {
+        return None;
+    }
+    Some(core::ptr::read_unaligned(addr as *const u32))
+}
+
+// The entry in `__unwind_info` for the run of functions an offset is in.
+#[derive(Debug, PartialEq)]
+struct Entry {
+    function_offset: u32,
+    encoding: u32,
+}
+
+// Looks up the entry for `offset`, from the start of the image's header, in
+// the contents of its `__unwind_info`.
+fn lookup(section: &[u8], offset: u32) -> Option<Entry> {
+    if read_u32(section, 0)? != UNWIND_SECTION_VERSION {
+        return None;
+    }
+    let common_offset = read_u32(section, 4)? as usize;
+    let common_count = read_u32(section, 8)?;
+    let index_offset = read_u32(section, 20)? as usize;
+    let index_count = read_u32(section, 24)? as usize;
+
+    // The last entry of the index only marks where the last page ends.
+    let index_entry = |i: usize| index_offset + i * 12;
+    let end = read_u32(section, index_entry(index_count.checked_sub(1)?))?;
+    if offset >= end {
+        return None;
+    }
+    let page = search(index_count - 1, offset, |i| {
+        read_u32(section, index_entry(i))
+    })?;
+    let page_start = read_u32(section, index_entry(page))?;
+    let page = match read_u32(section, index_entry(page) + 4)? {
+        0 => return None,
+        page_offset => section.get(page_offset as usize..)?,
+    };
+    let entries = read_u16(page, 4)? as usize;
+    let count = read_u16(page, 6)? as usize;
+
+    match read_u32(page, 0)? {
+        UNWIND_SECOND_LEVEL_REGULAR => {
+            let i = search(count, offset, |i| read_u32(page, entries + i * 8))?;
+            Some(Entry {
+                function_offset: read_u32(page, entries + i * 8)?,
+                encoding: read_u32(page, entries + i * 8 + 4)?,
+            })
+        }
+        UNWIND_SECOND_LEVEL_COMPRESSED => {
+            let encodings = read_u16(page, 8)? as usize;
+            let i = search(count, offset - page_start, |i| {
+                read_u32(page, entries + i * 4).map(|entry| entry & 0x00ff_ffff)
+            })?;
+            let entry = read_u32(page, entries + i * 4)?;
+            let index = entry >> 24;
+            let encoding = if index < common_count {
+                read_u32(section, common_offset + index as usize * 4)?
+            } else {
+                read_u32(page, encodings + (index - common_count) as usize * 4)?
+            };
+            Some(Entry {
+                function_offset: page_start.checked_add(entry & 0x00ff_ffff)?,
+                encoding,
+            })
+        }
+        _ => None,
+    }
+}
+
+// Returns the index of the last of `len` keys, sorted in ascending order,
+// which is at or below `target`, or `None` if they're all above it or one of
+// those looked at can't be read.
+fn search(len: usize, target: u32, key: impl Fn(usize) -> Option<u32>) -> Option<usize> {
+    let (mut lo, mut hi) = (0, len);
+    while lo < hi {
+        let mid = lo + (hi - lo) / 2;
+        if key(mid)? <= target {
+            lo = mid + 1;
+        } else {
+            hi = mid;
+        }
+    }
+    lo.checked_sub(1)
+}
+
+fn read_u16(data: &[u8], offset: usize) -> Option<u16> {
+    let bytes = data.get(offset..offset.checked_add(2)?)?;
+    Some(u16::from_ne_bytes([bytes[0], bytes[1]]))
+}
+
+fn read_u32(data: &[u8], offset: usize) -> Option<u32> {
+    let bytes = data.get(offset..offset.checked_add(4)?)?;
+    Some(u32::from_ne_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]))
+}
+
+cfg_if::cfg_if! {
+    if #[cfg(target_arch = "x86_64")] {
+        mod arch {
+            use super::{Rule, WORD};
+
+            const UNWIND_X86_64_MODE_MASK: u32 = 0x0f00_0000;
+            const UNWIND_X86_64_MODE_RBP_FRAME: u32 = 0x0100_0000;
+            const UNWIND_X86_64_MODE_STACK_IMMD: u32 = 0x0200_0000;
+            const UNWIND_X86_64_MODE_STACK_IND: u32 = 0x0300_0000;
+            const UNWIND_X86_64_REG_RBP: u32 = 6;
+
+            // Decodes `encoding`, calling `read_function` with an offset into
+            // the function for the 32 bits there when the size of the frame is
+            // an immediate of the instruction which allocates it.
+            pub(super) fn rule(
+                encoding: u32,
+                read_function: impl FnOnce(usize) -> Option<u32>,
+            ) -> Rule {
+                let size = match encoding & UNWIND_X86_64_MODE_MASK {
+                    UNWIND_X86_64_MODE_RBP_FRAME => return Rule::FrameRecord,
+                    UNWIND_X86_64_MODE_STACK_IMMD => ((encoding >> 16) & 0xff) as usize * WORD,
+                    UNWIND_X86_64_MODE_STACK_IND => {
+                        let offset = ((encoding >> 16) & 0xff) as usize;
+                        let immediate = match read_function(offset) {
+                            Some(immediate) => immediate as usize,
+                            None => return Rule::Stop,
+                        };
+                        immediate + ((encoding >> 13) & 0x7) as usize * WORD
+                    }
+                    // DWARF, or no encoding at all.
+                    _ => return Rule::FrameRecord,
+                };
+                let count = ((encoding >> 10) & 0x7) as usize;
+                if count > 6 {
+                    return Rule::Stop;
+                }
+                // The saved registers are pushed right below the return
+                // address, in this order.
+                let registers = saved_registers(count, encoding & 0x3ff);
+                let fp_slot = registers[..count]
+                    .iter()
+                    .position(|&reg| reg == UNWIND_X86_64_REG_RBP)
+                    .map(|i| count - i);
+                Rule::Frameless { size, fp_slot }
+            }
+
+            // Undoes the linker's packing of the order of the `count` registers
+            // saved by a frameless function into a single number, returning
+            // their numbers, from 1 to 6 for rbx, r12 to r15 and rbp. Each
+            // register is a digit of that number, counting only the registers
+            // not taken by those before it, so the radix shrinks as it goes.
+            fn saved_registers(count: usize, mut permutation: u32) -> [u32; 6] {
+                let divisors: &[u32] = match count {
+                    5 | 6 => &[120, 24, 6, 2],
+                    4 => &[60, 12, 3],
+                    3 => &[20, 4],
+                    2 => &[5],
+                    _ => &[],
+                };
+                let mut digits = [0u32; 6];
+                for (digit, &divisor) in digits.iter_mut().zip(divisors) {
+                    *digit = permutation / divisor;
+                    permutation %= divisor;
+                }
+                digits[divisors.len()] = permutation;
+
+                let mut registers = [0u32; 6];
+                let mut used = [false; 7];
+                for (register, &digit) in registers.iter_mut().zip(&digits[..count]) {
+                    if let Some(reg) = (1..7).filter(|&reg| !used[reg]).nth(digit as usize) {
+                        used[reg] = true;
+                        *register = reg as u32;
+                    }
+                }
+                registers
+            }
+
+            pub(super) fn strip_return_address(ret: usize) -> usize {
+                ret
+            }
+
+            #[cfg(all(test, feature = "std"))]
+            mod tests {
+                use super::*;
+
+                #[test]
+                fn decodes_saved_registers() {
+                    // rbx, r12, r13, r14, r15, rbp is the identity permutation.
+                    assert_eq!(saved_registers(6, 0), [1, 2, 3, 4, 5, 6]);
+                    // rbp, rbx: rbp is the last of six, then rbx the first of
+                    // the five left.
+                    assert_eq!(saved_registers(2, 5 * 5), [6, 1, 0, 0, 0, 0]);
+                    // r15, rbp, r14: 4 * 20 + 4 * 4 + 3.
+                    assert_eq!(saved_registers(3, 99), [5, 6, 4, 0, 0, 0]);
+                }
+
+                #[test]
+                fn decodes_frameless_encodings() {
+                    // 0x38 bytes of frame, saving rbx then rbp.
+                    let encoding = UNWIND_X86_64_MODE_STACK_IMMD | 0x07 << 16 | 2 << 10 | 4;
+                    assert_eq!(
+                        rule(encoding, |_| None),
+                        Rule::Frameless { size: 0x38, fp_slot: Some(1) },
+                    );
+
+                    // The frame size is the immediate 4 bytes into the
+                    // function, plus the two words pushed before it.
+                    let encoding = UNWIND_X86_64_MODE_STACK_IND | 4 << 16 | 2 << 13;
+                    assert_eq!(
+                        rule(encoding, |offset| {
+                            assert_eq!(offset, 4);
+                            Some(0x100)
+                        }),
+                        Rule::Frameless { size: 0x110, fp_slot: None },
+                    );
+                    assert_eq!(rule(encoding, |_| None), Rule::Stop);
+
+                    assert_eq!(rule(UNWIND_X86_64_MODE_RBP_FRAME, |_| None), Rule::FrameRecord);
+                    assert_eq!(rule(0x0400_0000, |_| None), Rule::FrameRecord);
+                    assert_eq!(rule(0, |_| None), Rule::FrameRecord);
+                }
+            }
+        }
+    } else {
+        mod arch {
+            use super::Rule;
+
+            const UNWIND_ARM64_MODE_MASK: u32 = 0x0f00_0000;
+            const UNWIND_ARM64_MODE_FRAMELESS: u32 = 0x0200_0000;
+
+            pub(super) fn rule(
+                encoding: u32,
+                _read_function: impl FnOnce(usize) -> Option<u32>,
+            ) -> Rule {
+                match encoding & UNWIND_ARM64_MODE_MASK {
+                    UNWIND_ARM64_MODE_FRAMELESS => Rule::Stop,
+                    // A frame, DWARF, or no encoding at all.
+                    _ => Rule::FrameRecord,
+                }
+            }
+
+            // Return addresses saved by arm64e code, such as the system
+            // libraries', may be signed, with the signature in the bits above
+            // the 47 bits of address space user processes get.
+            pub(super) fn strip_return_address(ret: usize) -> usize {
+                ret & ((1 << 47) - 1)
+            }
+        }
+    }
+}
+
+#[cfg(all(test, feature = "std"))]
+mod tests {
+    use super::*;
+    use std::prelude::v1::*;
+
+    fn push_u16(data: &mut Vec<u8>, value: u16) {
+        data.extend_from_slice(&value.to_ne_bytes());
+    }
+
+    fn push_u32(data: &mut Vec<u8>, value: u32) {
+        data.extend_from_slice(&value.to_ne_bytes());
+    }
+
+    // Lays out an `__unwind_info` with one common encoding, a regular page
+    // covering 0x1000 to 0x2000 and a compressed page covering 0x2000 to
+    // 0x3000.
+    fn section() -> Vec<u8> {
+        let mut data = Vec::new();
+        // Header, with the common encodings at 28 and the index at 32.
+        for &word in &[1, 28, 1, 0, 0, 32, 3] {
+            push_u32(&mut data, word);
+        }
+        push_u32(&mut data, 0x0100_0000);
+        // Index, with the pages at 68 and 92.
+        for &word in &[0x1000, 68, 0, 0x2000, 92, 0, 0x3000, 0, 0] {
+            push_u32(&mut data, word);
+        }
+        // Regular page, with two entries.
+        push_u32(&mut data, UNWIND_SECOND_LEVEL_REGULAR);
+        push_u16(&mut data, 8);
+        push_u16(&mut data, 2);
+        for &word in &[0x1000, 0x0100_0000, 0x1800, 0x0200_0000] {
+            push_u32(&mut data, word);
+        }
+        // Compressed page, with two entries and one encoding of its own.
+        push_u32(&mut data, UNWIND_SECOND_LEVEL_COMPRESSED);
+        push_u16(&mut data, 12);
+        push_u16(&mut data, 2);
+        push_u16(&mut data, 20);
+        push_u16(&mut data, 1);
+        push_u32(&mut data, 0x0000_0000);
+        push_u32(&mut data, 0x0100_0400);
+        push_u32(&mut data, 0x0300_0000);
+        data
+    }
+
+    fn entry(function_offset: u32, encoding: u32) -> Option<Entry> {
+        Some(Entry {
+            function_offset,
+            encoding,
+        })
+    }
+
+    #[test]
+    fn looks_up_regular_pages() {
+        let section = section();
+        assert_eq!(lookup(&section, 0x1000), entry(0x1000, 0x0100_0000));
+        assert_eq!(lookup(&section, 0x17ff), entry(0x1000, 0x0100_0000));
+        assert_eq!(lookup(&section, 0x1800), entry(0x1800, 0x0200_0000));
+        assert_eq!(lookup(&section, 0x1fff), entry(0x1800, 0x0200_0000));
+    }
+
+    #[test]
+    fn looks_up_compressed_pages() {
+        let section = section();
+        // The first entry uses the common encoding, the second the page's.
+        assert_eq!(lookup(&section, 0x2000), entry(0x2000, 0x0100_0000));
+        assert_eq!(lookup(&section, 0x23ff), entry(0x2000, 0x0100_0000));
+        assert_eq!(lookup(&section, 0x2400), entry(0x2400, 0x0300_0000));
+        assert_eq!(lookup(&section, 0x2fff), entry(0x2400, 0x0300_0000));
+    }
+
+    #[test]
+    fn misses_outside_the_index() {
+        let section = section();
+        assert_eq!(lookup(&section, 0xfff), None);
+        assert_eq!(lookup(&section, 0x3000), None);
+        assert_eq!(lookup(&section[..40], 0x1000), None);
+        assert_eq!(lookup(&[], 0x1000), None);
+    }
+
+    #[test]
+    fn walks_frame_records() {
+        let mut stack = [0usize; 8];
+        let base = stack.as_ptr() as usize;
+        stack[2] = base + 6 * WORD;
+        stack[3] = 0x2000;
+        stack[6] = 0;
+        stack[7] = 0;
+        let bounds = base..base + stack.len() * WORD;
+        let regs = Regs {
+            pc: 0,
+            sp: base,
+            fp: base + 2 * WORD,
+        };
+        let next = unsafe { step_frame_record(&regs, &bounds) }.unwrap();
+        assert_eq!(
+            (next.pc, next.sp, next.fp),
+            (0x2000, base + 4 * WORD, base + 6 * WORD)
+        );
+
+        // Records outside of the stack, or misaligned, aren't read.
+        let outside = Regs {
+            fp: base + 7 * WORD,
+            ..regs
+        };
+        assert!(unsafe { step_frame_record(&outside, &bounds) }.is_none());
+        let misaligned = Regs {
+            fp: base + 1,
+            ..regs
+        };
+        assert!(unsafe { step_frame_record(&misaligned, &bounds) }.is_none());
+    }
+}
@@ -49,6 +49,38 @@ impl Frame {
         Some(self.base_address)
     }
 
+    pub fn raw_unwind_context(&self) -> Option<*mut c_void> {
+        None
+    }
+
+    pub fn lsda(&self) -> Option<*const c_void> {
+        None
+    }
+
+    pub fn caller_ip(&self) -> Option<usize> {
+        None
+    }
+
+    pub fn proc_info(&self) -> Option<super::ProcInfo> {
+        None
+    }
+
+    pub fn frame_size(&self) -> Option<usize> {
+        None
+    }
+
+    pub fn capture_registers(&self) -> Option<crate::RegisterContext> {
+        None
+    }
+
+    pub fn function_start(&self) -> Option<usize> {
+        None
+    }
+
+    pub fn ip_is_exact(&self) -> bool {
+        false
+    }
+
     fn addr_pc(&self) -> &ADDRESS64 {
         match self.stack_frame {
             StackFrame::New(ref new) => &new.AddrPC,
@@ -85,6 +117,35 @@ impl Frame {
     }
 }
 
+// Frame pointer walking isn't implemented here, `StackWalkEx` is cheap enough.
+#[inline(always)]
+pub fn trace_frame_pointers(
+    _cb: &mut dyn FnMut(&super::Frame) -> bool,
+    _stack: Option<core::ops::Range<usize>>,
+) {
+}
+
+// `StackWalkEx` doesn't say why it stopped, which is usually the end of the
+// stack anyway.
+#[inline(always)]
+pub unsafe fn trace_status(cb: &mut dyn FnMut(&super::Frame) -> bool) -> super::TraceOutcome {
+    trace(cb);
+    super::TraceOutcome::Complete
+}
+
+// Nor does it have any reason codes to report.
+#[inline(always)]
+pub unsafe fn trace_debug(
+    cb: &mut dyn FnMut(&super::Frame) -> bool,
+    _on_reason: &mut dyn FnMut(Option<&super::Frame>, i32),
+) -> super::TraceOutcome {
+    trace_status(cb)
+}
+
+pub fn available() -> bool {
+    true
+}
+
 #[repr(C, align(16))] // required by `CONTEXT`, is a FIXME in winapi right now
 struct MyContext(CONTEXT);
 
@@ -0,0 +1,577 @@
+//! Backtrace support by walking the chain of saved frame pointers.
+//!
+//! This is far cheaper than `_Unwind_Backtrace` since it doesn't consult any
+//! unwind tables, but it's only accurate if everything on the stack was
+//! compiled with frame pointers (e.g. `-C force-frame-pointers=yes`). When
+//! that's not the case the "frame pointer" register holds arbitrary data, so
+//! every pointer is validated against the bounds of the stack before it's
+//! dereferenced and the walk stops at the first one that doesn't check out.
+//!
+//! Only x86_64 and aarch64 are supported, where a frame record is a pair of
+//! words holding the caller's frame pointer followed by the return address.
+//! The walk starts from the registers, which are read with `asm!`, so it's
+//! also only supported when built with Rust 1.59 or later.
+//!
+//! The bounds checks trust that the stack bounds are right and that all of the
+//! stack is mapped. With the `safe-deref` feature on Linux and Android each
+//! frame record is additionally read with `process_vm_readv` on this very
+//! process, which fails with `EFAULT` rather than faulting if the memory isn't
+//! readable, so that not even a garbage stack or bogus bounds can crash the
+//! walk.
+//!
+//! Runtimes built with `-fsplit-stack`, such as gccgo's, grow a thread's stack
+//! by allocating further segments anywhere in memory, so the chain may jump
+//! from one segment to another, and down as often as up. With the
+//! `split-stack` feature on Linux the segments in use are listed with libgcc's
+//! `__splitstack_find` at the start of each walk, and the chain may move on
+//! from one segment to any segment further out, in whichever direction. The
+//! checks within a segment are the same as for a contiguous stack.
+
+use super::libunwind::Frame;
+use core::mem;
+use core::ops::Range;
+
+const WORD: usize = mem::size_of::<usize>();
+
+#[inline(always)]
+pub unsafe fn trace(cb: &mut dyn FnMut(&super::Frame) -> bool, stack: Option<Range<usize>>) {
+    let (fp, sp) = match registers() {
+        Some(regs) => regs,
+        None => return,
+    };
+    let mut stacks = Stacks::new();
+    split_stack::find_segments(&mut stacks);
+    let on_segment = stacks.ranges().iter().any(|s| s.contains(&sp));
+    if let Some(stack) = stack.or_else(current_thread_stack) {
+        // Nothing below the current stack pointer belongs to a live frame,
+        // unless it's on a segment of a split stack instead.
+        let lo = if on_segment {
+            stack.start
+        } else {
+            stack.start.max(sp)
+        };
+        stacks.push(lo..stack.end);
+    }
+    walk(fp, stacks.ranges(), cb);
+}
+
+/// Follows the frame pointer chain starting at `fp`, never reading memory
+/// outside of `stacks`, the segments of the stack, innermost first.
+///
+/// The walk ends, delivering whatever frames were gathered so far, as soon as
+/// a frame record is misaligned, lies outside of the segment it's in and all
+/// those further out, or doesn't point strictly upwards to its caller within
+/// a segment (the stack grows down, so anything else is a loop or
+/// corruption). A contiguous stack is a single segment.
+unsafe fn walk(mut fp: usize, stacks: &[Range<usize>], cb: &mut dyn FnMut(&super::Frame) -> bool) {
+    let reader = Reader::new();
+    let mut current = match segment_of(fp, stacks) {
+        Some(i) => i,
+        None => return,
+    };
+    while let Some((next, ip)) = reader.read_record(fp) {
+        if ip == 0 {
+            break;
+        }
+
+        let frame = super::Frame {
+            inner: Frame::Cloned {
+                ip: ip as *mut _,
+                // The caller's stack pointer is just above the frame record.
+                sp: (fp + 2 * WORD) as *mut _,
+                // There are no unwind tables to consult for where the
+                // function starts, so this is the best we can do.
+                symbol_address: Some(ip as *mut _),
+                lsda: None,
+                caller_ip: None,
+                proc_info: None,
+                frame_size: None,
+                exact_ip: false,
+                registers: core::ptr::null(),
+            },
+        };
+        if !cb(&frame) {
+            break;
+        }
+
+        current = match segment_of(next, &stacks[current..]) {
+            Some(0) if next > fp => current,
+            Some(i) if i > 0 => current + i,
+            _ => break,
+        };
+        fp = next;
+    }
+}
+
+// Returns the index of the first of `stacks` which the frame record at `fp`
+// lies within.
+fn segment_of(fp: usize, stacks: &[Range<usize>]) -> Option<usize> {
+    stacks.iter().position(|stack| is_valid_record(fp, stack))
+}
+
+fn is_valid_record(fp: usize, stack: &Range<usize>) -> bool {
+    fp & (WORD - 1) == 0
+        && fp >= stack.start
+        && matches!(fp.checked_add(2 * WORD), Some(end) if end <= stack.end)
+}
+
+// The segments of the stack being walked, innermost first, kept in a fixed
+// array so that walks don't allocate.
+struct Stacks {
+    ranges: [Range<usize>; 64],
+    len: usize,
+}
+
+impl Stacks {
+    fn new() -> Stacks {
+        const EMPTY: Range<usize> = 0..0;
+        Stacks {
+            ranges: [EMPTY; 64],
+            len: 0,
+        }
+    }
+
+    // Adds a segment further out than those already added, returning `false`
+    // if there's no room left for it.
+    fn push(&mut self, range: Range<usize>) -> bool {
+        match self.ranges.get_mut(self.len) {
+            Some(slot) => {
+                *slot = range;
+                self.len += 1;
+                true
+            }
+            None => false,
+        }
+    }
+
+    fn ranges(&self) -> &[Range<usize>] {
+        &self.ranges[..self.len]
+    }
+}
+
+// Lists the segments of a split stack, if the current thread has one.
+cfg_if::cfg_if! {
+    if #[cfg(all(feature = "split-stack", target_os = "linux"))] {
+        mod split_stack {
+            use super::Stacks;
+            use core::ffi::c_void;
+            use core::ptr;
+            use core::sync::atomic::{AtomicUsize, Ordering};
+
+            type SplitstackFind = unsafe extern "C" fn(
+                *mut c_void,
+                *mut c_void,
+                *mut usize,
+                *mut *mut c_void,
+                *mut *mut c_void,
+                *mut *mut c_void,
+            ) -> *mut c_void;
+
+            const UNRESOLVED: usize = 0;
+            const MISSING: usize = 1;
+
+            static SPLITSTACK_FIND: AtomicUsize = AtomicUsize::new(UNRESOLVED);
+
+            // Looks up libgcc's `__splitstack_find`, which is only around if
+            // something in the process was built with `-fsplit-stack`. The
+            // first lookup isn't async-signal-safe, since it's a `dlsym`.
+            fn get() -> Option<SplitstackFind> {
+                let mut addr = SPLITSTACK_FIND.load(Ordering::Relaxed);
+                if addr == UNRESOLVED {
+                    let name = b"__splitstack_find\0";
+                    let sym = unsafe { libc::dlsym(libc::RTLD_DEFAULT, name.as_ptr().cast()) };
+                    addr = sym as usize;
+                    if addr == UNRESOLVED {
+                        addr = MISSING;
+                    }
+                    SPLITSTACK_FIND.store(addr, Ordering::Relaxed);
+                }
+                if addr == MISSING {
+                    None
+                } else {
+                    Some(unsafe { core::mem::transmute::<usize, SplitstackFind>(addr) })
+                }
+            }
+
+            // Adds the parts of the current thread's stack segments which are
+            // in use, starting with the one holding the current stack pointer
+            // and ending with the thread's original stack. Nothing is added
+            // if the thread's stack isn't split, or `__splitstack_find` isn't
+            // around.
+            pub(super) fn find_segments(stacks: &mut Stacks) {
+                let find = match get() {
+                    Some(find) => find,
+                    None => return,
+                };
+                let mut len = 0;
+                let mut next_segment = ptr::null_mut();
+                let mut next_sp = ptr::null_mut();
+                let mut initial_sp = ptr::null_mut();
+                let mut segment = ptr::null_mut();
+                let mut sp = ptr::null_mut();
+                loop {
+                    let start = unsafe {
+                        find(
+                            segment,
+                            sp,
+                            &mut len,
+                            &mut next_segment,
+                            &mut next_sp,
+                            &mut initial_sp,
+                        )
+                    };
+                    if start.is_null() || !stacks.push(start as usize..start as usize + len) {
+                        break;
+                    }
+                    segment = next_segment;
+                    sp = next_sp;
+                }
+            }
+        }
+    } else {
+        mod split_stack {
+            use super::Stacks;
+
+            pub(super) fn find_segments(_stacks: &mut Stacks) {}
+        }
+    }
+}
+
+// Reads frame records, checking that they're readable first with the
+// `safe-deref` feature.
+cfg_if::cfg_if! {
+    if #[cfg(all(feature = "safe-deref", any(target_os = "linux", target_os = "android")))] {
+        struct Reader {
+            pid: libc::pid_t,
+        }
+
+        impl Reader {
+            fn new() -> Reader {
+                Reader {
+                    pid: unsafe { libc::getpid() },
+                }
+            }
+
+            // Returns the caller's frame pointer and the return address stored
+            // at `fp`, or `None` if they can't be read. That includes the
+            // system call being unavailable, e.g. blocked by a seccomp filter,
+            // in which case no frames are walked at all.
+            unsafe fn read_record(&self, fp: usize) -> Option<(usize, usize)> {
+                let mut record = [0usize; 2];
+                let local = libc::iovec {
+                    iov_base: record.as_mut_ptr().cast(),
+                    iov_len: mem::size_of_val(&record),
+                };
+                let remote = libc::iovec {
+                    iov_base: fp as *mut _,
+                    iov_len: mem::size_of_val(&record),
+                };
+                let read = libc::process_vm_readv(self.pid, &local, 1, &remote, 1, 0);
+                if read == mem::size_of_val(&record) as isize {
+                    Some((record[0], record[1]))
+                } else {
+                    None
+                }
+            }
+        }
+    } else {
+        struct Reader;
+
+        impl Reader {
+            fn new() -> Reader {
+                Reader
+            }
+
+            unsafe fn read_record(&self, fp: usize) -> Option<(usize, usize)> {
+                let record = fp as *const usize;
+                Some((*record, *record.add(1)))
+            }
+        }
+    }
+}
+
+// Returns the current frame pointer and stack pointer.
+#[inline(always)]
+pub(super) fn registers() -> Option<(usize, usize)> {
+    cfg_if::cfg_if! {
+        if #[cfg(backtrace_no_asm)] {
+            None
+        } else if #[cfg(target_arch = "x86_64")] {
+            let (fp, sp): (usize, usize);
+            unsafe {
+                core::arch::asm!(
+                    "mov {}, rbp",
+                    "mov {}, rsp",
+                    out(reg) fp,
+                    out(reg) sp,
+                    options(nomem, nostack, preserves_flags),
+                );
+            }
+            Some((fp, sp))
+        } else if #[cfg(target_arch = "aarch64")] {
+            let (fp, sp): (usize, usize);
+            unsafe {
+                core::arch::asm!(
+                    "mov {}, x29",
+                    "mov {}, sp",
+                    out(reg) fp,
+                    out(reg) sp,
+                    options(nomem, nostack, preserves_flags),
+                );
+            }
+            Some((fp, sp))
+        } else {
+            None
+        }
+    }
+}
+
+// Learns the bounds of the current thread's stack, caching them per thread.
+//
+// The first lookup on each thread is not async-signal-safe, since
+// `pthread_getattr_np` may allocate (and on the main thread reads
+// `/proc/self/maps`), so signal handlers should either pass in the bounds or
+// make sure this has already run on the thread being interrupted.
+#[cfg(all(feature = "std", any(target_os = "linux", target_os = "android")))]
+pub(super) fn current_thread_stack() -> Option<Range<usize>> {
+    use core::cell::Cell;
+
+    thread_local!(static STACK: Cell<(usize, usize)> = Cell::new((0, 0)));
+
+    let (lo, hi) = STACK.try_with(|s| s.get()).ok()?;
+    if hi != 0 {
+        return Some(lo..hi);
+    }
+
+    let (lo, hi) = unsafe {
+        let mut attr = mem::zeroed::<libc::pthread_attr_t>();
+        if libc::pthread_getattr_np(libc::pthread_self(), &mut attr) != 0 {
+            return None;
+        }
+        let mut addr = core::ptr::null_mut();
+        let mut size = 0;
+        let ret = libc::pthread_attr_getstack(&attr, &mut addr, &mut size);
+        libc::pthread_attr_destroy(&mut attr);
+        if ret != 0 {
+            return None;
+        }
+        (addr as usize, addr as usize + size)
+    };
+    let _ = STACK.try_with(|s| s.set((lo, hi)));
+    Some(lo..hi)
+}
+
+// Apple's `pthread_get_stackaddr_np` and `pthread_get_stacksize_np` just read
+// the thread's own structure, so there's no need to cache them.
+#[cfg(all(feature = "std", any(target_os = "macos", target_os = "ios")))]
+pub(super) fn current_thread_stack() -> Option<Range<usize>> {
+    let (hi, size) = unsafe {
+        let thread = libc::pthread_self();
+        (
+            libc::pthread_get_stackaddr_np(thread) as usize,
+            libc::pthread_get_stacksize_np(thread),
+        )
+    };
+    if hi == 0 {
+        return None;
+    }
+    Some(hi.checked_sub(size)?..hi)
+}
+
+#[cfg(not(all(
+    feature = "std",
+    any(
+        target_os = "linux",
+        target_os = "android",
+        target_os = "macos",
+        target_os = "ios",
+    ),
+)))]
+pub(super) fn current_thread_stack() -> Option<Range<usize>> {
+    None
+}
+
+#[cfg(all(test, feature = "std"))]
+mod tests {
+    use super::*;
+    use std::prelude::v1::*;
+
+    // Walks the frame records laid out in `stack`, starting with the one at
+    // `fp_index`, returning the ips seen.
+    fn walk_fake(stack: &[usize], fp_index: usize) -> Vec<usize> {
+        let base = stack.as_ptr() as usize;
+        let mut ips = Vec::new();
+        unsafe {
+            walk(
+                base + fp_index * WORD,
+                &[base..base + stack.len() * WORD],
+                &mut |frame| {
+                    ips.push(frame.ip() as usize);
+                    true
+                },
+            );
+        }
+        ips
+    }
+
+    fn addr_of(stack: &[usize], index: usize) -> usize {
+        stack.as_ptr() as usize + index * WORD
+    }
+
+    #[test]
+    fn well_formed_chain() {
+        let mut stack = [0usize; 8];
+        stack[0] = addr_of(&stack, 2);
+        stack[1] = 0x1000;
+        stack[2] = addr_of(&stack, 6);
+        stack[3] = 0x2000;
+        stack[6] = 0;
+        stack[7] = 0x3000;
+        assert_eq!(walk_fake(&stack, 0), [0x1000, 0x2000, 0x3000]);
+    }
+
+    #[test]
+    fn corrupted_frame_pointer_stops() {
+        let mut stack = [0usize; 8];
+        stack[0] = addr_of(&stack, 2);
+        stack[1] = 0x1000;
+        // Points far outside the stack, e.g. into a guard page.
+        stack[2] = 0x10;
+        stack[3] = 0x2000;
+        assert_eq!(walk_fake(&stack, 0), [0x1000, 0x2000]);
+
+        // Points back down the stack, which would loop forever.
+        stack[2] = addr_of(&stack, 0);
+        assert_eq!(walk_fake(&stack, 0), [0x1000, 0x2000]);
+
+        // Misaligned.
+        stack[2] = addr_of(&stack, 4) + 1;
+        assert_eq!(walk_fake(&stack, 0), [0x1000, 0x2000]);
+
+        // The very first frame pointer is garbage.
+        assert!(walk_fake(&stack, 8).is_empty());
+        assert!(walk_fake(&stack, 7).is_empty());
+    }
+
+    #[test]
+    fn split_stack_segments() {
+        // The inner segment is the upper half, so the chain goes down into
+        // the outer segment, and then back up within it.
+        let mut stack = [0usize; 16];
+        let inner = addr_of(&stack, 8)..addr_of(&stack, 16);
+        let outer = addr_of(&stack, 0)..addr_of(&stack, 8);
+        stack[8] = addr_of(&stack, 10);
+        stack[9] = 0x1000;
+        stack[10] = addr_of(&stack, 2);
+        stack[11] = 0x2000;
+        stack[2] = addr_of(&stack, 6);
+        stack[3] = 0x3000;
+        stack[6] = 0;
+        stack[7] = 0x4000;
+        let walk_segments = |stack: &[usize], stacks: &[Range<usize>]| {
+            let mut ips = Vec::new();
+            unsafe {
+                walk(addr_of(stack, 8), stacks, &mut |frame| {
+                    ips.push(frame.ip() as usize);
+                    true
+                });
+            }
+            ips
+        };
+        assert_eq!(
+            walk_segments(&stack, &[inner.clone(), outer.clone()]),
+            [0x1000, 0x2000, 0x3000, 0x4000]
+        );
+
+        // Without knowing about the segments the jump down looks like a loop.
+        assert_eq!(
+            walk_segments(&stack, &[outer.start..inner.end]),
+            [0x1000, 0x2000]
+        );
+
+        // Segments are only ever left for ones further out.
+        stack[2] = addr_of(&stack, 12);
+        assert_eq!(
+            walk_segments(&stack, &[inner, outer]),
+            [0x1000, 0x2000, 0x3000]
+        );
+    }
+
+    // Walks stacks full of random words, including pointers to unmapped and
+    // unreadable memory, with bounds which don't rule any of them out, so
+    // only the checked reads stand between the walk and a crash.
+    #[test]
+    #[cfg(all(
+        feature = "safe-deref",
+        any(target_os = "linux", target_os = "android")
+    ))]
+    fn fuzz_random_stacks() {
+        let guard = unsafe {
+            libc::mmap(
+                core::ptr::null_mut(),
+                4096,
+                libc::PROT_NONE,
+                libc::MAP_PRIVATE | libc::MAP_ANONYMOUS,
+                -1,
+                0,
+            )
+        };
+        assert_ne!(guard, libc::MAP_FAILED);
+        let guard = guard as usize;
+
+        // xorshift, so failures are reproducible.
+        let mut state = 0x2545_f491_4f6c_dd1du64;
+        let mut random = move || {
+            state ^= state << 13;
+            state ^= state >> 7;
+            state ^= state << 17;
+            state as usize
+        };
+
+        let mut stack = [0usize; 64];
+        for _ in 0..10_000 {
+            let base = stack.as_ptr() as usize;
+            for i in 0..stack.len() {
+                stack[i] = match random() % 5 {
+                    0 => base + (random() % (stack.len() + 8)) * WORD,
+                    1 => guard + (random() % 512) * WORD,
+                    2 => random() & !(WORD - 1),
+                    3 => random() % 4096,
+                    _ => random(),
+                };
+            }
+            let fp = match random() % 3 {
+                0 => guard,
+                1 => random() & !(WORD - 1),
+                _ => base + (random() % stack.len()) * WORD,
+            };
+            let mut frames = 0;
+            unsafe {
+                walk(fp, &[0..usize::MAX], &mut |_| {
+                    frames += 1;
+                    true
+                });
+            }
+            assert!(frames <= stack.len() + 1024);
+        }
+
+        unsafe {
+            libc::munmap(guard as *mut _, 4096);
+        }
+    }
+
+    #[test]
+    fn real_stack_terminates() {
+        let mut frames = 0;
+        unsafe {
+            trace(
+                &mut |_| {
+                    frames += 1;
+                    true
+                },
+                None,
+            );
+        }
+        assert!(frames < 1024);
+    }
+}
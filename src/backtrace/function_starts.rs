@@ -0,0 +1,217 @@
+//! Finding the image and the start of the function an address is in on Apple
+//! platforms.
+//!
+//! `_Unwind_FindEnclosingFunction` can't be trusted here: it answers from the
+//! compact unwind table in `__unwind_info`, and the linker only gives a
+//! function an entry of its own there if it has an LSDA or unwinds
+//! differently from the function before it, so a run of similar functions
+//! shares the entry of the first of them. The compact unwinder, which reads
+//! `__unwind_info` itself, runs into just the same problem, so it also relies
+//! on this for function starts.
+//!
+//! The linker also records where every function starts, for the benefit of
+//! debuggers and profilers, in the `LC_FUNCTION_STARTS` load command. Its data
+//! lives in the `__LINKEDIT` segment, which dyld maps into memory along with
+//! the rest of the image, as a list of ULEB128 encoded offsets from the start
+//! of `__TEXT` to the first function and then from each function to the
+//! next, ending with a zero. This finds the image an address is in with
+//! dyld's list of loaded images, and then walks that list for the last start
+//! at or below the address.
+//!
+//! Everything is read straight out of the images' mapped headers, so this
+//! neither allocates nor caches anything, at the cost of a linear walk of the
+//! function starts on each lookup.
+
+#![allow(deprecated)]
+
+use core::ops::Range;
+use core::ptr;
+
+const MH_MAGIC: u32 = 0xfeed_face;
+const MH_MAGIC_64: u32 = 0xfeed_facf;
+
+const LC_SEGMENT: u32 = 0x1;
+const LC_SEGMENT_64: u32 = 0x19;
+const LC_FUNCTION_STARTS: u32 = 0x26;
+
+/// Returns the start of the function containing `addr`, or `None` if it isn't
+/// in any loaded image or its image has no `LC_FUNCTION_STARTS`.
+pub(super) fn function_start(addr: usize) -> Option<usize> {
+    find_image(addr)?.function_start(addr)
+}
+
+/// Returns the loaded image whose `__TEXT` segment contains `addr`.
+pub(super) fn find_image(addr: usize) -> Option<Image> {
+    let images = unsafe { libc::_dyld_image_count() };
+    (0..images).find_map(|i| unsafe { image_containing(i, addr) })
+}
+
+/// The parts of a loaded image needed to find function starts and to unwind
+/// through it.
+pub(super) struct Image {
+    /// Where the image's Mach-O header is, which `__unwind_info` counts its
+    /// function offsets from.
+    pub(super) header: usize,
+    /// Where `__TEXT` is.
+    pub(super) text: Range<usize>,
+    /// The contents of `__TEXT,__unwind_info`, if there is one.
+    pub(super) unwind_info: Option<&'static [u8]>,
+    function_starts: Option<&'static [u8]>,
+}
+
+impl Image {
+    /// Returns the start of the function containing `addr`, which must be in
+    /// this image, or `None` if it has no `LC_FUNCTION_STARTS`.
+    pub(super) fn function_start(&self, addr: usize) -> Option<usize> {
+        let mut best = None;
+        let mut start = self.text.start;
+        let mut rest = self.function_starts?;
+        while let Some((delta, len)) = read_uleb128(rest) {
+            if delta == 0 {
+                break;
+            }
+            start = start.wrapping_add(delta as usize);
+            if start > addr {
+                break;
+            }
+            best = Some(start);
+            rest = &rest[len..];
+        }
+        best
+    }
+}
+
+// The parts of an image's load commands needed to find its function starts
+// and unwind info, as addresses in the image's own address space, before the
+// slide is applied.
+#[derive(Default)]
+struct Layout {
+    text: (u64, u64),
+    linkedit: Option<(u64, u64)>,
+    function_starts: Option<(u32, u32)>,
+    unwind_info: Option<(u64, u64)>,
+}
+
+unsafe fn image_containing(image: u32, addr: usize) -> Option<Image> {
+    let header = libc::_dyld_get_image_header(image) as *const u8;
+    if header.is_null() {
+        return None;
+    }
+    let is_64 = match read_u32(header, 0) {
+        MH_MAGIC => false,
+        MH_MAGIC_64 => true,
+        _ => return None,
+    };
+    let layout = read_layout(header, is_64);
+    let slide = libc::_dyld_get_image_vmaddr_slide(image) as usize;
+
+    let (text_addr, text_size) = layout.text;
+    let text_start = (text_addr as usize).wrapping_add(slide);
+    if addr < text_start || addr - text_start >= text_size as usize {
+        return None;
+    }
+    let function_starts = match (layout.function_starts, layout.linkedit) {
+        (Some((dataoff, datasize)), Some((linkedit_addr, linkedit_fileoff))) => {
+            let data = (linkedit_addr as usize)
+                .wrapping_add(slide)
+                .wrapping_add(dataoff as usize)
+                .wrapping_sub(linkedit_fileoff as usize) as *const u8;
+            Some(core::slice::from_raw_parts(data, datasize as usize))
+        }
+        _ => None,
+    };
+    let unwind_info = layout.unwind_info.map(|(addr, size)| {
+        let data = (addr as usize).wrapping_add(slide) as *const u8;
+        core::slice::from_raw_parts(data, size as usize)
+    });
+    Some(Image {
+        header: header as usize,
+        text: text_start..text_start + text_size as usize,
+        unwind_info,
+        function_starts,
+    })
+}
+
+unsafe fn read_layout(header: *const u8, is_64: bool) -> Layout {
+    let ncmds = read_u32(header, 16);
+    let mut cmd = header.add(if is_64 { 32 } else { 28 });
+    let mut layout = Layout::default();
+    for _ in 0..ncmds {
+        let kind = read_u32(cmd, 0);
+        let size = read_u32(cmd, 4) as usize;
+        let segment = match kind {
+            LC_SEGMENT_64 => Some((read_u64(cmd, 24), read_u64(cmd, 32), read_u64(cmd, 40))),
+            LC_SEGMENT => Some((
+                u64::from(read_u32(cmd, 24)),
+                u64::from(read_u32(cmd, 28)),
+                u64::from(read_u32(cmd, 32)),
+            )),
+            _ => None,
+        };
+        if let Some((vmaddr, vmsize, fileoff)) = segment {
+            let name = core::slice::from_raw_parts(cmd.add(8), 16);
+            if name.starts_with(b"__TEXT\0") {
+                layout.text = (vmaddr, vmsize);
+                layout.unwind_info = find_unwind_info(cmd, is_64);
+            } else if name.starts_with(b"__LINKEDIT\0") {
+                layout.linkedit = Some((vmaddr, fileoff));
+            }
+        } else if kind == LC_FUNCTION_STARTS {
+            layout.function_starts = Some((read_u32(cmd, 8), read_u32(cmd, 12)));
+        }
+        if size == 0 {
+            break;
+        }
+        cmd = cmd.add(size);
+    }
+    layout
+}
+
+// Looks through the sections of the `__TEXT` segment command at `cmd` for
+// `__unwind_info`, returning its address and size.
+unsafe fn find_unwind_info(cmd: *const u8, is_64: bool) -> Option<(u64, u64)> {
+    let (nsects, first, section_size) = if is_64 {
+        (read_u32(cmd, 64), 72, 80)
+    } else {
+        (read_u32(cmd, 48), 56, 68)
+    };
+    (0..nsects as usize).find_map(|i| {
+        let section = cmd.add(first + i * section_size);
+        let name = core::slice::from_raw_parts(section, 16);
+        if !name.starts_with(b"__unwind_info\0") {
+            return None;
+        }
+        Some(if is_64 {
+            (read_u64(section, 32), read_u64(section, 40))
+        } else {
+            (
+                u64::from(read_u32(section, 32)),
+                u64::from(read_u32(section, 36)),
+            )
+        })
+    })
+}
+
+unsafe fn read_u32(base: *const u8, offset: usize) -> u32 {
+    ptr::read_unaligned(base.add(offset) as *const u32)
+}
+
+unsafe fn read_u64(base: *const u8, offset: usize) -> u64 {
+    ptr::read_unaligned(base.add(offset) as *const u64)
+}
+
+// Decodes a ULEB128 value from the start of `data`, returning it along with
+// the number of bytes it took up.
+fn read_uleb128(data: &[u8]) -> Option<(u64, usize)> {
+    let mut value = 0u64;
+    for (i, &byte) in data.iter().enumerate() {
+        if i >= 10 {
+            return None;
+        }
+        value |= u64::from(byte & 0x7f) << (7 * i);
+        if byte & 0x80 == 0 {
+            return Some((value, i + 1));
+        }
+    }
+    None
+}
@@ -17,14 +17,33 @@
 #![allow(unused)]
 use super::super::Bomb;
 use core::ffi::c_void;
-use addr2line::gimli::UnwindContext;
+use core::ptr;
 
 pub enum Frame {
     Raw(*mut uw::_Unwind_Context),
     Cloned {
         ip: *mut c_void,
         sp: *mut c_void,
-        symbol_address: *mut c_void,
+        // `None` if it hasn't been looked up yet, which is left until it's
+        // asked for since it's an extra trip into the unwinder.
+        symbol_address: Option<*mut c_void>,
+        // Only known to the external unwinder.
+        lsda: Option<*const c_void>,
+        // The next frame's `ip`, filled in by the external unwinder once it
+        // has stepped to that frame.
+        caller_ip: Option<*mut c_void>,
+        // Only known to the external unwinder.
+        proc_info: Option<super::ProcInfo>,
+        // How far the caller's `sp` is from this one's, filled in along with
+        // `caller_ip`.
+        frame_size: Option<usize>,
+        // Whether `ip` is the instruction the frame was interrupted at rather
+        // than a return address.
+        exact_ip: bool,
+        // The registers the external unwinder recovered for this frame, kept
+        // on the stack of the trace and so only set while the frame is being
+        // passed to the closure, and null otherwise.
+        registers: *const crate::RegisterContext,
     },
 }
 
@@ -53,100 +72,426 @@ impl Frame {
     }
 
     pub fn symbol_address(&self) -> *mut c_void {
-        if let Frame::Cloned { symbol_address, .. } = *self {
+        if let Frame::Cloned {
+            symbol_address: Some(symbol_address),
+            ..
+        } = *self
+        {
             return symbol_address;
         }
+        match self.enclosing_function() {
+            Some(start) => start,
+            // Where there's no telling, as with `_Unwind_FindEnclosingFunction`
+            // on macOS, the ip is the best guess. Otherwise the unwinder has
+            // no function there, and said so with a null pointer.
+            None if cfg!(any(target_os = "macos", target_os = "ios")) => self.ip(),
+            None => ptr::null_mut(),
+        }
+    }
+
+    pub fn function_start(&self) -> Option<usize> {
+        match *self {
+            Frame::Cloned {
+                proc_info: Some(info),
+                ..
+            } if info.start_ip != 0 => Some(info.start_ip),
+            // Frames from the external unwinder without proc info, and those
+            // from walking frame pointers, whose `symbol_address` is just a
+            // stand-in.
+            Frame::Cloned {
+                symbol_address: Some(_),
+                ..
+            } => None,
+            _ => self.enclosing_function().map(|start| start as usize),
+        }
+    }
+
+    // Looks up the start of the function `ip` is in with the unwinder.
+    fn enclosing_function(&self) -> Option<*mut c_void> {
+        // A return address may be just past the end of the function, if it
+        // ended in a call to a function which never returns. libgcc looks up
+        // the byte before the address it's given itself, but other
+        // implementations don't.
+        let ip = if self.ip_is_exact() || self.ip().is_null() {
+            self.ip()
+        } else {
+            (self.ip() as usize - 1) as *mut c_void
+        };
 
         // The macOS linker emits a "compact" unwind table that only includes an
         // entry for a function if that function either has an LSDA or its
         // encoding differs from that of the previous entry.  Consequently, on
         // macOS, `_Unwind_FindEnclosingFunction` is unreliable (it can return a
-        // pointer to some totally unrelated function).  Instead, we just always
-        // return the ip.
+        // pointer to some totally unrelated function).  Instead, the start is
+        // looked up in the image's `LC_FUNCTION_STARTS`.
         //
         // https://github.com/rust-lang/rust/issues/74771#issuecomment-664056788
-        //
-        // Note the `skip_inner_frames.rs` test is skipped on macOS due to this
-        // clause, and if this is fixed that test in theory can be run on macOS!
-        if cfg!(target_os = "macos") || cfg!(target_os = "ios") {
-            self.ip()
-        } else {
-            unsafe { uw::_Unwind_FindEnclosingFunction(self.ip()) }
+        #[cfg(any(target_os = "macos", target_os = "ios"))]
+        {
+            super::function_starts::function_start(ip as usize).map(|start| start as *mut c_void)
+        }
+        #[cfg(not(any(target_os = "macos", target_os = "ios")))]
+        {
+            let start = unsafe { uw::_Unwind_FindEnclosingFunction(ip) };
+            if start.is_null() {
+                None
+            } else {
+                Some(start)
+            }
         }
     }
 
     pub fn module_base_address(&self) -> Option<*mut c_void> {
         None
     }
+
+    pub fn raw_unwind_context(&self) -> Option<*mut c_void> {
+        match *self {
+            Frame::Raw(ctx) => Some(ctx as *mut c_void),
+            Frame::Cloned { .. } => None,
+        }
+    }
+
+    pub fn lsda(&self) -> Option<*const c_void> {
+        match *self {
+            Frame::Raw(_) => None,
+            Frame::Cloned { lsda, .. } => lsda,
+        }
+    }
+
+    pub fn caller_ip(&self) -> Option<usize> {
+        match *self {
+            Frame::Raw(_) => None,
+            Frame::Cloned { caller_ip, .. } => caller_ip.map(|ip| ip as usize),
+        }
+    }
+
+    pub fn proc_info(&self) -> Option<super::ProcInfo> {
+        match *self {
+            Frame::Raw(_) => None,
+            Frame::Cloned { proc_info, .. } => proc_info,
+        }
+    }
+
+    pub fn frame_size(&self) -> Option<usize> {
+        match *self {
+            Frame::Raw(_) => None,
+            Frame::Cloned { frame_size, .. } => frame_size,
+        }
+    }
+
+    pub fn ip_is_exact(&self) -> bool {
+        match *self {
+            // Set for the frame a signal interrupted, whose `ip` is where it
+            // was interrupted.
+            Frame::Raw(ctx) => unsafe {
+                let mut ip_before_insn = 0;
+                uw::_Unwind_GetIPInfo(ctx, &mut ip_before_insn);
+                ip_before_insn != 0
+            },
+            Frame::Cloned { exact_ip, .. } => exact_ip,
+        }
+    }
+
+    pub fn capture_registers(&self) -> Option<crate::RegisterContext> {
+        match *self {
+            Frame::Raw(ctx) => unsafe { raw_registers(ctx, self.ip(), self.sp()) },
+            Frame::Cloned { registers, .. } => unsafe { registers.as_ref().copied() },
+        }
+    }
+}
+
+// Reads what `_Unwind_GetGR` can be trusted with, which is only the
+// callee-saved registers: libgcc's reads through a pointer to wherever the
+// register was saved, and crashes for a register which no frame between the
+// start of the trace and this one has saved, as caller-saved ones needn't be.
+#[cfg(any(target_arch = "x86_64", target_arch = "aarch64"))]
+unsafe fn raw_registers(
+    ctx: *mut uw::_Unwind_Context,
+    ip: *mut c_void,
+    sp: *mut c_void,
+) -> Option<crate::RegisterContext> {
+    // The DWARF numbers of the callee-saved registers, and those of the stack
+    // pointer and instruction pointer.
+    let (callee_saved, sp_reg, ip_reg) = if cfg!(target_arch = "x86_64") {
+        (&[3, 6, 12, 13, 14, 15][..], 7, 16)
+    } else {
+        (&[19, 20, 21, 22, 23, 24, 25, 26, 27, 28, 29][..], 31, 32)
+    };
+    let mut registers = crate::RegisterContext::empty()?;
+    for &reg in callee_saved {
+        registers.set(reg, uw::_Unwind_GetGR(ctx, reg as libc::c_int) as u64);
+    }
+    registers.set(sp_reg, sp as u64);
+    registers.set(ip_reg, ip as u64);
+    Some(registers)
+}
+
+#[cfg(not(any(target_arch = "x86_64", target_arch = "aarch64")))]
+unsafe fn raw_registers(
+    _ctx: *mut uw::_Unwind_Context,
+    _ip: *mut c_void,
+    _sp: *mut c_void,
+) -> Option<crate::RegisterContext> {
+    None
 }
 
 impl Clone for Frame {
     fn clone(&self) -> Frame {
-        Frame::Cloned {
-            ip: self.ip(),
-            sp: self.sp(),
-            symbol_address: self.symbol_address(),
+        // `symbol_address` only depends on `ip`, so there's no need to pay
+        // for `_Unwind_FindEnclosingFunction` here when a lot of clones only
+        // ever have their `ip` looked at.
+        match *self {
+            Frame::Raw(_) => Frame::Cloned {
+                ip: self.ip(),
+                sp: self.sp(),
+                symbol_address: None,
+                lsda: None,
+                caller_ip: None,
+                proc_info: None,
+                frame_size: None,
+                exact_ip: self.ip_is_exact(),
+                registers: ptr::null(),
+            },
+            Frame::Cloned {
+                ip,
+                sp,
+                symbol_address,
+                lsda,
+                caller_ip,
+                proc_info,
+                frame_size,
+                exact_ip,
+                ..
+            } => Frame::Cloned {
+                ip,
+                sp,
+                symbol_address,
+                lsda,
+                caller_ip,
+                proc_info,
+                frame_size,
+                exact_ip,
+                registers: ptr::null(),
+            },
         }
     }
 }
 
 #[inline(always)]
-pub unsafe fn trace(mut cb: &mut dyn FnMut(&super::Frame) -> bool) {
-    uw::_Unwind_Backtrace(trace_fn, &mut cb as *mut _ as *mut _);
+pub unsafe fn trace(cb: &mut dyn FnMut(&super::Frame) -> bool) {
+    trace_status(cb);
+}
+
+pub fn available() -> bool {
+    uw::available()
+}
+
+#[inline(always)]
+pub unsafe fn trace_status(cb: &mut dyn FnMut(&super::Frame) -> bool) -> super::TraceOutcome {
+    trace_inner(cb, None)
+}
+
+#[inline(always)]
+pub unsafe fn trace_debug(
+    cb: &mut dyn FnMut(&super::Frame) -> bool,
+    on_reason: &mut dyn FnMut(Option<&super::Frame>, i32),
+) -> super::TraceOutcome {
+    trace_inner(cb, Some(on_reason))
+}
+
+type OnReason<'a> = &'a mut dyn FnMut(Option<&super::Frame>, i32);
+
+// What `trace_fn` is passed through `_Unwind_Backtrace`.
+struct TraceData<'a, 'b> {
+    cb: &'a mut dyn FnMut(&super::Frame) -> bool,
+    on_reason: Option<OnReason<'b>>,
+}
+
+#[inline(always)]
+unsafe fn trace_inner(
+    cb: &mut dyn FnMut(&super::Frame) -> bool,
+    on_reason: Option<OnReason<'_>>,
+) -> super::TraceOutcome {
     extern "C" fn trace_fn(
         ctx: *mut uw::_Unwind_Context,
         arg: *mut c_void,
     ) -> uw::_Unwind_Reason_Code {
-        let cb = unsafe { &mut *(arg as *mut &mut dyn FnMut(&super::Frame) -> bool) };
+        let data = unsafe { &mut *(arg as *mut TraceData<'_, '_>) };
         let cx = super::Frame {
             inner: Frame::Raw(ctx),
         };
 
         let mut bomb = Bomb { enabled: true };
-        let keep_going = cb(&cx);
-        bomb.enabled = false;
-
-        if keep_going {
+        let code = if (data.cb)(&cx) {
             uw::_URC_NO_REASON
         } else {
             uw::_URC_FAILURE
+        };
+        if let Some(on_reason) = &mut data.on_reason {
+            on_reason(Some(&cx), code as i32);
         }
+        bomb.enabled = false;
+        code
+    }
+
+    // Without an unwinder to be found there's nothing to walk the stack with,
+    // so this does what the no-op backend does.
+    if !uw::available() {
+        return super::TraceOutcome::Complete;
+    }
+
+    let mut data = TraceData { cb, on_reason };
+    // Stopping because `cb` returned `false` is reported as a failure too, so
+    // the caller is responsible for telling the two apart.
+    let code = uw::_Unwind_Backtrace(trace_fn, &mut data as *mut _ as *mut _);
+    if let Some(on_reason) = data.on_reason {
+        on_reason(None, code as i32);
+    }
+    match code {
+        uw::_URC_END_OF_STACK => super::TraceOutcome::Complete,
+        code => super::TraceOutcome::UnwinderError(code as i32),
     }
 }
 
+#[cfg(feature = "remote-unwind")]
+pub(crate) use self::external_unwind::UnwProcInfo;
+#[cfg(any(feature = "llvm-unwind", feature = "nongnu-unwind"))]
+pub use self::external_unwind::{
+    configure_unwind_caching, CachingPolicy, UnwContext, UnwCursor, UnwindError,
+};
+
 #[cfg(any(feature = "llvm-unwind", feature = "nongnu-unwind"))]
 #[inline(always)]
-pub unsafe fn trace_external_api<F: FnMut(&super::Frame) -> bool>(mut f: F, signal_frame: bool) {
+pub unsafe fn trace_external_api<F: FnMut(&super::Frame) -> bool>(
+    mut f: F,
+    signal_frame: bool,
+) -> Result<(), UnwindError> {
     use external_unwind::*;
-    match UnwContext::new()
-        .and_then(|mut x| { x.cursor(signal_frame) })
-        .and_then(|mut x| {
-            loop {
-                match x.get_frame() {
-                    Ok(frame) => {
-                        let frame = super::Frame {
-                            inner: frame,
-                        };
-                        let mut bomb = Bomb { enabled: true };
-                        let keep_going = f(&frame);
-                        bomb.enabled = false;
-                        if !keep_going {
-                            break;
-                        }
-                        match x.step() {
-                            StepResult::Success => continue,
-                            StepResult::Error(err) => panic!("{}", err),
-                            _ => break
-                        }
-                    }
-                    Err(err) => {
-                        panic!("{}", err);
-                    }
-                }
+    let mut context = UnwContext::zeroed();
+    let mut cursor = UnwCursor::zeroed();
+    walk_external_api(&mut context, &mut cursor, f, signal_frame, false)
+}
+
+#[cfg(any(feature = "llvm-unwind", feature = "nongnu-unwind"))]
+#[inline(always)]
+pub unsafe fn trace_external_api_with_registers<F: FnMut(&super::Frame) -> bool>(
+    f: F,
+    signal_frame: bool,
+) -> Result<(), UnwindError> {
+    use external_unwind::*;
+    let mut context = UnwContext::zeroed();
+    let mut cursor = UnwCursor::zeroed();
+    walk_external_api(&mut context, &mut cursor, f, signal_frame, true)
+}
+
+#[cfg(any(feature = "llvm-unwind", feature = "nongnu-unwind"))]
+#[inline(always)]
+pub unsafe fn trace_external_api_with_scratch<F: FnMut(&super::Frame) -> bool>(
+    context: &mut UnwContext,
+    cursor: &mut UnwCursor,
+    f: F,
+    signal_frame: bool,
+) -> Result<(), UnwindError> {
+    // A trace started from within the callback of another one on this thread,
+    // e.g. by an allocation in a heap profiler, can't have been handed the
+    // outer trace's scratch buffers, but callers would otherwise need another
+    // pair for each level of nesting. Nested traces get buffers of their own
+    // on the stack instead, leaving the ones they're handed untouched.
+    #[cfg(feature = "std")]
+    {
+        use core::cell::Cell;
+
+        std::thread_local!(static SCRATCH_IN_USE: Cell<bool> = Cell::new(false));
+
+        struct Release;
+
+        impl Drop for Release {
+            fn drop(&mut self) {
+                let _ = SCRATCH_IN_USE.try_with(|in_use| in_use.set(false));
+            }
+        }
+
+        // If the thread local is already gone the thread is exiting, and
+        // there's no telling whether a trace is in progress.
+        if SCRATCH_IN_USE.try_with(|in_use| in_use.replace(true)) != Ok(false) {
+            return trace_external_api(f, signal_frame);
+        }
+        let _release = Release;
+        walk_external_api(context, cursor, f, signal_frame, false)
+    }
+    #[cfg(not(feature = "std"))]
+    walk_external_api(context, cursor, f, signal_frame, false)
+}
+
+#[cfg(any(feature = "llvm-unwind", feature = "nongnu-unwind"))]
+#[inline(always)]
+unsafe fn walk_external_api<F: FnMut(&super::Frame) -> bool>(
+    context: &mut UnwContext,
+    cursor: &mut UnwCursor,
+    mut f: F,
+    signal_frame: bool,
+    with_registers: bool,
+) -> Result<(), UnwindError> {
+    context.capture()?;
+    context.init_cursor(cursor, signal_frame)?;
+    let mut frame = super::Frame {
+        inner: cursor.get_frame()?,
+    };
+    // The registers have to be read before the cursor moves on, which takes
+    // a call into the unwinder per register, so it's only done when asked
+    // for, and the frame points to them while it's out.
+    let mut registers = if with_registers {
+        cursor.registers()
+    } else {
+        None
+    };
+    loop {
+        // Step to the caller before handing out this frame so that it can say
+        // where it returns to. Errors doing so are only reported if the
+        // closure asks to keep going, as they would have been otherwise.
+        let next = match cursor.step().into_result() {
+            Ok(true) => cursor.get_frame().map(|next| {
+                let registers = if with_registers {
+                    cursor.registers()
+                } else {
+                    None
+                };
+                Some((next, registers))
+            }),
+            Ok(false) => Ok(None),
+            Err(err) => Err(err),
+        };
+        if let Frame::Cloned {
+            sp,
+            caller_ip,
+            frame_size,
+            registers: frame_registers,
+            ..
+        } = &mut frame.inner
+        {
+            if let Ok(Some((next, _))) = &next {
+                *caller_ip = Some(next.ip());
+                // The caller's `sp` is this frame's CFA, and this frame's `sp`
+                // is the CFA of the frame it called.
+                *frame_size = (next.sp() as usize).checked_sub(*sp as usize);
+            }
+            *frame_registers = registers.as_ref().map_or(ptr::null(), |r| r);
+        }
+
+        let mut bomb = Bomb { enabled: true };
+        let keep_going = f(&frame);
+        bomb.enabled = false;
+        if !keep_going {
+            return Ok(());
+        }
+        match next? {
+            Some((next, next_registers)) => {
+                frame = super::Frame { inner: next };
+                registers = next_registers;
             }
-            Ok(())
-        }) {
-        _ => ()
+            None => return Ok(()),
+        }
     }
 }
 
@@ -164,6 +509,7 @@ mod uw {
     use core::ffi::c_void;
 
     #[repr(C)]
+    #[derive(Clone, Copy)]
     pub enum _Unwind_Reason_Code {
         _URC_NO_REASON = 0,
         _URC_FOREIGN_EXCEPTION_CAUGHT = 1,
@@ -182,11 +528,144 @@ mod uw {
     pub type _Unwind_Trace_Fn =
     extern "C" fn(ctx: *mut _Unwind_Context, arg: *mut c_void) -> _Unwind_Reason_Code;
 
-    extern "C" {
-        pub fn _Unwind_Backtrace(
-            trace: _Unwind_Trace_Fn,
-            trace_argument: *mut c_void,
-        ) -> _Unwind_Reason_Code;
+    // Declares the functions of the unwinder which are used. They're normally
+    // linked against, but with `dynamic-unwind` each is a function calling
+    // through a pointer looked up by `available`, see `dynamic` below.
+    #[cfg(not(all(
+        feature = "dynamic-unwind",
+        any(target_os = "linux", target_os = "android")
+    )))]
+    macro_rules! unwinder_fns {
+        ($(
+            $(#[$attr:meta])*
+            $vis:vis fn $name:ident($($arg:ident: $ty:ty),* $(,)?) -> $ret:ty;
+        )*) => {
+            extern "C" {
+                $(
+                    $(#[$attr])*
+                    $vis fn $name($($arg: $ty),*) -> $ret;
+                )*
+            }
+
+            pub fn available() -> bool {
+                true
+            }
+        };
+    }
+
+    #[cfg(all(
+        feature = "dynamic-unwind",
+        any(target_os = "linux", target_os = "android")
+    ))]
+    macro_rules! unwinder_fns {
+        ($(
+            $(#[$attr:meta])*
+            $vis:vis fn $name:ident($($arg:ident: $ty:ty),* $(,)?) -> $ret:ty;
+        )*) => {
+            $(
+                // Where the function was found, filled in by `resolve`.
+                $(#[$attr])*
+                mod $name {
+                    pub static ADDR: core::sync::atomic::AtomicUsize =
+                        core::sync::atomic::AtomicUsize::new(0);
+                }
+
+                // Only called once `available` has returned `true`, which
+                // `trace_inner` makes sure of before starting a trace, and
+                // everything else is passed a context from one. Inlined so
+                // that traces start from the same frame as when linked.
+                $(#[$attr])*
+                #[inline(always)]
+                $vis unsafe fn $name($($arg: $ty),*) -> $ret {
+                    let addr = $name::ADDR.load(core::sync::atomic::Ordering::Relaxed);
+                    let f = core::mem::transmute::<usize, unsafe extern "C" fn($($ty),*) -> $ret>(
+                        addr,
+                    );
+                    f($($arg),*)
+                }
+            )*
+
+            // Looks up all of the functions in `handle`, and only if they're
+            // all there stores them, so that they're never mixed up between
+            // libraries.
+            unsafe fn resolve(handle: *mut c_void) -> bool {
+                $(
+                    $(#[$attr])*
+                    {
+                        let name = concat!(stringify!($name), "\0");
+                        if libc::dlsym(handle, name.as_ptr().cast()).is_null() {
+                            return false;
+                        }
+                    }
+                )*
+                $(
+                    $(#[$attr])*
+                    {
+                        let name = concat!(stringify!($name), "\0");
+                        let addr = libc::dlsym(handle, name.as_ptr().cast());
+                        $name::ADDR.store(addr as usize, core::sync::atomic::Ordering::Relaxed);
+                    }
+                )*
+                true
+            }
+
+            pub fn available() -> bool {
+                dynamic::available(resolve)
+            }
+        };
+    }
+
+    // Finds the unwinder at runtime, for `dynamic-unwind`.
+    #[cfg(all(
+        feature = "dynamic-unwind",
+        any(target_os = "linux", target_os = "android")
+    ))]
+    mod dynamic {
+        use core::ffi::c_void;
+        use core::sync::atomic::{AtomicUsize, Ordering};
+
+        const UNRESOLVED: usize = 0;
+        const MISSING: usize = 1;
+        const AVAILABLE: usize = 2;
+
+        static STATE: AtomicUsize = AtomicUsize::new(UNRESOLVED);
+
+        // Libraries providing `_Unwind_Backtrace` and friends, tried in turn
+        // if nothing loaded already does: libgcc's, LLVM's libunwind and
+        // nongnu libunwind. Whichever is opened stays loaded for good.
+        const LIBRARIES: [&str; 3] = ["libgcc_s.so.1\0", "libunwind.so.1\0", "libunwind.so.8\0"];
+
+        // Looks the unwinder up with `resolve` the first time it's called.
+        // Racing threads may both look it up, but then find the same one.
+        pub(super) fn available(resolve: unsafe fn(*mut c_void) -> bool) -> bool {
+            let mut state = STATE.load(Ordering::Acquire);
+            if state == UNRESOLVED {
+                state = if unsafe { open(resolve) } {
+                    AVAILABLE
+                } else {
+                    MISSING
+                };
+                STATE.store(state, Ordering::Release);
+            }
+            state == AVAILABLE
+        }
+
+        unsafe fn open(resolve: unsafe fn(*mut c_void) -> bool) -> bool {
+            if resolve(libc::RTLD_DEFAULT) {
+                return true;
+            }
+            for name in LIBRARIES.iter() {
+                let handle = libc::dlopen(name.as_ptr().cast(), libc::RTLD_NOW | libc::RTLD_LOCAL);
+                if handle.is_null() {
+                    continue;
+                }
+                if resolve(handle) {
+                    return true;
+                }
+                libc::dlclose(handle);
+            }
+            false
+        }
     }
 
     cfg_if::cfg_if! {
@@ -197,9 +676,21 @@ mod uw {
             not(all(target_os = "linux", target_arch = "arm")),
             not(all(target_os = "horizon", target_arch = "arm"))
         ))] {
-            extern "C" {
+            unwinder_fns! {
+                pub fn _Unwind_Backtrace(
+                    trace: _Unwind_Trace_Fn,
+                    trace_argument: *mut c_void,
+                ) -> _Unwind_Reason_Code;
                 pub fn _Unwind_GetIP(ctx: *mut _Unwind_Context) -> libc::uintptr_t;
+                pub fn _Unwind_GetIPInfo(
+                    ctx: *mut _Unwind_Context,
+                    ip_before_insn: *mut libc::c_int,
+                ) -> libc::uintptr_t;
                 pub fn _Unwind_FindEnclosingFunction(pc: *mut c_void) -> *mut c_void;
+                pub fn _Unwind_GetGR(
+                    ctx: *mut _Unwind_Context,
+                    index: libc::c_int,
+                ) -> libc::uintptr_t;
 
                 #[cfg(not(all(target_os = "linux", target_arch = "s390x")))]
                 // This function is a misnomer: rather than getting this frame's
@@ -207,19 +698,49 @@ mod uw {
                 // returns this frame's SP.
                 //
                 // https://github.com/libunwind/libunwind/blob/d32956507cf29d9b1a98a8bce53c78623908f4fe/src/unwind/GetCFA.c#L28-L35
-                #[link_name = "_Unwind_GetCFA"]
-                pub fn get_sp(ctx: *mut _Unwind_Context) -> libc::uintptr_t;
+                fn _Unwind_GetCFA(ctx: *mut _Unwind_Context) -> libc::uintptr_t;
 
             }
 
+            // The DWARF number of the stack pointer register, where it's known.
+            #[cfg(not(all(target_os = "linux", target_arch = "s390x")))]
+            fn sp_register() -> Option<libc::c_int> {
+                if cfg!(target_arch = "x86_64") {
+                    Some(7)
+                } else if cfg!(target_arch = "x86") {
+                    Some(4)
+                } else if cfg!(target_arch = "aarch64") {
+                    Some(31)
+                } else if cfg!(any(target_arch = "riscv32", target_arch = "riscv64")) {
+                    Some(2)
+                } else if cfg!(target_arch = "loongarch64") {
+                    Some(3)
+                } else if cfg!(any(target_arch = "powerpc", target_arch = "powerpc64")) {
+                    Some(1)
+                } else {
+                    None
+                }
+            }
+
+            #[cfg(not(all(target_os = "linux", target_arch = "s390x")))]
+            pub unsafe fn get_sp(ctx: *mut _Unwind_Context) -> libc::uintptr_t {
+                match _Unwind_GetCFA(ctx) {
+                    // Some versions of libunwind return 0 for the outermost
+                    // frame, so fall back to reading the register itself
+                    // rather than reporting a null SP.
+                    0 => match sp_register() {
+                        Some(sp) => _Unwind_GetGR(ctx, sp),
+                        None => 0,
+                    },
+                    sp => sp,
+                }
+            }
+
             // s390x uses a biased CFA value, therefore we need to use
             // _Unwind_GetGR to get the stack pointer register (%r15)
             // instead of relying on _Unwind_GetCFA.
             #[cfg(all(target_os = "linux", target_arch = "s390x"))]
             pub unsafe fn get_sp(ctx: *mut _Unwind_Context) -> libc::uintptr_t {
-                extern "C" {
-                    pub fn _Unwind_GetGR(ctx: *mut _Unwind_Context, index: libc::c_int) -> libc::uintptr_t;
-                }
                 _Unwind_GetGR(ctx, 15)
             }
         } else {
@@ -255,7 +776,11 @@ mod uw {
             }
 
             type _Unwind_Word = libc::c_uint;
-            extern "C" {
+            unwinder_fns! {
+                pub fn _Unwind_Backtrace(
+                    trace: _Unwind_Trace_Fn,
+                    trace_argument: *mut c_void,
+                ) -> _Unwind_Reason_Code;
                 fn _Unwind_VRS_Get(
                     ctx: *mut _Unwind_Context,
                     klass: _Unwind_VRS_RegClass,
@@ -278,6 +803,15 @@ mod uw {
                 (val & !1) as libc::uintptr_t
             }
 
+            // There's no telling signal frames apart here.
+            pub unsafe fn _Unwind_GetIPInfo(
+                ctx: *mut _Unwind_Context,
+                ip_before_insn: *mut libc::c_int,
+            ) -> libc::uintptr_t {
+                *ip_before_insn = 0;
+                _Unwind_GetIP(ctx)
+            }
+
             // R13 is the stack pointer on arm.
             const SP: _Unwind_Word = 13;
 
@@ -295,9 +829,10 @@ mod uw {
             }
 
             // This function also doesn't exist on Android or ARM/Linux, so make it
-            // a no-op.
+            // a no-op, apart from clearing the Thumb bit like `_Unwind_GetIP`
+            // does so that it's consistent with `ip` for any `pc`.
             pub unsafe fn _Unwind_FindEnclosingFunction(pc: *mut c_void) -> *mut c_void {
-                pc
+                (pc as usize & !1) as *mut c_void
             }
         }
     }
@@ -308,47 +843,102 @@ mod external_unwind {
     use core::fmt::{Display, Formatter};
     use libc::{backtrace, c_void};
 
+    /// An error reported by the external unwinding library.
     #[derive(Debug)]
     pub struct UnwindError(libc::c_int);
 
+    impl UnwindError {
+        pub(crate) fn new(code: libc::c_int) -> UnwindError {
+            UnwindError(code)
+        }
+
+        /// Returns the raw (negative) error code returned by libunwind, such as
+        /// `-UNW_EBADFRAME`.
+        pub fn code(&self) -> libc::c_int {
+            self.0
+        }
+    }
+
     impl Display for UnwindError {
         fn fmt(&self, f: &mut Formatter<'_>) -> core::fmt::Result {
             write!(f, "unwind failed with code: {}", self.0)
         }
     }
 
+    #[cfg(feature = "std")]
+    impl std::error::Error for UnwindError {}
+
     // the following length are defined enough for aarch64 and x86_64
     const UNW_TDEP_CURSOR_LEN: usize = 256;
     const LLVM_UNW_CONTEXT_SIZE: usize = 167;
     const LLVM_UNW_CURSOR_SIZE: usize = 179;
-    #[allow(dead_code)]
     const UNW_INIT_SIGNAL_FRAME: libc::c_int = 1;
 
     // these two things can be quite large, but we need enough area to make sure the compatibility.
+    /// Storage for the register state the external unwinder starts from.
+    ///
+    /// This is several hundred bytes, so callers tracing at a high rate can
+    /// keep one around and pass it to
+    /// `trace_unsynchronized_external_api_with_scratch` rather than having
+    /// every trace set up a new one on the stack.
     #[repr(C)]
     pub union UnwContext {
         __ucontext: libc::ucontext_t,
         __mem_block: [usize; LLVM_UNW_CONTEXT_SIZE],
     }
 
+    /// Storage for the external unwinder's position in the stack.
+    ///
+    /// See `UnwContext` for why this may be worth reusing.
     #[repr(C)]
     pub union UnwCursor {
         __llvm: [usize; LLVM_UNW_CURSOR_SIZE],
         __nongnu: [usize; UNW_TDEP_CURSOR_LEN],
     }
 
+    // `unw_proc_info_t`, which both unwinders lay out the same up to and
+    // including `unwind_info`. What follows is a single word for LLVM's
+    // libunwind and architecture specific for the nongnu one (nothing at all
+    // for x86_64 and AArch64), so the padding leaves room for plenty more.
     #[repr(C)]
     pub struct UnwProcInfo {
-        start_ip: *mut libc::c_void,
-        // this is what we need
-        __padding: [usize; 16], // enough space for padding
+        pub(crate) start_ip: *mut libc::c_void,
+        end_ip: *mut libc::c_void,
+        pub(crate) lsda: *mut libc::c_void,
+        handler: *mut libc::c_void,
+        gp: usize,
+        flags: usize,
+        format: u32,
+        unwind_info_size: u32,
+        pub(crate) unwind_info: *mut libc::c_void,
+        __padding: [usize; 9], // enough space for padding
     }
 
     impl UnwProcInfo {
-        fn new() -> Self {
+        pub(crate) fn new() -> Self {
             UnwProcInfo {
                 start_ip: core::ptr::null_mut(),
-                __padding: [0; 16],
+                end_ip: core::ptr::null_mut(),
+                lsda: core::ptr::null_mut(),
+                handler: core::ptr::null_mut(),
+                gp: 0,
+                flags: 0,
+                format: 0,
+                unwind_info_size: 0,
+                unwind_info: core::ptr::null_mut(),
+                __padding: [0; 9],
+            }
+        }
+
+        pub(crate) fn to_proc_info(&self) -> super::super::ProcInfo {
+            super::super::ProcInfo {
+                start_ip: self.start_ip as usize,
+                end_ip: self.end_ip as usize,
+                lsda: self.lsda as usize,
+                handler: self.handler as usize,
+                gp: self.gp,
+                flags: self.flags,
+                format: self.format,
             }
         }
     }
@@ -360,6 +950,18 @@ mod external_unwind {
         Success,
     }
 
+    impl StepResult {
+        /// Converts the outcome of `unw_step` into whether there's another
+        /// frame to visit, so the step loop can use `?` to propagate errors.
+        pub(crate) fn into_result(self) -> Result<bool, UnwindError> {
+            match self {
+                StepResult::Success => Ok(true),
+                StepResult::End => Ok(false),
+                StepResult::Error(err) => Err(UnwindError(err)),
+            }
+        }
+    }
+
     mod llvm {
         pub const UNW_REG_IP: libc::c_int = -1;
         pub const UNW_REG_SP: libc::c_int = -2;
@@ -385,6 +987,12 @@ mod external_unwind {
     #[cfg(all(feature = "nongnu-unwind", target_arch = "x86_64"))]
     use nongnu_x86_64::*;
 
+    #[cfg(all(
+        feature = "nongnu-unwind",
+        not(any(target_arch = "x86_64", target_arch = "aarch64"))
+    ))]
+    compile_error!("the `nongnu-unwind` feature is only supported on x86_64 and aarch64");
+
     #[cfg(all(feature = "llvm-unwind"))]
     use llvm::*;
 
@@ -432,31 +1040,201 @@ mod external_unwind {
         fn unw_get_proc_info(cursor: *mut UnwCursor, context: *mut UnwProcInfo) -> libc::c_int;
     }
 
+    #[cfg(all(feature = "nongnu-unwind", target_arch = "x86_64"))]
+    extern "C" {
+        #[link_name = "_ULx86_64_local_addr_space"]
+        static unw_local_addr_space: *mut c_void;
+
+        #[link_name = "_ULx86_64_set_caching_policy"]
+        fn unw_set_caching_policy(space: *mut c_void, policy: libc::c_int) -> libc::c_int;
+    }
+
+    #[cfg(all(feature = "nongnu-unwind", target_arch = "aarch64"))]
+    extern "C" {
+        #[link_name = "_ULaarch64_local_addr_space"]
+        static unw_local_addr_space: *mut c_void;
+
+        #[link_name = "_ULaarch64_set_caching_policy"]
+        fn unw_set_caching_policy(space: *mut c_void, policy: libc::c_int) -> libc::c_int;
+    }
+
+    /// How the external unwinder caches what it learns about the code it
+    /// unwinds through, as set by `configure_unwind_caching`.
+    #[derive(Clone, Copy, Debug, PartialEq, Eq)]
+    pub enum CachingPolicy {
+        /// Nothing is cached, `UNW_CACHE_NONE`.
+        None,
+        /// One cache is shared by all threads, guarded by a lock,
+        /// `UNW_CACHE_GLOBAL`. This is libunwind's default.
+        Global,
+        /// Each thread has a cache of its own, `UNW_CACHE_PER_THREAD`.
+        PerThread,
+    }
+
+    impl CachingPolicy {
+        fn raw(self) -> libc::c_int {
+            match self {
+                CachingPolicy::None => 0,
+                CachingPolicy::Global => 1,
+                CachingPolicy::PerThread => 2,
+            }
+        }
+    }
+
+    // The policy configured, plus one, or zero if none has been yet.
+    static CACHING_POLICY: core::sync::atomic::AtomicI32 = core::sync::atomic::AtomicI32::new(0);
+
+    // libunwind's `UNW_EINVAL`.
+    #[cfg(feature = "nongnu-unwind")]
+    const UNW_EINVAL: libc::c_int = 8;
+    // LLVM's libunwind's `UNW_EUNSPEC`.
+    #[cfg(feature = "llvm-unwind")]
+    const UNW_EUNSPEC: libc::c_int = 6540;
+
+    /// Sets the caching policy of the address space the external unwinder
+    /// walks this process's stacks in.
+    ///
+    /// With the default `CachingPolicy::Global`, threads tracing concurrently
+    /// with `trace_unsynchronized_external_api` all contend for the lock of
+    /// libunwind's one cache, which a multi-threaded sampling profiler feels
+    /// badly. `CachingPolicy::PerThread` gives each thread a cache of its
+    /// own instead, at the cost of each thread warming up its own. libunwind
+    /// quietly keeps using a global cache if it was built without support for
+    /// thread-local storage.
+    ///
+    /// The policy can only be configured once, and is best set at startup
+    /// before any thread traces. Only the first call takes effect; later calls
+    /// succeed if they ask for the same policy, and return an error with code
+    /// `-UNW_EINVAL` otherwise.
+    ///
+    /// Only the `nongnu-unwind` feature supports this, since LLVM's libunwind
+    /// has no caching policy to set. With `llvm-unwind` this always returns an
+    /// error with code `-UNW_EUNSPEC`.
+    ///
+    /// # Example
+    ///
+    /// ```no_run
+    /// use backtrace::CachingPolicy;
+    ///
+    /// backtrace::configure_unwind_caching(CachingPolicy::PerThread).unwrap();
+    /// ```
+    pub fn configure_unwind_caching(policy: CachingPolicy) -> Result<(), UnwindError> {
+        use core::sync::atomic::Ordering;
+
+        #[cfg(feature = "llvm-unwind")]
+        {
+            Err(UnwindError(-UNW_EUNSPEC))
+        }
+
+        #[cfg(feature = "nongnu-unwind")]
+        {
+            let wanted = policy.raw() + 1;
+            match CACHING_POLICY.compare_exchange(0, wanted, Ordering::SeqCst, Ordering::SeqCst) {
+                Ok(_) => {}
+                Err(configured) if configured == wanted => return Ok(()),
+                Err(_) => return Err(UnwindError(-UNW_EINVAL)),
+            }
+            let res = unsafe { unw_set_caching_policy(unw_local_addr_space, policy.raw()) };
+            if res == 0 {
+                Ok(())
+            } else {
+                CACHING_POLICY.store(0, Ordering::SeqCst);
+                Err(UnwindError(res))
+            }
+        }
+    }
+
+    /// Only some builds of LLVM's libunwind have `unw_init_local2`, which is
+    /// needed to tell the unwinder that it starts in a signal frame, so it's
+    /// looked up at runtime and `unw_init_local` is used when it's missing.
+    ///
+    /// `dlsym` isn't async-signal-safe, so the result is cached after the
+    /// first lookup, which is also done ahead of time by `UnwContext::zeroed`.
+    #[cfg(feature = "llvm-unwind")]
+    mod llvm_init_local2 {
+        use super::{UnwContext, UnwCursor};
+        use core::sync::atomic::{AtomicUsize, Ordering};
+
+        type InitLocal2 =
+            unsafe extern "C" fn(*mut UnwCursor, *mut UnwContext, libc::c_int) -> libc::c_int;
+
+        const UNRESOLVED: usize = 0;
+        const MISSING: usize = 1;
+
+        static INIT_LOCAL2: AtomicUsize = AtomicUsize::new(UNRESOLVED);
+
+        pub(super) fn get() -> Option<InitLocal2> {
+            let mut addr = INIT_LOCAL2.load(Ordering::Relaxed);
+            if addr == UNRESOLVED {
+                let name = b"unw_init_local2\0";
+                addr = unsafe { libc::dlsym(libc::RTLD_DEFAULT, name.as_ptr().cast()) } as usize;
+                if addr == UNRESOLVED {
+                    addr = MISSING;
+                }
+                INIT_LOCAL2.store(addr, Ordering::Relaxed);
+            }
+            if addr == MISSING {
+                None
+            } else {
+                Some(unsafe { core::mem::transmute::<usize, InitLocal2>(addr) })
+            }
+        }
+    }
+
     impl UnwContext {
+        /// Creates an empty context, which is filled in by each trace it's
+        /// passed to.
+        pub fn zeroed() -> Self {
+            // Get the lookup out of the way while we're (hopefully) not in a
+            // signal handler yet.
+            #[cfg(feature = "llvm-unwind")]
+            llvm_init_local2::get();
+            UnwContext { __mem_block: [0; LLVM_UNW_CONTEXT_SIZE] }
+        }
+
         pub(crate) fn new() -> Result<Self, UnwindError> {
-            let mut context = UnwContext { __mem_block: [0; LLVM_UNW_CONTEXT_SIZE] };
+            let mut context = UnwContext::zeroed();
+            context.capture()?;
+            Ok(context)
+        }
+
+        // Inlined so that the registers captured belong to a frame which is
+        // still live while the cursor walks up from it.
+        #[inline(always)]
+        pub(crate) fn capture(&mut self) -> Result<(), UnwindError> {
             unsafe {
-                if unw_getcontext(&mut context as *mut _) == 0 {
-                    Ok(context)
+                if unw_getcontext(self as *mut _) == 0 {
+                    Ok(())
                 } else {
                     Err(UnwindError(-1))
                 }
             }
         }
 
-        #[allow(unused_variables)]
         pub(crate) fn cursor(&mut self, signal_frame: bool) -> Result<UnwCursor, UnwindError> {
-            let mut cursor = UnwCursor { __nongnu: [0; UNW_TDEP_CURSOR_LEN] };
+            let mut cursor = UnwCursor::zeroed();
+            self.init_cursor(&mut cursor, signal_frame)?;
+            Ok(cursor)
+        }
+
+        pub(crate) fn init_cursor(
+            &mut self,
+            cursor: &mut UnwCursor,
+            signal_frame: bool,
+        ) -> Result<(), UnwindError> {
+            let flag = if signal_frame { UNW_INIT_SIGNAL_FRAME } else { 0 };
             unsafe {
                 #[cfg(feature = "nongnu-unwind")]
-                    let res = unw_init_local2(&mut cursor as _, self as _,
-                                              if signal_frame { UNW_INIT_SIGNAL_FRAME } else { 0 });
+                    let res = unw_init_local2(cursor as _, self as _, flag);
 
                 #[cfg(feature = "llvm-unwind")]
-                    let res = unw_init_local(&mut cursor as _, self as _);
+                    let res = match llvm_init_local2::get() {
+                        Some(init) => init(cursor as _, self as _, flag),
+                        None => unw_init_local(cursor as _, self as _),
+                    };
 
                 if res == 0 {
-                    Ok(cursor)
+                    Ok(())
                 } else {
                     Err(UnwindError(res))
                 }
@@ -465,6 +1243,12 @@ mod external_unwind {
     }
 
     impl UnwCursor {
+        /// Creates an empty cursor, which is reset by each trace it's passed
+        /// to.
+        pub fn zeroed() -> Self {
+            UnwCursor { __nongnu: [0; UNW_TDEP_CURSOR_LEN] }
+        }
+
         pub(crate) fn step(&mut self) -> StepResult {
             unsafe {
                 let res = unw_step(self as _);
@@ -477,16 +1261,31 @@ mod external_unwind {
                 }
             }
         }
+        // Reads every general purpose register the unwinder can recover for
+        // the frame the cursor is at. libunwind numbers them as DWARF does,
+        // along with `pstate` as 33 on aarch64.
+        pub(crate) fn registers(&mut self) -> Option<crate::RegisterContext> {
+            let count = if cfg!(target_arch = "x86_64") { 17 } else { 34 };
+            let mut registers = crate::RegisterContext::empty()?;
+            for reg in 0..count {
+                let mut value: *mut c_void = core::ptr::null_mut();
+                if unsafe { unw_get_reg(self as _, reg as libc::c_int, &mut value as _) } == 0 {
+                    registers.set(reg, value as u64);
+                }
+            }
+            Some(registers)
+        }
+
         pub(crate) fn get_frame(&mut self) -> Result<super::Frame, UnwindError> {
             let mut proc_info = UnwProcInfo::new();
             let mut ip: *mut c_void = core::ptr::null_mut();
             let mut sp: *mut c_void = core::ptr::null_mut();
+            let found_proc_info;
             unsafe {
                 let mut res = unw_get_proc_info(self as _, &mut proc_info as _);
-                if res != 0 {
-                    // TODO: handle this error
-                    // in signal frame, this function always return error
-                }
+                // This fails for signal frames, which have no unwind info of
+                // their own, and then there's no proc info to report.
+                found_proc_info = res == 0;
                 res = unw_get_reg(self as _, UNW_REG_IP, &mut ip as _);
                 if res != 0 {
                     return Err(UnwindError(res));
@@ -499,7 +1298,21 @@ mod external_unwind {
             Ok(super::Frame::Cloned {
                 ip,
                 sp,
-                symbol_address: proc_info.start_ip,
+                symbol_address: Some(proc_info.start_ip),
+                lsda: if proc_info.lsda.is_null() {
+                    None
+                } else {
+                    Some(proc_info.lsda as *const c_void)
+                },
+                caller_ip: None,
+                proc_info: if found_proc_info {
+                    Some(proc_info.to_proc_info())
+                } else {
+                    None
+                },
+                frame_size: None,
+                exact_ip: false,
+                registers: core::ptr::null(),
             })
         }
     }
@@ -507,6 +1320,8 @@ mod external_unwind {
     #[cfg(test)]
     mod test {
         use super::*;
+        use std::cell::RefCell;
+        use std::prelude::v1::*;
 
         #[test]
         fn unw_context_initialization() {
@@ -526,5 +1341,141 @@ mod external_unwind {
                     .and_then(|mut x| x.get_frame());
             assert!(frame.is_ok());
         }
+
+        #[test]
+        fn reuse_scratch() {
+            let mut context = UnwContext::zeroed();
+            let mut cursor = UnwCursor::zeroed();
+            let mut depths = [0; 2];
+            for depth in depths.iter_mut() {
+                let res = unsafe {
+                    super::super::trace_external_api_with_scratch(
+                        &mut context,
+                        &mut cursor,
+                        |_| {
+                            *depth += 1;
+                            true
+                        },
+                        false,
+                    )
+                };
+                assert!(res.is_ok());
+            }
+            assert!(depths[0] > 0);
+            assert_eq!(depths[0], depths[1]);
+        }
+
+        // Scratch buffers kept per thread, as a heap profiler would, with a
+        // second pair for traces started from within a trace.
+        std::thread_local! {
+            static SCRATCH: RefCell<(UnwContext, UnwCursor)> =
+                RefCell::new((UnwContext::zeroed(), UnwCursor::zeroed()));
+            static NESTED_SCRATCH: RefCell<(UnwContext, UnwCursor)> =
+                RefCell::new((UnwContext::zeroed(), UnwCursor::zeroed()));
+        }
+
+        // Traces with the thread's scratch buffers, optionally starting another
+        // trace with the second pair from within the callback, and returns the
+        // number of frames seen by the outer and the nested trace.
+        #[inline(never)]
+        fn trace_nested(nest: bool) -> (usize, usize) {
+            let mut outer = 0;
+            let mut inner = 0;
+            SCRATCH.with(|scratch| {
+                let (context, cursor) = &mut *scratch.borrow_mut();
+                let res = unsafe {
+                    super::super::trace_external_api_with_scratch(
+                        context,
+                        cursor,
+                        |_| {
+                            outer += 1;
+                            if nest && outer == 1 {
+                                NESTED_SCRATCH.with(|scratch| {
+                                    let (context, cursor) = &mut *scratch.borrow_mut();
+                                    let res = super::super::trace_external_api_with_scratch(
+                                        context,
+                                        cursor,
+                                        |_| {
+                                            inner += 1;
+                                            true
+                                        },
+                                        false,
+                                    );
+                                    assert!(res.is_ok());
+                                });
+                            }
+                            true
+                        },
+                        false,
+                    )
+                };
+                assert!(res.is_ok());
+            });
+            (outer, inner)
+        }
+
+        #[test]
+        fn nested_scratch() {
+            let (plain, _) = trace_nested(false);
+            let (outer, inner) = trace_nested(true);
+            assert_eq!(outer, plain);
+            assert!(inner > outer);
+
+            // The nested trace set up buffers of its own rather than filling
+            // in the pair it was given.
+            NESTED_SCRATCH.with(|scratch| {
+                let (context, _) = &*scratch.borrow();
+                assert!(unsafe { context.__mem_block.iter().all(|&word| word == 0) });
+            });
+        }
+
+        #[test]
+        #[cfg(feature = "nongnu-unwind")]
+        fn configure_caching_once() {
+            assert!(configure_unwind_caching(CachingPolicy::PerThread).is_ok());
+            assert!(configure_unwind_caching(CachingPolicy::PerThread).is_ok());
+            match configure_unwind_caching(CachingPolicy::Global) {
+                Err(err) => assert_eq!(err.code(), -UNW_EINVAL),
+                Ok(_) => panic!("expected an error"),
+            }
+            let res = unsafe { super::super::trace_external_api(|_| true, false) };
+            assert!(res.is_ok());
+        }
+
+        #[test]
+        fn proc_info_covers_ip() {
+            let mut infos = Vec::new();
+            let res = unsafe {
+                super::super::trace_external_api(
+                    |frame| {
+                        infos.push((frame.ip() as usize, frame.proc_info(), frame.lsda()));
+                        true
+                    },
+                    false,
+                )
+            };
+            assert!(res.is_ok());
+            let (ip, info, lsda) = infos[0];
+            let info = info.expect("the innermost frame should have unwind info");
+            assert!(info.start_ip <= ip && ip < info.end_ip, "{:?}", info);
+            assert_eq!(info.lsda, lsda.map_or(0, |lsda| lsda as usize));
+            for (ip, info, _) in infos.iter().skip(1) {
+                if let Some(info) = info {
+                    // Return addresses may be just past the end of a function
+                    // ending in a call.
+                    assert!(info.start_ip < *ip && *ip <= info.end_ip, "{:?}", info);
+                }
+            }
+        }
+
+        #[test]
+        fn step_into_result() {
+            assert!(matches!(StepResult::Success.into_result(), Ok(true)));
+            assert!(matches!(StepResult::End.into_result(), Ok(false)));
+            match StepResult::Error(-7).into_result() {
+                Err(err) => assert_eq!(err.code(), -7),
+                Ok(_) => panic!("expected an error"),
+            }
+        }
     }
 }
@@ -43,6 +43,38 @@ impl Frame {
     pub fn module_base_address(&self) -> Option<*mut c_void> {
         None
     }
+
+    pub fn raw_unwind_context(&self) -> Option<*mut c_void> {
+        None
+    }
+
+    pub fn lsda(&self) -> Option<*const c_void> {
+        None
+    }
+
+    pub fn caller_ip(&self) -> Option<usize> {
+        None
+    }
+
+    pub fn proc_info(&self) -> Option<super::ProcInfo> {
+        None
+    }
+
+    pub fn frame_size(&self) -> Option<usize> {
+        None
+    }
+
+    pub fn capture_registers(&self) -> Option<crate::RegisterContext> {
+        None
+    }
+
+    pub fn function_start(&self) -> Option<usize> {
+        None
+    }
+
+    pub fn ip_is_exact(&self) -> bool {
+        false
+    }
 }
 
 pub fn trace<F: FnMut(&super::Frame) -> bool>(cb: F) {
@@ -51,6 +83,31 @@ pub fn trace<F: FnMut(&super::Frame) -> bool>(cb: F) {
     unsafe { trace_unsynchronized(cb) };
 }
 
+// Miri's backtrace is captured all at once, so it can't fail part way through.
+pub fn trace_status(cb: &mut dyn FnMut(&super::Frame) -> bool) -> super::TraceOutcome {
+    trace(cb);
+    super::TraceOutcome::Complete
+}
+
+// Nor are there any reason codes to report.
+pub fn trace_debug(
+    cb: &mut dyn FnMut(&super::Frame) -> bool,
+    _on_reason: &mut dyn FnMut(Option<&super::Frame>, i32),
+) -> super::TraceOutcome {
+    trace_status(cb)
+}
+
+pub fn available() -> bool {
+    true
+}
+
+// Miri only knows how to trace by its own means.
+pub fn trace_frame_pointers(
+    _cb: &mut dyn FnMut(&super::Frame) -> bool,
+    _stack: Option<core::ops::Range<usize>>,
+) {
+}
+
 pub fn resolve_addr(ptr: *mut c_void) -> Frame {
     // SAFETY: Miri will stop execution with an error if this pointer
     // is invalid.
@@ -1,5 +1,6 @@
 use core::ffi::c_void;
 use core::fmt;
+use core::ops::Range;
 
 /// Inspects the current call-stack, passing all active frames into the closure
 /// provided to calculate a stack trace.
@@ -34,44 +35,1541 @@ use core::fmt;
 /// platforms use a C library which internally uses callbacks which cannot be
 /// unwound through, so panicking from `cb` may trigger a process abort.
 ///
+/// **With the `no-abort-on-panic` feature enabled, `cb` panicking is undefined
+/// behavior.** That feature removes the abort in favor of trusting every
+/// closure passed to this crate to never unwind out, e.g. by catching panics
+/// itself with `std::panic::catch_unwind`.
+///
+/// `trace_catch` catches panics in `cb` and returns them instead.
+///
+/// # Selecting an unwinder
+///
+/// The `BACKTRACE_UNWINDER` environment variable can be set to pick the
+/// unwinder used here at runtime, which is useful to compare them without
+/// rebuilding:
+///
+/// * `fp` walks frame pointers, like `trace_frame_pointers`.
+/// * `libunwind` uses `_Unwind_Backtrace`, the default on most platforms.
+/// * `compact` reads the compact unwind tables in `__unwind_info` directly,
+///   which is only supported on macOS and iOS on x86_64 and aarch64, when
+///   built with Rust 1.59 or later.
+/// * `external` uses the external libunwind API, like
+///   `trace_unsynchronized_external_api`, which requires the `llvm-unwind` or
+///   `nongnu-unwind` feature.
+///
+/// The variable is only read the first time a backtrace is taken. If it's
+/// set to anything else, or to an unwinder which isn't supported by this
+/// build, a message is printed to stderr and the default unwinder is used.
+/// Only this function, `trace_catch`, `trace_ordered`, `trace_detect_loops`
+/// (and so `Backtrace`) and `TraceOptions::capture` are affected, everything
+/// else always uses the default unwinder.
+///
+/// # Example
+///
+/// ```
+/// extern crate backtrace;
+///
+/// fn main() {
+///     backtrace::trace(|frame| {
+///         // ...
+///
+///         true // continue the backtrace
+///     });
+/// }
+/// ```
+#[cfg(feature = "std")]
+pub fn trace<F: FnMut(&Frame) -> bool>(mut cb: F) {
+    let _guard = crate::lock::lock();
+    crate::modules::check_for_changes();
+    unsafe { select::trace(&mut cb) }
+}
+
+/// Same as `trace`, only unsafe as it's unsynchronized.
+///
+/// This function does not have synchronization guarantees but is available
+/// when the `std` feature of this crate isn't compiled in. See the `trace`
+/// function for more documentation and examples.
+///
+/// # Panics
+///
+/// See information on `trace` for caveats on `cb` panicking.
+pub unsafe fn trace_unsynchronized<F: FnMut(&Frame) -> bool>(mut cb: F) {
+    trace_imp(&mut cb)
+}
+
+/// Same as `trace`, except that a panic in `cb` ends the trace and is
+/// returned, rather than aborting the process.
+///
+/// Unwinding out of `cb` would unwind through the unwinder's own C frames,
+/// which isn't possible, so `trace` turns a panic in `cb` into an abort. This
+/// instead calls `cb` for each frame within `std::panic::catch_unwind`,
+/// between the unwinder and `cb`, so a panic is caught before it reaches the
+/// unwinder. The trace then stops and the panic's payload is returned, e.g.
+/// to be passed on with `std::panic::resume_unwind` once out of the
+/// unwinder.
+///
+/// `catch_unwind` doesn't cost anything until something panics, beyond a
+/// call which can't be inlined, so the overhead per frame is negligible next
+/// to unwinding the frame.
+///
+/// As with `catch_unwind`, `cb` must be `UnwindSafe`, so state it updates has
+/// to be e.g. atomic, or be wrapped in `AssertUnwindSafe` if it's fine for it
+/// to be left half updated by a panic. `cb` is dropped without being called
+/// again once it panics. The panic hook still runs as usual when `cb` panics,
+/// printing the panic message unless it's been replaced.
+///
+/// # Required features
+///
+/// This function requires the `std` feature of the `backtrace` crate to be
+/// enabled, and the `std` feature is enabled by default.
+///
+/// # Example
+///
+/// ```
+/// use std::sync::atomic::{AtomicUsize, Ordering};
+///
+/// let frames = AtomicUsize::new(0);
+/// let result = backtrace::trace_catch(|_| {
+///     frames.fetch_add(1, Ordering::Relaxed);
+///     true
+/// });
+/// if let Err(payload) = result {
+///     std::panic::resume_unwind(payload);
+/// }
+/// ```
+#[cfg(feature = "std")]
+pub fn trace_catch<F>(mut cb: F) -> Result<(), std::boxed::Box<dyn core::any::Any + Send>>
+where
+    F: FnMut(&Frame) -> bool + std::panic::UnwindSafe,
+{
+    use std::panic::{self, AssertUnwindSafe};
+
+    let _guard = crate::lock::lock();
+    crate::modules::check_for_changes();
+    let mut payload = None;
+    unsafe {
+        select::trace(&mut |frame| {
+            // `cb` itself is `UnwindSafe`, and is never called again once it
+            // has panicked.
+            match panic::catch_unwind(AssertUnwindSafe(|| cb(frame))) {
+                Ok(keep_going) => keep_going,
+                Err(err) => {
+                    payload = Some(err);
+                    false
+                }
+            }
+        })
+    }
+    match payload {
+        Some(payload) => Err(payload),
+        None => Ok(()),
+    }
+}
+
+/// How a call to `trace_status` ended.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum TraceOutcome {
+    /// The unwinder reached the end of the stack.
+    Complete,
+    /// The closure returned `false` before the end of the stack was reached.
+    StoppedByCallback,
+    /// The unwinder gave up before reaching the end of the stack, for example
+    /// because it found a frame without unwind information. Frames up until
+    /// that point have still been passed to the closure.
+    ///
+    /// The code is the raw `_Unwind_Reason_Code` returned by
+    /// `_Unwind_Backtrace`, or the libunwind error code if the frames came
+    /// from the external unwinder (see `trace_with_fallback`).
+    UnwinderError(i32),
+    /// The trace took longer than the timeout given to `trace_with_timeout`.
+    /// Frames up until that point have still been passed to the closure.
+    TimedOut,
+}
+
+/// Same as `trace`, except that it reports why the trace ended.
+///
+/// A plain `trace` can't tell a stack which was fully walked apart from one
+/// where the unwinder hit a frame it couldn't make sense of, since in both
+/// cases the closure just stops being called. This distinguishes those, as
+/// well as the closure asking to stop.
+///
+/// Only the `_Unwind_Backtrace` based unwinder can actually detect errors;
+/// on other platforms a trace which wasn't stopped by the closure is always
+/// reported as `TraceOutcome::Complete`.
+///
+/// # Required features
+///
+/// This function requires the `std` feature of the `backtrace` crate to be
+/// enabled, and the `std` feature is enabled by default.
+///
+/// # Panics
+///
+/// See information on `trace` for caveats on `cb` panicking.
+///
+/// # Example
+///
+/// ```
+/// use backtrace::TraceOutcome;
+///
+/// let outcome = backtrace::trace_status(|_| true);
+/// if let TraceOutcome::UnwinderError(code) = outcome {
+///     println!("backtrace may be incomplete, unwinder error {}", code);
+/// }
+/// ```
+#[cfg(feature = "std")]
+pub fn trace_status<F: FnMut(&Frame) -> bool>(cb: F) -> TraceOutcome {
+    let _guard = crate::lock::lock();
+    crate::modules::check_for_changes();
+    unsafe { trace_unsynchronized_status(cb) }
+}
+
+/// Same as `trace_status`, only unsafe as it's unsynchronized.
+///
+/// This function does not have synchronization guarantees but is available
+/// when the `std` feature of this crate isn't compiled in. See the
+/// `trace_status` function for more documentation.
+///
+/// # Panics
+///
+/// See information on `trace` for caveats on `cb` panicking.
+///
+/// # Safety
+///
+/// The same requirements as `trace_unsynchronized` apply.
+pub unsafe fn trace_unsynchronized_status<F: FnMut(&Frame) -> bool>(mut cb: F) -> TraceOutcome {
+    let mut stopped = false;
+    let outcome = trace_imp_status(&mut |frame| {
+        let keep_going = cb(frame);
+        stopped = !keep_going;
+        keep_going
+    });
+    if stopped {
+        TraceOutcome::StoppedByCallback
+    } else {
+        outcome
+    }
+}
+
+/// Same as `trace_status`, except that it also reports the raw
+/// `_Unwind_Reason_Code`s passed between this crate and the unwinder, for
+/// finding out why unwinding stops early on a particular unwinder.
+///
+/// `on_reason` is called after each frame the unwinder finds with the code
+/// handed back to it for that frame: 0 (`_URC_NO_REASON`) to carry on, or 9
+/// (`_URC_FAILURE`) once `cb` has asked to stop. This includes the frame with
+/// a null instruction pointer which libgcc reports past the outermost one,
+/// which isn't passed to `cb`. It's then called once more with `None` and the
+/// code `_Unwind_Backtrace` returned, e.g. 5 (`_URC_END_OF_STACK`) for a
+/// complete stack, or 3 (`_URC_FATAL_PHASE1_ERROR`) when libgcc gave up or
+/// was asked to stop. What's returned in which case differs between
+/// unwinders, which is what this is for.
+///
+/// The frames passed to `cb` and the outcome returned are the same as for
+/// `trace_status`. `on_reason` is only ever called by the `_Unwind_Backtrace`
+/// based unwinder; other platforms don't have reason codes.
+///
+/// # Required features
+///
+/// This function requires the `std` feature of the `backtrace` crate to be
+/// enabled, and the `std` feature is enabled by default.
+///
+/// # Panics
+///
+/// See information on `trace` for caveats on `cb` and `on_reason`
+/// panicking.
+///
+/// # Example
+///
+/// ```
+/// backtrace::trace_debug(
+///     |_| true,
+///     |frame, code| match frame {
+///         Some(frame) => println!("{:?}: {}", frame.ip(), code),
+///         None => println!("_Unwind_Backtrace returned {}", code),
+///     },
+/// );
+/// ```
+#[cfg(feature = "std")]
+pub fn trace_debug<F, R>(cb: F, on_reason: R) -> TraceOutcome
+where
+    F: FnMut(&Frame) -> bool,
+    R: FnMut(Option<&Frame>, i32),
+{
+    let _guard = crate::lock::lock();
+    crate::modules::check_for_changes();
+    unsafe { trace_unsynchronized_debug(cb, on_reason) }
+}
+
+/// Same as `trace_debug`, only unsafe as it's unsynchronized.
+///
+/// This function does not have synchronization guarantees but is available
+/// when the `std` feature of this crate isn't compiled in. See the
+/// `trace_debug` function for more documentation.
+///
+/// # Panics
+///
+/// See information on `trace` for caveats on `cb` and `on_reason`
+/// panicking.
+///
+/// # Safety
+///
+/// The same requirements as `trace_unsynchronized` apply.
+pub unsafe fn trace_unsynchronized_debug<F, R>(mut cb: F, mut on_reason: R) -> TraceOutcome
+where
+    F: FnMut(&Frame) -> bool,
+    R: FnMut(Option<&Frame>, i32),
+{
+    let mut stopped = false;
+    let outcome = trace_imp_debug(
+        &mut |frame| {
+            let keep_going = cb(frame);
+            stopped = !keep_going;
+            keep_going
+        },
+        &mut on_reason,
+    );
+    if stopped {
+        TraceOutcome::StoppedByCallback
+    } else {
+        outcome
+    }
+}
+
+/// The unwinder which produced the frames of a `trace_with_fallback`.
+#[cfg(feature = "fallback-unwind")]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum UnwindBackend {
+    /// The platform's default unwinder, as used by `trace`.
+    Default,
+    /// The external libunwind API, as used by
+    /// `trace_unsynchronized_external_api`.
+    External,
+}
+
+/// How a call to `trace_with_fallback` ended.
+#[cfg(feature = "fallback-unwind")]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct FallbackTrace {
+    /// How the last unwinder to run ended.
+    pub outcome: TraceOutcome,
+    /// The last unwinder to run, which produced the outermost frames.
+    pub backend: UnwindBackend,
+}
+
+/// Same as `trace_status`, except that if the default unwinder gives up part
+/// way through the stack the rest of it is unwound with the external
+/// unwinder.
+///
+/// Some versions of `_Unwind_Backtrace` stop with a fatal error, such as
+/// `_URC_FATAL_PHASE1_ERROR`, on frames they can't make sense of, which for
+/// e.g. a crash handler leaves a truncated and undiagnosable backtrace. When
+/// that happens, and the `llvm-unwind` or `nongnu-unwind` feature is enabled,
+/// the stack is walked again with the external libunwind API. Frames of the
+/// retry whose stack pointer lies at or below the outermost one already seen
+/// are skipped, so `cb` carries on from where the first unwinder left off
+/// rather than seeing the same frames twice.
+///
+/// Without an external unwinder compiled in this behaves just like
+/// `trace_status`.
+///
+/// # Required features
+///
+/// This function requires the `std` and `fallback-unwind` features of the
+/// `backtrace` crate to be enabled, and the `std` feature is enabled by
+/// default.
+///
+/// # Panics
+///
+/// See information on `trace` for caveats on `cb` panicking.
+#[cfg(all(feature = "std", feature = "fallback-unwind"))]
+pub fn trace_with_fallback<F: FnMut(&Frame) -> bool>(cb: F) -> FallbackTrace {
+    let _guard = crate::lock::lock();
+    crate::modules::check_for_changes();
+    unsafe { trace_unsynchronized_with_fallback(cb) }
+}
+
+/// Same as `trace_with_fallback`, only unsafe as it's unsynchronized.
+///
+/// This function does not have synchronization guarantees but is available
+/// when the `std` feature of this crate isn't compiled in. See the
+/// `trace_with_fallback` function for more documentation.
+///
+/// # Required features
+///
+/// This function requires the `fallback-unwind` feature of the `backtrace`
+/// crate to be enabled.
+///
+/// # Panics
+///
+/// See information on `trace` for caveats on `cb` panicking.
+///
+/// # Safety
+///
+/// The same requirements as `trace_unsynchronized` apply.
+#[cfg(feature = "fallback-unwind")]
+pub unsafe fn trace_unsynchronized_with_fallback<F: FnMut(&Frame) -> bool>(
+    mut cb: F,
+) -> FallbackTrace {
+    let mut max_sp = 0;
+    let outcome = trace_unsynchronized_status(|frame| {
+        max_sp = max_sp.max(frame.sp() as usize);
+        cb(frame)
+    });
+    let default = FallbackTrace {
+        outcome,
+        backend: UnwindBackend::Default,
+    };
+    match outcome {
+        TraceOutcome::UnwinderError(_) => {}
+        _ => return default,
+    }
+
+    cfg_if::cfg_if! {
+        if #[cfg(any(feature = "llvm-unwind", feature = "nongnu-unwind"))] {
+            FallbackTrace {
+                outcome: resume_external_api(max_sp, &mut cb),
+                backend: UnwindBackend::External,
+            }
+        } else {
+            default
+        }
+    }
+}
+
+// Walks the stack with the external unwinder, passing `cb` only the frames
+// whose stack pointer is above `max_sp`. The stack grows down, so everything
+// at or below it was either already delivered or belongs to this function.
+//
+// Both unwinders give each frame the stack pointer within it, i.e. the CFA
+// of the frame it called, which is what `_Unwind_GetCFA` returns while
+// `_Unwind_Backtrace` is on that frame, so the two can be compared directly.
+#[cfg(all(
+    feature = "fallback-unwind",
+    any(feature = "llvm-unwind", feature = "nongnu-unwind")
+))]
+unsafe fn resume_external_api(max_sp: usize, cb: &mut dyn FnMut(&Frame) -> bool) -> TraceOutcome {
+    let mut stopped = false;
+    let res = trace_imp_external_api(
+        |frame: &Frame| {
+            if frame.sp() as usize <= max_sp {
+                return true;
+            }
+            let keep_going = cb(frame);
+            stopped = !keep_going;
+            keep_going
+        },
+        false,
+    );
+    match res {
+        _ if stopped => TraceOutcome::StoppedByCallback,
+        Ok(()) => TraceOutcome::Complete,
+        Err(err) => TraceOutcome::UnwinderError(err.code()),
+    }
+}
+
+/// Same as `trace`, except that it also returns how long the unwind took.
+///
+/// The clock is started once the global lock is held and stopped as soon as
+/// the unwinder returns, so time spent waiting on other threads' backtraces
+/// isn't counted but time spent in `cb` is; to time the unwinder alone `cb`
+/// should do as little as possible, e.g. just count frames.
+///
+/// On Unix the clock is read with `clock_gettime(CLOCK_MONOTONIC)` directly,
+/// whose overhead is low enough to not skew unwinds taking well under a
+/// microsecond. Elsewhere `std::time::Instant` is used.
+///
+/// This always uses the default unwinder, ignoring `BACKTRACE_UNWINDER`.
+///
+/// # Required features
+///
+/// This function requires the `std` feature of the `backtrace` crate to be
+/// enabled, and the `std` feature is enabled by default.
+///
+/// # Panics
+///
+/// See information on `trace` for caveats on `cb` panicking.
+///
+/// # Example
+///
+/// ```
+/// let mut frames = 0;
+/// let elapsed = backtrace::trace_timed(|_| {
+///     frames += 1;
+///     true
+/// });
+/// println!("unwound {} frames in {:?}", frames, elapsed);
+/// ```
+#[cfg(feature = "std")]
+pub fn trace_timed<F: FnMut(&Frame) -> bool>(mut cb: F) -> std::time::Duration {
+    let _guard = crate::lock::lock();
+    crate::modules::check_for_changes();
+    let start = monotonic_now();
+    unsafe { trace_imp(&mut cb) };
+    monotonic_now()
+        .checked_sub(start)
+        .unwrap_or(std::time::Duration::from_secs(0))
+}
+
+#[cfg(all(feature = "std", unix))]
+#[inline(always)]
+fn monotonic_now() -> std::time::Duration {
+    let mut ts = libc::timespec {
+        tv_sec: 0,
+        tv_nsec: 0,
+    };
+    // This can only fail for an invalid clock, and `CLOCK_MONOTONIC` is
+    // always supported.
+    unsafe { libc::clock_gettime(libc::CLOCK_MONOTONIC, &mut ts) };
+    std::time::Duration::new(ts.tv_sec as u64, ts.tv_nsec as u32)
+}
+
+#[cfg(all(feature = "std", not(unix)))]
+#[inline(always)]
+fn monotonic_now() -> std::time::Duration {
+    use std::time::Instant;
+
+    thread_local!(static EPOCH: Instant = Instant::now());
+    EPOCH.with(|epoch| epoch.elapsed())
+}
+
+/// Same as `trace_status`, except that the trace is given up on once it has
+/// taken longer than `timeout`, returning `TraceOutcome::TimedOut`.
+///
+/// A single trace can take seconds on a machine whose unwinder has to page
+/// in swapped out unwind tables, or which is otherwise struggling, which a
+/// watchdog or other liveness critical code can't afford. Frames gathered
+/// before the timeout expired have still been passed to `cb`.
+///
+/// A POSIX timer is armed to send `SIGRTMIN() + 5` to the calling thread
+/// when the timeout expires, and is deleted again before this returns. The
+/// handler for the signal is installed the first time this is called,
+/// replacing any handler the application had for it. If the signal is
+/// blocked, or the timer can't be created, the trace just runs to the end.
+///
+/// The timeout is checked before each frame is passed to `cb`, so a trace
+/// only ends at the next frame after the timeout expired, and the time spent
+/// in `cb` counts towards it. It only works where this crate drives the
+/// unwinding loop itself, so frames are walked with the external unwinder if
+/// the `llvm-unwind` or `nongnu-unwind` feature is enabled, and otherwise by
+/// following frame pointers, like `trace_frame_pointers`, which requires the
+/// code to have been built with them. `_Unwind_Backtrace`, and so
+/// `BACKTRACE_UNWINDER`, isn't used.
+///
+/// # Required features
+///
+/// This function requires the `std` feature of the `backtrace` crate to be
+/// enabled, and the `std` feature is enabled by default.
+///
+/// # Panics
+///
+/// See information on `trace` for caveats on `cb` panicking.
+///
+/// # Example
+///
+/// ```
+/// use backtrace::TraceOutcome;
+/// use std::time::Duration;
+///
+/// let outcome = backtrace::trace_with_timeout(Duration::from_millis(10), |_| true);
+/// if outcome == TraceOutcome::TimedOut {
+///     println!("gave up on the backtrace");
+/// }
+/// ```
+#[cfg(all(
+    feature = "std",
+    any(target_os = "linux", target_os = "android"),
+    any(target_arch = "x86_64", target_arch = "aarch64"),
+))]
+pub fn trace_with_timeout<F: FnMut(&Frame) -> bool>(
+    timeout: std::time::Duration,
+    mut cb: F,
+) -> TraceOutcome {
+    let _guard = crate::lock::lock();
+    crate::modules::check_for_changes();
+    unsafe { timeout::trace(timeout, &mut cb) }
+}
+
+/// Same as `trace`, except that the unwinder runs on `stack` rather than on
+/// the current thread's stack.
+///
+/// Unwinding, and `cb` in particular, can need a good few kilobytes of stack.
+/// A profiler's signal handler which interrupted code that was already close
+/// to overflowing its stack may not have that much left, even when running
+/// on a `sigaltstack`, in which case this can be used to unwind on a buffer
+/// set aside for it. The frames passed to `cb` are still those of the
+/// interrupted thread: only the memory the unwinder uses is different.
+///
+/// This is only available on Linux and Android, on x86_64 and aarch64, when
+/// built with Rust 1.59 or later since switching stacks needs `global_asm!`.
+/// It always uses the default unwinder, ignoring `BACKTRACE_UNWINDER`. The
+/// frame pointer unwinder only walks the thread's own stack so it can't be
+/// used here.
+///
+/// # Required features
+///
+/// This function requires the `std` feature of the `backtrace` crate to be
+/// enabled, and the `std` feature is enabled by default.
+///
+/// # Panics
+///
+/// See information on `trace` for caveats on `cb` panicking.
+///
+/// # Safety
+///
+/// `stack` must be large enough for the unwinder and `cb` to run on, since
+/// nothing detects it overflowing. 64KiB is plenty for `_Unwind_Backtrace`
+/// with a closure that just records frames. It must also not be the stack
+/// which is currently in use, such as the `sigaltstack` of the signal
+/// handler calling this.
+///
+/// # Example
+///
+/// ```
+/// # #[cfg(all(any(target_os = "linux", target_os = "android"),
+/// #           any(target_arch = "x86_64", target_arch = "aarch64")))]
+/// # {
+/// let mut stack = vec![0u8; 64 * 1024];
+/// let mut frames = 0;
+/// unsafe {
+///     backtrace::trace_on_altstack(&mut stack, |_| {
+///         frames += 1;
+///         true
+///     });
+/// }
+/// println!("unwound {} frames", frames);
+/// # }
+/// ```
+#[cfg(all(
+    feature = "std",
+    any(target_os = "linux", target_os = "android"),
+    any(target_arch = "x86_64", target_arch = "aarch64"),
+    not(backtrace_no_asm),
+))]
+pub unsafe fn trace_on_altstack<F: FnMut(&Frame) -> bool>(stack: &mut [u8], cb: F) {
+    let _guard = crate::lock::lock();
+    crate::modules::check_for_changes();
+    trace_unsynchronized_on_altstack(stack, cb)
+}
+
+/// Same as `trace_on_altstack`, only unsynchronized.
+///
+/// This function does not have synchronization guarantees but is available
+/// when the `std` feature of this crate isn't compiled in. See the
+/// `trace_on_altstack` function for more documentation.
+///
+/// # Panics
+///
+/// See information on `trace` for caveats on `cb` panicking.
+///
+/// # Safety
+///
+/// The same requirements as `trace_unsynchronized` and `trace_on_altstack`
+/// apply.
+#[cfg(all(
+    any(target_os = "linux", target_os = "android"),
+    any(target_arch = "x86_64", target_arch = "aarch64"),
+    not(backtrace_no_asm),
+))]
+pub unsafe fn trace_unsynchronized_on_altstack<F: FnMut(&Frame) -> bool>(
+    stack: &mut [u8],
+    mut cb: F,
+) {
+    altstack::on_stack(stack, &mut || trace_imp(&mut cb))
+}
+
+/// Traces the stack of the code interrupted by a signal, from within the
+/// `SA_SIGINFO` handler of that signal, e.g. to report where a `SIGSEGV`
+/// happened.
+///
+/// A plain `trace` from a signal handler starts with the frames of the
+/// handler itself and of the kernel's signal trampoline. This instead starts
+/// with the interrupted frame, whose `ip` is the program counter saved in
+/// `ctx`, i.e. the faulting instruction for a fault, and carries on from
+/// there to the rest of the stack.
+///
+/// If the unwinder can't find its way through the signal trampoline to the
+/// interrupted frame, only that frame is passed to `cb`, as recovered from
+/// the program counter and stack pointer in `ctx`.
+///
+/// `info` isn't currently read, it's only taken so the handler's arguments
+/// can be passed straight through.
+///
+/// Note that the `ip` of the first frame is the address of an instruction,
+/// not a return address like those of the frames after it, so it should be
+/// looked up with `resolve_frame`, which knows this, or `resolve_addr`, rather
+/// than `resolve`.
+///
+/// This is only available on Linux for x86_64 (glibc), aarch64 and arm
+/// (glibc), and on Android for aarch64, where the layout of the saved
+/// registers in `ucontext_t` is known. It always uses the default unwinder.
+///
+/// There's no synchronized version of this function since the lock taken by
+/// `trace` isn't async-signal-safe.
+///
+/// # Panics
+///
+/// See information on `trace` for caveats on `cb` panicking.
+///
+/// # Safety
+///
+/// `ctx` must be the context passed to the signal handler this is called
+/// from, and the same requirements as `trace_unsynchronized` apply. As for
+/// anything done in a signal handler, `cb` should stick to async-signal-safe
+/// operations.
+#[cfg(all(
+    not(miri),
+    any(
+        all(target_os = "linux", target_env = "gnu", target_arch = "x86_64"),
+        all(
+            any(target_os = "linux", target_os = "android"),
+            target_arch = "aarch64"
+        ),
+        all(target_os = "linux", target_env = "gnu", target_arch = "arm"),
+    ),
+))]
+pub unsafe fn trace_from_siginfo<F: FnMut(&Frame) -> bool>(
+    _info: *const libc::siginfo_t,
+    ctx: *mut libc::ucontext_t,
+    mut cb: F,
+) {
+    let mcontext = &(*ctx).uc_mcontext;
+    cfg_if::cfg_if! {
+        if #[cfg(target_arch = "x86_64")] {
+            let pc = mcontext.gregs[libc::REG_RIP as usize] as usize;
+            let sp = mcontext.gregs[libc::REG_RSP as usize] as usize;
+        } else if #[cfg(target_arch = "aarch64")] {
+            let pc = mcontext.pc as usize;
+            let sp = mcontext.sp as usize;
+        } else {
+            // Cleared of the Thumb bit, like the `ip`s of the other frames.
+            let pc = mcontext.arm_pc as usize & !1;
+            let sp = mcontext.arm_sp as usize;
+        }
+    }
+
+    // Everything up until the interrupted frame belongs to the handler.
+    let mut found = false;
+    trace_imp(&mut |frame| {
+        if !found {
+            if frame.ip() as usize != pc {
+                return true;
+            }
+            found = true;
+        }
+        cb(frame)
+    });
+    if !found {
+        let frame = Frame {
+            inner: FrameImp::Cloned {
+                ip: pc as *mut c_void,
+                sp: sp as *mut c_void,
+                symbol_address: None,
+                lsda: None,
+                caller_ip: None,
+                proc_info: None,
+                frame_size: None,
+                exact_ip: true,
+                registers: core::ptr::null(),
+            },
+        };
+        cb(&frame);
+    }
+}
+
+/// The order in which frames are passed to the closure of `trace_ordered`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum FrameOrder {
+    /// The most recently called function first, ending with the thread's
+    /// entry point. This is the order unwinders produce frames in, so it
+    /// requires no buffering.
+    InnermostFirst,
+    /// The thread's entry point first, ending with the most recently called
+    /// function, as used by e.g. flame graphs and the pprof format.
+    OutermostFirst,
+}
+
+impl Default for FrameOrder {
+    fn default() -> FrameOrder {
+        FrameOrder::InnermostFirst
+    }
+}
+
+/// Same as `trace`, except that frames are passed to `cb` in the given
+/// `order`.
+///
+/// `FrameOrder::InnermostFirst` is the same as calling `trace`. Unwinding can
+/// only proceed innermost-first though, so for `FrameOrder::OutermostFirst`
+/// the whole stack is first captured into a `Vec` of cloned frames, which
+/// are then passed to `cb` in reverse. Returning `false` from `cb` then stops
+/// delivery of the remaining (inner) frames, but not the unwinding itself.
+///
+/// # Required features
+///
+/// This function requires the `std` feature of the `backtrace` crate to be
+/// enabled, and the `std` feature is enabled by default.
+///
+/// # Panics
+///
+/// See information on `trace` for caveats on `cb` panicking.
+///
+/// # Example
+///
+/// ```
+/// use backtrace::FrameOrder;
+///
+/// backtrace::trace_ordered(FrameOrder::OutermostFirst, |frame| {
+///     println!("{:?}", frame.ip());
+///     true
+/// });
+/// ```
+#[cfg(feature = "std")]
+pub fn trace_ordered<F: FnMut(&Frame) -> bool>(order: FrameOrder, mut cb: F) {
+    match order {
+        FrameOrder::InnermostFirst => trace(cb),
+        FrameOrder::OutermostFirst => {
+            let mut frames = std::vec::Vec::with_capacity(MAX_RECOMMENDED_FRAMES);
+            trace(|frame| {
+                frames.push(frame.clone());
+                true
+            });
+            for frame in frames.iter().rev() {
+                if !cb(frame) {
+                    break;
+                }
+            }
+        }
+    }
+}
+
+/// A reasonable upper bound on the number of frames to capture.
+///
+/// Stacks deeper than this are almost always the result of runaway recursion,
+/// where the outermost frames add little. Callers using fixed-size buffers,
+/// such as with `trace_into`, can use this as a default size; anything beyond
+/// it is dropped and reported as truncated. `Backtrace` reserves this many
+/// frames up front but is never truncated.
+pub const MAX_RECOMMENDED_FRAMES: usize = 128;
+
+/// The outcome of `trace_into` and `trace_unsynchronized_into`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct TraceInto {
+    /// The number of leading entries of the buffer which were filled in.
+    pub len: usize,
+    /// Whether the stack had more frames than fit in the buffer, in which case
+    /// the outermost frames are missing.
+    pub truncated: bool,
+}
+
+/// Captures the instruction pointers of the current call-stack into `buf`
+/// without allocating.
+///
+/// Frames are stored top-down like those passed to the closure of `trace`.
+/// The returned `TraceInto` says how many entries of `buf` were filled in, and
+/// whether the backtrace was truncated because `buf` was full before the
+/// bottom of the stack was reached. `MAX_RECOMMENDED_FRAMES` is a reasonable
+/// size for `buf`.
+///
+/// # Required features
+///
+/// This function requires the `std` feature of the `backtrace` crate to be
+/// enabled, and the `std` feature is enabled by default.
+///
+/// # Example
+///
+/// ```
+/// use backtrace::MAX_RECOMMENDED_FRAMES;
+///
+/// let mut buf = [std::ptr::null_mut(); MAX_RECOMMENDED_FRAMES];
+/// let result = backtrace::trace_into(&mut buf);
+/// for ip in &buf[..result.len] {
+///     println!("{:?}", ip);
+/// }
+/// if result.truncated {
+///     println!("...");
+/// }
+/// ```
+#[cfg(feature = "std")]
+pub fn trace_into(buf: &mut [*mut c_void]) -> TraceInto {
+    let _guard = crate::lock::lock();
+    crate::modules::check_for_changes();
+    unsafe { trace_unsynchronized_into(buf) }
+}
+
+/// Same as `trace_into`, only unsafe as it's unsynchronized.
+///
+/// This function does not have synchronization guarantees but is available
+/// when the `std` feature of this crate isn't compiled in. Since it doesn't
+/// allocate it's also suitable for use from signal handlers, to the extent
+/// the underlying unwinder is. See the `trace_into` function for more
+/// documentation.
+///
+/// # Safety
+///
+/// The same requirements as `trace_unsynchronized` apply.
+pub unsafe fn trace_unsynchronized_into(buf: &mut [*mut c_void]) -> TraceInto {
+    let mut result = TraceInto {
+        len: 0,
+        truncated: false,
+    };
+    trace_imp(&mut |frame| {
+        if result.len == buf.len() {
+            result.truncated = true;
+            return false;
+        }
+        buf[result.len] = frame.ip();
+        result.len += 1;
+        true
+    });
+    result
+}
+
+/// Same as `trace`, except that the walk is cut short if the unwinder starts
+/// looping.
+///
+/// Some unwinders, when handed corrupt or unusual unwind info, will yield the
+/// same frame over and over and never terminate. This remembers the last few
+/// `(ip, sp)` pairs seen and stops as soon as one repeats, which can't happen
+/// in a well-formed stack. The bookkeeping lives entirely on the stack so this
+/// stays as signal-safe as the underlying unwinder.
+///
+/// Returns `true` if the trace was stopped because a loop was detected, in
+/// which case the repeated frame is not passed to `cb`.
+///
+/// # Required features
+///
+/// This function requires the `std` feature of the `backtrace` crate to be
+/// enabled, and the `std` feature is enabled by default.
+///
+/// # Panics
+///
+/// See information on `trace` for caveats on `cb` panicking.
+#[cfg(feature = "std")]
+pub fn trace_detect_loops<F: FnMut(&Frame) -> bool>(mut cb: F) -> bool {
+    let _guard = crate::lock::lock();
+    crate::modules::check_for_changes();
+    let mut looped = false;
+    unsafe { select::trace(&mut stop_at_loop(&mut cb, &mut looped)) };
+    looped
+}
+
+/// Same as `trace_detect_loops`, only unsafe as it's unsynchronized.
+///
+/// This function does not have synchronization guarantees but is available
+/// when the `std` feature of this crate isn't compiled in. See the
+/// `trace_detect_loops` function for more documentation.
+///
+/// # Safety
+///
+/// The same requirements as `trace_unsynchronized` apply.
+///
+/// # Panics
+///
+/// See information on `trace` for caveats on `cb` panicking.
+pub unsafe fn trace_unsynchronized_detect_loops<F: FnMut(&Frame) -> bool>(mut cb: F) -> bool {
+    let mut looped = false;
+    trace_imp(&mut stop_at_loop(&mut cb, &mut looped));
+    looped
+}
+
+// Wraps `cb` so that the trace stops, setting `looped`, at the first frame
+// which repeats one of the last few.
+fn stop_at_loop<'a>(
+    cb: &'a mut dyn FnMut(&Frame) -> bool,
+    looped: &'a mut bool,
+) -> impl FnMut(&Frame) -> bool + 'a {
+    let mut recent = RecentFrames::new();
+    move |frame| {
+        if !recent.insert(frame.ip() as usize, frame.sp() as usize) {
+            *looped = true;
+            return false;
+        }
+        cb(frame)
+    }
+}
+
+/// Same as `trace`, except that the outermost frame of the stack, where the
+/// thread started, is left out.
+///
+/// The outermost frame is `clone` (or `clone3`) for threads spawned with
+/// pthreads and `_start` for the main thread. It never says anything about
+/// what the thread was doing, and since which one it is differs between
+/// threads and between libc versions, leaving it out makes stacks captured on
+/// different threads easier to compare and aggregate.
+///
+/// The outermost frame is only recognized as such when the unwinder reaches
+/// the base of the stack, i.e. the frame whose unwind info says there's
+/// nothing beyond it. If it gives up before then (see `trace_status`) the
+/// last frame is kept, as it's some frame part way up the stack.
+///
+/// Unwinding with libgcc, `trace` ends with one more frame past the
+/// outermost one, whose ip is null as the outermost frame's unwind info
+/// marks its return address as undefined. That frame is left out too, so
+/// that stacks look the same whichever unwinder captured them.
+///
+/// Since it isn't known which frame is the last one until the unwinder has
+/// moved past it, each frame is only passed to `cb` once the next one has
+/// been found, as a clone. In particular `Frame::raw_unwind_context` is
+/// always `None` for them.
+///
+/// # Required features
+///
+/// This function requires the `std` feature of the `backtrace` crate to be
+/// enabled, and the `std` feature is enabled by default.
+///
+/// # Panics
+///
+/// See information on `trace` for caveats on `cb` panicking.
+///
 /// # Example
 ///
 /// ```
-/// extern crate backtrace;
+/// let mut ips = Vec::new();
+/// backtrace::trace_without_thread_entry(|frame| {
+///     ips.push(frame.ip());
+///     true
+/// });
+/// ```
+#[cfg(feature = "std")]
+pub fn trace_without_thread_entry<F: FnMut(&Frame) -> bool>(cb: F) {
+    let _guard = crate::lock::lock();
+    crate::modules::check_for_changes();
+    unsafe { trace_unsynchronized_without_thread_entry(cb) }
+}
+
+/// Same as `trace_without_thread_entry`, only unsafe as it's unsynchronized.
 ///
-/// fn main() {
-///     backtrace::trace(|frame| {
-///         // ...
+/// This function does not have synchronization guarantees but is available
+/// when the `std` feature of this crate isn't compiled in. Since it doesn't
+/// allocate it's also suitable for use from signal handlers, to the extent
+/// the underlying unwinder is. See the `trace_without_thread_entry` function
+/// for more documentation.
+///
+/// # Safety
+///
+/// The same requirements as `trace_unsynchronized` apply.
+///
+/// # Panics
+///
+/// See information on `trace` for caveats on `cb` panicking.
+pub unsafe fn trace_unsynchronized_without_thread_entry<F: FnMut(&Frame) -> bool>(mut cb: F) {
+    let mut held: Option<Frame> = None;
+    let mut stopped = false;
+    let outcome = trace_imp_status(&mut |frame| {
+        if frame.ip().is_null() {
+            return true;
+        }
+        if let Some(prev) = held.take() {
+            if !cb(&prev) {
+                stopped = true;
+                return false;
+            }
+        }
+        held = Some(frame.clone());
+        true
+    });
+    if let Some(last) = held {
+        if !stopped && outcome != TraceOutcome::Complete {
+            cb(&last);
+        }
+    }
+}
+
+/// Same as `trace`, except that only frames for which `predicate` returns
+/// `true` are passed to `cb`.
+///
+/// Frames which are filtered out are skipped over rather than ending the
+/// trace, so e.g. leaving out libc's frames still delivers the application
+/// frames on either side of a callback from `qsort`. `cb` returning `false`
+/// ends the trace as usual.
+///
+/// The predicate sees every frame before any symbolization has been done, so
+/// it's best kept cheap: `Frame::is_in_main_executable`, or checking the `ip`
+/// against the bounds of a `Module` looked up ahead of time with
+/// `loaded_modules`, rather than resolving each frame.
+///
+/// # Required features
+///
+/// This function requires the `std` feature of the `backtrace` crate to be
+/// enabled, and the `std` feature is enabled by default.
+///
+/// # Panics
+///
+/// See information on `trace` for caveats on `cb` or `predicate` panicking.
+///
+/// # Example
 ///
-///         true // continue the backtrace
-///     });
-/// }
+/// ```
+/// // Leave out frames in libc and other shared libraries.
+/// let mut ips = Vec::new();
+/// backtrace::trace_filtered(
+///     |frame| frame.is_in_main_executable(),
+///     |frame| {
+///         ips.push(frame.ip());
+///         true
+///     },
+/// );
 /// ```
 #[cfg(feature = "std")]
-pub fn trace<F: FnMut(&Frame) -> bool>(cb: F) {
+pub fn trace_filtered<P, F>(predicate: P, cb: F)
+where
+    P: FnMut(&Frame) -> bool,
+    F: FnMut(&Frame) -> bool,
+{
     let _guard = crate::lock::lock();
-    unsafe { trace_unsynchronized(cb) }
+    crate::modules::check_for_changes();
+    unsafe { trace_unsynchronized_filtered(predicate, cb) }
 }
 
-/// Same as `trace`, only unsafe as it's unsynchronized.
+/// Same as `trace_filtered`, only unsafe as it's unsynchronized.
 ///
 /// This function does not have synchronization guarantees but is available
-/// when the `std` feature of this crate isn't compiled in. See the `trace`
-/// function for more documentation and examples.
+/// when the `std` feature of this crate isn't compiled in. See the
+/// `trace_filtered` function for more documentation.
+///
+/// # Safety
+///
+/// The same requirements as `trace_unsynchronized` apply.
+///
+/// # Panics
+///
+/// See information on `trace` for caveats on `cb` or `predicate` panicking.
+pub unsafe fn trace_unsynchronized_filtered<P, F>(mut predicate: P, mut cb: F)
+where
+    P: FnMut(&Frame) -> bool,
+    F: FnMut(&Frame) -> bool,
+{
+    trace_imp(&mut |frame| !predicate(frame) || cb(frame))
+}
+
+/// Same as `trace`, except that once the last frame has been passed to the
+/// closure it's called one more time with `None`.
+///
+/// This gives consumers which aggregate frames as they're streamed, e.g.
+/// building up a key for the whole stack, an explicit point at which to
+/// finish up, rather than having to do so after `trace` returns.
+///
+/// The `None` call happens exactly once if the closure never returned
+/// `false`, whether the unwinder reached the end of the stack or gave up
+/// early (see `trace_status` for telling those apart). If the closure does
+/// return `false` it's not called again, so it isn't passed `None`; its
+/// return value for `None` is ignored.
+///
+/// # Required features
+///
+/// This function requires the `std` feature of the `backtrace` crate to be
+/// enabled, and the `std` feature is enabled by default.
 ///
 /// # Panics
 ///
 /// See information on `trace` for caveats on `cb` panicking.
-pub unsafe fn trace_unsynchronized<F: FnMut(&Frame) -> bool>(mut cb: F) {
-    trace_imp(&mut cb)
+///
+/// # Example
+///
+/// ```
+/// let mut depth = 0;
+/// backtrace::trace_with_end(|frame| {
+///     match frame {
+///         Some(_) => depth += 1,
+///         None => println!("stack of {} frames", depth),
+///     }
+///     true
+/// });
+/// ```
+#[cfg(feature = "std")]
+pub fn trace_with_end<F: FnMut(Option<&Frame>) -> bool>(cb: F) {
+    let _guard = crate::lock::lock();
+    crate::modules::check_for_changes();
+    unsafe { trace_unsynchronized_with_end(cb) }
+}
+
+/// Same as `trace_with_end`, only unsafe as it's unsynchronized.
+///
+/// This function does not have synchronization guarantees but is available
+/// when the `std` feature of this crate isn't compiled in. See the
+/// `trace_with_end` function for more documentation.
+///
+/// # Safety
+///
+/// The same requirements as `trace_unsynchronized` apply.
+///
+/// # Panics
+///
+/// See information on `trace` for caveats on `cb` panicking.
+pub unsafe fn trace_unsynchronized_with_end<F: FnMut(Option<&Frame>) -> bool>(mut cb: F) {
+    let mut stopped = false;
+    trace_imp(&mut |frame| {
+        stopped = !cb(Some(frame));
+        !stopped
+    });
+    if !stopped {
+        cb(None);
+    }
+}
+
+// A ring buffer of the `(ip, sp)` pairs most recently seen while unwinding.
+struct RecentFrames {
+    pairs: [(usize, usize); 4],
+    len: usize,
+    next: usize,
+}
+
+impl RecentFrames {
+    fn new() -> RecentFrames {
+        RecentFrames {
+            pairs: [(0, 0); 4],
+            len: 0,
+            next: 0,
+        }
+    }
+
+    // Records a pair, returning `false` if it was already among the recent
+    // ones.
+    fn insert(&mut self, ip: usize, sp: usize) -> bool {
+        if self.pairs[..self.len].contains(&(ip, sp)) {
+            return false;
+        }
+        self.pairs[self.next] = (ip, sp);
+        self.next = (self.next + 1) % self.pairs.len();
+        self.len = (self.len + 1).min(self.pairs.len());
+        true
+    }
 }
 
 /// trace_unsynchronized_external_api
+///
+/// Returns an error if the external unwinder fails to set up a cursor, read a
+/// frame's registers, or step to the next frame. Frames visited before the
+/// failure have already been passed to `cb`.
+///
+/// `signal_frame` tells the unwinder that the innermost frame was interrupted
+/// by a signal. With the `llvm-unwind` feature this is only honoured if the
+/// libunwind linked against provides `unw_init_local2`, which is looked up
+/// the first time an `UnwContext` is set up; since that lookup isn't
+/// async-signal-safe, callers tracing from a signal handler should create an
+/// `UnwContext` beforehand.
 #[cfg(any(feature = "llvm-unwind", feature = "nongnu-unwind"))]
-pub unsafe fn trace_unsynchronized_external_api<F: FnMut(&Frame) -> bool>(mut cb: F, signal_frame: bool) {
+pub unsafe fn trace_unsynchronized_external_api<F: FnMut(&Frame) -> bool>(
+    mut cb: F,
+    signal_frame: bool,
+) -> Result<(), UnwindError> {
     trace_imp_external_api(&mut cb, signal_frame)
 }
 
+/// Same as `trace_unsynchronized_external_api`, except that the registers of
+/// each frame are read as well, for `Frame::capture_registers`.
+///
+/// The external unwinder has moved on to a frame's caller by the time the
+/// frame is passed to `cb`, so its registers are read beforehand, which takes
+/// a call into the unwinder per register. Other traces skip this, and their
+/// frames' `capture_registers` returns `None`.
+///
+/// # Safety
+///
+/// The same requirements as `trace_unsynchronized` apply.
+#[cfg(any(feature = "llvm-unwind", feature = "nongnu-unwind"))]
+pub unsafe fn trace_unsynchronized_external_api_with_registers<F: FnMut(&Frame) -> bool>(
+    mut cb: F,
+    signal_frame: bool,
+) -> Result<(), UnwindError> {
+    trace_imp_external_api_with_registers(&mut cb, signal_frame)
+}
+
+/// Same as `trace_unsynchronized_external_api`, except that the unwinder's
+/// state is kept in the `context` and `cursor` given rather than set up
+/// afresh on the stack.
+///
+/// Both are several hundred bytes, so when sampling at a high rate it's
+/// cheaper to create them once with `UnwContext::zeroed` and
+/// `UnwCursor::zeroed` and pass the same ones to every trace, which then only
+/// has to capture the current registers and reset the cursor. Their contents
+/// are overwritten by each trace and meaningless outside of one.
+///
+/// The scratch buffers belong to the thread being traced: each thread needs
+/// its own pair, and they must not be shared across threads, e.g. through a
+/// `static`, as two concurrent traces would then clobber each other's state.
+///
+/// A trace started on the same thread from within `cb`, e.g. by a heap
+/// profiler hooking an allocation made by the callback, can't be given the
+/// same pair, which the outer trace has borrowed. With the `std` feature such
+/// nested traces are detected and set up their own context and cursor on the
+/// stack instead, leaving the pair they're given untouched, so a second pair
+/// per thread does for any depth of nesting, although nested traces don't
+/// save the setup. Without `std` there's no way to tell, and every level of
+/// nesting must be given a pair of its own.
+///
+/// # Safety
+///
+/// The same requirements as `trace_unsynchronized` apply.
+///
+/// # Example
+///
+/// ```
+/// use backtrace::{UnwContext, UnwCursor};
+///
+/// let mut context = UnwContext::zeroed();
+/// let mut cursor = UnwCursor::zeroed();
+/// for _ in 0..10 {
+///     let mut frames = 0;
+///     unsafe {
+///         backtrace::trace_unsynchronized_external_api_with_scratch(
+///             &mut context,
+///             &mut cursor,
+///             |_| {
+///                 frames += 1;
+///                 true
+///             },
+///             false,
+///         )
+///         .unwrap();
+///     }
+/// }
+/// ```
+#[cfg(any(feature = "llvm-unwind", feature = "nongnu-unwind"))]
+pub unsafe fn trace_unsynchronized_external_api_with_scratch<F: FnMut(&Frame) -> bool>(
+    context: &mut UnwContext,
+    cursor: &mut UnwCursor,
+    mut cb: F,
+    signal_frame: bool,
+) -> Result<(), UnwindError> {
+    trace_imp_external_api_with_scratch(context, cursor, &mut cb, signal_frame)
+}
+
+/// Checks that the external unwinder works, returning the number of frames it
+/// saw.
+///
+/// This walks the stack with the external unwinder from the innermost of a
+/// chain of `SELF_TEST_DEPTH` helper functions, so a working unwinder sees at
+/// least those frames, plus `self_test` itself and its callers. A broken one,
+/// e.g. from a libunwind build without unwind tables for this platform, sees
+/// only a frame or two or fails with an error, so an application can check
+/// the result once at startup rather than find out from its first backtrace:
+///
+/// ```
+/// let frames = backtrace::self_test().expect("unwinding failed");
+/// assert!(frames >= 3, "unwinding stopped after {} frames", frames);
+/// ```
+///
+/// # Required features
+///
+/// This function requires the `std` feature and the `llvm-unwind` or
+/// `nongnu-unwind` feature of the `backtrace` crate to be enabled.
+#[cfg(all(
+    feature = "std",
+    any(feature = "llvm-unwind", feature = "nongnu-unwind")
+))]
+pub fn self_test() -> Result<usize, UnwindError> {
+    let _guard = crate::lock::lock();
+    self_test_level(SELF_TEST_DEPTH)
+}
+
+/// The number of helper function frames `self_test` unwinds through.
+#[cfg(all(
+    feature = "std",
+    any(feature = "llvm-unwind", feature = "nongnu-unwind")
+))]
+pub const SELF_TEST_DEPTH: usize = 3;
+
+// Each level keeps a local alive across the call to the next one, so none of
+// them can become a tail call and drop out of the stack.
+#[cfg(all(
+    feature = "std",
+    any(feature = "llvm-unwind", feature = "nongnu-unwind")
+))]
+#[inline(never)]
+fn self_test_level(depth: usize) -> Result<usize, UnwindError> {
+    let level = depth;
+    let result = if depth > 1 {
+        self_test_level(depth - 1)
+    } else {
+        let mut frames = 0;
+        unsafe {
+            trace_imp_external_api(
+                &mut |_: &Frame| {
+                    frames += 1;
+                    true
+                },
+                false,
+            )
+        }
+        .map(|()| frames)
+    };
+    unsafe { core::ptr::read_volatile(&level) };
+    result
+}
+
+/// Inspects the current call-stack by following the chain of saved frame
+/// pointers, passing all active frames into the closure provided.
+///
+/// This behaves like `trace_unsynchronized`, but instead of consulting unwind
+/// tables it reads each frame's saved frame pointer and return address straight
+/// off the stack. That makes it much cheaper, which matters for high-frequency
+/// sampling, but it only produces a complete backtrace if all code on the stack
+/// was compiled with frame pointers (e.g. `-C force-frame-pointers=yes`).
+/// Frames yielded here report their `ip` as their `symbol_address` since there
+/// are no unwind tables to find the start of the function with.
+///
+/// Every frame pointer is checked to lie within the bounds of the stack, above
+/// the current stack pointer, before it's dereferenced. The bounds are either
+/// the `stack` range given or, if that's `None`, the bounds of the current
+/// thread's stack as reported by `pthread_getattr_np` (cached per thread), or
+/// by `pthread_get_stackaddr_np` and `pthread_get_stacksize_np` on macOS and
+/// iOS. As soon as a frame pointer fails that check, or doesn't point further
+/// up the stack than the previous one, the walk ends with the frames gathered
+/// so far rather than risking a fault.
+///
+/// With the `safe-deref` feature on Linux and Android every frame record is
+/// also checked to be readable before it's used, by reading it with
+/// `process_vm_readv` on this process rather than dereferencing it, so the
+/// walk can't fault even when the stack holds garbage or `stack` is wrong.
+/// That costs a system call per frame, and if the system call isn't permitted,
+/// e.g. by a seccomp filter, no frames are produced at all.
+///
+/// With the `split-stack` feature on Linux, threads whose stacks are split
+/// into segments by code built with `-fsplit-stack`, such as gccgo's runtime,
+/// are walked across those segments too, rather than stopping where the
+/// chain jumps from one to the next, which may well be downwards. The
+/// segments are listed with libgcc's `__splitstack_find` at the start of each
+/// walk, and a jump is only followed to a segment further out than the
+/// current one. The first walk looks `__splitstack_find` up with `dlsym`,
+/// which is not async-signal-safe, so callers sampling from within a signal
+/// handler should walk once beforehand.
+///
+/// Note that looking up the current thread's stack bounds for the first time
+/// on a thread is not async-signal-safe, so callers sampling from within a
+/// signal handler should either pass explicit bounds or make sure this function
+/// has already been called on each thread that may be interrupted.
+///
+/// This is currently only implemented for x86_64 and aarch64 on non-Windows
+/// platforms, when built with Rust 1.59 or later, and the closure is never
+/// called elsewhere. Without the `std` feature the stack bounds can't be looked
+/// up, so none are walked unless `stack` is given.
+///
+/// # Safety
+///
+/// If `stack` is given it must lie entirely within readable memory, unless the
+/// `safe-deref` feature is enabled on Linux or Android.
+///
+/// # Panics
+///
+/// See information on `trace` for caveats on `cb` panicking.
+pub unsafe fn trace_frame_pointers<F: FnMut(&Frame) -> bool>(
+    mut cb: F,
+    stack: Option<Range<usize>>,
+) {
+    trace_imp_frame_pointers(&mut cb, stack)
+}
+
+/// Returns whether this platform's unwinder is around to capture backtraces
+/// with.
+///
+/// This is always `true`, except on platforms without any unwinder, where
+/// every trace visits zero frames, and with the `dynamic-unwind` feature on
+/// Linux and Android. That feature has `_Unwind_Backtrace` and the other
+/// functions of the unwinder looked up with `dlsym` at runtime rather than
+/// linked against, from whatever is loaded already or else from the first of
+/// `libgcc_s.so.1`, `libunwind.so.1` and `libunwind.so.8` which can be
+/// opened, so that the same binary runs on systems without any of them. If
+/// none is found this returns `false`, and `trace` and friends visit zero
+/// frames, while `trace_frame_pointers` still works. (Unwinding panics need
+/// `_Unwind_Resume` from the unwinder regardless, so such a binary is built
+/// with `panic = "abort"`.)
+///
+/// The lookup is done by the first call to this or the first trace, and isn't
+/// async-signal-safe, so callers which trace from signal handlers should call
+/// this once beforehand. After that this is async-signal-safe.
+pub fn unwinder_available() -> bool {
+    available_imp()
+}
+
+/// Returns the value of the current thread's thread pointer register.
+///
+/// This is the base of the thread's TLS block: `fs_base` on x86_64 Linux,
+/// `tpidr_el0` on aarch64, and so on. Unlike a thread ID it's never reused
+/// while the thread is alive and reading it is a single instruction, which
+/// makes it a cheap per-thread key, e.g. for attributing allocations in a
+/// heap profiler. This is async-signal-safe.
+///
+/// Returns 0 on architectures where the thread pointer isn't known, and when
+/// built with a Rust older than 1.59, which can't read it without `asm!`.
+#[inline]
+pub fn current_thread_pointer() -> usize {
+    let tp: usize;
+    cfg_if::cfg_if! {
+        if #[cfg(any(miri, backtrace_no_asm))] {
+            tp = 0;
+        } else if #[cfg(all(target_arch = "x86_64", windows))] {
+            // The TEB's self pointer.
+            unsafe {
+                core::arch::asm!(
+                    "mov {}, gs:[0x30]",
+                    out(reg) tp,
+                    options(nostack, readonly, preserves_flags),
+                );
+            }
+        } else if #[cfg(all(target_arch = "x86_64", any(target_os = "macos", target_os = "ios")))] {
+            unsafe {
+                core::arch::asm!(
+                    "mov {}, gs:[0]",
+                    out(reg) tp,
+                    options(nostack, readonly, preserves_flags),
+                );
+            }
+        } else if #[cfg(target_arch = "x86_64")] {
+            // The TCB starts with a pointer to itself, which is `fs_base`.
+            unsafe {
+                core::arch::asm!(
+                    "mov {}, fs:[0]",
+                    out(reg) tp,
+                    options(nostack, readonly, preserves_flags),
+                );
+            }
+        } else if #[cfg(all(target_arch = "aarch64", any(target_os = "macos", target_os = "ios")))] {
+            unsafe {
+                core::arch::asm!(
+                    "mrs {}, tpidrro_el0",
+                    out(reg) tp,
+                    options(nomem, nostack, preserves_flags),
+                );
+            }
+        } else if #[cfg(all(target_arch = "aarch64", windows))] {
+            unsafe {
+                core::arch::asm!(
+                    "mov {}, x18",
+                    out(reg) tp,
+                    options(nomem, nostack, preserves_flags),
+                );
+            }
+        } else if #[cfg(target_arch = "aarch64")] {
+            unsafe {
+                core::arch::asm!(
+                    "mrs {}, tpidr_el0",
+                    out(reg) tp,
+                    options(nomem, nostack, preserves_flags),
+                );
+            }
+        } else if #[cfg(all(target_arch = "arm", not(windows)))] {
+            unsafe {
+                core::arch::asm!(
+                    "mrc p15, 0, {}, c13, c0, 3",
+                    out(reg) tp,
+                    options(nomem, nostack, preserves_flags),
+                );
+            }
+        } else if #[cfg(any(target_arch = "riscv64", target_arch = "riscv32"))] {
+            unsafe {
+                core::arch::asm!(
+                    "mv {}, tp",
+                    out(reg) tp,
+                    options(nomem, nostack, preserves_flags),
+                );
+            }
+        } else {
+            tp = 0;
+        }
+    }
+    tp
+}
+
 /// A trait representing one frame of a backtrace, yielded to the `trace`
 /// function of this crate.
 ///
@@ -112,6 +1610,14 @@ impl Frame {
     ///
     /// The returned value can sometimes be used if `backtrace::resolve` failed
     /// on the `ip` given above.
+    ///
+    /// With `_Unwind_Backtrace` this is looked up on each call, which isn't
+    /// free: with `_Unwind_FindEnclosingFunction`, or on macOS and iOS, where
+    /// that isn't reliable, in the `LC_FUNCTION_STARTS` load command of the
+    /// frame's image. Cloning a frame doesn't look it up either, so clones
+    /// that only ever have their `ip` read (e.g. in a sampling profiler) never
+    /// pay for it, while a clone still returns the same value here as the
+    /// frame it was cloned from.
     pub fn symbol_address(&self) -> *mut c_void {
         self.inner.symbol_address()
     }
@@ -120,6 +1626,275 @@ impl Frame {
     pub fn module_base_address(&self) -> Option<*mut c_void> {
         self.inner.module_base_address()
     }
+
+    /// Returns `ip` relative to the base address of the module it's in, i.e.
+    /// the address of the instruction within the object file, as expected by
+    /// e.g. the pprof format and offline symbolizers.
+    ///
+    /// Returns `None` if the module's base address isn't known, see
+    /// `module_base_address`.
+    pub fn module_relative_address(&self) -> Option<usize> {
+        let base = self.module_base_address()? as usize;
+        (self.ip() as usize).checked_sub(base)
+    }
+
+    /// Returns whether `ip` lies within the main executable, as opposed to a
+    /// shared library such as libc.
+    ///
+    /// This is far cheaper than symbolizing the frame, so it's useful for e.g.
+    /// filtering a profile down to the program's own code. The bounds of the
+    /// executable are looked up with `dl_iterate_phdr` the first time this is
+    /// called and cached from then on.
+    ///
+    /// On platforms without `dl_iterate_phdr` this always returns `false`.
+    ///
+    /// # Required features
+    ///
+    /// This function requires the `std` feature of the `backtrace` crate to be
+    /// enabled, and the `std` feature is enabled by default.
+    #[cfg(feature = "std")]
+    pub fn is_in_main_executable(&self) -> bool {
+        crate::modules::in_main_executable(self.ip() as usize)
+    }
+
+    /// Returns the permissions of the loaded segment `ip` lies in, as found
+    /// with `dl_iterate_phdr`.
+    ///
+    /// The `ip` of a genuine frame is in an executable segment, so a frame
+    /// whose `ip` isn't is almost certainly bogus, e.g. the result of an
+    /// unwinder walking into data. This makes it possible to validate the
+    /// frames produced by a fast unwinder such as `trace_frame_pointers`.
+    ///
+    /// Returns `None` if `ip` isn't in any loaded module, which is also
+    /// suspicious, unless it's in code generated at runtime. On platforms
+    /// without `dl_iterate_phdr` this always returns `None`.
+    ///
+    /// # Required features
+    ///
+    /// This function requires the `std` feature of the `backtrace` crate to be
+    /// enabled, and the `std` feature is enabled by default.
+    #[cfg(feature = "std")]
+    pub fn segment_flags(&self) -> Option<crate::SegmentFlags> {
+        crate::modules::segment_flags(self.ip() as usize)
+    }
+
+    /// Returns whether this frame is in a PLT stub, the trampolines through
+    /// which calls to functions in other modules are made.
+    ///
+    /// A profile of code calling into shared libraries is littered with
+    /// these frames, which say nothing the frame of the function being called
+    /// doesn't, so profilers will usually want to leave them out. A frame is
+    /// only reported as a stub if its `ip` lies within one of the `.plt`,
+    /// `.plt.sec`, `.plt.got`, `.plt.bnd` or `.iplt` sections of its module,
+    /// as found in the module's section headers, which are read from its file
+    /// the first time one of its frames is looked at. Other thunks can't be
+    /// told apart from ordinary functions without symbolizing the frame, so
+    /// aren't reported.
+    ///
+    /// As with symbolization, the `ip` of a frame which wasn't interrupted by a
+    /// signal is a return address and is looked up one byte back, so that a
+    /// call just before the start of the PLT isn't mistaken for a stub. This
+    /// returns `false` if the module's file can't be read or has no section
+    /// headers, and always on platforms without `dl_iterate_phdr`.
+    ///
+    /// # Required features
+    ///
+    /// This function requires the `std` feature of the `backtrace` crate to be
+    /// enabled, and the `std` feature is enabled by default.
+    #[cfg(feature = "std")]
+    pub fn is_thunk(&self) -> bool {
+        let ip = self.ip() as usize;
+        if ip == 0 {
+            return false;
+        }
+        let ip = if self.ip_is_exact() { ip } else { ip - 1 };
+        crate::modules::in_plt(ip)
+    }
+
+    /// Returns the `_Unwind_Context` this frame was read from, for calling
+    /// the parts of the libunwind API this crate doesn't wrap, such as
+    /// `_Unwind_GetGR`.
+    ///
+    /// This is only available for frames passed to the closure of `trace` and
+    /// `trace_unsynchronized` on platforms using `_Unwind_Backtrace`. `None` is
+    /// returned for frames which have been cloned, frames produced by any
+    /// other unwinder, and on all other platforms.
+    ///
+    /// The pointer is typed as `c_void` so it can be cast to whichever
+    /// `_Unwind_Context` bindings are in use.
+    ///
+    /// # Safety
+    ///
+    /// The context belongs to the unwinder and is only valid for the duration
+    /// of the call to the closure which this frame was passed to: using it
+    /// after the closure returns, or from another thread, is undefined
+    /// behavior. Modifying it (e.g. with `_Unwind_SetGR`) changes how the
+    /// rest of the stack is unwound and must be done with equal care.
+    pub unsafe fn raw_unwind_context(&self) -> Option<*mut c_void> {
+        self.inner.raw_unwind_context()
+    }
+
+    /// Returns the language-specific data area (LSDA) of this frame's
+    /// function, which describes the cleanups and `catch` handlers, if any,
+    /// which run when an exception or panic unwinds through it.
+    ///
+    /// Functions with neither have no LSDA, so this can be used to tell which
+    /// frames take part in unwinding. The data itself is in the format of the
+    /// function's personality routine, e.g. the `gcc_except_table` format
+    /// used by Rust and C++.
+    ///
+    /// This is only known to the external unwinder, i.e. for frames passed to
+    /// the closures of `trace_unsynchronized_external_api` and friends with
+    /// the `llvm-unwind` or `nongnu-unwind` feature. `None` is returned for
+    /// functions without an LSDA and for frames from any other unwinder.
+    pub fn lsda(&self) -> Option<*const c_void> {
+        self.inner.lsda()
+    }
+
+    /// Returns the address this frame will return to, i.e. the `ip` of the
+    /// frame which called it, without moving on to that frame.
+    ///
+    /// Where `ip` is the instruction this frame is currently executing (or,
+    /// for every frame but the innermost, the instruction after its call to
+    /// the next frame in), this is the instruction after the call *into* this
+    /// frame. It's the same value the next frame passed to the closure will
+    /// report as its `ip`, which makes it possible to e.g. spot tail calls or
+    /// PLT stubs while looking at a single frame.
+    ///
+    /// This is only known to the external unwinder, i.e. for frames passed to
+    /// the closures of `trace_unsynchronized_external_api` and friends with
+    /// the `llvm-unwind` or `nongnu-unwind` feature, which has already stepped
+    /// to the caller by the time the frame is seen. The default unwinder
+    /// drives the walk itself and only shows one frame at a time, so `None` is
+    /// returned for its frames, as well as for the outermost frame and any
+    /// frame whose caller couldn't be unwound to.
+    pub fn caller_ip(&self) -> Option<usize> {
+        self.inner.caller_ip()
+    }
+
+    /// Returns what the unwind tables say about this frame's function, as
+    /// libunwind's `unw_get_proc_info` reports it.
+    ///
+    /// This is meant for tools which check the unwinder against their own
+    /// reading of the CFI, or otherwise want more of the unwind table
+    /// metadata than `symbol_address` and `lsda` give. See `ProcInfo` for
+    /// what each field holds.
+    ///
+    /// This is only known to the external unwinder, i.e. for frames passed to
+    /// the closures of `trace_unsynchronized_external_api` and friends with
+    /// the `llvm-unwind` or `nongnu-unwind` feature, and is kept when such a
+    /// frame is cloned. `None` is returned for frames the unwinder found no
+    /// unwind info for, such as signal frames, and for frames from any other
+    /// unwinder.
+    pub fn proc_info(&self) -> Option<ProcInfo> {
+        self.inner.proc_info()
+    }
+
+    /// Returns how many bytes of stack this frame takes up, i.e. the
+    /// distance from its CFA, where the stack pointer was just before it was
+    /// called, down to the CFA of the frame it called.
+    ///
+    /// This is meant for finding out how much stack each function uses, e.g.
+    /// to size thread stacks or spot functions with large locals. For the
+    /// innermost frame it's measured down to its current `sp`.
+    ///
+    /// It takes the next frame out to work out, so it's only known to the
+    /// external unwinder, i.e. for frames passed to the closures of
+    /// `trace_unsynchronized_external_api` and friends with the `llvm-unwind`
+    /// or `nongnu-unwind` feature, along with `caller_ip`, and is kept when
+    /// such a frame is cloned. `None` is returned for the outermost frame,
+    /// for any frame whose caller couldn't be unwound to or whose caller's
+    /// stack pointer is below its own, e.g. when a signal handler ran on an
+    /// alternate stack, and for frames from any other unwinder.
+    pub fn frame_size(&self) -> Option<usize> {
+        self.inner.frame_size()
+    }
+
+    /// Returns the general purpose registers of this frame, all at once, as
+    /// for writing out the thread contexts of a minidump.
+    ///
+    /// The external unwinder, i.e. for frames passed to the closure of
+    /// `trace_unsynchronized_external_api_with_registers` with the
+    /// `llvm-unwind` or `nongnu-unwind` feature, reads every register it can
+    /// recover with `unw_get_reg`. Which those are depends on the unwinder:
+    /// only the innermost frame's are all known, and further out
+    /// `nongnu-unwind` can't tell what a caller-saved register held, while
+    /// `llvm-unwind` reports what was left in it. Since the cursor has moved
+    /// on by the time the closure sees a frame, the registers are read for
+    /// every frame of that trace, and are only available while the frame is
+    /// being passed to the closure. Frames of the other external traces don't
+    /// have them.
+    ///
+    /// Frames from `_Unwind_Backtrace`, while they're being passed to the
+    /// closure of `trace`, have only their stack pointer, instruction pointer
+    /// and callee-saved registers filled in, as those are all `_Unwind_GetGR`
+    /// can safely read.
+    ///
+    /// Either way, the `valid` mask of the register set returned tells which
+    /// registers were recovered, and the rest are zero. `None` is returned for
+    /// frames which have been cloned, frames from any other unwinder, and on
+    /// architectures other than x86_64 and aarch64.
+    pub fn capture_registers(&self) -> Option<crate::RegisterContext> {
+        self.inner.capture_registers()
+    }
+
+    /// Returns how many bytes into its function this frame's `ip` is, i.e.
+    /// the `0x1a` of the `func+0x1a` which `perf` shows, for attributing
+    /// samples to single instructions.
+    ///
+    /// The start of the function is taken from `proc_info` where the
+    /// unwinder knows it, and otherwise looked up with
+    /// `_Unwind_FindEnclosingFunction`, or on macOS and iOS in the image's
+    /// `LC_FUNCTION_STARTS`, as for `symbol_address`. Nothing is symbolized.
+    /// Like `ip`, the offset is that of the return address for every frame
+    /// but the one a signal interrupted, so it's that of the instruction
+    /// after the call.
+    ///
+    /// `None` is returned if the start of the function isn't known, as for
+    /// frames found by walking frame pointers, frames from any unwinder other
+    /// than libunwind's, or an `ip` in no function the unwinder knows of.
+    pub fn offset_in_function(&self) -> Option<usize> {
+        let start = self.inner.function_start()?;
+        (self.ip() as usize).checked_sub(start)
+    }
+
+    // Whether `ip` is the address of the instruction the frame was
+    // interrupted at, as for the frame a signal interrupted, rather than a
+    // return address, and so shouldn't be adjusted before resolving it.
+    pub(crate) fn ip_is_exact(&self) -> bool {
+        self.inner.ip_is_exact()
+    }
+}
+
+/// Unwind table information about the function a frame is in, as returned by
+/// `Frame::proc_info`.
+///
+/// This mirrors libunwind's `unw_proc_info_t`, which both the `llvm-unwind`
+/// and `nongnu-unwind` unwinders fill in. Addresses are 0 where there's
+/// nothing to point at.
+#[non_exhaustive]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct ProcInfo {
+    /// The address of the first instruction of the function.
+    pub start_ip: usize,
+    /// The address just past the last instruction of the function.
+    pub end_ip: usize,
+    /// The function's language-specific data area, as `Frame::lsda` returns.
+    pub lsda: usize,
+    /// The function's personality routine, which interprets its LSDA while
+    /// unwinding, e.g. `rust_eh_personality`.
+    pub handler: usize,
+    /// The global pointer the function expects, on architectures which have
+    /// one (e.g. IA-64). It's 0 on x86_64 and AArch64.
+    pub gp: usize,
+    /// Flags describing the function. No flags are currently defined by
+    /// either unwinder for x86_64 or AArch64, so it's 0 there.
+    pub flags: usize,
+    /// The format of the unwind info the unwinder found. For `nongnu-unwind`
+    /// this is one of libunwind's `UNW_INFO_FORMAT_*` values, e.g.
+    /// `UNW_INFO_FORMAT_TABLE` (1) for `.eh_frame`, and for `llvm-unwind` it's
+    /// the compact unwind encoding of the function, or 0 if it has none.
+    pub format: u32,
 }
 
 impl fmt::Debug for Frame {
@@ -131,12 +1906,28 @@ impl fmt::Debug for Frame {
     }
 }
 
+mod options;
+#[cfg(feature = "std")]
+mod select;
+
+pub use self::options::TraceOptions;
+
 cfg_if::cfg_if! {
     // This needs to come first, to ensure that
     // Miri takes priority over the host platform
     if #[cfg(miri)] {
         pub(crate) mod miri;
         use self::miri::trace as trace_imp;
+        use self::miri::trace_status as trace_imp_status;
+        use self::miri::trace_debug as trace_imp_debug;
+        use self::miri::available as available_imp;
+        #[cfg(feature = "std")]
+        const FRAME_POINTERS_SUPPORTED: bool = false;
+        #[cfg(feature = "std")]
+        const LIBUNWIND_SUPPORTED: bool = false;
+        #[cfg(feature = "std")]
+        const COMPACT_UNWIND_SUPPORTED: bool = false;
+        use self::miri::trace_frame_pointers as trace_imp_frame_pointers;
         pub(crate) use self::miri::Frame as FrameImp;
     } else if #[cfg(
         any(
@@ -152,19 +1943,185 @@ cfg_if::cfg_if! {
         )
     )] {
         mod libunwind;
+        mod frame_pointer;
+        #[cfg(any(target_os = "macos", target_os = "ios"))]
+        mod function_starts;
+        #[cfg(all(
+            feature = "std",
+            any(target_os = "macos", target_os = "ios"),
+            any(target_arch = "x86_64", target_arch = "aarch64"),
+            not(backtrace_no_asm),
+        ))]
+        mod compact_unwind;
+        #[cfg(all(
+            any(target_os = "linux", target_os = "android"),
+            any(target_arch = "x86_64", target_arch = "aarch64"),
+            not(backtrace_no_asm),
+        ))]
+        mod altstack;
+        #[cfg(all(
+            feature = "std",
+            any(target_os = "linux", target_os = "android"),
+            any(target_arch = "x86_64", target_arch = "aarch64"),
+        ))]
+        mod timeout;
+        #[cfg(all(
+            feature = "remote-unwind",
+            any(target_os = "linux", target_os = "android"),
+            any(target_arch = "x86_64", target_arch = "aarch64"),
+        ))]
+        mod remote;
+        #[cfg(all(
+            feature = "remote-unwind",
+            any(target_os = "linux", target_os = "android"),
+            any(target_arch = "x86_64", target_arch = "aarch64"),
+        ))]
+        pub use self::remote::{
+            trace_remote, RemoteAddressSpace, RemoteAddressSpaceBuilder, RemoteUnwindTable,
+        };
         use self::libunwind::trace as trace_imp;
+        use self::libunwind::trace_status as trace_imp_status;
+        use self::libunwind::trace_debug as trace_imp_debug;
+        use self::libunwind::available as available_imp;
+        #[cfg(feature = "std")]
+        const FRAME_POINTERS_SUPPORTED: bool = cfg!(all(
+            not(backtrace_no_asm),
+            any(target_arch = "x86_64", target_arch = "aarch64"),
+        ));
+        #[cfg(feature = "std")]
+        const LIBUNWIND_SUPPORTED: bool = true;
+        #[cfg(feature = "std")]
+        const COMPACT_UNWIND_SUPPORTED: bool = cfg!(all(
+            any(target_os = "macos", target_os = "ios"),
+            any(target_arch = "x86_64", target_arch = "aarch64"),
+            not(backtrace_no_asm),
+        ));
+        use self::frame_pointer::trace as trace_imp_frame_pointers;
+        #[cfg(all(
+            feature = "std",
+            any(target_os = "macos", target_os = "ios"),
+            any(target_arch = "x86_64", target_arch = "aarch64"),
+            not(backtrace_no_asm),
+        ))]
+        use self::compact_unwind::trace as trace_imp_compact_unwind;
         #[cfg(any(feature = "llvm-unwind", feature = "nongnu-unwind"))]
         use self::libunwind::trace_external_api as trace_imp_external_api;
+        #[cfg(any(feature = "llvm-unwind", feature = "nongnu-unwind"))]
+        use self::libunwind::trace_external_api_with_registers as trace_imp_external_api_with_registers;
+        #[cfg(any(feature = "llvm-unwind", feature = "nongnu-unwind"))]
+        use self::libunwind::trace_external_api_with_scratch as trace_imp_external_api_with_scratch;
+        #[cfg(any(feature = "llvm-unwind", feature = "nongnu-unwind"))]
+        pub use self::libunwind::{
+            configure_unwind_caching, CachingPolicy, UnwContext, UnwCursor, UnwindError,
+        };
         pub(crate) use self::libunwind::Frame as FrameImp;
     } else if #[cfg(all(windows, not(target_vendor = "uwp")))] {
         mod dbghelp;
         use self::dbghelp::trace as trace_imp;
+        use self::dbghelp::trace_status as trace_imp_status;
+        use self::dbghelp::trace_debug as trace_imp_debug;
+        use self::dbghelp::available as available_imp;
+        #[cfg(feature = "std")]
+        const FRAME_POINTERS_SUPPORTED: bool = false;
+        #[cfg(feature = "std")]
+        const LIBUNWIND_SUPPORTED: bool = false;
+        #[cfg(feature = "std")]
+        const COMPACT_UNWIND_SUPPORTED: bool = false;
+        use self::dbghelp::trace_frame_pointers as trace_imp_frame_pointers;
         pub(crate) use self::dbghelp::Frame as FrameImp;
         #[cfg(target_env = "msvc")] // only used in dbghelp symbolize
         pub(crate) use self::dbghelp::StackFrame;
     } else {
         mod noop;
         use self::noop::trace as trace_imp;
+        use self::noop::trace_status as trace_imp_status;
+        use self::noop::trace_debug as trace_imp_debug;
+        use self::noop::available as available_imp;
+        #[cfg(feature = "std")]
+        const FRAME_POINTERS_SUPPORTED: bool = false;
+        #[cfg(feature = "std")]
+        const LIBUNWIND_SUPPORTED: bool = false;
+        #[cfg(feature = "std")]
+        const COMPACT_UNWIND_SUPPORTED: bool = false;
+        use self::noop::trace_frame_pointers as trace_imp_frame_pointers;
+        #[cfg(any(feature = "llvm-unwind", feature = "nongnu-unwind"))]
+        use self::noop::trace_external_api as trace_imp_external_api;
+        #[cfg(any(feature = "llvm-unwind", feature = "nongnu-unwind"))]
+        use self::noop::trace_external_api_with_registers as trace_imp_external_api_with_registers;
+        #[cfg(any(feature = "llvm-unwind", feature = "nongnu-unwind"))]
+        use self::noop::trace_external_api_with_scratch as trace_imp_external_api_with_scratch;
+        #[cfg(any(feature = "llvm-unwind", feature = "nongnu-unwind"))]
+        pub use self::noop::{
+            configure_unwind_caching, CachingPolicy, UnwContext, UnwCursor, UnwindError,
+        };
         pub(crate) use self::noop::Frame as FrameImp;
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::RecentFrames;
+
+    #[test]
+    fn recent_frames_detects_repeats() {
+        let mut recent = RecentFrames::new();
+        for i in 1..=4 {
+            assert!(recent.insert(i, i * 16));
+        }
+        assert!(!recent.insert(1, 16));
+        assert!(!recent.insert(4, 64));
+        // Same ip on a different stack frame is plain recursion.
+        assert!(recent.insert(4, 80));
+        // Only the last four pairs are remembered.
+        assert!(recent.insert(1, 16));
+    }
+
+    // Has the external unwinder take over from the default one as if the
+    // latter had given up on the caller of `handoff`, which is then the first
+    // frame the external unwinder has to pass on.
+    #[test]
+    #[cfg(all(
+        feature = "std",
+        feature = "fallback-unwind",
+        any(feature = "llvm-unwind", feature = "nongnu-unwind")
+    ))]
+    fn fallback_resumes_at_handoff_frame() {
+        use super::{resume_external_api, trace_unsynchronized_status};
+        use std::prelude::v1::*;
+
+        #[inline(never)]
+        fn default_frames() -> Vec<(usize, usize, usize)> {
+            let mut frames = Vec::new();
+            unsafe {
+                trace_unsynchronized_status(|frame| {
+                    let symbol_address = frame.symbol_address() as usize;
+                    frames.push((frame.ip() as usize, frame.sp() as usize, symbol_address));
+                    true
+                });
+            }
+            frames
+        }
+
+        #[inline(never)]
+        fn handoff() -> (Vec<usize>, Vec<usize>) {
+            let default = default_frames();
+            let this = default
+                .iter()
+                .position(|&(_, _, symbol_address)| symbol_address == handoff as *const () as usize)
+                .unwrap();
+            let mut resumed = Vec::new();
+            unsafe {
+                resume_external_api(default[this].1, &mut |frame| {
+                    resumed.push(frame.ip() as usize);
+                    true
+                });
+            }
+            let outer = default[this + 1..].iter().map(|frame| frame.0).collect();
+            (outer, resumed)
+        }
+
+        let (outer, resumed) = handoff();
+        assert!(outer.len() > 1 && resumed.len() > 1);
+        assert_eq!(resumed[..2], outer[..2], "{:x?} {:x?}", outer, resumed);
+    }
+}
@@ -1,11 +1,145 @@
 //! Empty implementation of unwinding used when no other implementation is
-//! appropriate.
+//! appropriate, such as on `wasm32-unknown-unknown`.
+//!
+//! Every trace visits zero frames, so downstream crates still build on these
+//! targets even though no backtraces can be captured.
 
 use core::ffi::c_void;
 
 #[inline(always)]
 pub fn trace(_cb: &mut dyn FnMut(&super::Frame) -> bool) {}
 
+#[inline(always)]
+pub fn trace_status(_cb: &mut dyn FnMut(&super::Frame) -> bool) -> super::TraceOutcome {
+    super::TraceOutcome::Complete
+}
+
+#[inline(always)]
+pub fn trace_debug(
+    _cb: &mut dyn FnMut(&super::Frame) -> bool,
+    _on_reason: &mut dyn FnMut(Option<&super::Frame>, i32),
+) -> super::TraceOutcome {
+    super::TraceOutcome::Complete
+}
+
+pub fn available() -> bool {
+    false
+}
+
+#[inline(always)]
+pub fn trace_frame_pointers(
+    _cb: &mut dyn FnMut(&super::Frame) -> bool,
+    _stack: Option<core::ops::Range<usize>>,
+) {
+}
+
+#[cfg(any(feature = "llvm-unwind", feature = "nongnu-unwind"))]
+#[inline(always)]
+pub fn trace_external_api<F: FnMut(&super::Frame) -> bool>(
+    _cb: F,
+    _signal_frame: bool,
+) -> Result<(), UnwindError> {
+    Ok(())
+}
+
+#[cfg(any(feature = "llvm-unwind", feature = "nongnu-unwind"))]
+#[inline(always)]
+pub fn trace_external_api_with_registers<F: FnMut(&super::Frame) -> bool>(
+    _cb: F,
+    _signal_frame: bool,
+) -> Result<(), UnwindError> {
+    Ok(())
+}
+
+#[cfg(any(feature = "llvm-unwind", feature = "nongnu-unwind"))]
+#[inline(always)]
+pub fn trace_external_api_with_scratch<F: FnMut(&super::Frame) -> bool>(
+    _context: &mut UnwContext,
+    _cursor: &mut UnwCursor,
+    _cb: F,
+    _signal_frame: bool,
+) -> Result<(), UnwindError> {
+    Ok(())
+}
+
+/// Storage for the register state the external unwinder starts from.
+///
+/// There's no external unwinder on this platform, so this holds nothing.
+#[cfg(any(feature = "llvm-unwind", feature = "nongnu-unwind"))]
+pub struct UnwContext(());
+
+#[cfg(any(feature = "llvm-unwind", feature = "nongnu-unwind"))]
+impl UnwContext {
+    /// Creates an empty context.
+    pub fn zeroed() -> Self {
+        UnwContext(())
+    }
+}
+
+/// Storage for the external unwinder's position in the stack.
+///
+/// There's no external unwinder on this platform, so this holds nothing.
+#[cfg(any(feature = "llvm-unwind", feature = "nongnu-unwind"))]
+pub struct UnwCursor(());
+
+#[cfg(any(feature = "llvm-unwind", feature = "nongnu-unwind"))]
+impl UnwCursor {
+    /// Creates an empty cursor.
+    pub fn zeroed() -> Self {
+        UnwCursor(())
+    }
+}
+
+/// How the external unwinder caches what it learns about the code it
+/// unwinds through, as set by `configure_unwind_caching`.
+#[cfg(any(feature = "llvm-unwind", feature = "nongnu-unwind"))]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum CachingPolicy {
+    /// Nothing is cached.
+    None,
+    /// One cache is shared by all threads.
+    Global,
+    /// Each thread has a cache of its own.
+    PerThread,
+}
+
+/// Sets the caching policy of the external unwinder.
+///
+/// There's no external unwinder on this platform, so this does nothing.
+#[cfg(any(feature = "llvm-unwind", feature = "nongnu-unwind"))]
+pub fn configure_unwind_caching(_policy: CachingPolicy) -> Result<(), UnwindError> {
+    Ok(())
+}
+
+/// An error reported by the external unwinding library.
+///
+/// There's no external unwinder on this platform, so this can never be
+/// constructed.
+#[cfg(any(feature = "llvm-unwind", feature = "nongnu-unwind"))]
+#[derive(Debug)]
+pub enum UnwindError {}
+
+#[cfg(any(feature = "llvm-unwind", feature = "nongnu-unwind"))]
+impl UnwindError {
+    /// Returns the raw error code returned by libunwind.
+    pub fn code(&self) -> i32 {
+        match *self {}
+    }
+}
+
+#[cfg(any(feature = "llvm-unwind", feature = "nongnu-unwind"))]
+impl core::fmt::Display for UnwindError {
+    fn fmt(&self, _f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        match *self {}
+    }
+}
+
+#[cfg(all(
+    feature = "std",
+    any(feature = "llvm-unwind", feature = "nongnu-unwind")
+))]
+impl std::error::Error for UnwindError {}
+
 #[derive(Clone)]
 pub struct Frame;
 
@@ -25,4 +159,36 @@ impl Frame {
     pub fn module_base_address(&self) -> Option<*mut c_void> {
         None
     }
+
+    pub fn raw_unwind_context(&self) -> Option<*mut c_void> {
+        None
+    }
+
+    pub fn lsda(&self) -> Option<*const c_void> {
+        None
+    }
+
+    pub fn caller_ip(&self) -> Option<usize> {
+        None
+    }
+
+    pub fn proc_info(&self) -> Option<super::ProcInfo> {
+        None
+    }
+
+    pub fn frame_size(&self) -> Option<usize> {
+        None
+    }
+
+    pub fn capture_registers(&self) -> Option<crate::RegisterContext> {
+        None
+    }
+
+    pub fn function_start(&self) -> Option<usize> {
+        None
+    }
+
+    pub fn ip_is_exact(&self) -> bool {
+        false
+    }
 }
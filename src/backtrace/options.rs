@@ -0,0 +1,221 @@
+//! A builder gathering the options of a trace, so that each new one doesn't
+//! need its own set of `trace_*` functions.
+
+use super::{trace_imp, Frame, FrameOrder, RecentFrames};
+use alloc::vec::Vec;
+
+/// Options for capturing a backtrace, combined as needed and then passed
+/// frames with `capture`.
+///
+/// `trace` and its variants each offer one of these options. This gathers
+/// them so they can be used together, e.g. to skip this crate's caller's own
+/// frames, stop at 64 frames, and give up on a corrupted stack all at once.
+/// The options are applied to frames as the unwinder produces them, innermost
+/// first:
+///
+/// 1. With `validate`, the trace ends at the first frame which looks bogus.
+/// 2. The first `skip` frames are passed over.
+/// 3. Up to `max_frames` of the frames which remain are passed to the
+///    closure, in the order picked with `order`.
+///
+/// # Example
+///
+/// ```
+/// use backtrace::TraceOptions;
+///
+/// # #[cfg(feature = "std")] {
+/// let mut ips = Vec::new();
+/// TraceOptions::new()
+///     .skip(1)
+///     .max_frames(64)
+///     .validate(true)
+///     .capture(|frame| {
+///         ips.push(frame.ip());
+///         true
+///     });
+/// assert!(ips.len() <= 64);
+/// # }
+/// ```
+#[derive(Clone, Debug, Default)]
+pub struct TraceOptions {
+    skip: usize,
+    max_frames: Option<usize>,
+    validate: bool,
+    #[cfg(any(feature = "llvm-unwind", feature = "nongnu-unwind"))]
+    signal_frame: bool,
+    order: FrameOrder,
+}
+
+impl TraceOptions {
+    /// Creates options which capture every frame, innermost first, like
+    /// `trace` does.
+    pub fn new() -> TraceOptions {
+        TraceOptions::default()
+    }
+
+    /// Passes over the `skip` innermost frames, which are typically those of
+    /// the function capturing the backtrace and its helpers.
+    pub fn skip(mut self, skip: usize) -> TraceOptions {
+        self.skip = skip;
+        self
+    }
+
+    /// Passes at most `max_frames` frames to the closure, after any skipped
+    /// ones, leaving out the outermost frames of deeper stacks.
+    ///
+    /// `MAX_RECOMMENDED_FRAMES` is a reasonable limit.
+    pub fn max_frames(mut self, max_frames: usize) -> TraceOptions {
+        self.max_frames = Some(max_frames);
+        self
+    }
+
+    /// Ends the trace at the first frame which looks bogus, rather than
+    /// passing it and whatever the unwinder makes of the stack beyond it to
+    /// the closure.
+    ///
+    /// A frame is bogus if it repeats one of the few frames before it, as
+    /// `trace_detect_loops` checks for, or, with the `std` feature, if its
+    /// `ip` is in a loaded segment which isn't executable, as
+    /// `Frame::segment_flags` tells. Frames whose `ip` isn't in any loaded
+    /// segment are kept, as they may be in code generated at runtime.
+    pub fn validate(mut self, validate: bool) -> TraceOptions {
+        self.validate = validate;
+        self
+    }
+
+    /// Tells the unwinder that the innermost frame was interrupted by a
+    /// signal, as for `trace_unsynchronized_external_api`.
+    ///
+    /// Only the external unwinder takes this into account, so with it set
+    /// `capture` unwinds with the external unwinder rather than the default
+    /// one.
+    ///
+    /// # Required features
+    ///
+    /// This function requires the `llvm-unwind` or `nongnu-unwind` feature
+    /// of the `backtrace` crate to be enabled.
+    #[cfg(any(feature = "llvm-unwind", feature = "nongnu-unwind"))]
+    pub fn signal_frame(mut self, signal_frame: bool) -> TraceOptions {
+        self.signal_frame = signal_frame;
+        self
+    }
+
+    /// Passes frames to the closure in the given order, like
+    /// `trace_ordered`.
+    ///
+    /// `FrameOrder::OutermostFirst` needs the whole stack to be walked first,
+    /// so the frames are cloned into a `Vec` beforehand.
+    pub fn order(mut self, order: FrameOrder) -> TraceOptions {
+        self.order = order;
+        self
+    }
+
+    /// Captures a backtrace with these options, passing the frames to `cb`
+    /// until it returns `false`.
+    ///
+    /// Like `trace`, this takes the crate's global lock and honours the
+    /// `BACKTRACE_UNWINDER` environment variable.
+    ///
+    /// # Required features
+    ///
+    /// This function requires the `std` feature of the `backtrace` crate to be
+    /// enabled, and the `std` feature is enabled by default.
+    ///
+    /// # Panics
+    ///
+    /// See information on `trace` for caveats on `cb` panicking.
+    #[cfg(feature = "std")]
+    pub fn capture<F: FnMut(&Frame) -> bool>(&self, cb: F) {
+        let _guard = crate::lock::lock();
+        crate::modules::check_for_changes();
+        unsafe { self.capture_with(|f| super::select::trace(f), cb) }
+    }
+
+    /// Same as `capture`, only unsafe as it's unsynchronized.
+    ///
+    /// This function does not have synchronization guarantees but is
+    /// available when the `std` feature of this crate isn't compiled in. See
+    /// the `capture` function for more documentation.
+    ///
+    /// # Safety
+    ///
+    /// The same requirements as `trace_unsynchronized` apply.
+    ///
+    /// # Panics
+    ///
+    /// See information on `trace` for caveats on `cb` panicking.
+    pub unsafe fn capture_unsynchronized<F: FnMut(&Frame) -> bool>(&self, cb: F) {
+        self.capture_with(|f| trace_imp(f), cb)
+    }
+
+    // `unwind` walks the stack with whichever unwinder the caller picked.
+    unsafe fn capture_with<U, F>(&self, unwind: U, mut cb: F)
+    where
+        U: FnOnce(&mut dyn FnMut(&Frame) -> bool),
+        F: FnMut(&Frame) -> bool,
+    {
+        match self.order {
+            FrameOrder::InnermostFirst => self.walk(unwind, &mut cb),
+            FrameOrder::OutermostFirst => {
+                let mut frames = Vec::new();
+                self.walk(unwind, &mut |frame| {
+                    frames.push(frame.clone());
+                    true
+                });
+                for frame in frames.iter().rev() {
+                    if !cb(frame) {
+                        break;
+                    }
+                }
+            }
+        }
+    }
+
+    unsafe fn walk<U>(&self, unwind: U, cb: &mut dyn FnMut(&Frame) -> bool)
+    where
+        U: FnOnce(&mut dyn FnMut(&Frame) -> bool),
+    {
+        let mut recent = RecentFrames::new();
+        let mut skipped = 0;
+        let mut delivered = 0;
+        let max_frames = self.max_frames.unwrap_or(core::usize::MAX);
+        let mut each = |frame: &Frame| {
+            if self.validate && !is_plausible(&mut recent, frame) {
+                return false;
+            }
+            if skipped < self.skip {
+                skipped += 1;
+                return true;
+            }
+            if delivered >= max_frames {
+                return false;
+            }
+            delivered += 1;
+            cb(frame) && delivered < max_frames
+        };
+
+        #[cfg(any(feature = "llvm-unwind", feature = "nongnu-unwind"))]
+        {
+            if self.signal_frame {
+                // As with `trace`, there's no way to report an error, and the
+                // frames up until it have been delivered.
+                let _ = super::trace_imp_external_api(&mut each, true);
+                return;
+            }
+        }
+        unwind(&mut each)
+    }
+}
+
+fn is_plausible(recent: &mut RecentFrames, frame: &Frame) -> bool {
+    if !recent.insert(frame.ip() as usize, frame.sp() as usize) {
+        return false;
+    }
+    #[cfg(feature = "std")]
+    {
+        if let Some(flags) = frame.segment_flags() {
+            return flags.is_executable();
+        }
+    }
+    true
+}
@@ -0,0 +1,627 @@
+//! Unwinding a stack which isn't this thread's, through caller-provided
+//! accessors, with libunwind's remote API.
+//!
+//! libunwind reads everything it needs to unwind a remote stack through the
+//! callbacks of an address space made with `unw_create_addr_space`: memory,
+//! the registers of the innermost frame, and the unwind info covering each
+//! `ip`. The callbacks here forward to closures given to
+//! `RemoteAddressSpaceBuilder`, which are found through the `arg` pointer
+//! libunwind passes back to every callback, and which in turn hand the
+//! binary search table of an `.eh_frame_hdr` back to libunwind's own
+//! `dwarf_search_unwind_table` to find the FDE for an address.
+//!
+//! The remote API lives in the `libunwind-<arch>` library rather than in the
+//! local-only `libunwind` one the rest of the external unwinder links to.
+
+use super::libunwind::{UnwCursor, UnwProcInfo, UnwindError};
+use super::{Frame, FrameImp};
+use alloc::boxed::Box;
+use core::ffi::c_void;
+use core::fmt;
+use core::ptr;
+use libc::{c_char, c_int};
+
+const UNW_ENOMEM: c_int = 2;
+const UNW_EBADREG: c_int = 3;
+const UNW_EREADONLYREG: c_int = 4;
+const UNW_EINVAL: c_int = 8;
+const UNW_ENOINFO: c_int = 10;
+
+const UNW_INFO_FORMAT_REMOTE_TABLE: i32 = 2;
+
+#[cfg(target_arch = "x86_64")]
+mod arch {
+    // RIP and RSP.
+    pub const UNW_REG_IP: libc::c_int = 16;
+    pub const UNW_REG_SP: libc::c_int = 7;
+}
+
+#[cfg(target_arch = "aarch64")]
+mod arch {
+    // libunwind reports the link register X30 as the `ip`, and X31 is SP.
+    pub const UNW_REG_IP: libc::c_int = 30;
+    pub const UNW_REG_SP: libc::c_int = 31;
+}
+
+use self::arch::*;
+
+// `unw_accessors_t`. libunwind copies as much of it as it knows about, so the
+// callbacks added by versions newer than the one this was built against are
+// provided too, and harmlessly ignored by older ones.
+#[repr(C)]
+struct Accessors {
+    find_proc_info: unsafe extern "C" fn(
+        space: *mut c_void,
+        ip: usize,
+        info: *mut UnwProcInfo,
+        need_unwind_info: c_int,
+        arg: *mut c_void,
+    ) -> c_int,
+    put_unwind_info:
+        unsafe extern "C" fn(space: *mut c_void, info: *mut UnwProcInfo, arg: *mut c_void),
+    get_dyn_info_list_addr:
+        unsafe extern "C" fn(space: *mut c_void, addr: *mut usize, arg: *mut c_void) -> c_int,
+    access_mem: unsafe extern "C" fn(
+        space: *mut c_void,
+        addr: usize,
+        value: *mut usize,
+        write: c_int,
+        arg: *mut c_void,
+    ) -> c_int,
+    access_reg: unsafe extern "C" fn(
+        space: *mut c_void,
+        reg: c_int,
+        value: *mut usize,
+        write: c_int,
+        arg: *mut c_void,
+    ) -> c_int,
+    access_fpreg: unsafe extern "C" fn(
+        space: *mut c_void,
+        reg: c_int,
+        value: *mut c_void,
+        write: c_int,
+        arg: *mut c_void,
+    ) -> c_int,
+    resume:
+        unsafe extern "C" fn(space: *mut c_void, cursor: *mut UnwCursor, arg: *mut c_void) -> c_int,
+    get_proc_name: unsafe extern "C" fn(
+        space: *mut c_void,
+        addr: usize,
+        buf: *mut c_char,
+        len: usize,
+        offset: *mut usize,
+        arg: *mut c_void,
+    ) -> c_int,
+    get_proc_ip_range: unsafe extern "C" fn(
+        space: *mut c_void,
+        ip: usize,
+        start: *mut usize,
+        end: *mut usize,
+        arg: *mut c_void,
+    ) -> c_int,
+    get_elf_filename: unsafe extern "C" fn(
+        space: *mut c_void,
+        ip: usize,
+        buf: *mut c_char,
+        len: usize,
+        offset: *mut usize,
+        arg: *mut c_void,
+    ) -> c_int,
+}
+
+// `unw_dyn_info_t` describing a remote table, i.e. with the
+// `unw_dyn_remote_table_info_t` member of its union. This is the layout since
+// `load_offset` was added; older releases of libunwind, without it, aren't
+// supported.
+#[repr(C)]
+struct UnwDynInfo {
+    next: *mut UnwDynInfo,
+    prev: *mut UnwDynInfo,
+    start_ip: usize,
+    end_ip: usize,
+    gp: usize,
+    format: i32,
+    pad: i32,
+    load_offset: usize,
+    name_ptr: usize,
+    segbase: usize,
+    table_len: usize,
+    table_data: usize,
+}
+
+#[cfg(target_arch = "x86_64")]
+extern "C" {
+    #[link_name = "_Ux86_64_create_addr_space"]
+    fn unw_create_addr_space(accessors: *mut Accessors, byte_order: c_int) -> *mut c_void;
+    #[link_name = "_Ux86_64_destroy_addr_space"]
+    fn unw_destroy_addr_space(space: *mut c_void);
+    #[link_name = "_Ux86_64_init_remote"]
+    fn unw_init_remote(cursor: *mut UnwCursor, space: *mut c_void, arg: *mut c_void) -> c_int;
+    #[link_name = "_Ux86_64_step"]
+    fn unw_step(cursor: *mut UnwCursor) -> c_int;
+    #[link_name = "_Ux86_64_get_reg"]
+    fn unw_get_reg(cursor: *mut UnwCursor, reg: c_int, value: *mut usize) -> c_int;
+    #[link_name = "_Ux86_64_get_proc_info"]
+    fn unw_get_proc_info(cursor: *mut UnwCursor, info: *mut UnwProcInfo) -> c_int;
+    #[link_name = "_Ux86_64_dwarf_search_unwind_table"]
+    fn dwarf_search_unwind_table(
+        space: *mut c_void,
+        ip: usize,
+        table: *mut UnwDynInfo,
+        info: *mut UnwProcInfo,
+        need_unwind_info: c_int,
+        arg: *mut c_void,
+    ) -> c_int;
+}
+
+#[cfg(target_arch = "aarch64")]
+extern "C" {
+    #[link_name = "_Uaarch64_create_addr_space"]
+    fn unw_create_addr_space(accessors: *mut Accessors, byte_order: c_int) -> *mut c_void;
+    #[link_name = "_Uaarch64_destroy_addr_space"]
+    fn unw_destroy_addr_space(space: *mut c_void);
+    #[link_name = "_Uaarch64_init_remote"]
+    fn unw_init_remote(cursor: *mut UnwCursor, space: *mut c_void, arg: *mut c_void) -> c_int;
+    #[link_name = "_Uaarch64_step"]
+    fn unw_step(cursor: *mut UnwCursor) -> c_int;
+    #[link_name = "_Uaarch64_get_reg"]
+    fn unw_get_reg(cursor: *mut UnwCursor, reg: c_int, value: *mut usize) -> c_int;
+    #[link_name = "_Uaarch64_get_proc_info"]
+    fn unw_get_proc_info(cursor: *mut UnwCursor, info: *mut UnwProcInfo) -> c_int;
+    #[link_name = "_Uaarch64_dwarf_search_unwind_table"]
+    fn dwarf_search_unwind_table(
+        space: *mut c_void,
+        ip: usize,
+        table: *mut UnwDynInfo,
+        info: *mut UnwProcInfo,
+        need_unwind_info: c_int,
+        arg: *mut c_void,
+    ) -> c_int;
+}
+
+/// The `.eh_frame_hdr` binary search table covering a range of addresses of a
+/// remote address space, as returned by the `find_proc_info` callback of a
+/// `RemoteAddressSpace`.
+///
+/// Every address here is one in the remote address space, which libunwind
+/// reads through the `access_mem` callback.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct RemoteUnwindTable {
+    /// The start of the range of addresses the table covers, typically that
+    /// of the module's executable segment.
+    pub start_ip: usize,
+    /// The end of the range of addresses the table covers.
+    pub end_ip: usize,
+    /// The address of the `.eh_frame_hdr` section, which the table's entries
+    /// are relative to.
+    pub segbase: usize,
+    /// The address of the table itself, which follows the section's header
+    /// and encoded `eh_frame_ptr` and `fde_count` fields.
+    pub table_data: usize,
+    /// The length of the table in words, i.e. the number of FDEs for 64-bit
+    /// targets, whose entries are a pair of 32-bit offsets.
+    pub table_len: usize,
+}
+
+type FindProcInfo = dyn Fn(usize) -> Option<RemoteUnwindTable>;
+type AccessMem = dyn Fn(usize) -> Option<usize>;
+type AccessReg = dyn Fn(u32, &[usize]) -> Option<usize>;
+
+/// Builds a `RemoteAddressSpace`, as returned by `RemoteAddressSpace::builder`.
+///
+/// `find_proc_info` and `access_mem` must be provided, `access_reg` defaults
+/// to looking registers up in the `initial_regs` passed to `trace_remote`.
+pub struct RemoteAddressSpaceBuilder {
+    find_proc_info: Option<Box<FindProcInfo>>,
+    access_mem: Option<Box<AccessMem>>,
+    access_reg: Option<Box<AccessReg>>,
+}
+
+impl RemoteAddressSpaceBuilder {
+    /// Sets the function which finds the unwind table covering an `ip`, or
+    /// returns `None` if there's none, e.g. for an address outside of every
+    /// module of a core dump.
+    pub fn find_proc_info<F>(mut self, f: F) -> RemoteAddressSpaceBuilder
+    where
+        F: Fn(usize) -> Option<RemoteUnwindTable> + 'static,
+    {
+        self.find_proc_info = Some(Box::new(f));
+        self
+    }
+
+    /// Sets the function which reads the word at an address, or returns
+    /// `None` if it can't be read. Addresses are always word aligned.
+    pub fn access_mem<F>(mut self, f: F) -> RemoteAddressSpaceBuilder
+    where
+        F: Fn(usize) -> Option<usize> + 'static,
+    {
+        self.access_mem = Some(Box::new(f));
+        self
+    }
+
+    /// Sets the function which reads a register of the innermost frame, given
+    /// libunwind's number for it (e.g. `UNW_X86_64_RIP`, which is 16) and the
+    /// `initial_regs` passed to `trace_remote`, or returns `None` if it isn't
+    /// known.
+    pub fn access_reg<F>(mut self, f: F) -> RemoteAddressSpaceBuilder
+    where
+        F: Fn(u32, &[usize]) -> Option<usize> + 'static,
+    {
+        self.access_reg = Some(Box::new(f));
+        self
+    }
+
+    /// Creates the address space.
+    ///
+    /// Fails with `-UNW_EINVAL` if `find_proc_info` or `access_mem` weren't
+    /// given, and with `-UNW_ENOMEM` if libunwind couldn't create it.
+    pub fn build(self) -> Result<RemoteAddressSpace, UnwindError> {
+        let (find_proc_info, access_mem) = match (self.find_proc_info, self.access_mem) {
+            (Some(find_proc_info), Some(access_mem)) => (find_proc_info, access_mem),
+            _ => return Err(UnwindError::new(-UNW_EINVAL)),
+        };
+        // Byte order 0 is the host's.
+        let raw = unsafe { unw_create_addr_space(&ACCESSORS as *const _ as *mut _, 0) };
+        if raw.is_null() {
+            return Err(UnwindError::new(-UNW_ENOMEM));
+        }
+        Ok(RemoteAddressSpace {
+            raw,
+            find_proc_info,
+            access_mem,
+            access_reg: self.access_reg,
+        })
+    }
+}
+
+/// An address space other than this process's, such as that of a core dump,
+/// which `trace_remote` unwinds stacks of through caller-provided callbacks.
+///
+/// libunwind caches what it learns about the unwind info of an address space,
+/// so one should be kept around for all the stacks to be unwound in it.
+///
+/// # Required features
+///
+/// This type requires the `remote-unwind` feature of the `backtrace` crate
+/// to be enabled, which links against the `libunwind-<arch>` library of the
+/// nongnu libunwind (e.g. `libunwind-x86_64`).
+///
+/// # Example
+///
+/// ```no_run
+/// use backtrace::{RemoteAddressSpace, RemoteUnwindTable};
+///
+/// # fn read_core_word(_: usize) -> Option<usize> { None }
+/// # fn find_eh_frame_hdr(_: usize) -> Option<RemoteUnwindTable> { None }
+/// # let registers = [0; 17];
+/// let space = RemoteAddressSpace::builder()
+///     .find_proc_info(find_eh_frame_hdr)
+///     .access_mem(read_core_word)
+///     .build()
+///     .unwrap();
+/// backtrace::trace_remote(&space, &registers, |frame| {
+///     println!("{:?}", frame.ip());
+///     true
+/// })
+/// .unwrap();
+/// ```
+pub struct RemoteAddressSpace {
+    raw: *mut c_void,
+    find_proc_info: Box<FindProcInfo>,
+    access_mem: Box<AccessMem>,
+    access_reg: Option<Box<AccessReg>>,
+}
+
+impl RemoteAddressSpace {
+    /// Starts building an address space.
+    pub fn builder() -> RemoteAddressSpaceBuilder {
+        RemoteAddressSpaceBuilder {
+            find_proc_info: None,
+            access_mem: None,
+            access_reg: None,
+        }
+    }
+}
+
+impl Drop for RemoteAddressSpace {
+    fn drop(&mut self) {
+        unsafe { unw_destroy_addr_space(self.raw) }
+    }
+}
+
+impl fmt::Debug for RemoteAddressSpace {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("RemoteAddressSpace")
+            .field("raw", &self.raw)
+            .finish()
+    }
+}
+
+/// Unwinds a stack of `space`, starting from a frame whose registers are
+/// `initial_regs`, calling `cb` with each frame from the innermost out.
+///
+/// `initial_regs` is indexed by libunwind's register numbers, which are the
+/// same as DWARF's for x86_64 (`RIP` being 16), and for AArch64 are `X0`
+/// through `X30`, then `SP` and `PC`. Unless `space` was given its own
+/// `access_reg`, a register past the end of it isn't known. At least the
+/// instruction and stack pointers and whichever registers the outer frames'
+/// CFI says callers' registers are saved in should be provided.
+///
+/// All memory, including the stack, is read through `space`'s `access_mem`, and
+/// the unwind info through its `find_proc_info`, so nothing of this process is
+/// looked at. The frames passed to `cb` know their `ip`, `sp`, `proc_info`,
+/// `lsda`, `caller_ip` and `frame_size`, with `symbol_address` being the start
+/// of their function. Their addresses are in the remote address space, so they
+/// can't be resolved with `resolve_frame`.
+///
+/// Returns an error if libunwind couldn't start unwinding or failed part way
+/// through. Reaching the end of the stack, or `cb` returning `false`, isn't
+/// an error.
+///
+/// # Required features
+///
+/// This function requires the `remote-unwind` feature of the `backtrace`
+/// crate to be enabled.
+pub fn trace_remote<F: FnMut(&Frame) -> bool>(
+    space: &RemoteAddressSpace,
+    initial_regs: &[usize],
+    mut cb: F,
+) -> Result<(), UnwindError> {
+    let arg = Arg {
+        space,
+        regs: initial_regs,
+    };
+    let mut cursor = UnwCursor::zeroed();
+    unsafe {
+        let res = unw_init_remote(
+            &mut cursor,
+            space.raw,
+            &arg as *const Arg<'_> as *mut c_void,
+        );
+        if res < 0 {
+            return Err(UnwindError::new(res));
+        }
+        let mut frame = get_frame(&mut cursor)?;
+        loop {
+            // As with the local external unwinder, step first so the frame
+            // knows its caller.
+            let res = unw_step(&mut cursor);
+            let next = if res > 0 {
+                get_frame(&mut cursor).map(Some)
+            } else if res == 0 {
+                Ok(None)
+            } else {
+                Err(UnwindError::new(res))
+            };
+            if let (
+                FrameImp::Cloned {
+                    sp,
+                    caller_ip,
+                    frame_size,
+                    ..
+                },
+                Ok(Some(next)),
+            ) = (&mut frame.inner, &next)
+            {
+                *caller_ip = Some(next.ip());
+                *frame_size = (next.sp() as usize).checked_sub(*sp as usize);
+            }
+
+            let mut bomb = crate::Bomb { enabled: true };
+            let keep_going = cb(&frame);
+            bomb.enabled = false;
+            if !keep_going {
+                return Ok(());
+            }
+            match next? {
+                Some(next) => frame = next,
+                None => return Ok(()),
+            }
+        }
+    }
+}
+
+unsafe fn get_frame(cursor: &mut UnwCursor) -> Result<Frame, UnwindError> {
+    let mut ip = 0;
+    let mut sp = 0;
+    let res = unw_get_reg(cursor, UNW_REG_IP, &mut ip);
+    if res != 0 {
+        return Err(UnwindError::new(res));
+    }
+    let res = unw_get_reg(cursor, UNW_REG_SP, &mut sp);
+    if res != 0 {
+        return Err(UnwindError::new(res));
+    }
+    let mut info = UnwProcInfo::new();
+    let found = unw_get_proc_info(cursor, &mut info) == 0;
+    Ok(Frame {
+        inner: FrameImp::Cloned {
+            ip: ip as *mut c_void,
+            sp: sp as *mut c_void,
+            symbol_address: if found { Some(info.start_ip) } else { None },
+            lsda: if found && !info.lsda.is_null() {
+                Some(info.lsda as *const c_void)
+            } else {
+                None
+            },
+            caller_ip: None,
+            proc_info: if found {
+                Some(info.to_proc_info())
+            } else {
+                None
+            },
+            frame_size: None,
+            exact_ip: false,
+            registers: core::ptr::null(),
+        },
+    })
+}
+
+// What libunwind passes back to the callbacks as their `arg`.
+struct Arg<'a> {
+    space: &'a RemoteAddressSpace,
+    regs: &'a [usize],
+}
+
+static ACCESSORS: Accessors = Accessors {
+    find_proc_info,
+    put_unwind_info,
+    get_dyn_info_list_addr,
+    access_mem,
+    access_reg,
+    access_fpreg,
+    resume,
+    get_proc_name,
+    get_proc_ip_range,
+    get_elf_filename,
+};
+
+unsafe extern "C" fn find_proc_info(
+    space: *mut c_void,
+    ip: usize,
+    info: *mut UnwProcInfo,
+    need_unwind_info: c_int,
+    arg: *mut c_void,
+) -> c_int {
+    let mut bomb = crate::Bomb { enabled: true };
+    let table = ((*(arg as *const Arg<'_>)).space.find_proc_info)(ip);
+    bomb.enabled = false;
+    let table = match table {
+        Some(table) => table,
+        None => return -UNW_ENOINFO,
+    };
+    let mut dyn_info = UnwDynInfo {
+        next: ptr::null_mut(),
+        prev: ptr::null_mut(),
+        start_ip: table.start_ip,
+        end_ip: table.end_ip,
+        gp: 0,
+        format: UNW_INFO_FORMAT_REMOTE_TABLE,
+        pad: 0,
+        // The table's addresses are already where they're loaded.
+        load_offset: 0,
+        name_ptr: 0,
+        segbase: table.segbase,
+        table_len: table.table_len,
+        table_data: table.table_data,
+    };
+    dwarf_search_unwind_table(space, ip, &mut dyn_info, info, need_unwind_info, arg)
+}
+
+// Frees what `dwarf_search_unwind_table` allocated for the unwinder's own use.
+unsafe extern "C" fn put_unwind_info(
+    _space: *mut c_void,
+    info: *mut UnwProcInfo,
+    _arg: *mut c_void,
+) {
+    if !(*info).unwind_info.is_null() {
+        libc::free((*info).unwind_info);
+        (*info).unwind_info = ptr::null_mut();
+    }
+}
+
+unsafe extern "C" fn get_dyn_info_list_addr(
+    _space: *mut c_void,
+    _addr: *mut usize,
+    _arg: *mut c_void,
+) -> c_int {
+    -UNW_ENOINFO
+}
+
+unsafe extern "C" fn access_mem(
+    _space: *mut c_void,
+    addr: usize,
+    value: *mut usize,
+    write: c_int,
+    arg: *mut c_void,
+) -> c_int {
+    if write != 0 {
+        return -UNW_EINVAL;
+    }
+    let mut bomb = crate::Bomb { enabled: true };
+    let word = ((*(arg as *const Arg<'_>)).space.access_mem)(addr);
+    bomb.enabled = false;
+    match word {
+        Some(word) => {
+            *value = word;
+            0
+        }
+        None => -UNW_EINVAL,
+    }
+}
+
+unsafe extern "C" fn access_reg(
+    _space: *mut c_void,
+    reg: c_int,
+    value: *mut usize,
+    write: c_int,
+    arg: *mut c_void,
+) -> c_int {
+    if write != 0 {
+        return -UNW_EREADONLYREG;
+    }
+    let arg = &*(arg as *const Arg<'_>);
+    let mut bomb = crate::Bomb { enabled: true };
+    let word = match &arg.space.access_reg {
+        Some(access_reg) => access_reg(reg as u32, arg.regs),
+        None => arg.regs.get(reg as usize).cloned(),
+    };
+    bomb.enabled = false;
+    match word {
+        Some(word) => {
+            *value = word;
+            0
+        }
+        None => -UNW_EBADREG,
+    }
+}
+
+unsafe extern "C" fn access_fpreg(
+    _space: *mut c_void,
+    _reg: c_int,
+    _value: *mut c_void,
+    _write: c_int,
+    _arg: *mut c_void,
+) -> c_int {
+    -UNW_EBADREG
+}
+
+unsafe extern "C" fn resume(
+    _space: *mut c_void,
+    _cursor: *mut UnwCursor,
+    _arg: *mut c_void,
+) -> c_int {
+    -UNW_EINVAL
+}
+
+unsafe extern "C" fn get_proc_name(
+    _space: *mut c_void,
+    _addr: usize,
+    _buf: *mut c_char,
+    _len: usize,
+    _offset: *mut usize,
+    _arg: *mut c_void,
+) -> c_int {
+    -UNW_ENOINFO
+}
+
+unsafe extern "C" fn get_proc_ip_range(
+    _space: *mut c_void,
+    _ip: usize,
+    _start: *mut usize,
+    _end: *mut usize,
+    _arg: *mut c_void,
+) -> c_int {
+    -UNW_ENOINFO
+}
+
+unsafe extern "C" fn get_elf_filename(
+    _space: *mut c_void,
+    _ip: usize,
+    _buf: *mut c_char,
+    _len: usize,
+    _offset: *mut usize,
+    _arg: *mut c_void,
+) -> c_int {
+    -UNW_ENOINFO
+}
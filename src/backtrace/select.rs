@@ -0,0 +1,112 @@
+//! Picking the unwinder used by `trace` at runtime, through the
+//! `BACKTRACE_UNWINDER` environment variable.
+//!
+//! This makes it possible to compare unwinders with a single binary, e.g. to
+//! A/B the frame pointer walker against `_Unwind_Backtrace` in production.
+
+use core::sync::atomic::{AtomicU8, Ordering};
+use std::env;
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum Unwinder {
+    Default = 1,
+    FramePointers = 2,
+    Libunwind = 3,
+    External = 4,
+    CompactUnwind = 5,
+}
+
+const UNINITIALIZED: u8 = 0;
+
+static SELECTED: AtomicU8 = AtomicU8::new(UNINITIALIZED);
+
+/// Runs the unwinder selected by `BACKTRACE_UNWINDER`, or the default one for
+/// this platform if it isn't set.
+///
+/// Must be called with the global lock held, which is what makes reading and
+/// caching the selection race-free.
+pub(super) unsafe fn trace(cb: &mut dyn FnMut(&super::Frame) -> bool) {
+    match selected() {
+        // Where `_Unwind_Backtrace` is supported it's also the default, and
+        // selecting it elsewhere falls back to the default.
+        Unwinder::Default | Unwinder::Libunwind => super::trace_imp(cb),
+        Unwinder::FramePointers => super::trace_imp_frame_pointers(cb, None),
+        #[cfg(any(feature = "llvm-unwind", feature = "nongnu-unwind"))]
+        Unwinder::External => {
+            // There's no way to report the error through `trace`, the frames
+            // up until the failure have been delivered though.
+            let _ = super::trace_imp_external_api(cb, false);
+        }
+        #[cfg(not(any(feature = "llvm-unwind", feature = "nongnu-unwind")))]
+        Unwinder::External => super::trace_imp(cb),
+        #[cfg(all(
+            not(miri),
+            any(target_os = "macos", target_os = "ios"),
+            any(target_arch = "x86_64", target_arch = "aarch64"),
+            not(backtrace_no_asm),
+        ))]
+        Unwinder::CompactUnwind => super::trace_imp_compact_unwind(cb),
+        #[cfg(not(all(
+            not(miri),
+            any(target_os = "macos", target_os = "ios"),
+            any(target_arch = "x86_64", target_arch = "aarch64"),
+            not(backtrace_no_asm),
+        )))]
+        Unwinder::CompactUnwind => super::trace_imp(cb),
+    }
+}
+
+fn selected() -> Unwinder {
+    match SELECTED.load(Ordering::Relaxed) {
+        1 => Unwinder::Default,
+        2 => Unwinder::FramePointers,
+        3 => Unwinder::Libunwind,
+        4 => Unwinder::External,
+        5 => Unwinder::CompactUnwind,
+        _ => {
+            let selected = from_env();
+            SELECTED.store(selected as u8, Ordering::Relaxed);
+            selected
+        }
+    }
+}
+
+fn from_env() -> Unwinder {
+    let value = match env::var("BACKTRACE_UNWINDER") {
+        Ok(value) => value,
+        Err(env::VarError::NotPresent) => return Unwinder::Default,
+        Err(env::VarError::NotUnicode(value)) => {
+            eprintln!(
+                "backtrace: ignoring BACKTRACE_UNWINDER={:?}, it isn't valid unicode",
+                value
+            );
+            return Unwinder::Default;
+        }
+    };
+    let (unwinder, available) = match value.as_str() {
+        "fp" => (Unwinder::FramePointers, super::FRAME_POINTERS_SUPPORTED),
+        "libunwind" => (Unwinder::Libunwind, super::LIBUNWIND_SUPPORTED),
+        "external" => (
+            Unwinder::External,
+            cfg!(any(feature = "llvm-unwind", feature = "nongnu-unwind")),
+        ),
+        "compact" => (Unwinder::CompactUnwind, super::COMPACT_UNWIND_SUPPORTED),
+        _ => {
+            eprintln!(
+                "backtrace: ignoring unknown BACKTRACE_UNWINDER={:?}, expected one of \
+                 `fp`, `libunwind`, `external` or `compact`",
+                value
+            );
+            return Unwinder::Default;
+        }
+    };
+    if !available {
+        eprintln!(
+            "backtrace: ignoring BACKTRACE_UNWINDER={:?}, that unwinder isn't \
+             supported by this build",
+            value
+        );
+        return Unwinder::Default;
+    }
+    unwinder
+}
@@ -0,0 +1,103 @@
+//! Giving up on a trace which takes too long.
+//!
+//! A POSIX timer is armed to send a signal to the tracing thread itself once
+//! the timeout expires, and the signal's handler sets a thread-local flag
+//! which the trace checks before each frame. Only the signal handler has to
+//! run for the flag to be set, so this works even while the trace is stuck in
+//! a page fault or in the unwinder, though the trace only ends once it gets
+//! around to the next frame.
+
+use super::{Frame, TraceOutcome};
+use core::mem;
+use core::ptr;
+use core::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Once;
+use std::time::Duration;
+
+// Without a destructor this is a plain `#[thread_local]`, and `trace` touches
+// it before arming the timer so it's already initialized by the time the
+// signal handler gets to it.
+std::thread_local!(static EXPIRED: AtomicBool = AtomicBool::new(false));
+
+/// The signal the timer sends, `SIGRTMIN() + 5`.
+fn signal() -> libc::c_int {
+    libc::SIGRTMIN() + 5
+}
+
+extern "C" fn handler(_signum: libc::c_int) {
+    let _ = EXPIRED.try_with(|expired| expired.store(true, Ordering::Relaxed));
+}
+
+pub(super) unsafe fn trace(timeout: Duration, cb: &mut dyn FnMut(&Frame) -> bool) -> TraceOutcome {
+    static INIT: Once = Once::new();
+    INIT.call_once(|| {
+        let mut action = mem::zeroed::<libc::sigaction>();
+        action.sa_sigaction = handler as *const () as usize;
+        action.sa_flags = libc::SA_RESTART;
+        libc::sigemptyset(&mut action.sa_mask);
+        libc::sigaction(signal(), &action, ptr::null_mut());
+    });
+
+    EXPIRED.with(|expired| expired.store(false, Ordering::Relaxed));
+    let timer = arm(timeout);
+
+    let mut timed_out = false;
+    let mut stopped = false;
+    let mut each = |frame: &Frame| {
+        if EXPIRED.with(|expired| expired.load(Ordering::Relaxed)) {
+            timed_out = true;
+            return false;
+        }
+        let keep_going = cb(frame);
+        stopped = !keep_going;
+        keep_going
+    };
+    #[cfg(any(feature = "llvm-unwind", feature = "nongnu-unwind"))]
+    let outcome = match super::trace_imp_external_api(&mut each, false) {
+        Ok(()) => TraceOutcome::Complete,
+        Err(err) => TraceOutcome::UnwinderError(err.code()),
+    };
+    #[cfg(not(any(feature = "llvm-unwind", feature = "nongnu-unwind")))]
+    let outcome = {
+        super::trace_imp_frame_pointers(&mut each, None);
+        TraceOutcome::Complete
+    };
+
+    // Deleting the timer also discards its signal if it's still pending, so
+    // it can't cut short a later trace on this thread.
+    if let Some(timer) = timer {
+        libc::timer_delete(timer);
+    }
+
+    if timed_out {
+        TraceOutcome::TimedOut
+    } else if stopped {
+        TraceOutcome::StoppedByCallback
+    } else {
+        outcome
+    }
+}
+
+// Arms a timer to signal this thread once `timeout` has elapsed, returning
+// `None` if one couldn't be created, in which case the trace just runs to
+// completion.
+unsafe fn arm(timeout: Duration) -> Option<libc::timer_t> {
+    let mut event = mem::zeroed::<libc::sigevent>();
+    event.sigev_notify = libc::SIGEV_THREAD_ID;
+    event.sigev_signo = signal();
+    event.sigev_notify_thread_id = libc::syscall(libc::SYS_gettid) as libc::pid_t;
+    let mut timer = mem::zeroed::<libc::timer_t>();
+    if libc::timer_create(libc::CLOCK_MONOTONIC, &mut event, &mut timer) != 0 {
+        return None;
+    }
+
+    let mut spec = mem::zeroed::<libc::itimerspec>();
+    spec.it_value.tv_sec = timeout.as_secs() as libc::time_t;
+    spec.it_value.tv_nsec = timeout.subsec_nanos() as libc::c_long;
+    // An all zero value disarms the timer rather than firing it right away.
+    if timeout == Duration::from_secs(0) {
+        spec.it_value.tv_nsec = 1;
+    }
+    libc::timer_settime(timer, 0, &spec, ptr::null_mut());
+    Some(timer)
+}
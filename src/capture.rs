@@ -1,5 +1,9 @@
 use crate::PrintFmt;
-use crate::{resolve, resolve_frame, trace, BacktraceFmt, Symbol, SymbolName};
+use crate::{
+    resolve, resolve_frame, trace_detect_loops, BacktraceFmt, DepthHistogram, Symbol, SymbolName,
+};
+use core::ptr;
+use core::sync::atomic::{AtomicPtr, Ordering};
 use std::ffi::c_void;
 use std::fmt;
 use std::path::{Path, PathBuf};
@@ -29,11 +33,39 @@ pub struct Backtrace {
     // The index we believe is the actual start of the backtrace, omitting
     // frames like `Backtrace::new` and `backtrace::trace`.
     actual_start_index: usize,
+    // Whether the unwinder started looping, so `frames` stops short of the
+    // bottom of the stack.
+    #[cfg_attr(feature = "serde", serde(default))]
+    loop_detected: bool,
 }
 
 fn _assert_send_sync() {
     fn _assert<T: Send + Sync>() {}
     _assert::<Backtrace>();
+    _assert::<ResolvedBacktrace>();
+}
+
+static DEPTH_HISTOGRAM: AtomicPtr<DepthHistogram> = AtomicPtr::new(ptr::null_mut());
+
+/// Registers a histogram which the depth of every `Backtrace` captured from
+/// now on is recorded in, or with `None` stops recording them.
+///
+/// The depth recorded is the number of frames the unwinder walked, including
+/// those of `Backtrace::new` and of the unwinder itself which
+/// `Backtrace::frames` leaves out, since a buffer passed to `trace_into` has
+/// to hold those too. This covers `Backtrace::new`, `new_unresolved`,
+/// `resolved_backtrace` and `capture_with_stack_memory`; depths of stacks
+/// captured by other means can be recorded with `DepthHistogram::record`.
+///
+/// Registering a histogram replaces the previous one, if any.
+///
+/// # Required features
+///
+/// This function requires the `std` feature of the `backtrace` crate to be
+/// enabled, and the `std` feature is enabled by default.
+pub fn set_depth_histogram(histogram: Option<&'static DepthHistogram>) {
+    let histogram = histogram.map_or(ptr::null_mut(), |h| h as *const _ as *mut _);
+    DEPTH_HISTOGRAM.store(histogram, Ordering::Relaxed);
 }
 
 /// Captured version of a frame in a backtrace.
@@ -93,11 +125,17 @@ impl Frame {
 /// This type is returned as a list from `BacktraceFrame::symbols` and
 /// represents the metadata for a symbol in a backtrace.
 ///
+/// Symbols compare and hash equal only if everything about them is the same,
+/// including their `inline_depth`. The symbols of functions inlined into one
+/// another all share the address of their frame, so this is what keeps e.g.
+/// an inlined call apart from the function it was inlined into when symbols
+/// are used as keys to aggregate samples by.
+///
 /// # Required features
 ///
 /// This function requires the `std` feature of the `backtrace` crate to be
 /// enabled, and the `std` feature is enabled by default.
-#[derive(Clone)]
+#[derive(Clone, PartialEq, Eq, Hash)]
 #[cfg_attr(feature = "serialize-rustc", derive(RustcDecodable, RustcEncodable))]
 #[cfg_attr(feature = "serde", derive(Deserialize, Serialize))]
 pub struct BacktraceSymbol {
@@ -106,6 +144,8 @@ pub struct BacktraceSymbol {
     filename: Option<PathBuf>,
     lineno: Option<u32>,
     colno: Option<u32>,
+    #[cfg_attr(feature = "serde", serde(default))]
+    inline_depth: usize,
 }
 
 impl Backtrace {
@@ -171,9 +211,9 @@ impl Backtrace {
     }
 
     fn create(ip: usize) -> Backtrace {
-        let mut frames = Vec::new();
+        let mut frames = Vec::with_capacity(crate::MAX_RECOMMENDED_FRAMES);
         let mut actual_start_index = None;
-        trace(|frame| {
+        let loop_detected = trace_detect_loops(|frame| {
             frames.push(BacktraceFrame {
                 frame: Frame::Raw(frame.clone()),
                 symbols: None,
@@ -185,9 +225,15 @@ impl Backtrace {
             true
         });
 
+        let histogram = DEPTH_HISTOGRAM.load(Ordering::Relaxed);
+        if !histogram.is_null() {
+            unsafe { (*histogram).record(frames.len()) };
+        }
+
         Backtrace {
             frames,
             actual_start_index: actual_start_index.unwrap_or(0),
+            loop_detected,
         }
     }
 
@@ -205,6 +251,20 @@ impl Backtrace {
         &self.frames[self.actual_start_index..]
     }
 
+    /// Returns whether capturing this backtrace was cut short because the
+    /// unwinder started looping over the same frames.
+    ///
+    /// When this is `true` the frames are accurate up until the loop, but the
+    /// outermost frames of the stack are missing.
+    ///
+    /// # Required features
+    ///
+    /// This function requires the `std` feature of the `backtrace` crate to be
+    /// enabled, and the `std` feature is enabled by default.
+    pub fn loop_detected(&self) -> bool {
+        self.loop_detected
+    }
+
     /// If this backtrace was created from `new_unresolved` then this function
     /// will resolve all addresses in the backtrace to their symbolic names.
     ///
@@ -219,15 +279,7 @@ impl Backtrace {
         for frame in self.frames.iter_mut().filter(|f| f.symbols.is_none()) {
             let mut symbols = Vec::new();
             {
-                let sym = |symbol: &Symbol| {
-                    symbols.push(BacktraceSymbol {
-                        name: symbol.name().map(|m| m.as_bytes().to_vec()),
-                        addr: symbol.addr().map(|a| a as usize),
-                        filename: symbol.filename().map(|m| m.to_owned()),
-                        lineno: symbol.lineno(),
-                        colno: symbol.colno(),
-                    });
-                };
+                let sym = |symbol: &Symbol| symbols.push(BacktraceSymbol::from_symbol(symbol));
                 match frame.frame {
                     Frame::Raw(ref f) => resolve_frame(f, sym),
                     Frame::Deserialized { ip, .. } => {
@@ -240,11 +292,246 @@ impl Backtrace {
     }
 }
 
+/// A backtrace which has been captured and had all its symbols resolved, as
+/// returned by `resolved_backtrace`.
+///
+/// This is printed with `Display` the same way `Backtrace` is with `Debug`,
+/// including the `{:#}` alternate form printing every frame in full.
+///
+/// # Required features
+///
+/// This type requires the `std` feature of the `backtrace` crate to be
+/// enabled, and the `std` feature is enabled by default.
+#[derive(Clone)]
+pub struct ResolvedBacktrace {
+    inner: Backtrace,
+}
+
+/// Captures and symbolizes a backtrace of the calling thread in one go.
+///
+/// This is the simplest way to get hold of a backtrace to print or store, much
+/// like `std::backtrace::Backtrace::force_capture`. Every frame is resolved,
+/// with a symbol for each function inlined into it, before this returns, and
+/// this function's own frames are left out of the result.
+///
+/// Resolving symbols takes locks, allocates and can read debug info from
+/// disk, so this isn't safe to call from a signal handler and can take a long
+/// time. Code with real time constraints should capture frames with `trace`,
+/// or `trace_unsynchronized`, instead and resolve them later if at all.
+///
+/// # Required features
+///
+/// This function requires the `std` feature of the `backtrace` crate to be
+/// enabled, and the `std` feature is enabled by default.
+///
+/// # Example
+///
+/// ```
+/// let backtrace = backtrace::resolved_backtrace();
+/// println!("{}", backtrace);
+/// ```
+#[inline(never)] // want to make sure there's a frame here to remove
+pub fn resolved_backtrace() -> ResolvedBacktrace {
+    let mut inner = Backtrace::create(resolved_backtrace as *const () as usize);
+    inner.resolve();
+    ResolvedBacktrace { inner }
+}
+
+impl ResolvedBacktrace {
+    /// Returns the frames of this backtrace, innermost first, each with its
+    /// symbols resolved.
+    pub fn frames(&self) -> &[BacktraceFrame] {
+        self.inner.frames()
+    }
+
+    /// Returns whether capturing this backtrace was cut short because the
+    /// unwinder started looping over the same frames, as
+    /// `Backtrace::loop_detected` does.
+    pub fn loop_detected(&self) -> bool {
+        self.inner.loop_detected()
+    }
+
+    /// Converts this into a `Backtrace`, e.g. to serialize it.
+    pub fn into_backtrace(self) -> Backtrace {
+        self.inner
+    }
+}
+
+impl From<ResolvedBacktrace> for Backtrace {
+    fn from(backtrace: ResolvedBacktrace) -> Backtrace {
+        backtrace.inner
+    }
+}
+
+impl fmt::Display for ResolvedBacktrace {
+    fn fmt(&self, fmt: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt::Debug::fmt(&self.inner, fmt)
+    }
+}
+
+impl fmt::Debug for ResolvedBacktrace {
+    fn fmt(&self, fmt: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt::Debug::fmt(&self.inner, fmt)
+    }
+}
+
+/// A backtrace along with a copy of the stack memory of the frame it was
+/// captured in, as returned by `capture_with_stack_memory`.
+///
+/// # Required features
+///
+/// This type requires the `std` feature of the `backtrace` crate to be
+/// enabled, and the `std` feature is enabled by default.
+#[derive(Clone)]
+pub struct BacktraceWithStackMemory {
+    inner: Backtrace,
+    stack_address: usize,
+    stack_memory: Vec<u8>,
+}
+
+/// Captures a backtrace of the calling thread, unresolved, along with a copy
+/// of up to `bytes` bytes of its stack, for crash reports.
+///
+/// The copy starts at the stack pointer of the frame which called this
+/// function, i.e. the lowest address that frame was using, and runs up the
+/// stack from there through its locals and those of its callers. That lets
+/// whoever analyzes the report reconstruct local variables which no unwinder
+/// or debug info can recover after the fact.
+///
+/// The memory is read without dereferencing it, so the copy stops short,
+/// rather than faulting, at the first page which isn't readable, such as the
+/// guard page past the top of a thread's stack; `bytes` is only an upper
+/// bound. Reading memory that way is only implemented on Linux and Android,
+/// with `process_vm_readv` on this very process, which may be forbidden by a
+/// seccomp filter; elsewhere, or if the frame's stack pointer isn't known,
+/// no memory is copied at all.
+///
+/// The stack may hold secrets such as keys or passwords which were passed
+/// around in locals, so reports including it should be handled with care.
+///
+/// # Required features
+///
+/// This function requires the `std` feature of the `backtrace` crate to be
+/// enabled, and the `std` feature is enabled by default.
+///
+/// # Example
+///
+/// ```
+/// let capture = backtrace::capture_with_stack_memory(4096);
+/// println!(
+///     "{} bytes of stack at {:#x}",
+///     capture.stack_memory().len(),
+///     capture.stack_memory_address()
+/// );
+/// let mut backtrace = capture.into_backtrace();
+/// backtrace.resolve();
+/// println!("{:?}", backtrace);
+/// ```
+#[inline(never)] // want to make sure there's a frame here to remove
+pub fn capture_with_stack_memory(bytes: usize) -> BacktraceWithStackMemory {
+    let inner = Backtrace::create(capture_with_stack_memory as *const () as usize);
+    // The stack pointer of a frame is where the stack was when it was called,
+    // so that of this function's own frame is its caller's.
+    let stack_address = match inner.actual_start_index.checked_sub(1) {
+        Some(own) => match inner.frames[own].frame {
+            Frame::Raw(ref f) => f.sp() as usize,
+            Frame::Deserialized { .. } => 0,
+        },
+        None => 0,
+    };
+    let mut stack_memory = vec![0; bytes];
+    let len = if stack_address == 0 {
+        0
+    } else {
+        read_memory(stack_address, &mut stack_memory)
+    };
+    stack_memory.truncate(len);
+    BacktraceWithStackMemory {
+        inner,
+        stack_address,
+        stack_memory,
+    }
+}
+
+impl BacktraceWithStackMemory {
+    /// Returns the backtrace, whose frames start with the caller of
+    /// `capture_with_stack_memory`.
+    pub fn backtrace(&self) -> &Backtrace {
+        &self.inner
+    }
+
+    /// Resolves the symbols of the backtrace, like `Backtrace::resolve`.
+    pub fn resolve(&mut self) {
+        self.inner.resolve()
+    }
+
+    /// Returns the address the copy of the stack starts at, or 0 if the stack
+    /// pointer wasn't known.
+    pub fn stack_memory_address(&self) -> usize {
+        self.stack_address
+    }
+
+    /// Returns the copy of the stack, starting at `stack_memory_address`.
+    pub fn stack_memory(&self) -> &[u8] {
+        &self.stack_memory
+    }
+
+    /// Converts this into just the `Backtrace`, dropping the stack memory.
+    pub fn into_backtrace(self) -> Backtrace {
+        self.inner
+    }
+}
+
+impl fmt::Debug for BacktraceWithStackMemory {
+    fn fmt(&self, fmt: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt.debug_struct("BacktraceWithStackMemory")
+            .field("backtrace", &self.inner)
+            .field("stack_memory_address", &(self.stack_address as *const u8))
+            .field("stack_memory_len", &self.stack_memory.len())
+            .finish()
+    }
+}
+
+// Copies as much of the memory at `addr` into `buf` as can be read, page by
+// page, returning how many bytes that was.
+#[cfg(any(target_os = "linux", target_os = "android"))]
+fn read_memory(addr: usize, buf: &mut [u8]) -> usize {
+    const PAGE: usize = 4096;
+    let pid = unsafe { libc::getpid() };
+    let mut done = 0;
+    while done < buf.len() {
+        let start = addr.wrapping_add(done);
+        // Reads don't cross page boundaries, so that one unreadable page
+        // doesn't lose what could be read of the pages before it.
+        let len = (PAGE - start % PAGE).min(buf.len() - done);
+        let local = libc::iovec {
+            iov_base: buf[done..].as_mut_ptr().cast(),
+            iov_len: len,
+        };
+        let remote = libc::iovec {
+            iov_base: start as *mut _,
+            iov_len: len,
+        };
+        let read = unsafe { libc::process_vm_readv(pid, &local, 1, &remote, 1, 0) };
+        if read != len as isize {
+            break;
+        }
+        done += len;
+    }
+    done
+}
+
+#[cfg(not(any(target_os = "linux", target_os = "android")))]
+fn read_memory(_addr: usize, _buf: &mut [u8]) -> usize {
+    0
+}
+
 impl From<Vec<BacktraceFrame>> for Backtrace {
     fn from(frames: Vec<BacktraceFrame>) -> Self {
         Backtrace {
             frames,
             actual_start_index: 0,
+            loop_detected: false,
         }
     }
 }
@@ -258,6 +545,22 @@ impl From<crate::Frame> for BacktraceFrame {
     }
 }
 
+impl BacktraceFrame {
+    // Creates an unresolved frame from just an instruction pointer, as
+    // captured somewhere a `Frame` couldn't be kept.
+    #[allow(dead_code)]
+    pub(crate) fn from_ip(ip: *mut c_void) -> BacktraceFrame {
+        BacktraceFrame {
+            frame: Frame::Deserialized {
+                ip: ip as usize,
+                symbol_address: ip as usize,
+                module_base_address: None,
+            },
+            symbols: None,
+        }
+    }
+}
+
 impl Into<Vec<BacktraceFrame>> for Backtrace {
     fn into(self) -> Vec<BacktraceFrame> {
         self.frames
@@ -297,6 +600,17 @@ impl BacktraceFrame {
             .map(|addr| addr as *mut c_void)
     }
 
+    /// Same as `Frame::module_relative_address`
+    ///
+    /// # Required features
+    ///
+    /// This function requires the `std` feature of the `backtrace` crate to be
+    /// enabled, and the `std` feature is enabled by default.
+    pub fn module_relative_address(&self) -> Option<usize> {
+        let base = self.module_base_address()? as usize;
+        (self.ip() as usize).checked_sub(base)
+    }
+
     /// Returns the list of symbols that this frame corresponds to.
     ///
     /// Normally there is only one symbol per frame, but sometimes if a number
@@ -314,9 +628,38 @@ impl BacktraceFrame {
     pub fn symbols(&self) -> &[BacktraceSymbol] {
         self.symbols.as_ref().map(|s| &s[..]).unwrap_or(&[])
     }
+
+    /// Same as `is_frame_boundary`, for this frame.
+    ///
+    /// Frames deserialized rather than captured in this process are never
+    /// boundaries, since there's no `Frame` to pass to the predicate.
+    ///
+    /// # Required features
+    ///
+    /// This function requires the `std` feature of the `backtrace` crate to be
+    /// enabled, and the `std` feature is enabled by default.
+    pub fn is_frame_boundary(&self) -> bool {
+        match self.frame {
+            Frame::Raw(ref f) => crate::is_frame_boundary(f),
+            Frame::Deserialized { .. } => false,
+        }
+    }
 }
 
 impl BacktraceSymbol {
+    // Copies everything out of a symbol which is only valid inside a resolve
+    // callback.
+    pub(crate) fn from_symbol(symbol: &Symbol) -> BacktraceSymbol {
+        BacktraceSymbol {
+            name: symbol.name().map(|m| m.as_bytes().to_vec()),
+            addr: symbol.addr().map(|a| a as usize),
+            filename: symbol.filename().map(|m| m.to_owned()),
+            lineno: symbol.lineno(),
+            colno: symbol.colno(),
+            inline_depth: symbol.inline_depth(),
+        }
+    }
+
     /// Same as `Symbol::name`
     ///
     /// # Required features
@@ -366,6 +709,16 @@ impl BacktraceSymbol {
     pub fn colno(&self) -> Option<u32> {
         self.colno
     }
+
+    /// Same as `Symbol::inline_depth`
+    ///
+    /// # Required features
+    ///
+    /// This function requires the `std` feature of the `backtrace` crate to be
+    /// enabled, and the `std` feature is enabled by default.
+    pub fn inline_depth(&self) -> usize {
+        self.inline_depth
+    }
 }
 
 impl fmt::Debug for Backtrace {
@@ -428,6 +781,7 @@ impl fmt::Debug for BacktraceSymbol {
             .field("filename", &self.filename())
             .field("lineno", &self.lineno())
             .field("colno", &self.colno())
+            .field("inline_depth", &self.inline_depth())
             .finish()
     }
 }
@@ -0,0 +1,228 @@
+//! Writing sampled stacks out in the Trace Event Format read by Chrome's
+//! `about:tracing` and by Perfetto, so that they can be viewed on a timeline.
+//!
+//! Each sample is written as a complete event, `"ph":"X"`, spanning the time
+//! it covers, whose `sf` field refers to an entry of the `stackFrames` table
+//! for its innermost frame. Entries of that table point at their callers by
+//! `parent`, so the table is a tree of every distinct call path seen, which is
+//! interned as samples are added and only written out once at the end.
+//!
+//! The format is described in the "Trace Event Format" document linked from
+//! the Catapult project, `docs/trace-event-format.md`.
+
+use crate::BacktraceFrame;
+use std::collections::HashMap;
+use std::fmt::Write as _;
+use std::io::{self, Write};
+use std::prelude::v1::*;
+use std::time::Instant;
+
+/// Writes sampled stacks in the JSON Trace Event Format, for viewing in
+/// Chrome's `about:tracing` or in Perfetto.
+///
+/// This is meant for profiling async runtimes, where what's interesting is
+/// which task was running when, and what it was doing. Each sample is added
+/// with the times it started and ended, e.g. those of a poll of a task, the
+/// ID of the task or thread it belongs to, which becomes the row it's shown
+/// on, and the stack captured during it, which the viewer shows when the
+/// sample is selected.
+///
+/// The instruction pointers of the stacks are resolved to function names with
+/// `resolve` as they're added, once for each distinct address, so the frames
+/// passed in needn't be resolved. Frames inlined into one another are shown
+/// as separate frames, and each frame's category is the crate its function
+/// belongs to, as given by `Symbol::crate_name`, which the viewer colors it
+/// by. Frames which don't resolve show up as their address.
+///
+/// Timestamps are written relative to the time the writer was created, and
+/// everything is buffered in memory until `finish` writes the file, since the
+/// table of stack frames has to be complete to be written.
+///
+/// # Required features
+///
+/// This type requires the `chrome-trace` feature of the `backtrace` crate to
+/// be enabled.
+///
+/// # Example
+///
+/// ```no_run
+/// use backtrace::{Backtrace, ChromeTraceWriter};
+/// use std::fs::File;
+/// use std::time::Instant;
+///
+/// let mut writer = ChromeTraceWriter::new();
+/// let start = Instant::now();
+/// let backtrace = Backtrace::new_unresolved();
+/// writer.add_sample(1, "poll", start, Instant::now(), backtrace.frames());
+/// writer.finish(File::create("trace.json").unwrap()).unwrap();
+/// ```
+pub struct ChromeTraceWriter {
+    start: Instant,
+    pid: u32,
+    // The events written so far, each preceded by a comma but the first.
+    events: String,
+    // The symbols each address resolved to, innermost first, as indices into
+    // `names`, which interns each distinct name and category.
+    symbols: HashMap<usize, Vec<usize>>,
+    names: Vec<(String, String)>,
+    name_ids: HashMap<(String, String), usize>,
+    // Each entry of the stack frame table, as the name it has and the entry
+    // of its caller.
+    nodes: Vec<(usize, Option<usize>)>,
+    node_ids: HashMap<(usize, Option<usize>), usize>,
+}
+
+impl ChromeTraceWriter {
+    /// Creates a writer with no samples, whose timestamps start now.
+    pub fn new() -> ChromeTraceWriter {
+        ChromeTraceWriter {
+            start: Instant::now(),
+            pid: std::process::id(),
+            events: String::new(),
+            symbols: HashMap::new(),
+            names: Vec::new(),
+            name_ids: HashMap::new(),
+            nodes: Vec::new(),
+            node_ids: HashMap::new(),
+        }
+    }
+
+    /// Adds a sample named `name`, which ran from `start` to `end` on the row
+    /// `tid`, with the stack `frames`, innermost first, as returned by
+    /// `Backtrace::frames`.
+    ///
+    /// `tid` is whatever the samples should be grouped by, e.g. the ID of a
+    /// thread, or of an async task to show each task on its own row. Times
+    /// before the writer was created are clamped to its creation, and an
+    /// `end` before `start` gives a sample which lasts no time at all.
+    pub fn add_sample(
+        &mut self,
+        tid: u64,
+        name: &str,
+        start: Instant,
+        end: Instant,
+        frames: &[BacktraceFrame],
+    ) {
+        let ts = start.saturating_duration_since(self.start).as_nanos() as u64;
+        let dur = end.saturating_duration_since(start).as_nanos() as u64;
+        let mut node = None;
+        for frame in frames.iter().rev() {
+            let ip = frame.ip() as usize;
+            if !self.symbols.contains_key(&ip) {
+                self.resolve(ip);
+            }
+            for i in (0..self.symbols[&ip].len()).rev() {
+                let name = self.symbols[&ip][i];
+                node = Some(self.node(name, node));
+            }
+        }
+
+        if !self.events.is_empty() {
+            self.events.push(',');
+        }
+        self.events.push_str("\n{\"name\":");
+        push_json_str(&mut self.events, name);
+        // Times are in microseconds, down to the nanosecond.
+        let _ = write!(
+            self.events,
+            ",\"cat\":\"sample\",\"ph\":\"X\",\"ts\":{}.{:03},\"dur\":{}.{:03}",
+            ts / 1000,
+            ts % 1000,
+            dur / 1000,
+            dur % 1000,
+        );
+        let _ = write!(self.events, ",\"pid\":{},\"tid\":{}", self.pid, tid);
+        if let Some(node) = node {
+            let _ = write!(self.events, ",\"sf\":{}", node);
+        }
+        self.events.push('}');
+    }
+
+    /// Writes the whole trace to `out` as a JSON object.
+    pub fn finish<W: Write>(self, out: W) -> io::Result<()> {
+        let mut out = io::BufWriter::new(out);
+        out.write_all(b"{\"traceEvents\":[")?;
+        out.write_all(self.events.as_bytes())?;
+        out.write_all(b"\n],\n\"stackFrames\":{")?;
+        let mut entry = String::new();
+        for (id, &(name, parent)) in self.nodes.iter().enumerate() {
+            let (name, category) = &self.names[name];
+            entry.clear();
+            if id > 0 {
+                entry.push(',');
+            }
+            let _ = write!(entry, "\n\"{}\":{{\"name\":", id);
+            push_json_str(&mut entry, name);
+            entry.push_str(",\"category\":");
+            push_json_str(&mut entry, category);
+            if let Some(parent) = parent {
+                let _ = write!(entry, ",\"parent\":{}", parent);
+            }
+            entry.push('}');
+            out.write_all(entry.as_bytes())?;
+        }
+        out.write_all(b"\n},\n\"displayTimeUnit\":\"ns\"}\n")?;
+        out.flush()
+    }
+
+    // Resolves `ip` into `self.symbols`.
+    fn resolve(&mut self, ip: usize) {
+        let mut resolved = Vec::new();
+        crate::resolve(ip as *mut _, |symbol| {
+            let name = match symbol.name() {
+                Some(name) => name.to_string(),
+                None => format!("{:#x}", ip),
+            };
+            let category = symbol.crate_name().unwrap_or("unknown").to_string();
+            resolved.push((name, category));
+        });
+        if resolved.is_empty() {
+            resolved.push((format!("{:#x}", ip), "unknown".to_string()));
+        }
+        let ids = resolved.into_iter().map(|name| self.name(name)).collect();
+        self.symbols.insert(ip, ids);
+    }
+
+    fn name(&mut self, name: (String, String)) -> usize {
+        if let Some(&id) = self.name_ids.get(&name) {
+            return id;
+        }
+        let id = self.names.len();
+        self.names.push(name.clone());
+        self.name_ids.insert(name, id);
+        id
+    }
+
+    fn node(&mut self, name: usize, parent: Option<usize>) -> usize {
+        let nodes = &mut self.nodes;
+        *self.node_ids.entry((name, parent)).or_insert_with(|| {
+            nodes.push((name, parent));
+            nodes.len() - 1
+        })
+    }
+}
+
+impl Default for ChromeTraceWriter {
+    fn default() -> ChromeTraceWriter {
+        ChromeTraceWriter::new()
+    }
+}
+
+// Appends `s` to `out` as a JSON string, quoted and escaped.
+fn push_json_str(out: &mut String, s: &str) {
+    out.push('"');
+    for c in s.chars() {
+        match c {
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            '\n' => out.push_str("\\n"),
+            '\r' => out.push_str("\\r"),
+            '\t' => out.push_str("\\t"),
+            c if (c as u32) < 0x20 => {
+                let _ = write!(out, "\\u{:04x}", c as u32);
+            }
+            c => out.push(c),
+        }
+    }
+    out.push('"');
+}
@@ -0,0 +1,138 @@
+use crate::{Frame, Module};
+use std::prelude::v1::*;
+
+/// A hash of a stack which is the same from one run of a program to the
+/// next, as returned by `stack_fingerprint`.
+///
+/// # Required features
+///
+/// This type requires the `std` feature of the `backtrace` crate to be
+/// enabled, and the `std` feature is enabled by default.
+#[non_exhaustive]
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct StackFingerprint {
+    /// The hash of the stack.
+    pub hash: u64,
+    /// How many of the frames went into the hash by their absolute address,
+    /// because the module they're in couldn't be found.
+    pub unstable_frames: usize,
+}
+
+impl StackFingerprint {
+    /// Returns whether every frame was hashed independently of where its
+    /// module was loaded, so that the same stack in another run is sure to
+    /// get the same hash.
+    pub fn is_stable(&self) -> bool {
+        self.unstable_frames == 0
+    }
+}
+
+/// Hashes a stack in a way that doesn't depend on where its modules were
+/// loaded, for comparing and deduplicating stacks across runs.
+///
+/// Address space layout randomization loads the executable and its libraries
+/// at different addresses every time a program runs, so the same logical stack
+/// has different `ip`s from one run to the next. Rather than the `ip`s
+/// themselves, each frame contributes the build ID of the module its `ip` is
+/// in along with the `ip`'s offset into that module, so that the same stack
+/// hashes identically in every run of the same binaries. A module without a
+/// build ID contributes its file name instead.
+///
+/// Modules are found with `loaded_modules`, falling back to
+/// `Frame::module_relative_address` where that isn't supported. Frames for
+/// which neither works, e.g. in code generated at runtime, contribute their
+/// absolute `ip`, and are counted in `unstable_frames` of the result. The
+/// exception is a null `ip`, like that of the frame libgcc reports past the
+/// base of the stack, which is the same in every run.
+///
+/// The hash is 64-bit FNV-1a, which is stable across versions of this crate
+/// and of Rust, but not meant to withstand deliberate collisions.
+///
+/// # Required features
+///
+/// This function requires the `std` feature of the `backtrace` crate to be
+/// enabled, and the `std` feature is enabled by default.
+///
+/// # Example
+///
+/// ```
+/// let mut frames = Vec::new();
+/// backtrace::trace(|frame| {
+///     frames.push(frame.clone());
+///     true
+/// });
+/// let fingerprint = backtrace::stack_fingerprint(&frames);
+/// println!("{:016x} (stable: {})", fingerprint.hash, fingerprint.is_stable());
+/// ```
+pub fn stack_fingerprint(frames: &[Frame]) -> StackFingerprint {
+    let modules = crate::loaded_modules().collect::<Vec<_>>();
+    let mut hasher = Fnv1a::new();
+    let mut unstable_frames = 0;
+    for frame in frames {
+        let ip = frame.ip();
+        match modules.iter().find(|module| module.contains(ip)) {
+            Some(module) => {
+                hasher.write(&[0]);
+                write_module_id(&mut hasher, module);
+                hasher.write_usize(ip as usize - module.base() as usize);
+            }
+            None => match frame.module_relative_address() {
+                Some(offset) => {
+                    hasher.write(&[1]);
+                    hasher.write_usize(offset);
+                }
+                None => {
+                    hasher.write(&[2]);
+                    hasher.write_usize(ip as usize);
+                    if !ip.is_null() {
+                        unstable_frames += 1;
+                    }
+                }
+            },
+        }
+    }
+    StackFingerprint {
+        hash: hasher.finish(),
+        unstable_frames,
+    }
+}
+
+fn write_module_id(hasher: &mut Fnv1a, module: &Module) {
+    match module.build_id() {
+        Some(id) => {
+            hasher.write_usize(id.len());
+            hasher.write(id);
+        }
+        None => {
+            let name = module.name().file_name().unwrap_or_default();
+            let name = name.to_string_lossy();
+            hasher.write_usize(name.len());
+            hasher.write(name.as_bytes());
+        }
+    }
+}
+
+// Hand-rolled rather than `DefaultHasher`, whose algorithm may change between
+// Rust releases.
+struct Fnv1a(u64);
+
+impl Fnv1a {
+    fn new() -> Fnv1a {
+        Fnv1a(0xcbf2_9ce4_8422_2325)
+    }
+
+    fn write(&mut self, bytes: &[u8]) {
+        for &byte in bytes {
+            self.0 ^= u64::from(byte);
+            self.0 = self.0.wrapping_mul(0x0000_0100_0000_01b3);
+        }
+    }
+
+    fn write_usize(&mut self, n: usize) {
+        self.write(&(n as u64).to_le_bytes());
+    }
+
+    fn finish(&self) -> u64 {
+        self.0
+    }
+}
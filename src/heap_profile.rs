@@ -0,0 +1,322 @@
+//! A global allocator wrapper which captures the stacks of a sample of
+//! allocations, as the building block of a heap profiler.
+//!
+//! Capturing happens inside the allocator, so it mustn't allocate or take any
+//! lock an allocation might also want. Stacks are therefore captured with
+//! `trace_unsynchronized_into`, the path which is also used from signal
+//! handlers, into a fixed size ring of samples in a thread local which is
+//! initialized without allocating and has no destructor. A thread local flag
+//! keeps the unwinder from recursing into sampling should it allocate itself.
+
+use crate::backtrace::trace_unsynchronized_into;
+use core::alloc::{GlobalAlloc, Layout};
+use core::cell::{Cell, UnsafeCell};
+use core::ffi::c_void;
+use core::fmt;
+use core::ptr;
+use core::sync::atomic::{AtomicUsize, Ordering};
+
+// The number of samples each thread keeps until they're taken.
+const RING_LEN: usize = 32;
+// The most frames captured per sample.
+const MAX_FRAMES: usize = 32;
+const DEFAULT_SAMPLE_EVERY: usize = 1024;
+
+/// The captured stack of an allocation made through `TracingAlloc`, as
+/// returned by `take_allocation_samples`.
+///
+/// # Required features
+///
+/// This type requires the `heap-profile` feature of the `backtrace` crate to
+/// be enabled.
+#[derive(Clone, Copy)]
+pub struct AllocationSample {
+    ptr: *mut u8,
+    size: usize,
+    len: usize,
+    truncated: bool,
+    ips: [*mut c_void; MAX_FRAMES],
+}
+
+impl AllocationSample {
+    const EMPTY: AllocationSample = AllocationSample {
+        ptr: ptr::null_mut(),
+        size: 0,
+        len: 0,
+        truncated: false,
+        ips: [ptr::null_mut(); MAX_FRAMES],
+    };
+
+    /// Returns the pointer the allocator returned.
+    ///
+    /// The memory may well have been freed, and the pointer handed out again,
+    /// since.
+    pub fn ptr(&self) -> *mut u8 {
+        self.ptr
+    }
+
+    /// Returns the size in bytes of the allocation.
+    pub fn size(&self) -> usize {
+        self.size
+    }
+
+    /// Returns the instruction pointers of the stack which made the
+    /// allocation, innermost first, like those written by `trace_into`.
+    ///
+    /// The innermost frames are those of `TracingAlloc` itself and of the
+    /// unwinder.
+    pub fn ips(&self) -> &[*mut c_void] {
+        &self.ips[..self.len]
+    }
+
+    /// Returns whether the stack had more frames than were captured, in which
+    /// case the outermost frames are missing.
+    pub fn is_truncated(&self) -> bool {
+        self.truncated
+    }
+}
+
+impl fmt::Debug for AllocationSample {
+    fn fmt(&self, fmt: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt.debug_struct("AllocationSample")
+            .field("ptr", &self.ptr)
+            .field("size", &self.size)
+            .field("ips", &self.ips())
+            .field("truncated", &self.truncated)
+            .finish()
+    }
+}
+
+struct Ring {
+    samples: [AllocationSample; RING_LEN],
+    start: usize,
+    len: usize,
+    dropped: usize,
+}
+
+impl Ring {
+    // Returns the slot for a new sample, overwriting the oldest one if the
+    // ring is full.
+    fn push(&mut self) -> &mut AllocationSample {
+        let index = if self.len == RING_LEN {
+            let index = self.start;
+            self.start = (self.start + 1) % RING_LEN;
+            self.dropped += 1;
+            index
+        } else {
+            self.len += 1;
+            (self.start + self.len - 1) % RING_LEN
+        };
+        &mut self.samples[index]
+    }
+
+    fn pop(&mut self) -> Option<AllocationSample> {
+        if self.len == 0 {
+            return None;
+        }
+        let sample = self.samples[self.start];
+        self.start = (self.start + 1) % RING_LEN;
+        self.len -= 1;
+        Some(sample)
+    }
+}
+
+thread_local! {
+    static RING: UnsafeCell<Ring> = UnsafeCell::new(Ring {
+        samples: [AllocationSample::EMPTY; RING_LEN],
+        start: 0,
+        len: 0,
+        dropped: 0,
+    });
+    // Allocations left on this thread until the next one is sampled.
+    static COUNTDOWN: Cell<usize> = Cell::new(0);
+    static SAMPLING: Cell<bool> = Cell::new(false);
+}
+
+/// A `GlobalAlloc` which passes allocations on to another allocator, capturing
+/// the stack of one in every so many of them.
+///
+/// Each thread counts its own allocations, and every `sample_every`th one has
+/// the instruction pointers of its stack captured, up to `max_frames` of them,
+/// along with the pointer returned and the size. Samples are kept in a ring
+/// of 32 per thread, from which they're taken with `take_allocation_samples`;
+/// should a thread sample more than that in between, its oldest samples are
+/// overwritten. Reallocations are sampled as new allocations, while freeing
+/// memory isn't recorded at all, so a profiler wanting to know what's live
+/// should match samples up with frees itself.
+///
+/// Only instruction pointers are captured, as symbolizing them would allocate.
+/// They can be resolved later with `resolve`, or `BacktraceFrame`s made from
+/// them.
+///
+/// Capturing a stack is much slower than allocating, so the sampling rate
+/// should be kept low in production. By default one in 1024 allocations is
+/// sampled, capturing at most 32 frames. A rate of 0 turns sampling off.
+///
+/// Since stacks are captured without the lock `trace` takes, this isn't safe
+/// to use on platforms whose unwinder isn't thread safe, which this crate
+/// only supports on Unix.
+///
+/// # Required features
+///
+/// This type requires the `heap-profile` feature of the `backtrace` crate to
+/// be enabled.
+///
+/// # Example
+///
+/// ```
+/// use backtrace::TracingAlloc;
+/// use std::alloc::System;
+///
+/// #[global_allocator]
+/// static ALLOC: TracingAlloc<System> = TracingAlloc::new(System);
+///
+/// ALLOC.set_sample_every(1);
+/// let v = vec![0u8; 100];
+/// backtrace::take_allocation_samples(|sample| {
+///     println!("{} bytes at {:?}, {} frames", sample.size(), sample.ptr(), sample.ips().len());
+/// });
+/// drop(v);
+/// ```
+pub struct TracingAlloc<A> {
+    inner: A,
+    sample_every: AtomicUsize,
+    max_frames: AtomicUsize,
+}
+
+impl<A> TracingAlloc<A> {
+    /// Wraps `inner`, sampling one in 1024 allocations with at most 32 frames.
+    pub const fn new(inner: A) -> TracingAlloc<A> {
+        TracingAlloc::with_sampling(inner, DEFAULT_SAMPLE_EVERY, MAX_FRAMES)
+    }
+
+    /// Wraps `inner`, sampling one in `sample_every` allocations with at most
+    /// `max_frames` frames, which is itself capped at 32.
+    pub const fn with_sampling(
+        inner: A,
+        sample_every: usize,
+        max_frames: usize,
+    ) -> TracingAlloc<A> {
+        TracingAlloc {
+            inner,
+            sample_every: AtomicUsize::new(sample_every),
+            max_frames: AtomicUsize::new(max_frames),
+        }
+    }
+
+    /// Samples one in every `sample_every` allocations from now on, or none
+    /// if it's 0.
+    pub fn set_sample_every(&self, sample_every: usize) {
+        self.sample_every.store(sample_every, Ordering::Relaxed);
+    }
+
+    /// Captures at most `max_frames` frames, capped at 32, of each sample
+    /// from now on.
+    pub fn set_max_frames(&self, max_frames: usize) {
+        self.max_frames.store(max_frames, Ordering::Relaxed);
+    }
+
+    /// Returns the allocator allocations are passed on to.
+    pub fn inner(&self) -> &A {
+        &self.inner
+    }
+
+    fn maybe_sample(&self, ptr: *mut u8, size: usize) {
+        if ptr.is_null() {
+            return;
+        }
+        let sample_every = self.sample_every.load(Ordering::Relaxed);
+        if sample_every == 0 {
+            return;
+        }
+        // The thread locals may be gone if this thread is exiting.
+        let due = COUNTDOWN
+            .try_with(|countdown| match countdown.get() {
+                0 | 1 => {
+                    countdown.set(sample_every);
+                    true
+                }
+                n => {
+                    countdown.set(n.min(sample_every) - 1);
+                    false
+                }
+            })
+            .unwrap_or(false);
+        if due {
+            self.sample(ptr, size);
+        }
+    }
+
+    #[inline(never)]
+    fn sample(&self, ptr: *mut u8, size: usize) {
+        let max_frames = self.max_frames.load(Ordering::Relaxed).min(MAX_FRAMES);
+        let _ = SAMPLING.try_with(|sampling| {
+            if sampling.replace(true) {
+                return;
+            }
+            let _ = RING.try_with(|ring| unsafe {
+                let slot = (*ring.get()).push();
+                let result = trace_unsynchronized_into(&mut slot.ips[..max_frames]);
+                slot.ptr = ptr;
+                slot.size = size;
+                slot.len = result.len;
+                slot.truncated = result.truncated;
+            });
+            sampling.set(false);
+        });
+    }
+}
+
+unsafe impl<A: GlobalAlloc> GlobalAlloc for TracingAlloc<A> {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        let ptr = self.inner.alloc(layout);
+        self.maybe_sample(ptr, layout.size());
+        ptr
+    }
+
+    unsafe fn alloc_zeroed(&self, layout: Layout) -> *mut u8 {
+        let ptr = self.inner.alloc_zeroed(layout);
+        self.maybe_sample(ptr, layout.size());
+        ptr
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        self.inner.dealloc(ptr, layout)
+    }
+
+    unsafe fn realloc(&self, ptr: *mut u8, layout: Layout, new_size: usize) -> *mut u8 {
+        let ptr = self.inner.realloc(ptr, layout, new_size);
+        self.maybe_sample(ptr, new_size);
+        ptr
+    }
+}
+
+/// Takes the allocation samples captured on the calling thread by
+/// `TracingAlloc`, oldest first, passing each to `f`.
+///
+/// Returns the number of samples which were overwritten before they could be
+/// taken, since the last call.
+///
+/// Allocations made by `f` may be sampled in turn, and are left for a later
+/// call unless the ring overflows in the meantime.
+///
+/// # Required features
+///
+/// This function requires the `heap-profile` feature of the `backtrace` crate
+/// to be enabled.
+pub fn take_allocation_samples<F: FnMut(&AllocationSample)>(mut f: F) -> usize {
+    // Samples are copied out one at a time so that the ring isn't borrowed
+    // while `f` runs and perhaps allocates.
+    let (len, dropped) = RING.with(|ring| unsafe {
+        let ring = &mut *ring.get();
+        (ring.len, core::mem::replace(&mut ring.dropped, 0))
+    });
+    for _ in 0..len {
+        let sample = match RING.with(|ring| unsafe { (*ring.get()).pop() }) {
+            Some(sample) => sample,
+            None => break,
+        };
+        f(&sample);
+    }
+    dropped
+}
@@ -75,11 +75,11 @@
 //! * Not all platforms are supported. For example there's no way to get a
 //!   backtrace on WebAssembly at the moment.
 //!
-//! * Crate features may be disabled. Currently this crate supports using Gimli
-//!   libbacktrace on non-Windows platforms for reading debuginfo for
-//!   backtraces. If both crate features are disabled, however, then these
-//!   platforms will generate a backtrace but be unable to generate symbols for
-//!   it.
+//! * Crate features may be disabled. Symbols are only looked up with the
+//!   `symbolize` feature, which is enabled by default and pulls in Gimli for
+//!   reading debuginfo. If it's disabled backtraces are still generated but
+//!   without any symbols, which suits programs that only record raw addresses
+//!   and symbolize them elsewhere.
 //!
 //! In most standard workflows for most standard platforms you generally don't
 //! need to worry about these caveats. We'll try to fix ones where we can over
@@ -101,6 +101,14 @@
 // We know this is deprecated, it's only here for back-compat reasons.
 #![cfg_attr(feature = "rustc-serialize", allow(deprecated))]
 
+// The external unwinders export the same `unw_*` API under different symbol
+// names, so linking against both can't work.
+#[cfg(all(feature = "llvm-unwind", feature = "nongnu-unwind"))]
+compile_error!(
+    "the `llvm-unwind` and `nongnu-unwind` features select different unwinding \
+     libraries and can't be enabled at the same time"
+);
+
 #[cfg(feature = "std")]
 #[macro_use]
 extern crate std;
@@ -110,20 +118,76 @@ extern crate std;
 #[allow(unused_extern_crates)]
 extern crate alloc;
 
-pub use self::backtrace::{trace_unsynchronized, Frame};
+pub use self::backtrace::{
+    current_thread_pointer, trace_frame_pointers, trace_unsynchronized, trace_unsynchronized_debug,
+    trace_unsynchronized_detect_loops, trace_unsynchronized_filtered, trace_unsynchronized_into,
+    trace_unsynchronized_status, trace_unsynchronized_with_end,
+    trace_unsynchronized_without_thread_entry, unwinder_available, Frame, FrameOrder, ProcInfo,
+    TraceInto, TraceOptions, TraceOutcome, MAX_RECOMMENDED_FRAMES,
+};
+
+#[cfg(all(
+    any(target_os = "linux", target_os = "android"),
+    any(target_arch = "x86_64", target_arch = "aarch64"),
+    not(backtrace_no_asm),
+))]
+pub use self::backtrace::trace_unsynchronized_on_altstack;
+
+#[cfg(all(
+    not(miri),
+    any(
+        all(target_os = "linux", target_env = "gnu", target_arch = "x86_64"),
+        all(
+            any(target_os = "linux", target_os = "android"),
+            target_arch = "aarch64"
+        ),
+        all(target_os = "linux", target_env = "gnu", target_arch = "arm"),
+    ),
+))]
+pub use self::backtrace::trace_from_siginfo;
+
+#[cfg(feature = "fallback-unwind")]
+pub use self::backtrace::{trace_unsynchronized_with_fallback, FallbackTrace, UnwindBackend};
 
 #[cfg(any(feature = "llvm-unwind", feature = "nongnu-unwind"))]
-pub use self::backtrace::trace_unsynchronized_external_api;
+pub use self::backtrace::{
+    configure_unwind_caching, trace_unsynchronized_external_api,
+    trace_unsynchronized_external_api_with_registers,
+    trace_unsynchronized_external_api_with_scratch, CachingPolicy, UnwContext, UnwCursor,
+    UnwindError,
+};
+
+#[cfg(all(
+    feature = "std",
+    any(feature = "llvm-unwind", feature = "nongnu-unwind")
+))]
+pub use self::backtrace::{self_test, SELF_TEST_DEPTH};
+
+#[cfg(all(
+    feature = "remote-unwind",
+    any(target_os = "linux", target_os = "android"),
+    any(target_arch = "x86_64", target_arch = "aarch64"),
+))]
+pub use self::backtrace::{
+    trace_remote, RemoteAddressSpace, RemoteAddressSpaceBuilder, RemoteUnwindTable,
+};
 
 mod backtrace;
 
-pub use self::symbolize::resolve_frame_unsynchronized;
+pub use self::symbolize::{cache_stats, CacheCounters, CacheStats};
+pub use self::symbolize::{resolve_addr_unsynchronized, resolve_frame_unsynchronized};
 pub use self::symbolize::{resolve_unsynchronized, Symbol, SymbolName};
 mod symbolize;
 
 pub use self::types::BytesOrWideString;
 mod types;
 
+pub use self::registers::{
+    current_arch, dwarf_register_name, pointer_width, Aarch64Regs, Arch, RegisterContext,
+    X86_64Regs,
+};
+mod registers;
+
 #[cfg(feature = "std")]
 pub use self::symbolize::clear_symbol_cache;
 
@@ -132,13 +196,83 @@ pub use print::{BacktraceFmt, BacktraceFrameFmt, PrintFmt};
 
 cfg_if::cfg_if! {
     if #[cfg(feature = "std")] {
-        pub use self::backtrace::trace;
-        pub use self::symbolize::{resolve, resolve_frame};
-        pub use self::capture::{Backtrace, BacktraceFrame, BacktraceSymbol};
+        pub use self::backtrace::{
+            trace, trace_catch, trace_debug, trace_detect_loops, trace_filtered, trace_into,
+            trace_ordered, trace_status, trace_timed, trace_with_end, trace_without_thread_entry,
+        };
+        #[cfg(feature = "fallback-unwind")]
+        pub use self::backtrace::trace_with_fallback;
+        #[cfg(all(
+            any(target_os = "linux", target_os = "android"),
+            any(target_arch = "x86_64", target_arch = "aarch64"),
+            not(backtrace_no_asm),
+        ))]
+        pub use self::backtrace::trace_on_altstack;
+        #[cfg(all(
+            any(target_os = "linux", target_os = "android"),
+            any(target_arch = "x86_64", target_arch = "aarch64"),
+        ))]
+        pub use self::backtrace::trace_with_timeout;
+        pub use self::symbolize::{
+            current_function, prewarm, register_demangler, resolve, resolve_addr, resolve_frame,
+            resolve_iter, resolve_leaf, resolve_lines, resolve_stack, resolve_stack_deadline,
+            resolve_status, snapshot_modules, ModuleSnapshot, ResolveStatus, Symbolizer,
+        };
+        pub use self::capture::{
+            capture_with_stack_memory, resolved_backtrace, set_depth_histogram, Backtrace,
+            BacktraceFrame, BacktraceSymbol, BacktraceWithStackMemory, ResolvedBacktrace,
+        };
         mod capture;
+        pub use self::source::{SourceContext, SourceSnippet};
+        mod source;
+        pub use self::aggregate::{DepthHistogram, SampleAggregator};
+        mod aggregate;
+        pub use self::fingerprint::{stack_fingerprint, StackFingerprint};
+        mod fingerprint;
+        pub use self::pack::{pack_stack, unpack_stack, PackedFrame, PackedModuleId, UnpackedStack};
+        mod pack;
+        pub use self::print::{
+            format_addr2line_style, is_frame_boundary, set_frame_boundary_predicate,
+            set_symbol_filter, GdbFmt,
+        };
+        pub use self::modules::{loaded_modules, on_module_change, Module, SegmentFlags};
+        mod modules;
+        #[cfg(all(feature = "remote-thread", target_os = "linux"))]
+        pub use self::remote_thread::{
+            dump_all_threads, dump_all_threads_frozen, remote_thread_signal, set_sampling_signal,
+            thread_backtrace, ThreadDump,
+        };
+        #[cfg(all(feature = "remote-thread", target_os = "linux"))]
+        mod remote_thread;
+        #[cfg(all(feature = "perf-export", any(target_os = "linux", target_os = "android")))]
+        pub use self::perf_export::PerfDataWriter;
+        #[cfg(all(feature = "perf-export", any(target_os = "linux", target_os = "android")))]
+        mod perf_export;
+        #[cfg(all(feature = "heap-profile", unix))]
+        pub use self::heap_profile::{take_allocation_samples, AllocationSample, TracingAlloc};
+        #[cfg(all(feature = "heap-profile", unix))]
+        mod heap_profile;
+        #[cfg(all(feature = "per-cpu", any(target_os = "linux", target_os = "android")))]
+        pub use self::per_cpu::{PerCpuSample, PerCpuSampleBuffers};
+        #[cfg(all(feature = "per-cpu", any(target_os = "linux", target_os = "android")))]
+        mod per_cpu;
+        #[cfg(feature = "chrome-trace")]
+        pub use self::chrome_trace::ChromeTraceWriter;
+        #[cfg(feature = "chrome-trace")]
+        mod chrome_trace;
+        #[cfg(all(feature = "ring-file", unix, backtrace_has_atomic_64))]
+        pub use self::ring_file::{read_ring_file, RingFileSink, RingSample};
+        #[cfg(all(feature = "ring-file", unix, backtrace_has_atomic_64))]
+        mod ring_file;
     }
 }
 
+// Armed around calls to user closures made from within an unwinder, so that a
+// panic escaping the closure turns into a double panic, and so an abort,
+// rather than unwinding through the unwinder's C frames.
+//
+// The `no-abort-on-panic` feature disarms it for callers which promise their
+// closures never panic; see the feature's docs in `Cargo.toml`.
 #[allow(dead_code)]
 struct Bomb {
     enabled: bool,
@@ -147,8 +281,11 @@ struct Bomb {
 #[allow(dead_code)]
 impl Drop for Bomb {
     fn drop(&mut self) {
-        if self.enabled {
-            panic!("cannot panic during the backtrace function");
+        #[cfg(not(feature = "no-abort-on-panic"))]
+        {
+            if self.enabled {
+                panic!("cannot panic during the backtrace function");
+            }
         }
     }
 }
@@ -189,6 +326,22 @@ mod lock {
             LockGuard(Some((*LOCK).lock().unwrap()))
         }
     }
+
+    // Same as `lock`, except that this returns `None` rather than waiting if
+    // another thread holds the lock.
+    pub fn try_lock() -> Option<LockGuard> {
+        if LOCK_HELD.with(|l| l.get()) {
+            return Some(LockGuard(None));
+        }
+        let guard = unsafe {
+            INIT.call_once(|| {
+                LOCK = Box::into_raw(Box::new(Mutex::new(())));
+            });
+            (*LOCK).try_lock().ok()?
+        };
+        LOCK_HELD.with(|s| s.set(true));
+        Some(LockGuard(Some(guard)))
+    }
 }
 
 #[cfg(all(windows, not(target_vendor = "uwp")))]
@@ -0,0 +1,875 @@
+//! Enumeration of the objects (executable and shared libraries) currently
+//! loaded into this process.
+//!
+//! Symbolicating a backtrace in-process only ever needs to look up the one
+//! module an address falls in, but exporting raw addresses for offline
+//! symbolication needs the whole mapping table: which file was loaded where,
+//! how large it is, and its build ID so the matching debug info can be found
+//! later.
+
+use core::ffi::c_void;
+use core::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::fmt;
+use std::path::{Path, PathBuf};
+use std::prelude::v1::*;
+use std::sync::{Arc, Mutex, Once};
+
+/// Returns an iterator over all modules currently loaded into this process.
+///
+/// The first module yielded is typically the main executable, followed by each
+/// shared library in load order. The list is a snapshot taken when this
+/// function is called, so libraries loaded with `dlopen` afterwards will only
+/// show up in a subsequent call.
+///
+/// On platforms where the list of loaded modules can't be learned this
+/// returns an empty iterator.
+///
+/// # Required features
+///
+/// This function requires the `std` feature of the `backtrace` crate to be
+/// enabled, and the `std` feature is enabled by default.
+///
+/// # Example
+///
+/// ```
+/// for module in backtrace::loaded_modules() {
+///     println!("{:?} @ {:?} ({} bytes)", module.name(), module.base(), module.len());
+/// }
+/// ```
+pub fn loaded_modules() -> impl Iterator<Item = Module> {
+    imp::native_modules().into_iter()
+}
+
+/// Registers a hook which is invoked whenever libraries are loaded into or
+/// unloaded from this process.
+///
+/// Changes are not observed as they happen, but are instead detected lazily
+/// the next time `trace`, `resolve`, or `resolve_frame` is called. At that
+/// point this crate's own caches of loaded libraries and parsed debug info are
+/// invalidated, and then every registered hook is run, in registration order.
+/// This is a good place to refresh a mapping table built from
+/// `loaded_modules`.
+///
+/// Hooks are run on whichever thread noticed the change, while this crate's
+/// global lock is held. They may call back into this crate, but must not wait
+/// on other threads which might do so.
+///
+/// Changes are looked for whether or not any hooks are registered, since this
+/// crate's own caches depend on it, and only cost a call to `dl_iterate_phdr`
+/// which stops at the first library. A hook registered after libraries were
+/// loaded or unloaded isn't run for those changes.
+///
+/// On platforms which don't track a load generation (anything without
+/// `dl_iterate_phdr`'s `dlpi_adds`/`dlpi_subs` counters) hooks are never
+/// invoked, and this crate's caches are never invalidated.
+///
+/// # Required features
+///
+/// This function requires the `std` feature of the `backtrace` crate to be
+/// enabled, and the `std` feature is enabled by default.
+pub fn on_module_change<F>(f: F)
+where
+    F: Fn() + Send + Sync + 'static,
+{
+    hooks().lock().unwrap().push(Arc::new(f));
+    HOOKED.store(true, Ordering::Release);
+}
+
+type Hook = Arc<dyn Fn() + Send + Sync>;
+
+// `Mutex::new` can't be used in a `static`, so the list of hooks is created
+// on first use, like the global lock.
+fn hooks() -> &'static Mutex<Vec<Hook>> {
+    static mut HOOKS: *const Mutex<Vec<Hook>> = core::ptr::null();
+    static INIT: Once = Once::new();
+    unsafe {
+        INIT.call_once(|| {
+            HOOKS = Box::into_raw(Box::new(Mutex::new(Vec::new())));
+        });
+        &*HOOKS
+    }
+}
+
+// Whether any hooks have been registered, so that `check_for_changes` can
+// skip taking their lock when there are none.
+static HOOKED: AtomicBool = AtomicBool::new(false);
+
+// The load generation last observed by `check_for_changes`, where zero means
+// it hasn't been observed yet.
+static GENERATION: AtomicUsize = AtomicUsize::new(0);
+
+// The bounds of the main executable, which never moves once it's loaded, so
+// they're only looked up once. An empty range means they couldn't be found.
+static MAIN_EXECUTABLE: [AtomicUsize; 2] = [AtomicUsize::new(0), AtomicUsize::new(0)];
+static MAIN_EXECUTABLE_KNOWN: AtomicBool = AtomicBool::new(false);
+
+// Returns whether `addr` lies within the main executable rather than a shared
+// library.
+pub(crate) fn in_main_executable(addr: usize) -> bool {
+    if !MAIN_EXECUTABLE_KNOWN.load(Ordering::Acquire) {
+        // Racing threads all find the same bounds, so it doesn't matter which
+        // stores them.
+        let (start, end) = imp::main_executable_range().unwrap_or((0, 0));
+        MAIN_EXECUTABLE[0].store(start, Ordering::Relaxed);
+        MAIN_EXECUTABLE[1].store(end, Ordering::Relaxed);
+        MAIN_EXECUTABLE_KNOWN.store(true, Ordering::Release);
+    }
+    let start = MAIN_EXECUTABLE[0].load(Ordering::Relaxed);
+    let end = MAIN_EXECUTABLE[1].load(Ordering::Relaxed);
+    start <= addr && addr < end
+}
+
+// Returns a counter which changes whenever a library is loaded or unloaded,
+// if the platform provides one.
+pub(crate) fn generation() -> Option<usize> {
+    imp::generation()
+}
+
+// Returns the permissions of the loaded segment `addr` lies in, if any.
+pub(crate) fn segment_flags(addr: usize) -> Option<SegmentFlags> {
+    imp::segment_flags(addr).map(|bits| SegmentFlags { bits })
+}
+
+// The PLT sections of each module `in_plt` has looked at. Section headers
+// aren't loaded into memory, so finding them means reading the module's file,
+// which is only done once per module, until `check_for_changes` notices
+// libraries coming and going. Created on first use like the list of hooks.
+fn plt_sections() -> &'static Mutex<Vec<PltSections>> {
+    static mut PLT_SECTIONS: *const Mutex<Vec<PltSections>> = core::ptr::null();
+    static INIT: Once = Once::new();
+    unsafe {
+        INIT.call_once(|| {
+            PLT_SECTIONS = Box::into_raw(Box::new(Mutex::new(Vec::new())));
+        });
+        &*PLT_SECTIONS
+    }
+}
+
+struct PltSections {
+    path: PathBuf,
+    bias: usize,
+    // Stated virtual memory addresses, as in the file.
+    ranges: Vec<(usize, usize)>,
+}
+
+// Returns whether `addr` lies within one of the PLT sections of the module
+// it's in, or `false` if the module or its section headers can't be found.
+pub(crate) fn in_plt(addr: usize) -> bool {
+    let (path, bias) = match imp::module_file(addr) {
+        Some(module) => module,
+        None => return false,
+    };
+    let mut cache = plt_sections().lock().unwrap();
+    let i = match cache.iter().position(|m| m.bias == bias && m.path == path) {
+        Some(i) => i,
+        None => {
+            let ranges = imp::plt_sections(&path);
+            cache.push(PltSections { path, bias, ranges });
+            cache.len() - 1
+        }
+    };
+    let svma = addr.wrapping_sub(bias);
+    cache[i]
+        .ranges
+        .iter()
+        .any(|&(start, end)| start <= svma && svma < end)
+}
+
+// Checks whether the set of loaded modules has changed since the last call,
+// and if so invalidates the symbolication caches and runs the hooks registered
+// with `on_module_change`.
+//
+// This must be called with the global lock held.
+pub(crate) fn check_for_changes() {
+    let generation = match imp::generation() {
+        Some(generation) => generation,
+        None => return,
+    };
+    let previous = GENERATION.swap(generation, Ordering::Relaxed);
+    if previous == 0 || previous == generation {
+        return;
+    }
+
+    unsafe {
+        crate::symbolize::clear_library_cache();
+    }
+    plt_sections().lock().unwrap().clear();
+
+    if !HOOKED.load(Ordering::Acquire) {
+        return;
+    }
+    // Don't hold the hook lock while running hooks, so they're free to
+    // register more hooks.
+    let hooks = hooks().lock().unwrap().clone();
+    for hook in hooks {
+        hook();
+    }
+}
+
+/// A module (the executable or a shared library) that is loaded into the
+/// current process.
+///
+/// Instances of this type are yielded by `loaded_modules`.
+#[derive(Clone)]
+pub struct Module {
+    name: PathBuf,
+    base: usize,
+    len: usize,
+    bias: usize,
+    build_id: Option<Vec<u8>>,
+}
+
+impl Module {
+    /// Returns the path this module was loaded from.
+    ///
+    /// For the main executable this is the path of the current executable, if
+    /// it could be determined, and may otherwise be empty.
+    pub fn name(&self) -> &Path {
+        &self.name
+    }
+
+    /// Returns the lowest address at which this module is mapped into memory.
+    pub fn base(&self) -> *mut c_void {
+        self.base as *mut c_void
+    }
+
+    /// Returns the number of bytes spanned by this module's loaded segments,
+    /// starting at `base`.
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    /// Returns whether this module has no loaded segments.
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// Returns the difference between the addresses stated in the object file
+    /// and where they are actually loaded into memory.
+    ///
+    /// Subtracting this from a runtime address yields the address that the
+    /// module's debug info and symbol table use.
+    pub fn bias(&self) -> usize {
+        self.bias
+    }
+
+    /// Returns the build ID of this module, if it has one.
+    ///
+    /// On ELF platforms this is the contents of the `NT_GNU_BUILD_ID` note,
+    /// which can be used to look up separate debug info for the module.
+    pub fn build_id(&self) -> Option<&[u8]> {
+        self.build_id.as_ref().map(|id| &id[..])
+    }
+
+    /// Returns whether `addr` falls within the range spanned by this module.
+    pub fn contains(&self, addr: *mut c_void) -> bool {
+        let addr = addr as usize;
+        self.base <= addr && addr - self.base < self.len
+    }
+}
+
+impl fmt::Debug for Module {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Module")
+            .field("name", &self.name)
+            .field("base", &self.base())
+            .field("len", &self.len)
+            .field("build_id", &self.build_id)
+            .finish()
+    }
+}
+
+/// The permissions a loaded segment of a module is mapped with, as returned
+/// by `Frame::segment_flags`.
+///
+/// These are the `p_flags` of the segment's ELF program header, which is
+/// what the loader maps it with. They don't reflect later `mprotect` calls.
+#[derive(Clone, Copy, PartialEq, Eq, Hash)]
+pub struct SegmentFlags {
+    bits: u32,
+}
+
+impl SegmentFlags {
+    const EXECUTE: u32 = 1;
+    const WRITE: u32 = 2;
+    const READ: u32 = 4;
+
+    /// Returns whether the segment is readable.
+    pub fn is_readable(&self) -> bool {
+        self.bits & SegmentFlags::READ != 0
+    }
+
+    /// Returns whether the segment is writable.
+    pub fn is_writable(&self) -> bool {
+        self.bits & SegmentFlags::WRITE != 0
+    }
+
+    /// Returns whether the segment is executable.
+    pub fn is_executable(&self) -> bool {
+        self.bits & SegmentFlags::EXECUTE != 0
+    }
+
+    /// Returns the raw `PF_R`, `PF_W` and `PF_X` bits, along with any
+    /// processor or OS specific ones.
+    pub fn bits(&self) -> u32 {
+        self.bits
+    }
+}
+
+impl fmt::Debug for SegmentFlags {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let flag = |set, c| if set { c } else { '-' };
+        write!(
+            f,
+            "SegmentFlags({}{}{})",
+            flag(self.is_readable(), 'r'),
+            flag(self.is_writable(), 'w'),
+            flag(self.is_executable(), 'x'),
+        )
+    }
+}
+
+cfg_if::cfg_if! {
+    if #[cfg(all(
+        any(
+            target_os = "linux",
+            target_os = "fuchsia",
+            target_os = "freebsd",
+            target_os = "openbsd",
+            all(target_os = "android", feature = "dl_iterate_phdr"),
+        ),
+        not(target_env = "uclibc"),
+    ))] {
+        mod imp {
+            use super::Module;
+            use core::{mem, slice};
+            use std::env;
+            use std::ffi::{CStr, OsStr};
+            use std::fs::File;
+            use std::os::unix::prelude::*;
+            use std::path::{Path, PathBuf};
+            use std::prelude::v1::*;
+
+            // Defined here rather than taken from `object`, which is only a
+            // dependency when the `symbolize` feature is enabled.
+            const PT_LOAD: u32 = 1;
+            const PT_NOTE: u32 = 4;
+            const NT_GNU_BUILD_ID: u32 = 3;
+            const ELF_NOTE_GNU: &[u8] = b"GNU";
+            // `.plt.sec` and `.plt.bnd` hold the second half of each PLT entry
+            // when CET or MPX are enabled, and `.iplt` the entries of `IFUNC`
+            // symbols in static executables.
+            const PLT_SECTION_NAMES: &[&[u8]] =
+                &[b".plt", b".plt.sec", b".plt.got", b".plt.bnd", b".iplt"];
+
+            pub fn native_modules() -> Vec<Module> {
+                let mut ret = Vec::new();
+                unsafe {
+                    libc::dl_iterate_phdr(Some(callback), &mut ret as *mut Vec<_> as *mut _);
+                }
+                ret
+            }
+
+            // `info` should be a valid pointers.
+            // `vec` should be a valid pointer to a `std::Vec`.
+            unsafe extern "C" fn callback(
+                info: *mut libc::dl_phdr_info,
+                _size: libc::size_t,
+                vec: *mut libc::c_void,
+            ) -> libc::c_int {
+                let info = &*info;
+                let modules = &mut *(vec as *mut Vec<Module>);
+                let is_main_prog = info.dlpi_name.is_null() || *info.dlpi_name == 0;
+                let name = if is_main_prog {
+                    if modules.is_empty() {
+                        env::current_exe().unwrap_or_default()
+                    } else {
+                        PathBuf::new()
+                    }
+                } else {
+                    let bytes = CStr::from_ptr(info.dlpi_name).to_bytes();
+                    PathBuf::from(OsStr::from_bytes(bytes))
+                };
+                let bias = info.dlpi_addr as usize;
+                let (start, end) = load_range(info);
+
+                let headers = slice::from_raw_parts(info.dlpi_phdr, info.dlpi_phnum as usize);
+                // The ELF header is mapped along with the segment which
+                // starts at the beginning of the file.
+                let endian = headers
+                    .iter()
+                    .find(|header| header.p_type == PT_LOAD && header.p_offset == 0)
+                    .and_then(|header| {
+                        header_endian(bias.wrapping_add(header.p_vaddr as usize) as *const u8)
+                    })
+                    .unwrap_or(Endian::NATIVE);
+                let mut build_id = None;
+                for header in headers {
+                    if header.p_type == PT_NOTE {
+                        let vaddr = bias.wrapping_add(header.p_vaddr as usize);
+                        let memsz = header.p_memsz as usize;
+                        let notes = slice::from_raw_parts(vaddr as *const u8, memsz);
+                        build_id = find_build_id(notes, endian).map(|id| id.to_vec());
+                        if build_id.is_some() {
+                            break;
+                        }
+                    }
+                }
+
+                modules.push(Module {
+                    name,
+                    base: start,
+                    len: end - start,
+                    bias,
+                    build_id,
+                });
+                0
+            }
+
+            // Returns the range of addresses covered by the `PT_LOAD` segments
+            // of the module described by `info`, which is empty if it has no
+            // segments.
+            unsafe fn load_range(info: &libc::dl_phdr_info) -> (usize, usize) {
+                let bias = info.dlpi_addr as usize;
+                let mut start = core::usize::MAX;
+                let mut end = 0;
+                let headers = slice::from_raw_parts(info.dlpi_phdr, info.dlpi_phnum as usize);
+                for header in headers {
+                    if header.p_type == PT_LOAD {
+                        let vaddr = bias.wrapping_add(header.p_vaddr as usize);
+                        start = start.min(vaddr);
+                        end = end.max(vaddr.wrapping_add(header.p_memsz as usize));
+                    }
+                }
+                if start > end {
+                    (bias, bias)
+                } else {
+                    (start, end)
+                }
+            }
+
+            // Returns the range of addresses the main executable is loaded
+            // at, i.e. the first module without a name.
+            pub fn main_executable_range() -> Option<(usize, usize)> {
+                unsafe extern "C" fn callback(
+                    info: *mut libc::dl_phdr_info,
+                    _size: libc::size_t,
+                    range: *mut libc::c_void,
+                ) -> libc::c_int {
+                    let info = &*info;
+                    if !info.dlpi_name.is_null() && *info.dlpi_name != 0 {
+                        return 0;
+                    }
+                    *(range as *mut Option<(usize, usize)>) = Some(load_range(info));
+                    1
+                }
+
+                let mut range = None;
+                unsafe {
+                    libc::dl_iterate_phdr(Some(callback), &mut range as *mut Option<_> as *mut _);
+                }
+                range
+            }
+
+            // Returns the `p_flags` of the `PT_LOAD` segment `addr` lies in.
+            pub fn segment_flags(addr: usize) -> Option<u32> {
+                struct Search {
+                    addr: usize,
+                    flags: Option<u32>,
+                }
+
+                unsafe extern "C" fn callback(
+                    info: *mut libc::dl_phdr_info,
+                    _size: libc::size_t,
+                    search: *mut libc::c_void,
+                ) -> libc::c_int {
+                    let info = &*info;
+                    let search = &mut *(search as *mut Search);
+                    let bias = info.dlpi_addr as usize;
+                    let headers = slice::from_raw_parts(info.dlpi_phdr, info.dlpi_phnum as usize);
+                    for header in headers {
+                        let vaddr = bias.wrapping_add(header.p_vaddr as usize);
+                        if header.p_type == PT_LOAD
+                            && vaddr <= search.addr
+                            && search.addr - vaddr < header.p_memsz as usize
+                        {
+                            search.flags = Some(header.p_flags);
+                            return 1;
+                        }
+                    }
+                    0
+                }
+
+                let mut search = Search { addr, flags: None };
+                unsafe {
+                    libc::dl_iterate_phdr(Some(callback), &mut search as *mut Search as *mut _);
+                }
+                search.flags
+            }
+
+            // Returns the file and bias of the module whose `PT_LOAD` segments
+            // `addr` lies in.
+            pub fn module_file(addr: usize) -> Option<(PathBuf, usize)> {
+                struct Search {
+                    addr: usize,
+                    first: bool,
+                    found: Option<(PathBuf, usize)>,
+                }
+
+                unsafe extern "C" fn callback(
+                    info: *mut libc::dl_phdr_info,
+                    _size: libc::size_t,
+                    search: *mut libc::c_void,
+                ) -> libc::c_int {
+                    let info = &*info;
+                    let search = &mut *(search as *mut Search);
+                    let first = mem::replace(&mut search.first, false);
+                    let bias = info.dlpi_addr as usize;
+                    let headers = slice::from_raw_parts(info.dlpi_phdr, info.dlpi_phnum as usize);
+                    let contains = headers.iter().any(|header| {
+                        let vaddr = bias.wrapping_add(header.p_vaddr as usize);
+                        header.p_type == PT_LOAD
+                            && vaddr <= search.addr
+                            && search.addr - vaddr < (header.p_memsz as usize)
+                    });
+                    if !contains {
+                        return 0;
+                    }
+                    // As in `native_modules`, only the first module without a
+                    // name is the main executable.
+                    let path = if !info.dlpi_name.is_null() && *info.dlpi_name != 0 {
+                        let bytes = CStr::from_ptr(info.dlpi_name).to_bytes();
+                        Some(PathBuf::from(OsStr::from_bytes(bytes)))
+                    } else if first {
+                        env::current_exe().ok()
+                    } else {
+                        None
+                    };
+                    search.found = path.map(|path| (path, bias));
+                    1
+                }
+
+                let mut search = Search {
+                    addr,
+                    first: true,
+                    found: None,
+                };
+                unsafe {
+                    libc::dl_iterate_phdr(Some(callback), &mut search as *mut Search as *mut _);
+                }
+                search.found
+            }
+
+            // Returns the stated address ranges of the PLT sections listed in
+            // the section headers of the ELF file at `path`, which is empty if
+            // it can't be read.
+            pub fn plt_sections(path: &Path) -> Vec<(usize, usize)> {
+                read_plt_sections(path).unwrap_or_default()
+            }
+
+            fn read_plt_sections(path: &Path) -> Option<Vec<(usize, usize)>> {
+                const EI_CLASS: usize = 4;
+                const EI_DATA: usize = 5;
+
+                let file = File::open(path).ok()?;
+                let mut header = [0; 64];
+                file.read_exact_at(&mut header, 0).ok()?;
+                if &header[..4] != b"\x7fELF" {
+                    return None;
+                }
+                let endian = match header[EI_DATA] {
+                    1 => Endian::Little,
+                    2 => Endian::Big,
+                    _ => return None,
+                };
+                let is_64 = match header[EI_CLASS] {
+                    1 => false,
+                    2 => true,
+                    _ => return None,
+                };
+                let field = |bytes: &[u8], offset: usize, len: usize| {
+                    endian.read_uint(&bytes[offset..][..len]) as usize
+                };
+                // `e_shoff`, `e_shentsize`, `e_shnum` and `e_shstrndx`.
+                let (shoff, shentsize, shnum, shstrndx) = if is_64 {
+                    (
+                        field(&header, 0x28, 8),
+                        field(&header, 0x3a, 2),
+                        field(&header, 0x3c, 2),
+                        field(&header, 0x3e, 2),
+                    )
+                } else {
+                    (
+                        field(&header, 0x20, 4),
+                        field(&header, 0x2e, 2),
+                        field(&header, 0x30, 2),
+                        field(&header, 0x32, 2),
+                    )
+                };
+                // Only the fields up to `sh_size` are read.
+                let min_shentsize = if is_64 { 0x28 } else { 0x18 };
+                if shentsize < min_shentsize || shstrndx >= shnum {
+                    return None;
+                }
+                let mut sections = vec![0; shnum * shentsize];
+                file.read_exact_at(&mut sections, shoff as u64).ok()?;
+                // Returns the `sh_name`, `sh_addr`, `sh_offset` and `sh_size`
+                // of the `i`th section header.
+                let section = |i: usize| {
+                    let header = &sections[i * shentsize..][..shentsize];
+                    if is_64 {
+                        (
+                            field(header, 0, 4),
+                            field(header, 0x10, 8),
+                            field(header, 0x18, 8),
+                            field(header, 0x20, 8),
+                        )
+                    } else {
+                        (
+                            field(header, 0, 4),
+                            field(header, 0xc, 4),
+                            field(header, 0x10, 4),
+                            field(header, 0x14, 4),
+                        )
+                    }
+                };
+
+                let (_, _, offset, size) = section(shstrndx);
+                // Section names are short, so don't trust a huge string table.
+                let mut names = vec![0; size.min(1 << 20)];
+                file.read_exact_at(&mut names, offset as u64).ok()?;
+                let mut ranges = Vec::new();
+                for i in 0..shnum {
+                    let (name, addr, _, size) = section(i);
+                    let name = match names.get(name..) {
+                        Some(name) => name.split(|&b| b == 0).next().unwrap_or_default(),
+                        None => continue,
+                    };
+                    if PLT_SECTION_NAMES.contains(&name) {
+                        ranges.push((addr, addr.wrapping_add(size)));
+                    }
+                }
+                Some(ranges)
+            }
+
+            // Returns a counter which changes whenever a library is loaded or
+            // unloaded, if the platform provides one.
+            #[cfg(not(target_os = "openbsd"))]
+            pub fn generation() -> Option<usize> {
+                unsafe extern "C" fn callback(
+                    info: *mut libc::dl_phdr_info,
+                    _size: libc::size_t,
+                    generation: *mut libc::c_void,
+                ) -> libc::c_int {
+                    let info = &*info;
+                    let generation = &mut *(generation as *mut usize);
+                    *generation = info.dlpi_adds.wrapping_add(info.dlpi_subs) as usize;
+                    // The counters are the same for every entry, so stop here.
+                    1
+                }
+
+                let mut generation = 0;
+                unsafe {
+                    libc::dl_iterate_phdr(Some(callback), &mut generation as *mut usize as *mut _);
+                }
+                if generation == 0 {
+                    None
+                } else {
+                    Some(generation)
+                }
+            }
+
+            #[cfg(target_os = "openbsd")]
+            pub fn generation() -> Option<usize> {
+                None
+            }
+
+            /// The byte order an ELF image declares for its headers and notes.
+            #[derive(Clone, Copy, Debug, PartialEq, Eq)]
+            enum Endian {
+                Little,
+                Big,
+            }
+
+            impl Endian {
+                #[cfg(target_endian = "little")]
+                const NATIVE: Endian = Endian::Little;
+                #[cfg(target_endian = "big")]
+                const NATIVE: Endian = Endian::Big;
+
+                fn read_u32(self, bytes: [u8; 4]) -> u32 {
+                    match self {
+                        Endian::Little => u32::from_le_bytes(bytes),
+                        Endian::Big => u32::from_be_bytes(bytes),
+                    }
+                }
+
+                // Reads an unsigned integer of up to eight bytes.
+                fn read_uint(self, bytes: &[u8]) -> u64 {
+                    let shift = |n: u64, b: &u8| n << 8 | u64::from(*b);
+                    match self {
+                        Endian::Little => bytes.iter().rev().fold(0, shift),
+                        Endian::Big => bytes.iter().fold(0, shift),
+                    }
+                }
+            }
+
+            /// Reads the byte order from the `EI_DATA` field of the ELF
+            /// header at `header`, if it is one.
+            unsafe fn header_endian(header: *const u8) -> Option<Endian> {
+                const ELFMAG: &[u8] = b"\x7fELF";
+                const EI_DATA: usize = 5;
+                let ident = slice::from_raw_parts(header, EI_DATA + 1);
+                if &ident[..ELFMAG.len()] != ELFMAG {
+                    return None;
+                }
+                match ident[EI_DATA] {
+                    1 => Some(Endian::Little),
+                    2 => Some(Endian::Big),
+                    _ => None,
+                }
+            }
+
+            /// Searches a `PT_NOTE` segment for the GNU build ID note.
+            ///
+            /// Notes are laid out as a `namesz`, `descsz`, `type` header of
+            /// words in the image's byte order, followed by the name and
+            /// descriptor, each padded to a 4-byte boundary.
+            fn find_build_id(mut notes: &[u8], endian: Endian) -> Option<&[u8]> {
+                const HEADER: usize = 3 * mem::size_of::<u32>();
+                let align = |n: usize| (n + 3) & !3;
+                let word = |bytes: &[u8], i: usize| {
+                    let mut word = [0; 4];
+                    word.copy_from_slice(&bytes[i * 4..][..4]);
+                    endian.read_u32(word)
+                };
+
+                while notes.len() >= HEADER {
+                    let namesz = word(notes, 0) as usize;
+                    let descsz = word(notes, 1) as usize;
+                    let ty = word(notes, 2);
+                    let name_end = HEADER.checked_add(namesz)?;
+                    let desc_start = HEADER.checked_add(align(namesz))?;
+                    let desc_end = desc_start.checked_add(descsz)?;
+                    if desc_end > notes.len() {
+                        return None;
+                    }
+                    // The name's length includes its nul terminator.
+                    let mut name = &notes[HEADER..name_end];
+                    if name.last() == Some(&0) {
+                        name = &name[..name.len() - 1];
+                    }
+                    if name == ELF_NOTE_GNU && ty == NT_GNU_BUILD_ID {
+                        return Some(&notes[desc_start..desc_end]);
+                    }
+                    notes = notes.get(desc_start.checked_add(align(descsz))?..)?;
+                }
+                None
+            }
+
+            #[cfg(test)]
+            mod tests {
+                use super::*;
+
+                // A note section holding an unrelated note followed by a
+                // build ID, with the header words written by `word`.
+                fn notes(word: fn(u32) -> [u8; 4]) -> Vec<u8> {
+                    let mut notes = Vec::new();
+                    notes.extend_from_slice(&word(4));
+                    notes.extend_from_slice(&word(4));
+                    notes.extend_from_slice(&word(1));
+                    notes.extend_from_slice(b"GNU\0");
+                    notes.extend_from_slice(&[0; 4]);
+                    notes.extend_from_slice(&word(4));
+                    notes.extend_from_slice(&word(6));
+                    notes.extend_from_slice(&word(NT_GNU_BUILD_ID));
+                    notes.extend_from_slice(b"GNU\0");
+                    notes.extend_from_slice(&[1, 2, 3, 4, 5, 6, 0, 0]);
+                    notes
+                }
+
+                #[test]
+                fn build_id_in_either_byte_order() {
+                    let little = notes(u32::to_le_bytes);
+                    let big = notes(u32::to_be_bytes);
+                    let id = &[1, 2, 3, 4, 5, 6][..];
+                    assert_eq!(find_build_id(&little, Endian::Little), Some(id));
+                    assert_eq!(find_build_id(&big, Endian::Big), Some(id));
+                    assert_ne!(find_build_id(&big, Endian::Little), Some(id));
+                }
+
+                #[test]
+                #[cfg(target_arch = "s390x")]
+                fn build_id_on_s390x() {
+                    let main = &native_modules()[0];
+                    let endian = unsafe { header_endian(main.base as *const u8) };
+                    assert_eq!(endian, Some(Endian::Big));
+                    let id = &[1, 2, 3, 4, 5, 6][..];
+                    assert_eq!(find_build_id(&notes(u32::to_ne_bytes), Endian::Big), Some(id));
+                }
+
+                #[test]
+                fn segment_flags_of_code_and_data() {
+                    const PF_X: u32 = 1;
+                    const PF_W: u32 = 2;
+                    // Interior mutability puts it in a writable segment.
+                    static DATA: core::sync::atomic::AtomicU8 =
+                        core::sync::atomic::AtomicU8::new(1);
+
+                    let code = segment_flags_of_code_and_data as *const () as usize;
+                    let code = segment_flags(code).unwrap();
+                    assert_eq!(code & (PF_X | PF_W), PF_X);
+                    let data = segment_flags(&DATA as *const _ as usize).unwrap();
+                    assert_eq!(data & (PF_X | PF_W), PF_W);
+                    let stack = 0u8;
+                    assert_eq!(segment_flags(&stack as *const u8 as usize), None);
+                }
+
+                #[test]
+                fn plt_of_this_executable() {
+                    let code = plt_of_this_executable as *const () as usize;
+                    let (path, bias) = module_file(code).unwrap();
+                    assert_eq!(path, env::current_exe().unwrap());
+                    let plt = plt_sections(&path);
+                    assert!(!plt.is_empty());
+                    for &(start, end) in &plt {
+                        assert!(start < end);
+                        assert!(super::super::in_plt(bias + start));
+                    }
+                    assert!(!super::super::in_plt(code));
+                }
+            }
+        }
+    } else {
+        mod imp {
+            use super::Module;
+            use std::path::{Path, PathBuf};
+            use std::prelude::v1::*;
+
+            // Everything else doesn't know how to enumerate loaded modules.
+            pub fn native_modules() -> Vec<Module> {
+                Vec::new()
+            }
+
+            pub fn generation() -> Option<usize> {
+                None
+            }
+
+            pub fn main_executable_range() -> Option<(usize, usize)> {
+                None
+            }
+
+            pub fn segment_flags(_addr: usize) -> Option<u32> {
+                None
+            }
+
+            pub fn module_file(_addr: usize) -> Option<(PathBuf, usize)> {
+                None
+            }
+
+            pub fn plt_sections(_path: &Path) -> Vec<(usize, usize)> {
+                Vec::new()
+            }
+        }
+    }
+}
@@ -0,0 +1,299 @@
+use crate::{Frame, Module};
+use std::prelude::v1::*;
+
+/// How a module is identified in a stack written by `pack_stack`.
+///
+/// # Required features
+///
+/// This type requires the `std` feature of the `backtrace` crate to be
+/// enabled, and the `std` feature is enabled by default.
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub enum PackedModuleId {
+    /// The module's build ID, for modules which have one.
+    BuildId(Vec<u8>),
+    /// The file name of the module, without its directory, for modules
+    /// without a build ID.
+    Name(String),
+}
+
+/// A frame of a stack read back by `unpack_stack`.
+///
+/// # Required features
+///
+/// This type requires the `std` feature of the `backtrace` crate to be
+/// enabled, and the `std` feature is enabled by default.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct PackedFrame {
+    /// The index into `UnpackedStack::modules` of the module the frame's `ip`
+    /// was in, or `None` if it wasn't in any.
+    pub module: Option<usize>,
+    /// The frame's `ip` as an address in its module's file, i.e. less the
+    /// module's `bias`, or the absolute `ip` if `module` is `None`.
+    pub address: usize,
+}
+
+/// A stack read back by `unpack_stack`.
+///
+/// # Required features
+///
+/// This type requires the `std` feature of the `backtrace` crate to be
+/// enabled, and the `std` feature is enabled by default.
+#[derive(Clone, Debug, Default, PartialEq, Eq, Hash)]
+pub struct UnpackedStack {
+    /// The modules the frames are in, each listed once.
+    pub modules: Vec<PackedModuleId>,
+    /// The frames, in the order they were passed to `pack_stack`.
+    pub frames: Vec<PackedFrame>,
+}
+
+/// Appends a compact encoding of a stack to `out`, for sending stacks to be
+/// symbolized elsewhere.
+///
+/// Profilers shipping huge numbers of samples can't afford to send each
+/// frame's full `ip`, let alone the modules they're in by name. Here the stack
+/// starts with a table of just the modules its frames are in, identified by
+/// build ID, or by file name where there's none, and then each frame refers to
+/// its module by index in that table and gives its `ip` as an address in the
+/// module's file. Addresses are written as the difference from the previous
+/// frame's, and all integers are LEB128 varints, so that a frame typically
+/// takes three or four bytes.
+///
+/// Addresses in a module's file are its frames' `ip`s less the module's
+/// `bias`, which is what its symbol table and debug info use, so they can be
+/// looked up in the file with a `Symbolizer` whose bias is zero, on any
+/// machine which has it. This holds whatever address the file's first segment
+/// is linked at, which isn't zero for executables built without PIE or for
+/// prelinked libraries. Frames whose `ip` isn't in any module, e.g. in code
+/// generated at runtime, are written with their absolute `ip`.
+///
+/// Stacks can be appended one after another to the same buffer, and read back
+/// in turn with `unpack_stack`.
+///
+/// # Required features
+///
+/// This function requires the `std` feature of the `backtrace` crate to be
+/// enabled, and the `std` feature is enabled by default.
+///
+/// # Example
+///
+/// ```
+/// let mut frames = Vec::new();
+/// backtrace::trace(|frame| {
+///     frames.push(frame.clone());
+///     true
+/// });
+/// let mut packed = Vec::new();
+/// backtrace::pack_stack(&frames, &mut packed);
+///
+/// let mut input = &packed[..];
+/// let stack = backtrace::unpack_stack(&mut input).unwrap();
+/// assert_eq!(stack.frames.len(), frames.len());
+/// assert!(input.is_empty());
+/// ```
+pub fn pack_stack(frames: &[Frame], out: &mut Vec<u8>) {
+    let loaded = crate::loaded_modules().collect::<Vec<_>>();
+    let mut modules = Vec::<&Module>::new();
+    let mut packed = Vec::with_capacity(frames.len());
+    for frame in frames {
+        let ip = frame.ip() as usize;
+        let module = loaded.iter().find(|module| module.contains(ip as *mut _));
+        packed.push(match module {
+            Some(module) => {
+                let index = match modules.iter().position(|m| m.base() == module.base()) {
+                    Some(index) => index,
+                    None => {
+                        modules.push(module);
+                        modules.len() - 1
+                    }
+                };
+                PackedFrame {
+                    module: Some(index),
+                    address: ip.wrapping_sub(module.bias()),
+                }
+            }
+            None => PackedFrame {
+                module: None,
+                address: ip,
+            },
+        });
+    }
+
+    write_varint(out, modules.len() as u64);
+    for module in modules {
+        match module_id(module) {
+            PackedModuleId::BuildId(id) => {
+                out.push(0);
+                write_bytes(out, &id);
+            }
+            PackedModuleId::Name(name) => {
+                out.push(1);
+                write_bytes(out, name.as_bytes());
+            }
+        }
+    }
+    write_varint(out, packed.len() as u64);
+    let mut prev = 0usize;
+    for frame in packed {
+        write_varint(out, frame.module.map_or(0, |index| index as u64 + 1));
+        write_varint(out, zigzag(frame.address.wrapping_sub(prev) as i64));
+        prev = frame.address;
+    }
+}
+
+/// Reads back a stack written by `pack_stack` from the front of `input`,
+/// advancing it past the stack.
+///
+/// Returns `None`, leaving `input` as it was, if it doesn't start with a
+/// whole stack, e.g. because it's been truncated.
+///
+/// # Required features
+///
+/// This function requires the `std` feature of the `backtrace` crate to be
+/// enabled, and the `std` feature is enabled by default.
+pub fn unpack_stack(input: &mut &[u8]) -> Option<UnpackedStack> {
+    let mut rest = *input;
+    let stack = read_stack(&mut rest)?;
+    *input = rest;
+    Some(stack)
+}
+
+fn read_stack(input: &mut &[u8]) -> Option<UnpackedStack> {
+    let mut stack = UnpackedStack::default();
+    let modules = read_varint(input)?;
+    for _ in 0..modules {
+        let (&tag, rest) = input.split_first()?;
+        *input = rest;
+        let bytes = read_bytes(input)?;
+        stack.modules.push(match tag {
+            0 => PackedModuleId::BuildId(bytes.to_vec()),
+            1 => PackedModuleId::Name(String::from_utf8(bytes.to_vec()).ok()?),
+            _ => return None,
+        });
+    }
+    let frames = read_varint(input)?;
+    let mut prev = 0usize;
+    for _ in 0..frames {
+        let module = match read_varint(input)? {
+            0 => None,
+            index if index <= stack.modules.len() as u64 => Some(index as usize - 1),
+            _ => return None,
+        };
+        let address = prev.wrapping_add(unzigzag(read_varint(input)?) as usize);
+        stack.frames.push(PackedFrame { module, address });
+        prev = address;
+    }
+    Some(stack)
+}
+
+fn module_id(module: &Module) -> PackedModuleId {
+    match module.build_id() {
+        Some(id) => PackedModuleId::BuildId(id.to_vec()),
+        None => {
+            let name = module.name().file_name().unwrap_or_default();
+            PackedModuleId::Name(name.to_string_lossy().into_owned())
+        }
+    }
+}
+
+// Deltas between frames go both ways, so they're zigzag encoded to keep small
+// negative ones small.
+fn zigzag(n: i64) -> u64 {
+    ((n << 1) ^ (n >> 63)) as u64
+}
+
+fn unzigzag(n: u64) -> i64 {
+    ((n >> 1) as i64) ^ -((n & 1) as i64)
+}
+
+fn write_varint(out: &mut Vec<u8>, mut n: u64) {
+    while n >= 0x80 {
+        out.push(n as u8 | 0x80);
+        n >>= 7;
+    }
+    out.push(n as u8);
+}
+
+fn read_varint(input: &mut &[u8]) -> Option<u64> {
+    let mut n = 0u64;
+    for shift in (0..64).step_by(7) {
+        let (&byte, rest) = input.split_first()?;
+        *input = rest;
+        n |= u64::from(byte & 0x7f) << shift;
+        if byte & 0x80 == 0 {
+            return Some(n);
+        }
+    }
+    None
+}
+
+fn write_bytes(out: &mut Vec<u8>, bytes: &[u8]) {
+    write_varint(out, bytes.len() as u64);
+    out.extend_from_slice(bytes);
+}
+
+fn read_bytes<'a>(input: &mut &'a [u8]) -> Option<&'a [u8]> {
+    let len = read_varint(input)?;
+    if len > input.len() as u64 {
+        return None;
+    }
+    let (bytes, rest) = input.split_at(len as usize);
+    *input = rest;
+    Some(bytes)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn varints_round_trip() {
+        for &n in &[0, 1, 0x7f, 0x80, 0x3fff, 0x4000, u64::MAX] {
+            let mut out = Vec::new();
+            write_varint(&mut out, n);
+            let mut input = &out[..];
+            assert_eq!(read_varint(&mut input), Some(n));
+            assert!(input.is_empty());
+        }
+        for &n in &[0, 1, -1, 63, -64, i64::MAX, i64::MIN] {
+            assert_eq!(unzigzag(zigzag(n)), n);
+        }
+        assert_eq!(zigzag(-1), 1);
+    }
+
+    #[test]
+    fn truncated_stack_is_rejected() {
+        let mut out = Vec::new();
+        write_varint(&mut out, 1);
+        out.push(1);
+        write_bytes(&mut out, b"libfoo.so");
+        write_varint(&mut out, 2);
+        write_varint(&mut out, 1);
+        write_varint(&mut out, zigzag(0x1234));
+        write_varint(&mut out, 0);
+        write_varint(&mut out, zigzag(-0x34));
+
+        let mut input = &out[..];
+        let stack = unpack_stack(&mut input).unwrap();
+        assert!(input.is_empty());
+        assert_eq!(stack.modules, [PackedModuleId::Name("libfoo.so".into())]);
+        assert_eq!(
+            stack.frames,
+            [
+                PackedFrame {
+                    module: Some(0),
+                    address: 0x1234
+                },
+                PackedFrame {
+                    module: None,
+                    address: 0x1200
+                },
+            ]
+        );
+
+        for len in 0..out.len() {
+            let mut input = &out[..len];
+            assert!(unpack_stack(&mut input).is_none());
+            assert_eq!(input.len(), len);
+        }
+    }
+}
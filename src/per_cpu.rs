@@ -0,0 +1,249 @@
+//! Per-CPU buffers of stack samples, for profilers sampling at a high rate
+//! from many threads at once.
+//!
+//! Each CPU has its own fixed set of slots, so that threads sampling on
+//! different CPUs never touch the same memory. `sched_getcpu` only says which
+//! CPU the thread was on a moment ago, though, and a sample may also be
+//! interrupted by a signal handler taking another on the same CPU, so rather
+//! than trusting the CPU to be exclusive, as `rseq` critical sections would
+//! need to, a slot is claimed with a compare-and-swap of its state before
+//! it's written. That's uncontended as long as threads stay put, and keeps
+//! both capturing and draining free of locks and allocations.
+
+use crate::backtrace::trace_unsynchronized_into;
+use core::cell::UnsafeCell;
+use core::ffi::c_void;
+use core::fmt;
+use core::ptr;
+use core::slice;
+use core::sync::atomic::{AtomicUsize, Ordering};
+use std::prelude::v1::*;
+
+// The states a slot goes through: captured into, drained, and emptied again.
+const EMPTY: usize = 0;
+const WRITING: usize = 1;
+const FULL: usize = 2;
+const READING: usize = 3;
+
+/// Fixed size buffers of stack samples, one for each CPU, which samples are
+/// captured into with `capture_to_current_cpu` and taken out of with `drain`.
+///
+/// This is meant for profilers sampling at such a rate that threads contending
+/// on a shared buffer would be noticeable. `capture_to_current_cpu` captures
+/// the instruction pointers of the calling thread's stack, like `trace_into`,
+/// into a free slot of the buffer of the CPU the thread is running on,
+/// without taking any lock or allocating, so it can be called from a signal
+/// handler such as one for `SIGPROF`. A consumer thread then periodically
+/// calls `drain` to take the samples of all CPUs.
+///
+/// Should all of a CPU's slots be full, the sample is dropped and counted, as
+/// `dropped` reports, rather than spilling over to another CPU's buffer.
+///
+/// All of the buffers are allocated up front by `new`, which is the only
+/// part which isn't async-signal-safe: `slots_per_cpu * max_frames` pointers
+/// for each CPU the system has configured.
+///
+/// Since stacks are captured without the lock `trace` takes, this relies on
+/// the unwinder being thread safe, as it is on Linux and Android.
+///
+/// # Required features
+///
+/// This type requires the `per-cpu` feature of the `backtrace` crate to be
+/// enabled.
+///
+/// # Example
+///
+/// ```
+/// use backtrace::PerCpuSampleBuffers;
+///
+/// let buffers = PerCpuSampleBuffers::new(16, 64);
+/// buffers.capture_to_current_cpu();
+/// buffers.drain(|sample| {
+///     println!("cpu {}: {} frames", sample.cpu(), sample.ips().len());
+/// });
+/// ```
+pub struct PerCpuSampleBuffers {
+    cpus: usize,
+    slots_per_cpu: usize,
+    max_frames: usize,
+    // The state, length, and whether the stack was truncated, of each slot,
+    // with all of the first CPU's slots first.
+    states: Box<[AtomicUsize]>,
+    lens: Box<[UnsafeCell<(usize, bool)>]>,
+    // `max_frames` instruction pointers for each slot, in the same order.
+    ips: Box<[UnsafeCell<*mut c_void>]>,
+    // For each CPU, where to start looking for a free slot.
+    next: Box<[AtomicUsize]>,
+    dropped: AtomicUsize,
+}
+
+// Slots are only ever written by whoever moved them to `WRITING`, and only
+// read by whoever moved them to `READING`.
+unsafe impl Send for PerCpuSampleBuffers {}
+unsafe impl Sync for PerCpuSampleBuffers {}
+
+impl PerCpuSampleBuffers {
+    /// Allocates buffers of `slots_per_cpu` samples of at most `max_frames`
+    /// frames each for every CPU the system has configured.
+    ///
+    /// `MAX_RECOMMENDED_FRAMES` is a reasonable `max_frames`.
+    pub fn new(slots_per_cpu: usize, max_frames: usize) -> PerCpuSampleBuffers {
+        let cpus = unsafe { libc::sysconf(libc::_SC_NPROCESSORS_CONF) }.max(1) as usize;
+        let slots = cpus * slots_per_cpu;
+        PerCpuSampleBuffers {
+            cpus,
+            slots_per_cpu,
+            max_frames,
+            states: (0..slots).map(|_| AtomicUsize::new(EMPTY)).collect(),
+            lens: (0..slots).map(|_| UnsafeCell::new((0, false))).collect(),
+            ips: (0..slots * max_frames)
+                .map(|_| UnsafeCell::new(ptr::null_mut()))
+                .collect(),
+            next: (0..cpus).map(|_| AtomicUsize::new(0)).collect(),
+            dropped: AtomicUsize::new(0),
+        }
+    }
+
+    /// Returns the number of CPUs there are buffers for.
+    pub fn cpus(&self) -> usize {
+        self.cpus
+    }
+
+    /// Captures the calling thread's stack into a free slot of the buffer of
+    /// the CPU it's running on, returning `false` if the sample was dropped
+    /// because that buffer is full.
+    ///
+    /// This is async-signal-safe to the extent the unwinder is, as with
+    /// `trace_unsynchronized_into`. The innermost frames captured are those
+    /// of this function and of the unwinder.
+    #[inline(never)]
+    pub fn capture_to_current_cpu(&self) -> bool {
+        let cpu = current_cpu() % self.cpus;
+        let slot = match self.claim(cpu) {
+            Some(slot) => slot,
+            None => {
+                self.dropped.fetch_add(1, Ordering::Relaxed);
+                return false;
+            }
+        };
+        unsafe {
+            let ips = slice::from_raw_parts_mut(self.slot_ips(slot), self.max_frames);
+            let result = trace_unsynchronized_into(ips);
+            *self.lens[slot].get() = (result.len, result.truncated);
+        }
+        self.states[slot].store(FULL, Ordering::Release);
+        true
+    }
+
+    // Returns where the instruction pointers of `slot` start.
+    fn slot_ips(&self, slot: usize) -> *mut *mut c_void {
+        // `UnsafeCell<T>` has the same in-memory representation as `T`.
+        unsafe { self.ips.as_ptr().add(slot * self.max_frames) as *mut *mut c_void }
+    }
+
+    // Moves a slot of `cpu`'s buffer from empty to being written, returning
+    // its index.
+    fn claim(&self, cpu: usize) -> Option<usize> {
+        let first = cpu * self.slots_per_cpu;
+        let start = self.next[cpu].fetch_add(1, Ordering::Relaxed);
+        (0..self.slots_per_cpu)
+            .map(|i| first + start.wrapping_add(i) % self.slots_per_cpu)
+            .find(|&slot| {
+                self.states[slot]
+                    .compare_exchange(EMPTY, WRITING, Ordering::Acquire, Ordering::Relaxed)
+                    .is_ok()
+            })
+    }
+
+    /// Takes every sample captured so far, passing each to `f` and freeing
+    /// its slot for another sample afterwards, and returns how many there
+    /// were.
+    ///
+    /// Samples are taken one CPU after another, and in no particular order
+    /// within a CPU's buffer. Samples still being captured are left for a
+    /// later call. This may run at the same time as captures, but only one
+    /// thread should drain at a time, or the samples are split between them.
+    pub fn drain<F: FnMut(&PerCpuSample<'_>)>(&self, mut f: F) -> usize {
+        let mut drained = 0;
+        for (slot, state) in self.states.iter().enumerate() {
+            if state
+                .compare_exchange(FULL, READING, Ordering::Acquire, Ordering::Relaxed)
+                .is_err()
+            {
+                continue;
+            }
+            let sample = unsafe {
+                let (len, truncated) = *self.lens[slot].get();
+                PerCpuSample {
+                    cpu: slot / self.slots_per_cpu,
+                    ips: slice::from_raw_parts(self.slot_ips(slot), len),
+                    truncated,
+                }
+            };
+            f(&sample);
+            state.store(EMPTY, Ordering::Release);
+            drained += 1;
+        }
+        drained
+    }
+
+    /// Returns the number of samples dropped because the buffer of the CPU
+    /// they were captured on was full, since the last call.
+    pub fn dropped(&self) -> usize {
+        self.dropped.swap(0, Ordering::Relaxed)
+    }
+}
+
+impl fmt::Debug for PerCpuSampleBuffers {
+    fn fmt(&self, fmt: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt.debug_struct("PerCpuSampleBuffers")
+            .field("cpus", &self.cpus)
+            .field("slots_per_cpu", &self.slots_per_cpu)
+            .field("max_frames", &self.max_frames)
+            .finish()
+    }
+}
+
+/// A stack sample taken out of `PerCpuSampleBuffers` by `drain`.
+///
+/// # Required features
+///
+/// This type requires the `per-cpu` feature of the `backtrace` crate to be
+/// enabled.
+#[derive(Debug)]
+pub struct PerCpuSample<'a> {
+    cpu: usize,
+    ips: &'a [*mut c_void],
+    truncated: bool,
+}
+
+impl PerCpuSample<'_> {
+    /// Returns the CPU the sample was captured on, or at least the one the
+    /// thread was on when its slot was picked.
+    pub fn cpu(&self) -> usize {
+        self.cpu
+    }
+
+    /// Returns the instruction pointers of the stack, innermost first, like
+    /// those written by `trace_into`.
+    pub fn ips(&self) -> &[*mut c_void] {
+        self.ips
+    }
+
+    /// Returns whether the stack had more frames than were captured, in which
+    /// case the outermost frames are missing.
+    pub fn is_truncated(&self) -> bool {
+        self.truncated
+    }
+}
+
+// Returns the CPU the calling thread is running on, or 0 if that can't be
+// learned.
+fn current_cpu() -> usize {
+    let cpu = unsafe { libc::sched_getcpu() };
+    if cpu < 0 {
+        0
+    } else {
+        cpu as usize
+    }
+}
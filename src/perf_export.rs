@@ -0,0 +1,267 @@
+//! Writing captured backtraces out in the `perf.data` format of Linux's `perf`
+//! tool, so that they can be viewed with `perf report` and friends.
+//!
+//! Only as much of the format is written as `perf report` needs to attribute
+//! samples to functions: a file header, the attributes of a single software
+//! event, and a data section with one `PERF_RECORD_COMM` naming this process,
+//! a `PERF_RECORD_MMAP2` for each loaded module, and a `PERF_RECORD_SAMPLE`
+//! with a callchain for each sample. Everything is written in native byte
+//! order, which `perf` works out from the file's magic number.
+//!
+//! The layout is described in `tools/perf/Documentation/perf.data-file-format.txt`
+//! and `include/uapi/linux/perf_event.h` in the kernel tree.
+
+use crate::{BacktraceFrame, Module};
+use std::io::{self, Write};
+use std::prelude::v1::*;
+use std::time::Instant;
+
+const MAGIC: &[u8; 8] = b"PERFILE2";
+
+// `struct perf_file_header`: the magic number and its own size, the size of
+// each attribute entry, three sections and a 256 bit bitmap of the optional
+// feature sections which follow the data, of which there are none here.
+const FILE_HEADER_SIZE: u64 = 8 + 8 + 8 + 3 * 16 + 256 / 8;
+
+// `struct perf_event_attr` as of `PERF_ATTR_SIZE_VER5`.
+const ATTR_SIZE: u64 = 112;
+// Each attribute is followed by the section holding its event IDs.
+const FILE_ATTR_SIZE: u64 = ATTR_SIZE + 16;
+const DATA_OFFSET: u64 = FILE_HEADER_SIZE + FILE_ATTR_SIZE;
+
+const PERF_TYPE_SOFTWARE: u32 = 1;
+const PERF_COUNT_SW_CPU_CLOCK: u64 = 0;
+
+const PERF_SAMPLE_IP: u64 = 1 << 0;
+const PERF_SAMPLE_TID: u64 = 1 << 1;
+const PERF_SAMPLE_TIME: u64 = 1 << 2;
+const PERF_SAMPLE_CALLCHAIN: u64 = 1 << 5;
+const PERF_SAMPLE_PERIOD: u64 = 1 << 8;
+const SAMPLE_TYPE: u64 = PERF_SAMPLE_IP
+    | PERF_SAMPLE_TID
+    | PERF_SAMPLE_TIME
+    | PERF_SAMPLE_CALLCHAIN
+    | PERF_SAMPLE_PERIOD;
+
+// Bits of the flags word of `struct perf_event_attr`.
+const ATTR_EXCLUDE_KERNEL: u64 = 1 << 5;
+const ATTR_EXCLUDE_HV: u64 = 1 << 6;
+const ATTR_MMAP: u64 = 1 << 8;
+const ATTR_COMM: u64 = 1 << 9;
+const ATTR_MMAP2: u64 = 1 << 23;
+
+const PERF_RECORD_COMM: u32 = 3;
+const PERF_RECORD_SAMPLE: u32 = 9;
+const PERF_RECORD_MMAP2: u32 = 10;
+const PERF_RECORD_MISC_USER: u16 = 2;
+
+// Marks the start of the user space part of a callchain.
+const PERF_CONTEXT_USER: u64 = (-512i64) as u64;
+
+// `struct perf_event_header`, which starts every record.
+const RECORD_HEADER_SIZE: usize = 8;
+// A sample's ip, pid and tid, time, period and callchain length.
+const SAMPLE_FIXED_SIZE: usize = RECORD_HEADER_SIZE + 8 + 8 + 8 + 8 + 8;
+
+/// Writes samples in the `perf.data` format read by Linux's `perf` tool.
+///
+/// A writer starts off by recording the modules currently loaded into this
+/// process, as listed by `loaded_modules`, so that `perf report` can find the
+/// functions the samples' frames are in. Samples are then added one at a time,
+/// and the file is written out by `finish`. The whole file is buffered in
+/// memory until then, since its header describes how long it is.
+///
+/// All samples are attributed to a single thread of this process, and their
+/// timestamps are the time since the writer was created. Only the
+/// instruction pointers of the frames are written, symbolication is left to
+/// `perf`.
+///
+/// Libraries loaded after the writer is created aren't known to it, so their
+/// frames show up as unknown.
+///
+/// # Required features
+///
+/// This type requires the `perf-export` feature of the `backtrace` crate to be
+/// enabled.
+///
+/// # Example
+///
+/// ```no_run
+/// use backtrace::{Backtrace, PerfDataWriter};
+/// use std::fs::File;
+///
+/// let mut writer = PerfDataWriter::new();
+/// let backtrace = Backtrace::new_unresolved();
+/// writer.add_sample(backtrace.frames(), 1).unwrap();
+/// writer.finish(File::create("perf.data").unwrap()).unwrap();
+/// ```
+pub struct PerfDataWriter {
+    data: Vec<u8>,
+    pid: u32,
+    start: Instant,
+}
+
+impl PerfDataWriter {
+    /// Creates a writer, recording the modules currently loaded into this
+    /// process.
+    pub fn new() -> PerfDataWriter {
+        let mut writer = PerfDataWriter {
+            data: Vec::new(),
+            pid: std::process::id(),
+            start: Instant::now(),
+        };
+        writer.write_comm();
+        for module in crate::loaded_modules() {
+            writer.write_mmap2(&module);
+        }
+        writer
+    }
+
+    /// Adds a sample with the callchain given by the `ip`s of `frames`,
+    /// innermost first, as returned by `Backtrace::frames`.
+    ///
+    /// `period` is the weight of the sample, e.g. the number of times this
+    /// stack was seen when adding the output of `SampleAggregator`.
+    ///
+    /// Returns an error if the callchain is too long to fit in a record,
+    /// which holds a little over 8000 frames.
+    pub fn add_sample(&mut self, frames: &[BacktraceFrame], period: u64) -> io::Result<()> {
+        let nr = frames.len() + 1;
+        let size = SAMPLE_FIXED_SIZE + nr * 8;
+        if size > usize::from(core::u16::MAX) {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "callchain too long for a perf sample",
+            ));
+        }
+        let ip = frames.first().map_or(0, |f| f.ip() as u64);
+        let time = self.start.elapsed().as_nanos() as u64;
+
+        self.write_header(PERF_RECORD_SAMPLE, size);
+        self.write_u64(ip);
+        self.write_u32(self.pid);
+        self.write_u32(self.pid);
+        self.write_u64(time);
+        self.write_u64(period);
+        self.write_u64(nr as u64);
+        self.write_u64(PERF_CONTEXT_USER);
+        for frame in frames {
+            self.write_u64(frame.ip() as u64);
+        }
+        Ok(())
+    }
+
+    /// Writes the whole `perf.data` file to `out`.
+    pub fn finish<W: Write>(self, mut out: W) -> io::Result<()> {
+        let mut header = Vec::with_capacity(DATA_OFFSET as usize);
+        header.extend_from_slice(MAGIC);
+        header.extend_from_slice(&FILE_HEADER_SIZE.to_ne_bytes());
+        header.extend_from_slice(&FILE_ATTR_SIZE.to_ne_bytes());
+        // The attributes, data, and (unused) event types sections.
+        for &(offset, size) in &[
+            (FILE_HEADER_SIZE, FILE_ATTR_SIZE),
+            (DATA_OFFSET, self.data.len() as u64),
+            (0, 0),
+        ] {
+            header.extend_from_slice(&offset.to_ne_bytes());
+            header.extend_from_slice(&size.to_ne_bytes());
+        }
+        header.extend_from_slice(&[0; 256 / 8]);
+
+        // `struct perf_event_attr`, followed by an empty section of IDs.
+        let start = header.len();
+        header.extend_from_slice(&PERF_TYPE_SOFTWARE.to_ne_bytes());
+        header.extend_from_slice(&(ATTR_SIZE as u32).to_ne_bytes());
+        header.extend_from_slice(&PERF_COUNT_SW_CPU_CLOCK.to_ne_bytes());
+        // The sample period.
+        header.extend_from_slice(&1u64.to_ne_bytes());
+        header.extend_from_slice(&SAMPLE_TYPE.to_ne_bytes());
+        // The read format.
+        header.extend_from_slice(&0u64.to_ne_bytes());
+        let flags = ATTR_EXCLUDE_KERNEL | ATTR_EXCLUDE_HV | ATTR_MMAP | ATTR_COMM | ATTR_MMAP2;
+        header.extend_from_slice(&flags.to_ne_bytes());
+        header.resize(start + ATTR_SIZE as usize + 16, 0);
+        debug_assert_eq!(header.len() as u64, DATA_OFFSET);
+
+        out.write_all(&header)?;
+        out.write_all(&self.data)?;
+        out.flush()
+    }
+
+    fn write_comm(&mut self) {
+        let exe = std::env::current_exe().ok();
+        let name = exe
+            .as_ref()
+            .and_then(|exe| exe.file_name())
+            .map(|name| name.to_string_lossy().into_owned())
+            .unwrap_or_default();
+        // The kernel truncates names to 15 bytes, `TASK_COMM_LEN` less the nul.
+        let name = &name.as_bytes()[..name.len().min(15)];
+        let size = RECORD_HEADER_SIZE + 8 + padded_len(name);
+        self.write_header(PERF_RECORD_COMM, size);
+        self.write_u32(self.pid);
+        self.write_u32(self.pid);
+        self.write_str(name);
+    }
+
+    fn write_mmap2(&mut self, module: &Module) {
+        use std::os::unix::ffi::OsStrExt;
+
+        let name = module.name().as_os_str().as_bytes();
+        let size = RECORD_HEADER_SIZE + 8 + 3 * 8 + 2 * 4 + 2 * 8 + 2 * 4 + padded_len(name);
+        if size > usize::from(core::u16::MAX) {
+            return;
+        }
+        self.write_header(PERF_RECORD_MMAP2, size);
+        self.write_u32(self.pid);
+        self.write_u32(self.pid);
+        self.write_u64(module.base() as u64);
+        self.write_u64(module.len() as u64);
+        // The file offset the mapping starts at. Modules start with the
+        // segment holding the ELF header, at the start of the file.
+        self.write_u64(0);
+        // The device numbers, inode and inode generation aren't known, and
+        // `perf` only uses them to tell apart files with the same name.
+        self.write_u32(0);
+        self.write_u32(0);
+        self.write_u64(0);
+        self.write_u64(0);
+        self.write_u32((libc::PROT_READ | libc::PROT_EXEC) as u32);
+        self.write_u32(libc::MAP_PRIVATE as u32);
+        self.write_str(name);
+    }
+
+    fn write_header(&mut self, ty: u32, size: usize) {
+        debug_assert_eq!(size % 8, 0);
+        self.write_u32(ty);
+        self.data
+            .extend_from_slice(&PERF_RECORD_MISC_USER.to_ne_bytes());
+        self.data.extend_from_slice(&(size as u16).to_ne_bytes());
+    }
+
+    fn write_u32(&mut self, n: u32) {
+        self.data.extend_from_slice(&n.to_ne_bytes());
+    }
+
+    fn write_u64(&mut self, n: u64) {
+        self.data.extend_from_slice(&n.to_ne_bytes());
+    }
+
+    // Writes a nul terminated string, padded to a multiple of 8 bytes.
+    fn write_str(&mut self, s: &[u8]) {
+        let len = padded_len(s);
+        self.data.extend_from_slice(s);
+        self.data.resize(self.data.len() + len - s.len(), 0);
+    }
+}
+
+impl Default for PerfDataWriter {
+    fn default() -> PerfDataWriter {
+        PerfDataWriter::new()
+    }
+}
+
+// The length of `s` with a nul terminator, rounded up to a multiple of 8.
+fn padded_len(s: &[u8]) -> usize {
+    (s.len() + 1 + 7) & !7
+}
@@ -3,12 +3,269 @@ use super::{BacktraceFrame, BacktraceSymbol};
 use super::{BytesOrWideString, Frame, SymbolName};
 use core::ffi::c_void;
 use core::fmt;
+#[cfg(feature = "std")]
+use std::borrow::Cow;
+#[cfg(feature = "std")]
+use std::boxed::Box;
+#[cfg(feature = "std")]
+use std::sync::{Arc, Mutex, Once};
 
 const HEX_WIDTH: usize = 2 + 2 * core::mem::size_of::<usize>();
 
 #[cfg(target_os = "fuchsia")]
 mod fuchsia;
 
+#[cfg(feature = "std")]
+mod addr2line;
+#[cfg(feature = "std")]
+pub use self::addr2line::format_addr2line_style;
+#[cfg(feature = "std")]
+mod gdb;
+#[cfg(feature = "std")]
+pub use self::gdb::GdbFmt;
+
+#[cfg(feature = "std")]
+type SymbolFilter = dyn Fn(&str) -> Cow<'_, str> + Send + Sync;
+
+// `Mutex::new` can't be used in a `static`, so the filter's slot is created
+// on first use, like the global lock.
+#[cfg(feature = "std")]
+fn symbol_filter() -> &'static Mutex<Option<Arc<SymbolFilter>>> {
+    static mut SYMBOL_FILTER: *const Mutex<Option<Arc<SymbolFilter>>> = core::ptr::null();
+    static INIT: Once = Once::new();
+    unsafe {
+        INIT.call_once(|| {
+            SYMBOL_FILTER = Box::into_raw(Box::new(Mutex::new(None)));
+        });
+        &*SYMBOL_FILTER
+    }
+}
+
+/// Registers a function which rewrites symbol names before they're printed.
+///
+/// The filter is passed each demangled name as it would otherwise have been
+/// printed, and returns what to print instead, e.g. to strip generic
+/// parameters, collapse `{{closure}}` noise, or give runtime internals
+/// friendlier labels. Returning `Cow::Borrowed` leaves a name as it is without
+/// allocating.
+///
+/// It applies to the formatting of frames by `BacktraceFmt` (and so the
+/// `Debug` output of `Backtrace`) and `GdbFmt`. It doesn't affect `SymbolName`
+/// itself, nor `format_addr2line_style`, whose output is meant to match that
+/// of `addr2line`. Registering a filter replaces the previous one, if any.
+///
+/// The filter may be called from any thread which formats a backtrace, and
+/// must not itself format a backtrace.
+///
+/// # Required features
+///
+/// This function requires the `std` feature of the `backtrace` crate to be
+/// enabled, and the `std` feature is enabled by default.
+///
+/// # Example
+///
+/// ```
+/// use std::borrow::Cow;
+///
+/// backtrace::set_symbol_filter(|name| {
+///     if name.contains("{{closure}}") {
+///         Cow::Owned(name.replace("::{{closure}}", " (closure)"))
+///     } else {
+///         Cow::Borrowed(name)
+///     }
+/// });
+/// println!("{:?}", backtrace::Backtrace::new());
+/// ```
+#[cfg(feature = "std")]
+pub fn set_symbol_filter<F>(f: F)
+where
+    F: Fn(&str) -> Cow<'_, str> + Send + Sync + 'static,
+{
+    *symbol_filter().lock().unwrap() = Some(Arc::new(f));
+}
+
+#[cfg(feature = "std")]
+type FrameBoundaryPredicate = dyn Fn(&Frame) -> bool + Send + Sync;
+
+// Created on first use, like the symbol filter's slot.
+#[cfg(feature = "std")]
+fn frame_boundary_predicate() -> &'static Mutex<Option<Arc<FrameBoundaryPredicate>>> {
+    static mut PREDICATE: *const Mutex<Option<Arc<FrameBoundaryPredicate>>> = core::ptr::null();
+    static INIT: Once = Once::new();
+    unsafe {
+        INIT.call_once(|| {
+            PREDICATE = Box::into_raw(Box::new(Mutex::new(None)));
+        });
+        &*PREDICATE
+    }
+}
+
+/// Registers a function which tells which frames are boundaries between the
+/// synchronous segments of a stack, such as the `poll` trampoline of an async
+/// executor.
+///
+/// The stack of an async task being polled is the task's own frames on top of
+/// those of the executor, and a task polling other futures may stack a few
+/// such segments on top of each other. The predicate is passed each frame and
+/// returns whether it's a boundary, typically by resolving it and looking at
+/// its name. This doesn't reconstruct the logical stack of a task, which
+/// isn't on the thread's stack at all, but lets tooling tell the segments
+/// apart: `is_frame_boundary` and `BacktraceFrame::is_frame_boundary` ask the
+/// predicate about a frame, and `BacktraceFmt::frame_boundary_separator`
+/// prints a separator before each boundary frame. Registering a predicate
+/// replaces the previous one, if any.
+///
+/// The predicate may be called from any thread, and must not itself format a
+/// backtrace.
+///
+/// # Required features
+///
+/// This function requires the `std` feature of the `backtrace` crate to be
+/// enabled, and the `std` feature is enabled by default.
+///
+/// # Example
+///
+/// ```
+/// backtrace::set_frame_boundary_predicate(|frame| {
+///     let mut boundary = false;
+///     backtrace::resolve_frame(frame, |symbol| {
+///         if let Some(name) = symbol.name() {
+///             boundary |= name.to_string().contains("Executor::poll_task");
+///         }
+///     });
+///     boundary
+/// });
+/// ```
+#[cfg(feature = "std")]
+pub fn set_frame_boundary_predicate<F>(f: F)
+where
+    F: Fn(&Frame) -> bool + Send + Sync + 'static,
+{
+    *frame_boundary_predicate().lock().unwrap() = Some(Arc::new(f));
+}
+
+/// Returns whether `frame` is a boundary between synchronous segments of a
+/// stack, according to the predicate registered with
+/// `set_frame_boundary_predicate`, or `false` if there is none.
+///
+/// # Required features
+///
+/// This function requires the `std` feature of the `backtrace` crate to be
+/// enabled, and the `std` feature is enabled by default.
+#[cfg(feature = "std")]
+pub fn is_frame_boundary(frame: &Frame) -> bool {
+    // Don't hold the lock while the predicate runs.
+    let predicate = frame_boundary_predicate().lock().unwrap().clone();
+    match predicate {
+        Some(predicate) => predicate(frame),
+        None => false,
+    }
+}
+
+// Writes `name` as `{}`, or `{:#}` if `alternate`, would, passed through the
+// filter registered with `set_symbol_filter` and then cut down to `max_len`
+// characters if given.
+fn write_symbol_name(
+    fmt: &mut fmt::Formatter<'_>,
+    name: &SymbolName<'_>,
+    alternate: bool,
+    max_len: Option<usize>,
+) -> fmt::Result {
+    #[cfg(feature = "std")]
+    {
+        // Don't hold the lock while the filter runs.
+        let filter = symbol_filter().lock().unwrap().clone();
+        if let Some(filter) = filter {
+            let name = if alternate {
+                format!("{:#}", name)
+            } else {
+                format!("{}", name)
+            };
+            let name = filter(&name);
+            return write_truncated(fmt, max_len, |w| w.write_str(&name));
+        }
+    }
+    write_truncated(fmt, max_len, |w| {
+        if alternate {
+            write!(w, "{:#}", name)
+        } else {
+            write!(w, "{}", name)
+        }
+    })
+}
+
+// Writes what `write` does to `fmt`, but if that's longer than `max_len`
+// characters replaces enough of its middle with `...` to fit. Keeping both
+// ends means a long generic like `Vec<...>::push` still reads as what it is.
+//
+// This works in two passes over `write` rather than buffering so that it
+// doesn't need to allocate.
+fn write_truncated(
+    fmt: &mut fmt::Formatter<'_>,
+    max_len: Option<usize>,
+    write: impl Fn(&mut dyn fmt::Write) -> fmt::Result,
+) -> fmt::Result {
+    const ELLIPSIS: &str = "...";
+
+    let max_len = match max_len {
+        Some(max_len) => max_len,
+        None => return write(fmt),
+    };
+    let mut count = CountChars(0);
+    write(&mut count)?;
+    if count.0 <= max_len {
+        return write(fmt);
+    }
+    let kept = max_len.saturating_sub(ELLIPSIS.len());
+    let tail = kept / 2;
+    let head = kept - tail;
+    write(&mut Elide {
+        fmt,
+        pos: 0,
+        head,
+        tail_start: count.0 - tail,
+        ellipsis: if max_len >= ELLIPSIS.len() {
+            ELLIPSIS
+        } else {
+            &ELLIPSIS[..max_len]
+        },
+    })
+}
+
+struct CountChars(usize);
+
+impl fmt::Write for CountChars {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        self.0 += s.chars().count();
+        Ok(())
+    }
+}
+
+// Passes through the first `head` characters written and those from
+// `tail_start` on, with `ellipsis` in place of everything in between.
+struct Elide<'a, 'b> {
+    fmt: &'a mut fmt::Formatter<'b>,
+    pos: usize,
+    head: usize,
+    tail_start: usize,
+    ellipsis: &'static str,
+}
+
+impl fmt::Write for Elide<'_, '_> {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        for c in s.chars() {
+            if self.pos == self.head {
+                self.fmt.write_str(self.ellipsis)?;
+            }
+            if self.pos < self.head || self.pos >= self.tail_start {
+                self.fmt.write_char(c)?;
+            }
+            self.pos += 1;
+        }
+        Ok(())
+    }
+}
+
 /// A formatter for backtraces.
 ///
 /// This type can be used to print a backtrace regardless of where the backtrace
@@ -20,6 +277,9 @@ pub struct BacktraceFmt<'a, 'b> {
     format: PrintFmt,
     print_path:
         &'a mut (dyn FnMut(&mut fmt::Formatter<'_>, BytesOrWideString<'_>) -> fmt::Result + 'b),
+    max_name_len: Option<usize>,
+    #[cfg(feature = "std")]
+    boundary_separator: Option<&'a str>,
 }
 
 /// The styles of printing that we can print
@@ -52,9 +312,47 @@ impl<'a, 'b> BacktraceFmt<'a, 'b> {
             frame_index: 0,
             format,
             print_path,
+            max_name_len: None,
+            #[cfg(feature = "std")]
+            boundary_separator: None,
         }
     }
 
+    /// Limits symbol names to `len` characters.
+    ///
+    /// Monomorphized generics and nested closures can give names thousands of
+    /// characters long. Longer names have their middle replaced with `...`,
+    /// keeping their start and end, so that a name like
+    /// `alloc::vec::Vec<...>::push` still says what it is. Names are cut
+    /// between characters, never within one. The `...` counts towards `len`.
+    ///
+    /// By default names are printed in full.
+    pub fn max_name_len(&mut self, len: usize) -> &mut Self {
+        self.max_name_len = Some(len);
+        self
+    }
+
+    /// Prints `separator` on a line of its own before each frame which is a
+    /// boundary between synchronous segments of the stack, according to the
+    /// predicate registered with `set_frame_boundary_predicate`.
+    ///
+    /// Only frames printed with `BacktraceFrameFmt::backtrace_frame`, as the
+    /// `Debug` output of `Backtrace` is, are checked. Frames printed with
+    /// `symbol` are printed from within a `resolve_frame` callback, where the
+    /// predicate couldn't resolve the frame itself.
+    ///
+    /// By default no separators are printed.
+    ///
+    /// # Required features
+    ///
+    /// This function requires the `std` feature of the `backtrace` crate to be
+    /// enabled, and the `std` feature is enabled by default.
+    #[cfg(feature = "std")]
+    pub fn frame_boundary_separator(&mut self, separator: &'a str) -> &mut Self {
+        self.boundary_separator = Some(separator);
+        self
+    }
+
     /// Prints a preamble for the backtrace about to be printed.
     ///
     /// This is required on some platforms for backtraces to be fully
@@ -108,6 +406,11 @@ impl BacktraceFrameFmt<'_, '_, '_> {
     /// enabled, and the `std` feature is enabled by default.
     #[cfg(feature = "std")]
     pub fn backtrace_frame(&mut self, frame: &BacktraceFrame) -> fmt::Result {
+        if let Some(separator) = self.fmt.boundary_separator {
+            if frame.is_frame_boundary() {
+                writeln!(self.fmt.fmt, "{}", separator)?;
+            }
+        }
         let symbols = frame.symbols();
         for symbol in symbols {
             self.backtrace_symbol(frame, symbol)?;
@@ -243,8 +546,12 @@ impl BacktraceFrameFmt<'_, '_, '_> {
         // more information if we're a full backtrace. Here we also handle
         // symbols which don't have a name,
         match (symbol_name, &self.fmt.format) {
-            (Some(name), PrintFmt::Short) => write!(self.fmt.fmt, "{:#}", name)?,
-            (Some(name), PrintFmt::Full) => write!(self.fmt.fmt, "{}", name)?,
+            (Some(name), PrintFmt::Short) => {
+                write_symbol_name(self.fmt.fmt, &name, true, self.fmt.max_name_len)?
+            }
+            (Some(name), PrintFmt::Full) => {
+                write_symbol_name(self.fmt.fmt, &name, false, self.fmt.max_name_len)?
+            }
             (None, _) | (_, PrintFmt::__Nonexhaustive) => write!(self.fmt.fmt, "<unknown>")?,
         }
         self.fmt.fmt.write_str("\n")?;
@@ -300,3 +607,48 @@ impl Drop for BacktraceFrameFmt<'_, '_, '_> {
         self.fmt.frame_index += 1;
     }
 }
+
+#[cfg(all(test, feature = "std"))]
+mod tests {
+    use super::*;
+    use std::prelude::v1::*;
+
+    struct Truncated<'a>(&'a str, Option<usize>);
+
+    impl fmt::Display for Truncated<'_> {
+        fn fmt(&self, fmt: &mut fmt::Formatter<'_>) -> fmt::Result {
+            // Written in pieces, as demangling does.
+            write_truncated(fmt, self.1, |w| {
+                for piece in self.0.split_inclusive("::") {
+                    w.write_str(piece)?;
+                }
+                Ok(())
+            })
+        }
+    }
+
+    fn truncate(name: &str, max_len: Option<usize>) -> String {
+        Truncated(name, max_len).to_string()
+    }
+
+    #[test]
+    fn truncates_the_middle() {
+        let name = "alloc::vec::Vec<core::option::Option<alloc::string::String>>::push";
+        assert_eq!(truncate(name, None), name);
+        assert_eq!(truncate(name, Some(name.len())), name);
+        assert_eq!(truncate(name, Some(23)), "alloc::vec...ng>>::push");
+        assert_eq!(truncate(name, Some(23)).len(), 23);
+        assert_eq!(truncate(name, Some(3)), "...");
+        assert_eq!(truncate(name, Some(4)), "a...");
+        assert_eq!(truncate(name, Some(1)), ".");
+        assert_eq!(truncate(name, Some(0)), "");
+    }
+
+    #[test]
+    fn truncates_between_characters() {
+        let name = "crate::ünïcödé::名前::<ŧÿþë>::fünçtïön";
+        let truncated = truncate(name, Some(13));
+        assert_eq!(truncated, "crate...çtïön");
+        assert_eq!(truncated.chars().count(), 13);
+    }
+}
@@ -0,0 +1,61 @@
+use crate::BacktraceFrame;
+use std::io::{self, Write};
+
+/// Writes resolved frames the way the binutils `addr2line -f -C` command
+/// prints the addresses it's given.
+///
+/// For each frame two lines are written: the demangled name of the function,
+/// and then its location as `<file>:<line>`. Anything which isn't known is
+/// printed as `??`, and a line number which isn't known as `?`, while a frame
+/// with no symbols at all, including frames which haven't been resolved, is
+/// printed as `??` and `??:0`, just as `addr2line` does for an address it
+/// can't find. The output can then be diffed against that of `addr2line` run
+/// on the frames' `ip`s, which helps when moving symbolication from a script
+/// to this crate.
+///
+/// Like `addr2line` without `-i`, only the innermost symbol of each frame is
+/// written, so functions inlined into it are left out. Names are printed
+/// without their hash, as `addr2line -C` does, and aren't passed through the
+/// filter registered with `set_symbol_filter`. Discriminators aren't recorded
+/// by this crate, so lines are never followed by `(discriminator N)`.
+///
+/// # Required features
+///
+/// This function requires the `std` feature of the `backtrace` crate to be
+/// enabled, and the `std` feature is enabled by default.
+///
+/// # Example
+///
+/// ```
+/// use backtrace::Backtrace;
+///
+/// let backtrace = Backtrace::new();
+/// let mut out = Vec::new();
+/// backtrace::format_addr2line_style(backtrace.frames(), &mut out).unwrap();
+/// assert_eq!(out.iter().filter(|&&b| b == b'\n').count(), 2 * backtrace.frames().len());
+/// ```
+pub fn format_addr2line_style<W: Write>(frames: &[BacktraceFrame], out: &mut W) -> io::Result<()> {
+    for frame in frames {
+        let symbol = match frame.symbols().first() {
+            Some(symbol) => symbol,
+            None => {
+                writeln!(out, "??\n??:0")?;
+                continue;
+            }
+        };
+
+        match symbol.name() {
+            Some(name) => writeln!(out, "{:#}", name)?,
+            None => writeln!(out, "??")?,
+        }
+        match symbol.filename() {
+            Some(file) => write!(out, "{}:", file.display())?,
+            None => write!(out, "??:")?,
+        }
+        match symbol.lineno() {
+            Some(line) if line != 0 => writeln!(out, "{}", line)?,
+            _ => writeln!(out, "?")?,
+        }
+    }
+    Ok(())
+}
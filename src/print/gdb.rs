@@ -0,0 +1,103 @@
+use super::HEX_WIDTH;
+use crate::BacktraceFrame;
+use core::fmt;
+
+/// A `Display` adapter printing a resolved frame the way `gdb`'s `backtrace`
+/// command does.
+///
+/// Each symbol of the frame is printed on its own line, as
+/// `#<index>  0x<ip> in <name> () at <file>:<line>`, so that output can be fed
+/// to tooling which already parses `gdb` backtraces. The location is left off
+/// if it isn't known, and symbols whose name isn't known are printed as `??`.
+/// Unlike `gdb` the column is appended to the location, as in
+/// `<file>:<line>:<column>`, when the debug info records one, which helps tell
+/// apart the many calls macro-heavy code can put on a single line.
+/// Frames with no symbols at all, including frames which haven't been
+/// resolved, are printed as a single line with the name `??`.
+///
+/// `gdb` numbers frames starting at 0 for the innermost frame, which the
+/// caller is responsible for doing by passing in each frame's `index`.
+///
+/// # Required features
+///
+/// This function requires the `std` feature of the `backtrace` crate to be
+/// enabled, and the `std` feature is enabled by default.
+///
+/// # Example
+///
+/// ```
+/// use backtrace::{Backtrace, GdbFmt};
+///
+/// let backtrace = Backtrace::new();
+/// for (i, frame) in backtrace.frames().iter().enumerate() {
+///     println!("{}", GdbFmt::new(i, frame));
+/// }
+/// ```
+pub struct GdbFmt<'a> {
+    index: usize,
+    frame: &'a BacktraceFrame,
+    max_name_len: Option<usize>,
+}
+
+impl<'a> GdbFmt<'a> {
+    /// Creates an adapter printing `frame` as the frame numbered `index`.
+    pub fn new(index: usize, frame: &'a BacktraceFrame) -> GdbFmt<'a> {
+        GdbFmt {
+            index,
+            frame,
+            max_name_len: None,
+        }
+    }
+
+    /// Limits symbol names to `len` characters, replacing the middle of longer
+    /// ones with `...`, as `BacktraceFmt::max_name_len` does.
+    ///
+    /// By default names are printed in full.
+    pub fn max_name_len(mut self, len: usize) -> GdbFmt<'a> {
+        self.max_name_len = Some(len);
+        self
+    }
+}
+
+impl fmt::Display for GdbFmt<'_> {
+    fn fmt(&self, fmt: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let ip = self.frame.ip() as usize;
+        let symbols = self.frame.symbols();
+        if symbols.is_empty() {
+            return write!(
+                fmt,
+                "#{:<2} {:#0width$x} in ?? ()",
+                self.index,
+                ip,
+                width = HEX_WIDTH
+            );
+        }
+
+        for (i, symbol) in symbols.iter().enumerate() {
+            if i > 0 {
+                writeln!(fmt)?;
+            }
+            write!(
+                fmt,
+                "#{:<2} {:#0width$x} in ",
+                self.index,
+                ip,
+                width = HEX_WIDTH
+            )?;
+            match symbol.name() {
+                Some(name) => {
+                    super::write_symbol_name(fmt, &name, true, self.max_name_len)?;
+                    fmt.write_str(" ()")?;
+                }
+                None => write!(fmt, "?? ()")?,
+            }
+            if let (Some(file), Some(line)) = (symbol.filename(), symbol.lineno()) {
+                write!(fmt, " at {}:{}", file.display(), line)?;
+                if let Some(col) = symbol.colno() {
+                    write!(fmt, ":{}", col)?;
+                }
+            }
+        }
+        Ok(())
+    }
+}
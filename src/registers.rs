@@ -0,0 +1,342 @@
+//! Names of the registers numbered by DWARF, for labelling register values
+//! read out of a frame, and the architecture they depend on.
+
+/// A machine architecture, as returned by `current_arch` for the target this
+/// crate was compiled for.
+///
+/// This is what exporters need to label their output with the right machine
+/// type, and what `dwarf_register_name` needs to pick a register numbering.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum Arch {
+    /// 64-bit x86, numbered as in the System V AMD64 psABI.
+    X86_64,
+    /// 64-bit ARM, numbered as in the "DWARF for the Arm 64-bit
+    /// Architecture" supplement.
+    Aarch64,
+    /// 32-bit ARM, numbered as in the "DWARF for the Arm Architecture"
+    /// supplement.
+    Arm,
+    /// IBM Z, numbered as in the s390x ELF ABI supplement.
+    S390x,
+    /// 64-bit RISC-V, numbered as in the RISC-V ELF psABI.
+    Riscv64,
+    /// 64-bit PowerPC, either endianness, numbered as in the 64-bit ELF ABI
+    /// for Power.
+    Powerpc64,
+    /// 64-bit LoongArch, numbered as in the LoongArch ELF psABI.
+    LoongArch64,
+    /// Any other architecture, whose registers aren't named.
+    Unknown,
+}
+
+impl Arch {
+    /// Returns the architecture this crate was compiled for, if it's one of
+    /// the known ones.
+    pub fn current() -> Option<Arch> {
+        match current_arch() {
+            Arch::Unknown => None,
+            arch => Some(arch),
+        }
+    }
+}
+
+/// Returns the architecture this crate was compiled for, or `Arch::Unknown`
+/// if it's none of the others.
+///
+/// # Example
+///
+/// ```
+/// use backtrace::{current_arch, Arch};
+///
+/// let machine = match current_arch() {
+///     Arch::X86_64 => "x86_64",
+///     Arch::Aarch64 => "aarch64",
+///     _ => "other",
+/// };
+/// println!("{} ({}-bit)", machine, backtrace::pointer_width());
+/// ```
+pub fn current_arch() -> Arch {
+    if cfg!(target_arch = "x86_64") {
+        Arch::X86_64
+    } else if cfg!(target_arch = "aarch64") {
+        Arch::Aarch64
+    } else if cfg!(target_arch = "arm") {
+        Arch::Arm
+    } else if cfg!(target_arch = "s390x") {
+        Arch::S390x
+    } else if cfg!(target_arch = "riscv64") {
+        Arch::Riscv64
+    } else if cfg!(target_arch = "powerpc64") {
+        Arch::Powerpc64
+    } else if cfg!(target_arch = "loongarch64") {
+        Arch::LoongArch64
+    } else {
+        Arch::Unknown
+    }
+}
+
+/// Returns the width in bits of pointers, and so of instruction pointers, on
+/// the target this crate was compiled for.
+pub fn pointer_width() -> u32 {
+    core::mem::size_of::<usize>() as u32 * 8
+}
+
+const X86_64: [&str; 17] = [
+    "rax", "rdx", "rcx", "rbx", "rsi", "rdi", "rbp", "rsp", "r8", "r9", "r10", "r11", "r12", "r13",
+    "r14", "r15", "rip",
+];
+
+const AARCH64: [&str; 33] = [
+    "x0", "x1", "x2", "x3", "x4", "x5", "x6", "x7", "x8", "x9", "x10", "x11", "x12", "x13", "x14",
+    "x15", "x16", "x17", "x18", "x19", "x20", "x21", "x22", "x23", "x24", "x25", "x26", "x27",
+    "x28", "x29", "x30", "sp", "pc",
+];
+
+const ARM: [&str; 16] = [
+    "r0", "r1", "r2", "r3", "r4", "r5", "r6", "r7", "r8", "r9", "r10", "r11", "r12", "sp", "lr",
+    "pc",
+];
+
+const S390X: [&str; 16] = [
+    "r0", "r1", "r2", "r3", "r4", "r5", "r6", "r7", "r8", "r9", "r10", "r11", "r12", "r13", "r14",
+    "r15",
+];
+
+const RISCV64: [&str; 32] = [
+    "zero", "ra", "sp", "gp", "tp", "t0", "t1", "t2", "s0", "s1", "a0", "a1", "a2", "a3", "a4",
+    "a5", "a6", "a7", "s2", "s3", "s4", "s5", "s6", "s7", "s8", "s9", "s10", "s11", "t3", "t4",
+    "t5", "t6",
+];
+
+const POWERPC64: [&str; 32] = [
+    "r0", "r1", "r2", "r3", "r4", "r5", "r6", "r7", "r8", "r9", "r10", "r11", "r12", "r13", "r14",
+    "r15", "r16", "r17", "r18", "r19", "r20", "r21", "r22", "r23", "r24", "r25", "r26", "r27",
+    "r28", "r29", "r30", "r31",
+];
+
+const LOONGARCH64: [&str; 32] = [
+    "zero", "ra", "tp", "sp", "a0", "a1", "a2", "a3", "a4", "a5", "a6", "a7", "t0", "t1", "t2",
+    "t3", "t4", "t5", "t6", "t7", "t8", "r21", "fp", "s0", "s1", "s2", "s3", "s4", "s5", "s6",
+    "s7", "s8",
+];
+
+/// Returns the conventional name of the core register which DWARF numbers
+/// `num` on `arch`, e.g. `"rsp"` for register 7 on x86_64.
+///
+/// Only the general purpose registers, the stack pointer and the program
+/// counter are named; `None` is returned for anything else, including
+/// floating point and vector registers.
+///
+/// Note that on x86_64 DWARF register 16 is the return address column, which
+/// is labelled `"rip"` as that's where it's restored to. The program counter
+/// isn't a DWARF register on s390x, RISC-V, PowerPC or LoongArch, where only
+/// the general purpose registers are named, by their ABI names where the
+/// architecture has them. Nothing is named for `Arch::Unknown`.
+///
+/// # Example
+///
+/// ```
+/// use backtrace::{dwarf_register_name, Arch};
+///
+/// assert_eq!(dwarf_register_name(Arch::X86_64, 7), Some("rsp"));
+/// assert_eq!(dwarf_register_name(Arch::Aarch64, 32), Some("pc"));
+/// assert_eq!(dwarf_register_name(Arch::Arm, 100), None);
+/// assert_eq!(dwarf_register_name(Arch::Riscv64, 2), Some("sp"));
+/// ```
+pub fn dwarf_register_name(arch: Arch, num: u16) -> Option<&'static str> {
+    let names: &[&'static str] = match arch {
+        Arch::X86_64 => &X86_64,
+        Arch::Aarch64 => &AARCH64,
+        Arch::Arm => &ARM,
+        Arch::S390x => &S390X,
+        Arch::Riscv64 => &RISCV64,
+        Arch::Powerpc64 => &POWERPC64,
+        Arch::LoongArch64 => &LOONGARCH64,
+        Arch::Unknown => &[],
+    };
+    names.get(usize::from(num)).copied()
+}
+
+/// The general purpose registers of a frame, as `Frame::capture_registers`
+/// recovers them, for the architecture this crate was compiled for.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum RegisterContext {
+    /// The registers of an x86_64 frame.
+    X86_64(X86_64Regs),
+    /// The registers of an aarch64 frame.
+    Aarch64(Aarch64Regs),
+}
+
+// Registers are only recovered by the libunwind backend, on the architectures
+// listed here.
+#[cfg(all(
+    not(miri),
+    any(
+        all(
+            unix,
+            not(target_os = "emscripten"),
+            not(all(target_os = "ios", target_arch = "arm")),
+        ),
+        all(target_env = "sgx", target_vendor = "fortanix"),
+    ),
+    any(target_arch = "x86_64", target_arch = "aarch64"),
+))]
+impl RegisterContext {
+    // A register set with nothing recovered yet, if this architecture has
+    // one.
+    pub(crate) fn empty() -> Option<RegisterContext> {
+        match current_arch() {
+            Arch::X86_64 => Some(RegisterContext::X86_64(X86_64Regs::default())),
+            Arch::Aarch64 => Some(RegisterContext::Aarch64(Aarch64Regs::default())),
+            _ => None,
+        }
+    }
+
+    // Records `value` for the register with bit `index` of `valid`, which for
+    // the general purpose registers is their DWARF number.
+    pub(crate) fn set(&mut self, index: u32, value: u64) {
+        match self {
+            RegisterContext::X86_64(regs) => regs.set(index, value),
+            RegisterContext::Aarch64(regs) => regs.set(index, value),
+        }
+    }
+}
+
+/// The general purpose registers of an x86_64 frame.
+///
+/// The registers are listed in the order DWARF numbers them, followed by
+/// `rflags`, and bit `n` of `valid` is set if the `n`th of them, counting from
+/// zero, was recovered, so bits 0 to 16 are those of the DWARF register
+/// numbers. Registers which weren't recovered are left zero. Neither unwinder
+/// can recover `rflags`, which is only here for formats, such as minidumps,
+/// which expect it.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct X86_64Regs {
+    /// `rax`.
+    pub rax: u64,
+    /// `rdx`.
+    pub rdx: u64,
+    /// `rcx`.
+    pub rcx: u64,
+    /// `rbx`.
+    pub rbx: u64,
+    /// `rsi`.
+    pub rsi: u64,
+    /// `rdi`.
+    pub rdi: u64,
+    /// `rbp`, the frame pointer.
+    pub rbp: u64,
+    /// `rsp`, the stack pointer.
+    pub rsp: u64,
+    /// `r8`.
+    pub r8: u64,
+    /// `r9`.
+    pub r9: u64,
+    /// `r10`.
+    pub r10: u64,
+    /// `r11`.
+    pub r11: u64,
+    /// `r12`.
+    pub r12: u64,
+    /// `r13`.
+    pub r13: u64,
+    /// `r14`.
+    pub r14: u64,
+    /// `r15`.
+    pub r15: u64,
+    /// `rip`, the instruction pointer.
+    pub rip: u64,
+    /// `rflags`, never recovered.
+    pub rflags: u64,
+    /// Which of the registers above were recovered.
+    pub valid: u64,
+}
+
+#[cfg(all(
+    not(miri),
+    any(
+        all(
+            unix,
+            not(target_os = "emscripten"),
+            not(all(target_os = "ios", target_arch = "arm")),
+        ),
+        all(target_env = "sgx", target_vendor = "fortanix"),
+    ),
+    any(target_arch = "x86_64", target_arch = "aarch64"),
+))]
+impl X86_64Regs {
+    fn set(&mut self, index: u32, value: u64) {
+        let reg = match index {
+            0 => &mut self.rax,
+            1 => &mut self.rdx,
+            2 => &mut self.rcx,
+            3 => &mut self.rbx,
+            4 => &mut self.rsi,
+            5 => &mut self.rdi,
+            6 => &mut self.rbp,
+            7 => &mut self.rsp,
+            8 => &mut self.r8,
+            9 => &mut self.r9,
+            10 => &mut self.r10,
+            11 => &mut self.r11,
+            12 => &mut self.r12,
+            13 => &mut self.r13,
+            14 => &mut self.r14,
+            15 => &mut self.r15,
+            16 => &mut self.rip,
+            17 => &mut self.rflags,
+            _ => return,
+        };
+        *reg = value;
+        self.valid |= 1 << index;
+    }
+}
+
+/// The general purpose registers of an aarch64 frame.
+///
+/// Bit `n` of `valid` is set if `x[n]` was recovered for `n` up to 30, and
+/// bits 31, 32 and 33 are those of `sp`, `pc` and `pstate`, so bits 0 to 32
+/// are those of the DWARF register numbers. Registers which weren't recovered
+/// are left zero. `pstate` is only recovered by the `nongnu-unwind` unwinder.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct Aarch64Regs {
+    /// `x0` to `x30`, with `x29` the frame pointer and `x30` the link
+    /// register.
+    pub x: [u64; 31],
+    /// The stack pointer.
+    pub sp: u64,
+    /// The program counter.
+    pub pc: u64,
+    /// The processor state.
+    pub pstate: u64,
+    /// Which of the registers above were recovered.
+    pub valid: u64,
+}
+
+#[cfg(all(
+    not(miri),
+    any(
+        all(
+            unix,
+            not(target_os = "emscripten"),
+            not(all(target_os = "ios", target_arch = "arm")),
+        ),
+        all(target_env = "sgx", target_vendor = "fortanix"),
+    ),
+    any(target_arch = "x86_64", target_arch = "aarch64"),
+))]
+impl Aarch64Regs {
+    fn set(&mut self, index: u32, value: u64) {
+        let reg = match index {
+            0..=30 => &mut self.x[index as usize],
+            31 => &mut self.sp,
+            32 => &mut self.pc,
+            33 => &mut self.pstate,
+            _ => return,
+        };
+        *reg = value;
+        self.valid |= 1 << index;
+    }
+}
@@ -0,0 +1,776 @@
+//! Capturing the backtrace of another live thread.
+//!
+//! There's no portable way to unwind a thread other than the current one, so
+//! the target thread is instead asked to unwind itself: it's sent a dedicated
+//! signal, whose handler captures its stack into a shared slot and then posts
+//! a semaphore that the requesting thread waits on. Only the raw instruction
+//! pointers are captured in the handler, with symbolication done afterwards by
+//! the requesting thread.
+//!
+//! `dump_all_threads_frozen` uses the same signal as a cooperative barrier:
+//! every thread is signalled at once, and each handler captures its stack into
+//! a slot of its own and then parks on a futex until the requesting thread has
+//! heard from all of them, so that no thread resumes before every stack has
+//! been captured.
+
+use crate::{BacktraceFrame, MAX_RECOMMENDED_FRAMES};
+use core::cell::UnsafeCell;
+use core::ffi::c_void;
+use core::mem::{self, MaybeUninit};
+use core::ptr;
+use core::slice;
+use core::sync::atomic::{AtomicI32, AtomicPtr, AtomicUsize, Ordering};
+use std::fmt;
+use std::fs;
+use std::os::unix::thread::JoinHandleExt;
+use std::prelude::v1::*;
+use std::sync::{Mutex, Once};
+use std::thread::JoinHandle;
+use std::time::{Duration, Instant};
+
+/// How long `thread_backtrace` waits for the target thread to respond.
+const TIMEOUT: Duration = Duration::from_secs(1);
+
+/// Returns the signal used to ask a thread for its backtrace, which is
+/// `SIGRTMIN() + 3` unless another one was picked with
+/// `set_sampling_signal`.
+///
+/// The handler for this signal is installed the first time a remote backtrace
+/// is requested, replacing any existing handler. Applications using this
+/// signal for something else should pick another one with
+/// `set_sampling_signal`.
+///
+/// # Required features
+///
+/// This function requires the `remote-thread` feature of the `backtrace`
+/// crate to be enabled.
+pub fn remote_thread_signal() -> libc::c_int {
+    match SIGNAL.load(Ordering::Relaxed) {
+        0 => libc::SIGRTMIN() + 3,
+        signal => signal,
+    }
+}
+
+/// Picks the signal used to ask threads for their backtraces, in place of
+/// the default of `SIGRTMIN() + 3`, for applications which already use that
+/// one.
+///
+/// The signal must be a real-time one, between `SIGRTMIN()` and `SIGRTMAX()`
+/// inclusive, since those are queued rather than coalesced, which a frozen
+/// dump relies on when several threads are signalled at once. The handler for
+/// it is installed right away, replacing any handler the application had for
+/// it, which won't be called again. No thread may block it either, or that
+/// thread's backtrace can't be captured.
+///
+/// This has to be called before anything asks a thread for its backtrace,
+/// since the handler for the signal in use is only ever installed once.
+/// Returns `false`, changing nothing, if `signal` isn't a real-time signal or
+/// a signal has already been installed.
+///
+/// # Required features
+///
+/// This function requires the `remote-thread` feature of the `backtrace`
+/// crate to be enabled.
+///
+/// # Example
+///
+/// ```
+/// // Leave the default signal to the application.
+/// assert!(backtrace::set_sampling_signal(libc::SIGRTMIN() + 4));
+/// assert_eq!(backtrace::remote_thread_signal(), libc::SIGRTMIN() + 4);
+/// ```
+pub fn set_sampling_signal(signal: libc::c_int) -> bool {
+    if signal < libc::SIGRTMIN() || signal > libc::SIGRTMAX() {
+        return false;
+    }
+    let mut set = false;
+    INIT.call_once(|| unsafe {
+        SIGNAL.store(signal, Ordering::Relaxed);
+        init();
+        set = true;
+    });
+    set
+}
+
+/// Captures and resolves the backtrace of the thread behind `handle`, while
+/// it keeps running.
+///
+/// The thread is interrupted with the signal returned by
+/// `remote_thread_signal`, and captures its own stack from within the signal
+/// handler, so it must not block that signal. Threads which are blocked in a
+/// system call are interrupted as well, and the system call restarted.
+///
+/// Returns `None` if the thread has exited, or didn't respond within a
+/// second (e.g. because it blocks the signal), or if the thread asked for a
+/// backtrace before that still hasn't finished capturing it. The frames
+/// returned start with
+/// the function that was interrupted, omitting the signal handler itself, and
+/// are resolved by the calling thread once the target thread has resumed.
+///
+/// Only one remote backtrace is captured at a time; concurrent calls wait for
+/// each other.
+///
+/// # Caveats
+///
+/// The unwinder runs inside a signal handler, which it isn't strictly
+/// guaranteed to be safe to do. In particular, if the target thread is
+/// interrupted while it holds the dynamic loader's lock (for example during
+/// `dlopen`) the unwinder may deadlock on it, in which case this times out
+/// and the target thread stays stuck. Until it gets unstuck, if ever, every
+/// later request returns `None` right away rather than waiting on it.
+///
+/// # Required features
+///
+/// This function requires the `remote-thread` feature of the `backtrace`
+/// crate to be enabled.
+///
+/// # Example
+///
+/// ```
+/// use std::sync::mpsc;
+///
+/// let (tx, rx) = mpsc::channel::<()>();
+/// let handle = std::thread::spawn(move || rx.recv());
+/// if let Some(frames) = backtrace::thread_backtrace(&handle) {
+///     println!("{:?}", backtrace::Backtrace::from(frames));
+/// }
+/// drop(tx);
+/// handle.join().unwrap().ok();
+/// ```
+pub fn thread_backtrace<T>(handle: &JoinHandle<T>) -> Option<Vec<BacktraceFrame>> {
+    let thread = handle.as_pthread_t();
+    if thread == unsafe { libc::pthread_self() } {
+        return Some(crate::Backtrace::new().into());
+    }
+    let ips = capture(Target::Pthread(thread), TIMEOUT)?;
+    Some(resolve(&ips))
+}
+
+/// Captures and resolves the backtraces of every thread in this process, for
+/// "what is every thread doing right now" diagnostics.
+///
+/// The threads are found by listing `/proc/self/task`, and each one other
+/// than the current thread is interrupted in turn just like with
+/// `thread_backtrace`, with the same caveats. Threads which exit or don't
+/// respond within 100 milliseconds, for example because they block the
+/// signal, are left out. All stacks are captured before any symbols are
+/// resolved, so that the snapshots are as close together as possible.
+///
+/// The `Debug` implementation of each `ThreadDump` prints a header line
+/// followed by the thread's backtrace in the same format as `Backtrace`.
+///
+/// # Required features
+///
+/// This function requires the `remote-thread` feature of the `backtrace`
+/// crate to be enabled.
+///
+/// # Example
+///
+/// ```
+/// for thread in backtrace::dump_all_threads() {
+///     eprintln!("{:?}", thread);
+/// }
+/// ```
+pub fn dump_all_threads() -> Vec<ThreadDump> {
+    let current = current_tid();
+    let tids = list_threads();
+
+    let mut captured = Vec::with_capacity(tids.len());
+    for tid in tids {
+        if tid == current {
+            let mut ips = Vec::new();
+            crate::trace(|frame| {
+                ips.push(frame.ip() as usize);
+                true
+            });
+            captured.push((tid, ips));
+        } else if let Some(ips) = capture(Target::Tid(tid), DUMP_TIMEOUT) {
+            captured.push((tid, ips));
+        }
+    }
+
+    into_dumps(captured)
+}
+
+/// Captures and resolves the backtraces of every thread in this process as a
+/// single point-in-time snapshot, by stopping all of them before any is
+/// allowed to carry on.
+///
+/// This is a variant of `dump_all_threads` for when the stacks of different
+/// threads need to agree with each other, e.g. to work out which thread holds
+/// a lock that another is waiting for. Rather than being interrupted in turn,
+/// every thread is sent the signal returned by `remote_thread_signal` at
+/// once, and each one captures its stack and then waits inside the signal
+/// handler until all of the others have done so too. The calling thread
+/// captures its own stack just before signalling the others, and lets them go
+/// once they've all stopped. Symbols are resolved only after that.
+///
+/// Threads which exit, or which don't respond within 100 milliseconds of the
+/// first signal being sent, are left out, and so is a thread which only
+/// responds after the others have been released, since its stack would no
+/// longer belong to the same snapshot.
+///
+/// # Caveats
+///
+/// Besides those of `thread_backtrace`, freezing the whole process has costs
+/// of its own:
+///
+/// * Every thread is stopped for as long as the slowest one takes to capture
+///   its stack, which can be up to the 100 millisecond timeout if some thread
+///   doesn't respond. Timing-sensitive code, heartbeats and the like will see
+///   that pause.
+///
+/// * Threads are stopped wherever they happen to be, which may be while
+///   holding the allocator's lock, the dynamic loader's lock, or the lock
+///   behind `backtrace::trace`. Nothing which may block on such a lock is done
+///   by the calling thread while the threads are stopped: it captures its own
+///   stack before stopping any, the signal handlers only capture raw
+///   instruction pointers into buffers allocated beforehand, without
+///   allocating or taking any locks, and everything else waits until the
+///   threads have been released.
+///
+/// * The calling thread also holds the lock behind `backtrace::trace` while
+///   the threads are stopped, so that none is stopped halfway through this
+///   crate's walk of the loaded libraries, which holds the dynamic loader's
+///   lock. That lock is only tried for up to the 100 millisecond timeout,
+///   rather than waited on, in case a thread holding it is stuck, and
+///   the dump goes ahead without it if it can't be had.
+///
+/// * The unwinder in each signal handler may still need the dynamic loader's
+///   lock (glibc before 2.35 takes it to find unwind info), so a thread
+///   stopped inside `dlopen` can hold up the others until the timeout, at
+///   which point every thread is released. Those which haven't captured their
+///   stack by then are left out and not waited for, so a thread stuck in the
+///   unwinder for good doesn't hang this function.
+///
+/// # Required features
+///
+/// This function requires the `remote-thread` feature of the `backtrace`
+/// crate to be enabled.
+///
+/// # Example
+///
+/// ```
+/// for thread in backtrace::dump_all_threads_frozen() {
+///     eprintln!("{:?}", thread);
+/// }
+/// ```
+pub fn dump_all_threads_frozen() -> Vec<ThreadDump> {
+    let current = current_tid();
+    let tids = list_threads();
+    if tids.is_empty() {
+        return Vec::new();
+    }
+
+    INIT.call_once(|| unsafe { init() });
+    let _guard = request().lock().unwrap_or_else(|e| e.into_inner());
+    let lock = try_lock_within(DUMP_TIMEOUT);
+    free_retired_slots();
+
+    // Nothing is stopped yet, so the unwinder can't get stuck on a lock held
+    // by a stopped thread.
+    let mut own = [ptr::null_mut(); MAX_RECOMMENDED_FRAMES];
+    let own_len = unsafe { crate::trace_unsynchronized_into(&mut own).len };
+
+    // Everything the handlers write into while the threads are stopped is
+    // allocated up front.
+    let slots = tids
+        .iter()
+        .filter(|&&tid| tid != current)
+        .map(|&tid| Slot {
+            tid,
+            state: AtomicUsize::new(REQUESTED),
+            len: AtomicUsize::new(0),
+            ips: UnsafeCell::new([ptr::null_mut(); MAX_RECOMMENDED_FRAMES]),
+        })
+        .collect::<Vec<_>>();
+
+    FROZEN_LEN.store(slots.len(), Ordering::SeqCst);
+    FROZEN.store(slots.as_ptr() as *mut Slot, Ordering::SeqCst);
+
+    for slot in &slots {
+        let sent = unsafe {
+            libc::syscall(
+                libc::SYS_tgkill,
+                libc::getpid(),
+                slot.tid,
+                remote_thread_signal(),
+            ) == 0
+        };
+        if !sent {
+            slot.state.store(IDLE, Ordering::SeqCst);
+        }
+    }
+
+    unsafe {
+        wait_until(DUMP_TIMEOUT, || {
+            slots
+                .iter()
+                .all(|slot| !matches!(slot.state.load(Ordering::SeqCst), REQUESTED | WRITING))
+        });
+    }
+
+    // Withdraw the requests of the threads that haven't responded, and let
+    // everyone go, including the handlers still capturing their stack, which
+    // will find out they're late.
+    for slot in &slots {
+        let _ = slot
+            .state
+            .compare_exchange(REQUESTED, IDLE, Ordering::SeqCst, Ordering::SeqCst);
+    }
+    FREEZE_GENERATION.fetch_add(1, Ordering::SeqCst);
+    unsafe { futex_wake(&FREEZE_GENERATION) };
+    FROZEN.store(ptr::null_mut(), Ordering::SeqCst);
+    drop(lock);
+
+    let mut captured = slots
+        .iter()
+        .filter(|slot| slot.state.load(Ordering::Acquire) == DONE)
+        .map(|slot| {
+            let len = slot.len.load(Ordering::Acquire);
+            let ips: &[*mut c_void] = unsafe { &*slot.ips.get() };
+            (slot.tid, ips[..len].iter().map(|&ip| ip as usize).collect())
+        })
+        .collect::<Vec<_>>();
+    retire_slots(slots);
+    let own = own[..own_len].iter().map(|&ip| ip as usize).collect();
+    captured.push((current, own));
+    captured.sort_unstable_by_key(|&(tid, _)| tid);
+    into_dumps(captured)
+}
+
+/// How long `dump_all_threads` waits for each thread to respond, and
+/// `dump_all_threads_frozen` for all of them.
+const DUMP_TIMEOUT: Duration = Duration::from_millis(100);
+
+// Takes this crate's global lock, unless it can't be had within `timeout`.
+fn try_lock_within(timeout: Duration) -> Option<crate::lock::LockGuard> {
+    let deadline = Instant::now() + timeout;
+    loop {
+        if let Some(guard) = crate::lock::try_lock() {
+            return Some(guard);
+        }
+        if Instant::now() >= deadline {
+            return None;
+        }
+        std::thread::sleep(Duration::from_millis(1));
+    }
+}
+
+// Frees the slots of a frozen dump once no handler can be looking at them.
+// Until then they're kept in `RETIRED`, rather than waiting for the handlers,
+// which may be stuck in the unwinder for good.
+fn retire_slots(slots: Vec<Slot>) {
+    let slots = Box::into_raw(Box::new(slots));
+    // A dump before this one whose slots are still in use means some handler
+    // has been stuck for a while, so its slots are leaked.
+    let previous = RETIRED.swap(slots, Ordering::SeqCst);
+    if !previous.is_null() {
+        mem::forget(unsafe { Box::from_raw(previous) });
+    }
+    free_retired_slots();
+}
+
+fn free_retired_slots() {
+    // Handlers count themselves before looking at `FROZEN`, which has been
+    // cleared, so none can get to the retired slots after this.
+    if FROZEN_HANDLERS.load(Ordering::SeqCst) != 0 {
+        return;
+    }
+    let slots = RETIRED.swap(ptr::null_mut(), Ordering::SeqCst);
+    if !slots.is_null() {
+        drop(unsafe { Box::from_raw(slots) });
+    }
+}
+
+fn current_tid() -> libc::pid_t {
+    unsafe { libc::syscall(libc::SYS_gettid) as libc::pid_t }
+}
+
+// The IDs of this process's threads, in ascending order.
+fn list_threads() -> Vec<libc::pid_t> {
+    let mut tids = match fs::read_dir("/proc/self/task") {
+        Ok(dir) => dir
+            .filter_map(|entry| entry.ok()?.file_name().to_str()?.parse().ok())
+            .collect::<Vec<libc::pid_t>>(),
+        Err(_) => return Vec::new(),
+    };
+    tids.sort_unstable();
+    tids
+}
+
+fn into_dumps(captured: Vec<(libc::pid_t, Vec<usize>)>) -> Vec<ThreadDump> {
+    captured
+        .into_iter()
+        .map(|(tid, ips)| ThreadDump {
+            tid,
+            name: thread_name(tid),
+            frames: resolve(&ips),
+        })
+        .collect()
+}
+
+fn thread_name(tid: libc::pid_t) -> Option<String> {
+    let name = fs::read_to_string(format!("/proc/self/task/{}/comm", tid)).ok()?;
+    Some(name.trim_end_matches('\n').to_string())
+}
+
+/// The backtrace of one thread, as captured by `dump_all_threads` or
+/// `dump_all_threads_frozen`.
+///
+/// # Required features
+///
+/// This function requires the `remote-thread` feature of the `backtrace`
+/// crate to be enabled.
+#[derive(Clone)]
+pub struct ThreadDump {
+    tid: libc::pid_t,
+    name: Option<String>,
+    frames: Vec<BacktraceFrame>,
+}
+
+impl ThreadDump {
+    /// Returns the operating system's ID for the thread, as returned by
+    /// `gettid`.
+    pub fn tid(&self) -> libc::pid_t {
+        self.tid
+    }
+
+    /// Returns the name of the thread, as seen by the operating system.
+    pub fn name(&self) -> Option<&str> {
+        self.name.as_deref()
+    }
+
+    /// Returns the thread's resolved frames, starting with the function that
+    /// was interrupted.
+    pub fn frames(&self) -> &[BacktraceFrame] {
+        &self.frames
+    }
+}
+
+impl fmt::Debug for ThreadDump {
+    fn fmt(&self, fmt: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.name {
+            Some(ref name) => writeln!(fmt, "thread {} ({}):", self.tid, name)?,
+            None => writeln!(fmt, "thread {}:", self.tid)?,
+        }
+        fmt::Debug::fmt(&crate::Backtrace::from(self.frames.clone()), fmt)
+    }
+}
+
+/// A thread that can be asked for its backtrace.
+#[derive(Clone, Copy)]
+pub(crate) enum Target {
+    Pthread(libc::pthread_t),
+    Tid(libc::pid_t),
+}
+
+/// Resolves instruction pointers captured by `capture`.
+pub(crate) fn resolve(ips: &[usize]) -> Vec<BacktraceFrame> {
+    let frames = ips
+        .iter()
+        .map(|&ip| BacktraceFrame::from_ip(ip as *mut c_void))
+        .collect::<Vec<_>>();
+    let mut backtrace = crate::Backtrace::from(frames);
+    backtrace.resolve();
+    backtrace.into()
+}
+
+// The request's progress, transitioned with compare-and-swap so that a
+// handler running late, after its request timed out, can't write into the slot
+// while it's in use by a new request.
+const IDLE: usize = 0;
+const REQUESTED: usize = 1;
+const WRITING: usize = 2;
+const DONE: usize = 3;
+// A handler which was still writing when its request gave up on it, and which
+// resets `STATE` itself once it's done. For a frozen dump, one which only
+// finished after the threads were released.
+const LATE: usize = 4;
+
+static STATE: AtomicUsize = AtomicUsize::new(IDLE);
+// Which thread the current request is for, so a late signal delivered to some
+// other thread is ignored. Only one of these is set at a time.
+static TARGET_PTHREAD: AtomicUsize = AtomicUsize::new(0);
+static TARGET_TID: AtomicI32 = AtomicI32::new(0);
+static INIT: Once = Once::new();
+// The signal picked with `set_sampling_signal`, or 0 for the default. Only
+// written while `INIT` is running, so it never changes once installed.
+static SIGNAL: AtomicI32 = AtomicI32::new(0);
+static SEMAPHORE: Shared<MaybeUninit<libc::sem_t>> = Shared(UnsafeCell::new(MaybeUninit::uninit()));
+static IPS: Shared<[*mut c_void; MAX_RECOMMENDED_FRAMES]> =
+    Shared(UnsafeCell::new([ptr::null_mut(); MAX_RECOMMENDED_FRAMES]));
+static LEN: AtomicUsize = AtomicUsize::new(0);
+
+// `Mutex::new` can't be used in a `static`, so the lock serializing requests
+// is created on first use, like the global lock.
+fn request() -> &'static Mutex<()> {
+    static mut REQUEST: *const Mutex<()> = ptr::null();
+    static INIT: Once = Once::new();
+    unsafe {
+        INIT.call_once(|| {
+            REQUEST = Box::into_raw(Box::new(Mutex::new(())));
+        });
+        &*REQUEST
+    }
+}
+
+// A `static` which the signal handler and the requesting thread take turns
+// writing, as `STATE` says.
+struct Shared<T>(UnsafeCell<T>);
+
+unsafe impl<T> Sync for Shared<T> {}
+
+fn semaphore() -> *mut libc::sem_t {
+    SEMAPHORE.0.get() as *mut libc::sem_t
+}
+
+// One thread's part of a frozen dump, with `state` going through the same
+// transitions as `STATE`.
+struct Slot {
+    tid: libc::pid_t,
+    state: AtomicUsize,
+    len: AtomicUsize,
+    ips: UnsafeCell<[*mut c_void; MAX_RECOMMENDED_FRAMES]>,
+}
+
+// The slots of the frozen dump in progress, if any, sorted by thread ID.
+static FROZEN: AtomicPtr<Slot> = AtomicPtr::new(ptr::null_mut());
+static FROZEN_LEN: AtomicUsize = AtomicUsize::new(0);
+// How many handlers may be looking at `FROZEN`, so that the slots aren't freed
+// from under them.
+static FROZEN_HANDLERS: AtomicUsize = AtomicUsize::new(0);
+// Bumped to release the threads stopped by a frozen dump, which wait on it.
+static FREEZE_GENERATION: AtomicI32 = AtomicI32::new(0);
+// The slots of the last frozen dump, if handlers might still have been looking
+// at them when it returned.
+static RETIRED: AtomicPtr<Vec<Slot>> = AtomicPtr::new(ptr::null_mut());
+
+/// Asks `target` for its backtrace, returning the raw instruction pointers of
+/// its frames.
+pub(crate) fn capture(target: Target, timeout: Duration) -> Option<Vec<usize>> {
+    INIT.call_once(|| unsafe { init() });
+    let _guard = request().lock().unwrap_or_else(|e| e.into_inner());
+    // The handler of an earlier request that timed out may still be writing,
+    // and may never finish if it's stuck in the unwinder.
+    if STATE.load(Ordering::SeqCst) != IDLE {
+        return None;
+    }
+
+    match target {
+        Target::Pthread(thread) => {
+            TARGET_PTHREAD.store(thread as usize, Ordering::Relaxed);
+            TARGET_TID.store(0, Ordering::Relaxed);
+        }
+        Target::Tid(tid) => {
+            TARGET_PTHREAD.store(0, Ordering::Relaxed);
+            TARGET_TID.store(tid, Ordering::Relaxed);
+        }
+    }
+    STATE.store(REQUESTED, Ordering::SeqCst);
+
+    let sent = unsafe {
+        match target {
+            Target::Pthread(thread) => libc::pthread_kill(thread, remote_thread_signal()) == 0,
+            Target::Tid(tid) => {
+                libc::syscall(
+                    libc::SYS_tgkill,
+                    libc::getpid(),
+                    tid,
+                    remote_thread_signal(),
+                ) == 0
+            }
+        }
+    };
+    if !sent {
+        STATE.store(IDLE, Ordering::SeqCst);
+        return None;
+    }
+
+    if !unsafe { wait_until(timeout, || STATE.load(Ordering::SeqCst) == DONE) } {
+        // Withdraw the request, or if the handler has already started on it,
+        // leave it to go back to idle by itself whenever it's done, unless it
+        // just did finish.
+        if STATE
+            .compare_exchange(REQUESTED, IDLE, Ordering::SeqCst, Ordering::SeqCst)
+            .is_ok()
+            || STATE
+                .compare_exchange(WRITING, LATE, Ordering::SeqCst, Ordering::SeqCst)
+                .is_ok()
+        {
+            return None;
+        }
+    }
+
+    let len = LEN.load(Ordering::Acquire);
+    let ips: &[*mut c_void] = unsafe { &*IPS.0.get() };
+    let ips = ips[..len].iter().map(|&ip| ip as usize).collect();
+    STATE.store(IDLE, Ordering::SeqCst);
+    Some(ips)
+}
+
+unsafe fn init() {
+    libc::sem_init(semaphore(), 0, 0);
+
+    let mut action = mem::zeroed::<libc::sigaction>();
+    action.sa_sigaction = handler as *const () as usize;
+    action.sa_flags = libc::SA_SIGINFO | libc::SA_RESTART;
+    libc::sigemptyset(&mut action.sa_mask);
+    libc::sigaction(remote_thread_signal(), &action, ptr::null_mut());
+}
+
+// Waits for `done` to return `true`, checking it each time a handler posts the
+// semaphore, and returning whether it did before `timeout` passed. A post
+// alone means nothing, since handlers which finish after their request gave
+// up on them still post it.
+unsafe fn wait_until(timeout: Duration, mut done: impl FnMut() -> bool) -> bool {
+    let deadline = Instant::now() + timeout;
+    while !done() {
+        if !wait(deadline.saturating_duration_since(Instant::now())) {
+            return done();
+        }
+    }
+    true
+}
+
+// Waits for a handler to post the semaphore, returning `false` on timeout.
+unsafe fn wait(timeout: Duration) -> bool {
+    let mut deadline = mem::zeroed::<libc::timespec>();
+    libc::clock_gettime(libc::CLOCK_REALTIME, &mut deadline);
+    let nanos = deadline.tv_nsec as u64 + u64::from(timeout.subsec_nanos());
+    deadline.tv_sec += (timeout.as_secs() + nanos / 1_000_000_000) as libc::time_t;
+    deadline.tv_nsec = (nanos % 1_000_000_000) as _;
+
+    let semaphore = semaphore();
+    loop {
+        if libc::sem_timedwait(semaphore, &deadline) == 0 {
+            return true;
+        }
+        if *libc::__errno_location() != libc::EINTR {
+            return false;
+        }
+    }
+}
+
+// Everything in here needs to be async-signal-safe.
+extern "C" fn handler(_signum: libc::c_int, _info: *mut libc::siginfo_t, context: *mut c_void) {
+    unsafe {
+        let saved_errno = *libc::__errno_location();
+        FROZEN_HANDLERS.fetch_add(1, Ordering::SeqCst);
+        let slots = FROZEN.load(Ordering::SeqCst);
+        if !slots.is_null() {
+            let slots = slice::from_raw_parts(slots, FROZEN_LEN.load(Ordering::SeqCst));
+            freeze(slots, context);
+        }
+        FROZEN_HANDLERS.fetch_sub(1, Ordering::SeqCst);
+        if slots.is_null() {
+            respond(context);
+        }
+        *libc::__errno_location() = saved_errno;
+    }
+}
+
+// Answers a request made by `capture`.
+unsafe fn respond(context: *mut c_void) {
+    let is_target = match TARGET_TID.load(Ordering::Relaxed) {
+        0 => TARGET_PTHREAD.load(Ordering::Relaxed) == libc::pthread_self() as usize,
+        tid => tid == libc::syscall(libc::SYS_gettid) as libc::pid_t,
+    };
+    if !is_target
+        || STATE
+            .compare_exchange(REQUESTED, WRITING, Ordering::SeqCst, Ordering::SeqCst)
+            .is_err()
+    {
+        return;
+    }
+
+    let len = capture_here(&mut *IPS.0.get(), context);
+    LEN.store(len, Ordering::Release);
+    if STATE
+        .compare_exchange(WRITING, DONE, Ordering::SeqCst, Ordering::SeqCst)
+        .is_err()
+    {
+        // The request timed out, so nobody's going to read the frames.
+        STATE.store(IDLE, Ordering::SeqCst);
+    }
+    libc::sem_post(semaphore());
+}
+
+// Takes part in a frozen dump: captures this thread's stack into its slot,
+// and then waits for `dump_all_threads_frozen` to release it.
+unsafe fn freeze(slots: &[Slot], context: *mut c_void) {
+    // Read before claiming the slot, which can only happen before the release.
+    let generation = FREEZE_GENERATION.load(Ordering::SeqCst);
+    let tid = libc::syscall(libc::SYS_gettid) as libc::pid_t;
+    let slot = match slots.binary_search_by_key(&tid, |slot| slot.tid) {
+        Ok(i) => &slots[i],
+        Err(_) => return,
+    };
+    if slot
+        .state
+        .compare_exchange(REQUESTED, WRITING, Ordering::SeqCst, Ordering::SeqCst)
+        .is_err()
+    {
+        return;
+    }
+
+    let len = capture_here(&mut *slot.ips.get(), context);
+    slot.len.store(len, Ordering::Release);
+    let in_time = FREEZE_GENERATION.load(Ordering::SeqCst) == generation;
+    slot.state
+        .store(if in_time { DONE } else { LATE }, Ordering::SeqCst);
+    libc::sem_post(semaphore());
+
+    while FREEZE_GENERATION.load(Ordering::SeqCst) == generation {
+        futex_wait(&FREEZE_GENERATION, generation);
+    }
+}
+
+// Captures the stack of the thread running the signal handler into `ips`,
+// returning how many entries were filled in.
+unsafe fn capture_here(ips: &mut [*mut c_void], context: *mut c_void) -> usize {
+    let mut len = crate::trace_unsynchronized_into(ips).len;
+
+    // Drop the frames of the handler itself, up to the one that was
+    // interrupted, if we know where that is.
+    if let Some(pc) = interrupted_pc(context) {
+        if let Some(i) = ips[..len].iter().position(|&ip| ip == pc) {
+            ips.copy_within(i..len, 0);
+            len -= i;
+        }
+    }
+    len
+}
+
+unsafe fn futex_wait(word: &AtomicI32, expected: i32) {
+    libc::syscall(
+        libc::SYS_futex,
+        word as *const AtomicI32,
+        libc::FUTEX_WAIT | libc::FUTEX_PRIVATE_FLAG,
+        expected,
+        ptr::null::<libc::timespec>(),
+    );
+}
+
+unsafe fn futex_wake(word: &AtomicI32) {
+    libc::syscall(
+        libc::SYS_futex,
+        word as *const AtomicI32,
+        libc::FUTEX_WAKE | libc::FUTEX_PRIVATE_FLAG,
+        libc::INT_MAX,
+    );
+}
+
+unsafe fn interrupted_pc(context: *mut c_void) -> Option<*mut c_void> {
+    let context = &*(context as *const libc::ucontext_t);
+    cfg_if::cfg_if! {
+        if #[cfg(all(target_arch = "x86_64", target_env = "gnu"))] {
+            Some(context.uc_mcontext.gregs[libc::REG_RIP as usize] as *mut c_void)
+        } else if #[cfg(all(target_arch = "aarch64", target_os = "linux"))] {
+            Some(context.uc_mcontext.pc as *mut c_void)
+        } else {
+            let _ = context;
+            None
+        }
+    }
+}
@@ -0,0 +1,410 @@
+//! A ring of recent stack samples kept in a memory-mapped file, so that what
+//! a process was doing can still be read out after it has crashed.
+//!
+//! The file is a header followed by a fixed number of equally sized slots,
+//! which samples are written to in turn, the oldest being overwritten once all
+//! are used. Since the file is mapped shared, what's written to it is in the
+//! kernel's page cache as soon as it's written, and ends up in the file even
+//! if the process is killed before it could write anything out.
+//!
+//! The header is the magic number, then the number of slots, the size of
+//! their data and the number of samples ever written, as native endian 64 bit
+//! integers, padded out to `HEADER_SIZE` bytes. Each slot starts with the
+//! sequence number of the sample in it, which doubles as its state, then the
+//! length of the sample's data and its flags as 32 bit integers, and then the
+//! data itself, as written by `pack_stack`.
+
+use crate::{unpack_stack, Frame, UnpackedStack};
+use core::fmt;
+use core::ptr;
+use core::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
+use std::fs::{self, OpenOptions};
+use std::io;
+use std::os::unix::io::AsRawFd;
+use std::path::Path;
+use std::prelude::v1::*;
+
+const MAGIC: &[u8; 8] = b"BTRING\0\x01";
+const HEADER_SIZE: usize = 64;
+const SLOT_HEADER_SIZE: usize = 16;
+
+// The offsets of the fields of the header.
+const SLOTS: usize = 8;
+const SLOT_SIZE: usize = 16;
+const NEXT: usize = 24;
+
+// The sequence numbers of samples start at 1, leaving these for slots without
+// one.
+const EMPTY: u64 = 0;
+const WRITING: u64 = core::u64::MAX;
+
+const FLAG_TRUNCATED: u32 = 1;
+
+/// Writes stack samples into a ring of fixed size slots in a memory-mapped
+/// file, to be read back with `read_ring_file` after the process has died.
+///
+/// This is meant for crash analysis: a periodic sampler writes each stack it
+/// captures here, and since the file is mapped shared, the last `slots`
+/// samples are in it whichever way the process goes down, short of the whole
+/// system going down with it. Reading the file back, from another process or
+/// after a restart, tells what the process was doing up until then.
+///
+/// Stacks are written with `pack_stack`, so their frames are given as addresses
+/// in the files of the modules they were in, which can be symbolized later on
+/// a machine with the same binaries, even though the process and its address
+/// space are gone. Each sample gets a sequence number counting up from 1,
+/// which tells the reader their order, and how many were lost to being
+/// overwritten.
+///
+/// Writing only needs `&self`, so a sink can be shared between threads, which
+/// claim a slot with a compare-and-swap of its state before writing it. A
+/// sample whose slot is still being written by another thread, because
+/// samples are written faster than there are slots for, is dropped and
+/// counted, as `dropped` reports.
+///
+/// The file is in native byte order and is meant to be read back on the same
+/// machine, or at least one of the same byte order.
+///
+/// # Required features
+///
+/// This type requires the `ring-file` feature of the `backtrace` crate to be
+/// enabled.
+///
+/// # Example
+///
+/// ```
+/// use backtrace::{read_ring_file, RingFileSink};
+///
+/// let path = std::env::temp_dir().join(format!("ring-{}", std::process::id()));
+/// let sink = RingFileSink::create(&path, 64, 1024).unwrap();
+/// sink.capture();
+/// drop(sink);
+///
+/// // Usually after the process has crashed and been restarted.
+/// for sample in read_ring_file(&path).unwrap() {
+///     println!("{}: {} frames", sample.sequence(), sample.stack().frames.len());
+/// }
+/// # std::fs::remove_file(&path).unwrap();
+/// ```
+pub struct RingFileSink {
+    ptr: *mut u8,
+    len: usize,
+    slots: usize,
+    slot_size: usize,
+    stride: usize,
+    dropped: AtomicUsize,
+}
+
+// Slots are only ever written by whoever moved them to `WRITING`, and the
+// header only through atomics.
+unsafe impl Send for RingFileSink {}
+unsafe impl Sync for RingFileSink {}
+
+impl RingFileSink {
+    /// Creates the file at `path`, or truncates it if it exists, with room for
+    /// `slots` samples of up to `slot_size` bytes of packed stack each, and
+    /// maps it.
+    ///
+    /// A packed frame typically takes three or four bytes, on top of the
+    /// names or build IDs of the modules the stack goes through, so a few
+    /// hundred bytes hold most stacks. Stacks which don't fit are truncated.
+    ///
+    /// Returns an error if the file can't be created or mapped, or if `slots`
+    /// is 0 or the file would be too big.
+    pub fn create<P: AsRef<Path>>(
+        path: P,
+        slots: usize,
+        slot_size: usize,
+    ) -> io::Result<RingFileSink> {
+        let invalid = || io::Error::new(io::ErrorKind::InvalidInput, "invalid ring file size");
+        if slots == 0 || slot_size > core::u32::MAX as usize {
+            return Err(invalid());
+        }
+        let stride = slot_size
+            .checked_add(SLOT_HEADER_SIZE + 7)
+            .ok_or_else(invalid)?
+            / 8
+            * 8;
+        let len = stride
+            .checked_mul(slots)
+            .and_then(|len| len.checked_add(HEADER_SIZE))
+            .ok_or_else(invalid)?;
+
+        let file = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .create(true)
+            .truncate(true)
+            .open(path)?;
+        // Zero filled, which leaves every slot `EMPTY`.
+        file.set_len(len as u64)?;
+        let ptr = unsafe {
+            libc::mmap(
+                ptr::null_mut(),
+                len,
+                libc::PROT_READ | libc::PROT_WRITE,
+                libc::MAP_SHARED,
+                file.as_raw_fd(),
+                0,
+            )
+        };
+        if ptr == libc::MAP_FAILED {
+            return Err(io::Error::last_os_error());
+        }
+
+        let sink = RingFileSink {
+            ptr: ptr as *mut u8,
+            len,
+            slots,
+            slot_size,
+            stride,
+            dropped: AtomicUsize::new(0),
+        };
+        sink.header(SLOTS).store(slots as u64, Ordering::Relaxed);
+        sink.header(SLOT_SIZE)
+            .store(slot_size as u64, Ordering::Relaxed);
+        // The magic number goes last, so that a file which was never finished
+        // isn't mistaken for a ring.
+        unsafe {
+            ptr::copy_nonoverlapping(MAGIC.as_ptr(), sink.ptr, MAGIC.len());
+        }
+        Ok(sink)
+    }
+
+    /// Captures the calling thread's stack and writes it to the next slot,
+    /// returning `false` if the sample was dropped.
+    ///
+    /// The innermost frames captured are those of this function and of the
+    /// unwinder.
+    #[inline(never)]
+    pub fn capture(&self) -> bool {
+        let mut frames = Vec::new();
+        crate::trace(|frame| {
+            frames.push(frame.clone());
+            true
+        });
+        self.write_stack(&frames)
+    }
+
+    /// Packs the stack `frames`, innermost first, with `pack_stack`, and
+    /// writes it to the next slot, returning `false` if the sample was
+    /// dropped.
+    ///
+    /// If the packed stack doesn't fit in a slot, outermost frames are left
+    /// out until it does, and the sample is marked as truncated.
+    ///
+    /// This allocates and looks up the loaded modules, so unlike
+    /// `write_packed` it isn't async-signal-safe.
+    pub fn write_stack(&self, frames: &[Frame]) -> bool {
+        let mut packed = Vec::new();
+        let mut len = frames.len();
+        loop {
+            packed.clear();
+            crate::pack_stack(&frames[..len], &mut packed);
+            if packed.len() <= self.slot_size || len == 0 {
+                break;
+            }
+            // Leave out as large a share of the frames as the stack is too
+            // big, and at least one.
+            len = (len * self.slot_size / packed.len()).min(len - 1);
+        }
+        self.write(&packed, len < frames.len())
+    }
+
+    /// Writes a stack already packed with `pack_stack` to the next slot,
+    /// returning `false` if the sample was dropped, including when it doesn't
+    /// fit in a slot.
+    ///
+    /// This neither allocates nor takes any lock, so it's async-signal-safe.
+    pub fn write_packed(&self, packed: &[u8]) -> bool {
+        self.write(packed, false)
+    }
+
+    fn write(&self, packed: &[u8], truncated: bool) -> bool {
+        if packed.len() > self.slot_size {
+            self.dropped.fetch_add(1, Ordering::Relaxed);
+            return false;
+        }
+        let sequence = self.header(NEXT).fetch_add(1, Ordering::Relaxed) + 1;
+        let offset = HEADER_SIZE + self.slot(sequence) * self.stride;
+        let slot = unsafe { self.ptr.add(offset) };
+        let state = unsafe { &*(slot as *const AtomicU64) };
+        let current = state.load(Ordering::Relaxed);
+        if current == WRITING
+            || state
+                .compare_exchange(current, WRITING, Ordering::Acquire, Ordering::Relaxed)
+                .is_err()
+        {
+            self.dropped.fetch_add(1, Ordering::Relaxed);
+            return false;
+        }
+        let flags = if truncated { FLAG_TRUNCATED } else { 0 };
+        unsafe {
+            ptr::write(slot.add(8) as *mut u32, packed.len() as u32);
+            ptr::write(slot.add(12) as *mut u32, flags);
+            ptr::copy_nonoverlapping(packed.as_ptr(), slot.add(SLOT_HEADER_SIZE), packed.len());
+        }
+        state.store(sequence, Ordering::Release);
+        true
+    }
+
+    /// Returns the number of samples written so far, dropped ones included,
+    /// which is also the sequence number of the last one.
+    pub fn written(&self) -> u64 {
+        self.header(NEXT).load(Ordering::Relaxed)
+    }
+
+    /// Returns the number of samples dropped since the last call, because
+    /// they didn't fit in a slot or their slot was still being written.
+    pub fn dropped(&self) -> usize {
+        self.dropped.swap(0, Ordering::Relaxed)
+    }
+
+    /// Writes the file out to disk, for the samples in it to survive not only
+    /// the process but the system going down.
+    pub fn flush(&self) -> io::Result<()> {
+        let r = unsafe { libc::msync(self.ptr as *mut libc::c_void, self.len, libc::MS_SYNC) };
+        if r == 0 {
+            Ok(())
+        } else {
+            Err(io::Error::last_os_error())
+        }
+    }
+
+    // The slot the sample numbered `sequence` goes in.
+    fn slot(&self, sequence: u64) -> usize {
+        ((sequence - 1) % self.slots as u64) as usize
+    }
+
+    fn header(&self, offset: usize) -> &AtomicU64 {
+        unsafe { &*(self.ptr.add(offset) as *const AtomicU64) }
+    }
+}
+
+impl Drop for RingFileSink {
+    fn drop(&mut self) {
+        unsafe {
+            let r = libc::munmap(self.ptr as *mut libc::c_void, self.len);
+            debug_assert_eq!(r, 0);
+        }
+    }
+}
+
+impl fmt::Debug for RingFileSink {
+    fn fmt(&self, fmt: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt.debug_struct("RingFileSink")
+            .field("slots", &self.slots)
+            .field("slot_size", &self.slot_size)
+            .field("written", &self.written())
+            .finish()
+    }
+}
+
+/// A stack sample read back from a ring file by `read_ring_file`.
+///
+/// # Required features
+///
+/// This type requires the `ring-file` feature of the `backtrace` crate to be
+/// enabled.
+#[derive(Clone, Debug)]
+pub struct RingSample {
+    sequence: u64,
+    stack: UnpackedStack,
+    truncated: bool,
+}
+
+impl RingSample {
+    /// Returns the sequence number of the sample, counting up from 1 in the
+    /// order samples were written.
+    ///
+    /// Gaps between the samples read back are samples which were dropped, or
+    /// were overwritten while the ones after them were written.
+    pub fn sequence(&self) -> u64 {
+        self.sequence
+    }
+
+    /// Returns the stack of the sample, as read back by `unpack_stack`.
+    pub fn stack(&self) -> &UnpackedStack {
+        &self.stack
+    }
+
+    /// Returns the stack of the sample, as read back by `unpack_stack`.
+    pub fn into_stack(self) -> UnpackedStack {
+        self.stack
+    }
+
+    /// Returns whether the stack had more frames than fit in a slot, in which
+    /// case the outermost frames are missing.
+    pub fn is_truncated(&self) -> bool {
+        self.truncated
+    }
+}
+
+/// Reads back the samples of a ring file written by `RingFileSink`, oldest
+/// first.
+///
+/// Slots which were never written, or were being written when the process
+/// died, are left out, as are any whose contents don't make sense.
+///
+/// Returns an error if the file can't be read, or isn't a ring file.
+///
+/// # Required features
+///
+/// This function requires the `ring-file` feature of the `backtrace` crate to
+/// be enabled.
+pub fn read_ring_file<P: AsRef<Path>>(path: P) -> io::Result<Vec<RingSample>> {
+    let bytes = fs::read(path)?;
+    let invalid = || io::Error::new(io::ErrorKind::InvalidData, "not a backtrace ring file");
+    if bytes.len() < HEADER_SIZE || bytes[..MAGIC.len()] != MAGIC[..] {
+        return Err(invalid());
+    }
+    let slots = read_u64(&bytes[SLOTS..]) as usize;
+    let slot_size = read_u64(&bytes[SLOT_SIZE..]) as usize;
+    let stride = slot_size
+        .checked_add(SLOT_HEADER_SIZE + 7)
+        .ok_or_else(invalid)?
+        / 8
+        * 8;
+    let len = stride
+        .checked_mul(slots)
+        .and_then(|len| len.checked_add(HEADER_SIZE))
+        .ok_or_else(invalid)?;
+    if bytes.len() < len {
+        return Err(invalid());
+    }
+
+    let mut samples = Vec::new();
+    for slot in bytes[HEADER_SIZE..len].chunks(stride) {
+        let sequence = read_u64(slot);
+        if sequence == EMPTY || sequence == WRITING {
+            continue;
+        }
+        let packed_len = read_u32(&slot[8..]) as usize;
+        let flags = read_u32(&slot[12..]);
+        if packed_len > slot_size {
+            continue;
+        }
+        let mut packed = &slot[SLOT_HEADER_SIZE..][..packed_len];
+        if let Some(stack) = unpack_stack(&mut packed) {
+            samples.push(RingSample {
+                sequence,
+                stack,
+                truncated: flags & FLAG_TRUNCATED != 0,
+            });
+        }
+    }
+    samples.sort_by_key(|sample| sample.sequence);
+    Ok(samples)
+}
+
+fn read_u64(bytes: &[u8]) -> u64 {
+    let mut buf = [0; 8];
+    buf.copy_from_slice(&bytes[..8]);
+    u64::from_ne_bytes(buf)
+}
+
+fn read_u32(bytes: &[u8]) -> u32 {
+    let mut buf = [0; 4];
+    buf.copy_from_slice(&bytes[..4]);
+    u32::from_ne_bytes(buf)
+}
@@ -0,0 +1,143 @@
+use crate::Symbol;
+use core::fmt;
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::prelude::v1::*;
+
+/// Reads the lines of source code around resolved frames, for crash reports
+/// meant to be read by people.
+///
+/// A context reads each source file the first time a snippet of it is asked
+/// for, and keeps its lines around for later snippets, so that reporting a
+/// backtrace with many frames in the same file reads it only once. Files
+/// which can't be read are remembered as such too. Everything read is kept
+/// until the context is dropped, so a context is best made for a report and
+/// dropped with it.
+///
+/// Source files are looked for at the paths recorded in the debug info, and
+/// so are only found when running on the machine the program was built on,
+/// or one with the sources in the same place. Relative paths are relative to
+/// the current directory.
+///
+/// # Required features
+///
+/// This type requires the `std` feature of the `backtrace` crate to be
+/// enabled, and the `std` feature is enabled by default.
+///
+/// # Example
+///
+/// ```
+/// let mut context = backtrace::SourceContext::new(2);
+/// backtrace::trace(|frame| {
+///     backtrace::resolve_frame(frame, |symbol| {
+///         if let Some(snippet) = context.snippet(symbol) {
+///             println!("{:?}\n{}", symbol.name(), snippet);
+///         }
+///     });
+///     true
+/// });
+/// ```
+#[derive(Debug)]
+pub struct SourceContext {
+    context_lines: usize,
+    files: HashMap<PathBuf, Option<Vec<String>>>,
+}
+
+impl SourceContext {
+    /// Creates a context whose snippets have `context_lines` lines on either
+    /// side of the line asked for, where the file has them.
+    pub fn new(context_lines: usize) -> SourceContext {
+        SourceContext {
+            context_lines,
+            files: HashMap::new(),
+        }
+    }
+
+    /// Returns the lines around the file and line of `symbol`, or `None` if it
+    /// has no file or line, or the file can't be read or is shorter than that.
+    pub fn snippet(&mut self, symbol: &Symbol) -> Option<SourceSnippet<'_>> {
+        self.snippet_at(symbol.filename()?, symbol.lineno()?)
+    }
+
+    /// Returns the lines around line `line` of `file`, counting from 1, or
+    /// `None` if the file can't be read or is shorter than that.
+    ///
+    /// This takes the file and line from anywhere, e.g. a `BacktraceSymbol`.
+    pub fn snippet_at(&mut self, file: &Path, line: u32) -> Option<SourceSnippet<'_>> {
+        if !self.files.contains_key(file) {
+            let lines = fs::read(file).ok().map(|bytes| {
+                String::from_utf8_lossy(&bytes)
+                    .lines()
+                    .map(|line| line.to_string())
+                    .collect()
+            });
+            self.files.insert(file.to_path_buf(), lines);
+        }
+        let lines = self.files[file].as_ref()?;
+        let index = (line as usize).checked_sub(1)?;
+        if index >= lines.len() {
+            return None;
+        }
+        let first = index.saturating_sub(self.context_lines);
+        let last = index
+            .saturating_add(self.context_lines)
+            .min(lines.len() - 1);
+        Some(SourceSnippet {
+            first_line: first as u32 + 1,
+            line,
+            lines: &lines[first..=last],
+        })
+    }
+}
+
+/// Lines of source code around a line of interest, as returned by
+/// `SourceContext::snippet`.
+///
+/// Its `Display` output is the lines numbered and with the line of interest
+/// marked, like
+///
+/// ```text
+///   41 |     let x = f();
+/// > 42 |     x.unwrap()
+///   43 | }
+/// ```
+///
+/// with a newline after each line.
+///
+/// # Required features
+///
+/// This type requires the `std` feature of the `backtrace` crate to be
+/// enabled, and the `std` feature is enabled by default.
+#[derive(Debug)]
+pub struct SourceSnippet<'a> {
+    first_line: u32,
+    line: u32,
+    lines: &'a [String],
+}
+
+impl<'a> SourceSnippet<'a> {
+    /// Returns the line the snippet is around, counting from 1.
+    pub fn line(&self) -> u32 {
+        self.line
+    }
+
+    /// Returns each line of the snippet along with its number, counting from
+    /// 1, without their line endings.
+    pub fn lines(&self) -> impl Iterator<Item = (u32, &'a str)> + 'a {
+        let first_line = self.first_line;
+        (first_line..).zip(self.lines.iter().map(|line| &line[..]))
+    }
+}
+
+impl fmt::Display for SourceSnippet<'_> {
+    fn fmt(&self, fmt: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let last_line = self.first_line as usize + self.lines.len() - 1;
+        let width = last_line.to_string().len();
+        for (number, line) in self.lines() {
+            let marker = if number == self.line { '>' } else { ' ' };
+            writeln!(fmt, "{} {:>3$} | {}", marker, number, line, width)?;
+        }
+        Ok(())
+    }
+}
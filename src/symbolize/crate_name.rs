@@ -0,0 +1,228 @@
+//! Finding the crate a Rust symbol belongs to straight from its mangled name,
+//! so that it can be borrowed from the name rather than from a demangled copy.
+
+use core::str;
+
+/// Returns the name of the crate that `name`, a mangled Rust symbol, belongs
+/// to, or `None` if it isn't one or the crate name can't be borrowed from it.
+pub(super) fn crate_name(name: &[u8]) -> Option<&str> {
+    let name = str::from_utf8(name).ok()?;
+    let name = strip_prefix(name, "_").unwrap_or(name);
+    // macOS adds an extra underscore.
+    let name = strip_prefix(name, "_").unwrap_or(name);
+    if let Some(legacy) = strip_prefix(name, "ZN") {
+        legacy_crate_name(legacy)
+    } else if let Some(v0) = strip_prefix(name, "R") {
+        let mut parser = V0 {
+            sym: v0,
+            pos: 0,
+            depth: 0,
+        };
+        // An optional encoding version comes first.
+        while matches!(parser.peek(), Some(b'0'..=b'9')) {
+            parser.pos += 1;
+        }
+        parser.path()
+    } else {
+        None
+    }
+}
+
+// Legacy symbols are `_ZN`, followed by the length prefixed segments of the
+// path, which start with the crate's unless the path is a qualified one such
+// as `<foo::Bar as core::fmt::Debug>::fmt`, whose first segment is the whole
+// `<...>` with `::` as `..` and punctuation escaped like `$LT$`. The crate of
+// the self type is used for those.
+fn legacy_crate_name(sym: &str) -> Option<&str> {
+    let digits = sym.bytes().take_while(|b| b.is_ascii_digit()).count();
+    let len = sym[..digits].parse::<usize>().ok()?;
+    let segment = sym[digits..].get(..len)?;
+    let segment = match strip_prefix(segment, "_$LT$").or_else(|| strip_prefix(segment, "$LT$")) {
+        Some(qualified) => {
+            let end = qualified.find(&['.', '$'][..]).unwrap_or(qualified.len());
+            &qualified[..end]
+        }
+        None => segment,
+    };
+    if is_ident(segment) {
+        Some(segment)
+    } else {
+        None
+    }
+}
+
+fn is_ident(s: &str) -> bool {
+    !s.is_empty() && s.bytes().all(|b| b.is_ascii_alphanumeric() || b == b'_')
+}
+
+// `str::strip_prefix` is newer than the MSRV.
+fn strip_prefix<'a>(s: &'a str, prefix: &str) -> Option<&'a str> {
+    if s.starts_with(prefix) {
+        Some(&s[prefix.len()..])
+    } else {
+        None
+    }
+}
+
+// Just enough of a v0 demangler to walk down a path to its crate root.
+struct V0<'a> {
+    sym: &'a str,
+    pos: usize,
+    depth: u32,
+}
+
+impl<'a> V0<'a> {
+    fn peek(&self) -> Option<u8> {
+        self.sym.as_bytes().get(self.pos).copied()
+    }
+
+    fn next(&mut self) -> Option<u8> {
+        let b = self.peek()?;
+        self.pos += 1;
+        Some(b)
+    }
+
+    fn eat(&mut self, b: u8) -> bool {
+        let eaten = self.peek() == Some(b);
+        if eaten {
+            self.pos += 1;
+        }
+        eaten
+    }
+
+    fn path(&mut self) -> Option<&'a str> {
+        // Backreferences can nest, but a real symbol never needs them to
+        // nest deeply.
+        self.depth += 1;
+        if self.depth > 100 {
+            return None;
+        }
+        match self.next()? {
+            b'C' => {
+                self.disambiguator()?;
+                self.ident()
+            }
+            // `N <namespace> <path> <ident>`, whose crate is that of the
+            // parent path.
+            b'N' => {
+                self.next()?;
+                self.path()
+            }
+            // Inherent and trait impls, `M <impl-path> <type>` and
+            // `X <impl-path> <type> <path>`, belong to the crate they're in.
+            b'M' | b'X' => {
+                self.disambiguator()?;
+                self.path()
+            }
+            // `<type as trait>` goes by the self type, which needs to be a
+            // path to be in a crate.
+            b'Y' => match self.peek()? {
+                b'C' | b'N' | b'M' | b'X' | b'Y' | b'I' | b'B' => self.path(),
+                _ => None,
+            },
+            // Generic arguments come after the path they apply to.
+            b'I' => self.path(),
+            b'B' => {
+                let start = self.pos - 1;
+                let target = self.base62()?;
+                if target >= start as u64 {
+                    return None;
+                }
+                let mut backref = V0 {
+                    sym: self.sym,
+                    pos: target as usize,
+                    depth: self.depth,
+                };
+                backref.path()
+            }
+            _ => None,
+        }
+    }
+
+    fn disambiguator(&mut self) -> Option<()> {
+        if self.eat(b's') {
+            self.base62()?;
+        }
+        Some(())
+    }
+
+    fn base62(&mut self) -> Option<u64> {
+        if self.eat(b'_') {
+            return Some(0);
+        }
+        let mut n = 0u64;
+        loop {
+            let digit = match self.next()? {
+                b @ b'0'..=b'9' => b - b'0',
+                b @ b'a'..=b'z' => 10 + b - b'a',
+                b @ b'A'..=b'Z' => 36 + b - b'A',
+                b'_' => return n.checked_add(1),
+                _ => return None,
+            };
+            n = n.checked_mul(62)?.checked_add(u64::from(digit))?;
+        }
+    }
+
+    fn ident(&mut self) -> Option<&'a str> {
+        // Punycode identifiers would need decoding into a new string.
+        if self.peek()? == b'u' {
+            return None;
+        }
+        let start = self.pos;
+        while matches!(self.peek(), Some(b'0'..=b'9')) {
+            self.pos += 1;
+        }
+        let len = self.sym[start..self.pos].parse::<usize>().ok()?;
+        self.eat(b'_');
+        let ident = self.sym.get(self.pos..self.pos + len)?;
+        self.pos += len;
+        Some(ident)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::crate_name;
+
+    fn name(s: &str) -> Option<&str> {
+        crate_name(s.as_bytes())
+    }
+
+    #[test]
+    fn legacy() {
+        assert_eq!(name("_ZN4core3fmt5write17h0123456789abcdefE"), Some("core"));
+        assert_eq!(
+            name("__ZN3std2rt10lang_start17h0123456789abcdefE"),
+            Some("std")
+        );
+        assert_eq!(
+            name("_ZN60_$LT$alloc..vec..Vec$LT$T$GT$$u20$as$u20$core..ops..Drop$GT$4drop17h0123456789abcdefE"),
+            Some("alloc")
+        );
+        assert_eq!(name("_ZN14_$LT$$RF$T$GT$3fmt17h0123456789abcdefE"), None);
+        assert_eq!(name("_ZN4cor"), None);
+        assert_eq!(name("main"), None);
+    }
+
+    #[test]
+    fn v0() {
+        // backtrace::trace
+        assert_eq!(name("_RNvCs1234_9backtrace5trace"), Some("backtrace"));
+        // <std::path::PathBuf>::new
+        assert_eq!(
+            name("_RNvMsr_NtCs3ssYzQotkvD_3std4pathNtB5_7PathBuf3new"),
+            Some("std")
+        );
+        // <mycrate::Foo as core::fmt::Display>::fmt
+        assert_eq!(
+            name("_RNvXCs1234_7mycrateNtB2_3FooNtNtCs5678_4core3fmt7Display3fmt"),
+            Some("mycrate")
+        );
+        // <u32 as core::fmt::Display>::fmt, in no crate of its own
+        assert_eq!(name("_RNvYmNtNtCs5678_4core3fmt7Display3fmt"), None);
+        // Generic instances go by the generic item.
+        assert_eq!(name("_RINvCs1234_5hello6squarejEB2_"), Some("hello"));
+        assert_eq!(name("_RNvCs1234_u7pcgt1a5trace"), None);
+        assert_eq!(name("_RNvB9_5trace"), None);
+    }
+}
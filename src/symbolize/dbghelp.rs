@@ -47,6 +47,10 @@ impl Symbol<'_> {
         Some(self.addr as *mut _)
     }
 
+    pub fn symbol_offset(&self) -> Option<usize> {
+        None
+    }
+
     pub fn filename_raw(&self) -> Option<BytesOrWideString<'_>> {
         self.filename
             .map(|slice| unsafe { BytesOrWideString::Wide(&*slice) })
@@ -66,6 +70,31 @@ impl Symbol<'_> {
 
         self._filename_cache.as_ref().map(Path::new)
     }
+
+    pub fn call_filename_raw(&self) -> Option<BytesOrWideString<'_>> {
+        None
+    }
+
+    #[cfg(feature = "std")]
+    pub fn call_filename(&self) -> Option<&::std::path::Path> {
+        None
+    }
+
+    pub fn call_lineno(&self) -> Option<u32> {
+        None
+    }
+
+    pub fn call_colno(&self) -> Option<u32> {
+        None
+    }
+
+    pub fn inline_depth(&self) -> usize {
+        0
+    }
+
+    pub fn is_from_cache(&self) -> bool {
+        false
+    }
 }
 
 #[repr(C, align(8))]
@@ -79,7 +108,9 @@ pub unsafe fn resolve(what: ResolveWhat<'_>, cb: &mut dyn FnMut(&super::Symbol))
     };
 
     match what {
-        ResolveWhat::Address(_) => resolve_without_inline(&dbghelp, what.address_or_ip(), cb),
+        ResolveWhat::Address(_) | ResolveWhat::ExactAddress(_) => {
+            resolve_without_inline(&dbghelp, what.address_or_ip(), cb)
+        }
         ResolveWhat::Frame(frame) => match &frame.inner.stack_frame {
             StackFrame::New(frame) => resolve_with_inline(&dbghelp, frame, cb),
             StackFrame::Old(_) => resolve_without_inline(&dbghelp, frame.ip(), cb),
@@ -87,6 +118,40 @@ pub unsafe fn resolve(what: ResolveWhat<'_>, cb: &mut dyn FnMut(&super::Symbol))
     }
 }
 
+pub unsafe fn resolve_frames(
+    frames: &[super::Frame],
+    cb: &mut dyn FnMut(&super::Frame, Option<&super::Symbol>),
+) {
+    for frame in frames {
+        let mut any = false;
+        resolve(ResolveWhat::Frame(frame), &mut |symbol| {
+            any = true;
+            cb(frame, Some(symbol));
+        });
+        if !any {
+            cb(frame, None);
+        }
+    }
+}
+
+#[cfg(feature = "std")]
+pub unsafe fn resolve_lines(frames: &[super::Frame], cb: &mut dyn FnMut(usize, &str, u32)) {
+    // There's no getting at lines without symbolizing all of the frame, so
+    // the innermost symbol's are used.
+    for (i, frame) in frames.iter().enumerate() {
+        let mut first = true;
+        resolve(ResolveWhat::Frame(frame), &mut |symbol| {
+            if !core::mem::replace(&mut first, false) {
+                return;
+            }
+            let file = symbol.filename().and_then(|file| file.to_str());
+            if let (Some(file), Some(line)) = (file, symbol.lineno()) {
+                cb(i, file, line);
+            }
+        });
+    }
+}
+
 unsafe fn resolve_with_inline(
     dbghelp: &dbghelp::Init,
     frame: &STACKFRAME_EX,
@@ -216,3 +281,20 @@ unsafe fn cache(filename: Option<*const [u16]>) -> Option<::std::ffi::OsString>
 unsafe fn cache(_filename: Option<*const [u16]>) {}
 
 pub unsafe fn clear_symbol_cache() {}
+
+pub unsafe fn clear_library_cache() {}
+
+// There's no way to symbolicate against an arbitrary file here.
+#[cfg(feature = "std")]
+pub struct Symbolizer(());
+
+#[cfg(feature = "std")]
+impl Symbolizer {
+    pub fn from_file(_path: &::std::path::Path, _bias: usize) -> Option<Symbolizer> {
+        None
+    }
+
+    pub fn set_bias(&mut self, _bias: usize) {}
+
+    pub fn resolve(&mut self, _what: ResolveWhat<'_>, _cb: &mut dyn FnMut(&super::Symbol)) {}
+}
@@ -0,0 +1,203 @@
+//! Demangling the names of symbols from languages other than Rust and C++.
+//!
+//! `SymbolName` tries `rustc-demangle` first and then, with the
+//! `cpp_demangle` feature, `cpp_demangle`. Names neither of them recognize
+//! are passed to the demanglers here, in order, before being printed as they
+//! are: first a cleaner for Go's symbol names, and then those registered with
+//! `register_demangler`.
+
+use std::prelude::v1::*;
+use std::sync::{Arc, Mutex, Once};
+
+type Demangler = dyn Fn(&str) -> Option<String> + Send + Sync;
+
+// `Mutex::new` can't be used in a `static`, so the list of demanglers is
+// created on first use, like the global lock.
+fn demanglers() -> &'static Mutex<Vec<Arc<Demangler>>> {
+    static mut DEMANGLERS: *const Mutex<Vec<Arc<Demangler>>> = core::ptr::null();
+    static INIT: Once = Once::new();
+    unsafe {
+        INIT.call_once(|| {
+            DEMANGLERS = Box::into_raw(Box::new(Mutex::new(Vec::new())));
+        });
+        &*DEMANGLERS
+    }
+}
+
+/// Registers a demangler for symbol names which this crate doesn't know how
+/// to demangle itself.
+///
+/// The demangler is passed each symbol name which `rustc-demangle` and, with
+/// the `cpp_demangle` feature, `cpp_demangle` don't recognize, as well as
+/// the built-in cleaner for Go's names, and returns the name to print for it,
+/// or `None` if it doesn't recognize it either. Demanglers are tried in the
+/// order they were registered and the first to return a name wins, so the
+/// names of e.g. an embedded runtime's or JIT's functions can be made
+/// readable in the `Display` and `Debug` output of `SymbolName`, and so in
+/// all of this crate's formatting of backtraces. Names no demangler
+/// recognizes are printed as they are.
+///
+/// Demanglers can't be unregistered. They may be called from any thread which
+/// formats a symbol name, and must not themselves register demanglers.
+///
+/// # Required features
+///
+/// This function requires the `std` feature of the `backtrace` crate to be
+/// enabled, and the `std` feature is enabled by default.
+///
+/// # Example
+///
+/// ```
+/// backtrace::register_demangler(|name| {
+///     let name = name.strip_prefix("jit$")?;
+///     Some(format!("{} (jitted)", name))
+/// });
+/// let name = backtrace::SymbolName::new(b"jit$compute");
+/// assert_eq!(name.to_string(), "compute (jitted)");
+/// ```
+pub fn register_demangler<F>(f: F)
+where
+    F: Fn(&str) -> Option<String> + Send + Sync + 'static,
+{
+    demanglers().lock().unwrap().push(Arc::new(f));
+}
+
+/// Returns what to print for `name`, a symbol name which isn't a Rust or C++
+/// one, or `None` to print it as it is.
+pub(super) fn demangle(name: &str) -> Option<String> {
+    if let Some(name) = clean_go(name) {
+        return Some(name);
+    }
+    // Don't hold the lock while the demanglers run.
+    let demanglers = demanglers().lock().unwrap().clone();
+    demanglers.iter().find_map(|demangler| demangler(name))
+}
+
+// Go's symbol names are mostly readable as they are, as the package's import
+// path followed by the function's name, e.g. `net/http.(*Server).Serve`. The
+// exceptions this tidies up, returning `None` if there are none, are:
+//
+// * `%xx` escapes of characters such as `.` in the last element of the import
+//   path, e.g. in `gopkg.in/yaml%2ev3.Unmarshal`.
+// * The `·` which Go's assembler uses in place of `.`.
+// * The type arguments of generic functions, which are printed as `[...]` as
+//   Go's own tracebacks do, e.g. `slices.Sort[go.shape.int]`.
+// * The `.abi0` and `.abiinternal` suffixes of ABI wrappers.
+//
+// A name is only taken to be a Go one if it has a `.` after the last `/` of
+// its import path.
+fn clean_go(name: &str) -> Option<String> {
+    let dotted = name.replace('\u{b7}', ".");
+    let head = &dotted[..dotted.find('[').unwrap_or(dotted.len())];
+    let path_start = head.rfind('/').map_or(0, |i| i + 1);
+    let path_end = path_start + head[path_start..].find('.')?;
+
+    let mut cleaned = String::with_capacity(dotted.len());
+    let mut path = &dotted[..path_end];
+    while let Some(i) = path.find('%') {
+        cleaned.push_str(&path[..i]);
+        match path
+            .get(i + 1..i + 3)
+            .and_then(|hex| u8::from_str_radix(hex, 16).ok())
+        {
+            Some(byte) if byte.is_ascii() => {
+                cleaned.push(char::from(byte));
+                path = &path[i + 3..];
+            }
+            _ => {
+                cleaned.push('%');
+                path = &path[i + 1..];
+            }
+        }
+    }
+    cleaned.push_str(path);
+
+    let mut rest = &dotted[path_end..];
+    while let Some(open) = rest.find('[') {
+        cleaned.push_str(&rest[..=open]);
+        let close = match matching_bracket(&rest[open..]) {
+            Some(len) => open + len,
+            None => {
+                rest = &rest[open + 1..];
+                continue;
+            }
+        };
+        let args = &rest[open + 1..close];
+        if args.contains("go.shape.") {
+            cleaned.push_str("...");
+        } else {
+            cleaned.push_str(args);
+        }
+        cleaned.push(']');
+        rest = &rest[close + 1..];
+    }
+    cleaned.push_str(rest);
+
+    for suffix in &[".abi0", ".abiinternal"] {
+        if cleaned.ends_with(suffix) {
+            cleaned.truncate(cleaned.len() - suffix.len());
+        }
+    }
+
+    if cleaned == name {
+        None
+    } else {
+        Some(cleaned)
+    }
+}
+
+// Returns the offset of the `]` closing the `[` which `s` starts with.
+fn matching_bracket(s: &str) -> Option<usize> {
+    let mut depth = 0;
+    for (i, c) in s.char_indices() {
+        match c {
+            '[' => depth += 1,
+            ']' => {
+                depth -= 1;
+                if depth == 0 {
+                    return Some(i);
+                }
+            }
+            _ => {}
+        }
+    }
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::clean_go;
+
+    #[test]
+    fn go_names() {
+        assert_eq!(clean_go("net/http.(*Server).Serve"), None);
+        assert_eq!(clean_go("main.main"), None);
+        assert_eq!(
+            clean_go("gopkg.in/yaml%2ev3.Unmarshal").as_deref(),
+            Some("gopkg.in/yaml.v3.Unmarshal")
+        );
+        assert_eq!(
+            clean_go("runtime\u{b7}morestack").as_deref(),
+            Some("runtime.morestack")
+        );
+        assert_eq!(
+            clean_go("slices.Sort[go.shape.[]int]").as_deref(),
+            Some("slices.Sort[...]")
+        );
+        assert_eq!(
+            clean_go("main.Map[go.shape.string,go.shape.int].func1").as_deref(),
+            Some("main.Map[...].func1")
+        );
+        assert_eq!(
+            clean_go("runtime.goexit.abi0").as_deref(),
+            Some("runtime.goexit")
+        );
+    }
+
+    #[test]
+    fn not_go_names() {
+        assert_eq!(clean_go("memcpy"), None);
+        assert_eq!(clean_go("foo.cold"), None);
+        assert_eq!(clean_go("_ZN4core3fmt5write17h0123456789abcdefE"), None);
+    }
+}
@@ -3,13 +3,15 @@
 //! This is the default symbolication implementation for Rust.
 
 use self::gimli::read::EndianSlice;
-use self::gimli::NativeEndian as Endian;
+use self::gimli::RunTimeEndian as Endian;
 use self::mmap::Mmap;
 use self::stash::Stash;
 use super::BytesOrWideString;
 use super::ResolveWhat;
 use super::SymbolName;
+use super::{MODULE_CACHE, SYMBOL_CACHE};
 use addr2line::gimli;
+use core::cell::Cell;
 use core::convert::TryInto;
 use core::mem;
 use core::u32;
@@ -54,6 +56,9 @@ mod stash;
 
 const MAPPINGS_CACHE_SIZE: usize = 4;
 
+/// The number of addresses remembered as having no symbol, see `Negatives`.
+const NEGATIVE_CACHE_SIZE: usize = 256;
+
 struct Mapping {
     // 'static lifetime is a lie to hack around lack of support for self-referential structs.
     cx: Context<'static>,
@@ -113,17 +118,19 @@ impl<'data> Context<'data> {
         object: Object<'data>,
         sup: Option<Object<'data>>,
     ) -> Option<Context<'data>> {
+        let endian = object.dwarf_endian();
         let mut sections = gimli::Dwarf::load(|id| -> Result<_, ()> {
             let data = object.section(stash, id.name()).unwrap_or(&[]);
-            Ok(EndianSlice::new(data, Endian))
+            Ok(EndianSlice::new(data, endian))
         })
         .ok()?;
 
         if let Some(sup) = sup {
+            let endian = sup.dwarf_endian();
             sections
                 .load_sup(|id| -> Result<_, ()> {
                     let data = sup.section(stash, id.name()).unwrap_or(&[]);
-                    Ok(EndianSlice::new(data, Endian))
+                    Ok(EndianSlice::new(data, endian))
                 })
                 .ok()?;
         }
@@ -154,6 +161,8 @@ cfg_if::cfg_if! {
     } else {
         mod elf;
         use self::elf::Object;
+        #[cfg(feature = "debuginfod")]
+        mod debuginfod;
     }
 }
 
@@ -213,6 +222,49 @@ struct Cache {
     /// Note that this is basically an LRU cache and we'll be shifting things
     /// around in here as we symbolize addresses.
     mappings: Vec<(usize, Mapping)>,
+
+    /// Addresses recently found to have no symbol at all.
+    negatives: Negatives,
+}
+
+/// A direct-mapped cache of addresses which are known not to resolve to any
+/// symbol.
+///
+/// Addresses in stripped regions or JIT code never resolve, but finding that
+/// out can mean a full search of an object's debug info every time. Hot
+/// addresses like that are common in profiles, so remembering them makes
+/// repeated lookups cheap. Colliding addresses simply evict each other, which
+/// keeps this bounded and lookups constant-time.
+struct Negatives {
+    slots: [usize; NEGATIVE_CACHE_SIZE],
+}
+
+impl Default for Negatives {
+    fn default() -> Negatives {
+        Negatives {
+            slots: [0; NEGATIVE_CACHE_SIZE],
+        }
+    }
+}
+
+impl Negatives {
+    fn slot(addr: usize) -> usize {
+        (addr ^ (addr >> 8) ^ (addr >> 16)) % NEGATIVE_CACHE_SIZE
+    }
+
+    fn contains(&self, addr: usize) -> bool {
+        // Zero marks an empty slot, and the null address never resolves
+        // anyway.
+        addr != 0 && self.slots[Negatives::slot(addr)] == addr
+    }
+
+    fn insert(&mut self, addr: usize) {
+        self.slots[Negatives::slot(addr)] = addr;
+    }
+
+    fn clear(&mut self) {
+        self.slots = [0; NEGATIVE_CACHE_SIZE];
+    }
 }
 
 struct Library {
@@ -227,6 +279,24 @@ struct Library {
     bias: usize,
 }
 
+impl Library {
+    fn contains(&self, addr: *const u8) -> bool {
+        // Note that we're using `wrapping_add` here to avoid overflow checks.
+        // It's been seen in the wild that the SVMA + bias computation
+        // overflows. It seems a bit odd that would happen but there's not a
+        // huge amount we can do about it other than probably just ignore
+        // those segments since they're likely pointing off into space. This
+        // originally came up in rust-lang/backtrace-rs#329.
+        self.segments.iter().any(|s| {
+            let svma = s.stated_virtual_memory_address;
+            let start = svma.wrapping_add(self.bias);
+            let end = start.wrapping_add(s.len);
+            let address = addr as usize;
+            start <= address && address < end
+        })
+    }
+}
+
 struct LibrarySegment {
     /// The stated address of this segment in the object file. This is not
     /// actually where the segment is loaded, but rather this address plus the
@@ -236,9 +306,34 @@ struct LibrarySegment {
     len: usize,
 }
 
+// A very small, very simple LRU cache for debug info mappings.
+//
+// The hit rate should be very high, since the typical stack doesn't cross
+// between many shared libraries.
+//
+// The `addr2line::Context` structures are pretty expensive to create. Its
+// cost is expected to be amortized by subsequent `locate` queries, which
+// leverage the structures built when constructing `addr2line::Context`s to
+// get nice speedups. If we didn't have this cache, that amortization would
+// never happen, and symbolicating backtraces would be ssssllllooooowwww.
+static mut MAPPINGS_CACHE: Option<Cache> = None;
+
 // unsafe because this is required to be externally synchronized
 pub unsafe fn clear_symbol_cache() {
-    Cache::with_global(|cache| cache.mappings.clear());
+    if let Some(ref mut cache) = MAPPINGS_CACHE {
+        cache.mappings.clear();
+        cache.negatives.clear();
+    }
+    SYMBOL_CACHE.invalidate();
+}
+
+// unsafe because this is required to be externally synchronized
+pub unsafe fn clear_library_cache() {
+    // Dropping the whole cache, rather than just the mappings, means the list
+    // of loaded libraries is re-read the next time an address is resolved.
+    MAPPINGS_CACHE = None;
+    MODULE_CACHE.invalidate();
+    SYMBOL_CACHE.invalidate();
 }
 
 impl Cache {
@@ -246,24 +341,25 @@ impl Cache {
         Cache {
             mappings: Vec::with_capacity(MAPPINGS_CACHE_SIZE),
             libraries: native_libraries(),
+            negatives: Negatives::default(),
         }
     }
 
     // unsafe because this is required to be externally synchronized
     unsafe fn with_global(f: impl FnOnce(&mut Self)) {
-        // A very small, very simple LRU cache for debug info mappings.
-        //
-        // The hit rate should be very high, since the typical stack doesn't cross
-        // between many shared libraries.
-        //
-        // The `addr2line::Context` structures are pretty expensive to create. Its
-        // cost is expected to be amortized by subsequent `locate` queries, which
-        // leverage the structures built when constructing `addr2line::Context`s to
-        // get nice speedups. If we didn't have this cache, that amortization would
-        // never happen, and symbolicating backtraces would be ssssllllooooowwww.
-        static mut MAPPINGS_CACHE: Option<Cache> = None;
-
-        f(MAPPINGS_CACHE.get_or_insert_with(|| Cache::new()))
+        let cache = match &mut MAPPINGS_CACHE {
+            Some(cache) => {
+                MODULE_CACHE.hit();
+                cache
+            }
+            cache @ None => {
+                MODULE_CACHE.miss();
+                let cache = cache.get_or_insert_with(|| Cache::new());
+                MODULE_CACHE.set_entries(cache.libraries.len());
+                cache
+            }
+        };
+        f(cache)
     }
 
     fn avma_to_svma(&self, addr: *const u8) -> Option<(usize, *const u8)> {
@@ -274,21 +370,7 @@ impl Cache {
                 // First up, test if this `lib` has any segment containing the
                 // `addr` (handling relocation). If this check passes then we
                 // can continue below and actually translate the address.
-                //
-                // Note that we're using `wrapping_add` here to avoid overflow
-                // checks. It's been seen in the wild that the SVMA + bias
-                // computation overflows. It seems a bit odd that would happen
-                // but there's not a huge amount we can do about it other than
-                // probably just ignore those segments since they're likely
-                // pointing off into space. This originally came up in
-                // rust-lang/backtrace-rs#329.
-                if !lib.segments.iter().any(|s| {
-                    let svma = s.stated_virtual_memory_address;
-                    let start = svma.wrapping_add(lib.bias);
-                    let end = start.wrapping_add(s.len);
-                    let address = addr as usize;
-                    start <= address && address < end
-                }) {
+                if !lib.contains(addr) {
                     return None;
                 }
 
@@ -300,13 +382,32 @@ impl Cache {
             .next()
     }
 
-    fn mapping_for_lib<'a>(&'a mut self, lib: usize) -> Option<&'a mut Context<'a>> {
+    // Same as `avma_to_svma`, except that the library at index `hint` is
+    // checked before any others.
+    fn avma_to_svma_from(
+        &self,
+        hint: Option<usize>,
+        addr: *const u8,
+    ) -> Option<(usize, *const u8)> {
+        match hint {
+            Some(lib) if self.libraries[lib].contains(addr) => {
+                let svma = (addr as usize).wrapping_sub(self.libraries[lib].bias);
+                Some((lib, svma as *const u8))
+            }
+            _ => self.avma_to_svma(addr),
+        }
+    }
+
+    // Also returns whether the mapping was already in the cache, rather than
+    // just loaded.
+    fn mapping_for_lib<'a>(&'a mut self, lib: usize) -> Option<(&'a mut Context<'a>, bool)> {
         let idx = self.mappings.iter().position(|(idx, _)| *idx == lib);
 
         // Invariant: after this conditional completes without early returning
         // from an error, the cache entry for this path is at index 0.
 
         if let Some(idx) = idx {
+            SYMBOL_CACHE.hit();
             // When the mapping is already in the cache, move it to the front.
             if idx != 0 {
                 let entry = self.mappings.remove(idx);
@@ -316,6 +417,7 @@ impl Cache {
             // When the mapping is not in the cache, create a new mapping,
             // insert it into the front of the cache, and evict the oldest cache
             // entry if necessary.
+            SYMBOL_CACHE.miss();
             let name = &self.libraries[lib].name;
             let mapping = Mapping::new(name.as_ref())?;
 
@@ -324,18 +426,22 @@ impl Cache {
             }
 
             self.mappings.insert(0, (lib, mapping));
+            SYMBOL_CACHE.set_entries(self.mappings.len());
         }
 
         let cx: &'a mut Context<'static> = &mut self.mappings[0].1.cx;
         // don't leak the `'static` lifetime, make sure it's scoped to just
         // ourselves
-        Some(unsafe { mem::transmute::<&'a mut Context<'static>, &'a mut Context<'a>>(cx) })
+        let cx = unsafe { mem::transmute::<&'a mut Context<'static>, &'a mut Context<'a>>(cx) };
+        Some((cx, idx.is_some()))
     }
 }
 
 pub unsafe fn resolve(what: ResolveWhat<'_>, cb: &mut dyn FnMut(&super::Symbol)) {
     let addr = what.address_or_ip();
+    let found = Cell::new(false);
     let mut call = |sym: Symbol<'_>| {
+        found.set(true);
         // Extend the lifetime of `sym` to `'static` since we are unfortunately
         // required to here, but it's only ever going out as a reference so no
         // reference to it should be persisted beyond this frame anyway.
@@ -344,55 +450,273 @@ pub unsafe fn resolve(what: ResolveWhat<'_>, cb: &mut dyn FnMut(&super::Symbol))
     };
 
     Cache::with_global(|cache| {
-        let (lib, addr) = match cache.avma_to_svma(addr as *const u8) {
-            Some(pair) => pair,
-            None => return,
-        };
+        if cache.negatives.contains(addr as usize) {
+            return;
+        }
+        resolve_in(cache, None, addr, &mut call);
+        if !found.get() {
+            cache.negatives.insert(addr as usize);
+        }
+    });
+}
 
-        // Finally, get a cached mapping or create a new mapping for this file, and
-        // evaluate the DWARF info to find the file/line/name for this address.
-        let cx = match cache.mapping_for_lib(lib) {
-            Some(cx) => cx,
-            None => return,
-        };
-        let mut any_frames = false;
-        if let Ok(mut frames) = cx.dwarf.find_frames(addr as u64) {
-            while let Ok(Some(frame)) = frames.next() {
-                any_frames = true;
-                let name = match frame.function {
-                    Some(f) => Some(f.name.slice()),
-                    None => cx.object.search_symtab(addr as u64),
+/// Resolves each of `frames` in turn, all while the global cache is borrowed
+/// once, passing `None` to `cb` for frames without any symbols.
+///
+/// A stack's frames tend to come in runs from the same library, so the
+/// library the previous frame was in is checked first, before searching all
+/// of them. Its parsed debug info is then at the front of the mappings cache
+/// already.
+pub unsafe fn resolve_frames(
+    frames: &[super::Frame],
+    cb: &mut dyn FnMut(&super::Frame, Option<&super::Symbol>),
+) {
+    Cache::with_global(|cache| {
+        let mut last_lib = None;
+        // Recursion gives runs of frames returning to the same call, which
+        // are handed the symbols of the first frame of the run rather than
+        // looking them up again. The symbols are only kept when the next
+        // frame has the same address, so other stacks don't pay for copying
+        // them, and they're dropped before the cache they borrow from is
+        // touched again.
+        let mut last_addr = None;
+        let mut last_symbols: Vec<Symbol<'static>> = Vec::new();
+        for (i, frame) in frames.iter().enumerate() {
+            let addr = ResolveWhat::Frame(frame).address_or_ip();
+            let keep = match frames.get(i + 1) {
+                Some(next) => ResolveWhat::Frame(next).address_or_ip() == addr,
+                None => false,
+            };
+            if last_addr == Some(addr) {
+                for sym in &last_symbols {
+                    let sym = super::Symbol {
+                        inner: sym.duplicate(),
+                    };
+                    cb(frame, Some(&sym));
+                }
+                if last_symbols.is_empty() {
+                    cb(frame, None);
+                }
+                continue;
+            }
+            last_addr = if keep { Some(addr) } else { None };
+            last_symbols.clear();
+            let found = Cell::new(false);
+            if !cache.negatives.contains(addr as usize) {
+                let mut call = |sym: Symbol<'_>| {
+                    found.set(true);
+                    // See `resolve` for why this is extended to `'static`.
+                    let sym = mem::transmute::<Symbol<'_>, Symbol<'static>>(sym);
+                    if keep {
+                        last_symbols.push(sym.duplicate());
+                    }
+                    cb(frame, Some(&super::Symbol { inner: sym }));
                 };
-                call(Symbol::Frame {
-                    addr: addr as *mut c_void,
-                    location: frame.location,
-                    name,
-                });
+                last_lib = resolve_in(cache, last_lib, addr, &mut call).or(last_lib);
+                if !found.get() {
+                    cache.negatives.insert(addr as usize);
+                }
+            }
+            if !found.get() {
+                cb(frame, None);
             }
         }
-        if !any_frames {
-            if let Some((object_cx, object_addr)) = cx.object.search_object_map(addr as u64) {
-                if let Ok(mut frames) = object_cx.dwarf.find_frames(object_addr) {
-                    while let Ok(Some(frame)) = frames.next() {
-                        any_frames = true;
-                        call(Symbol::Frame {
-                            addr: addr as *mut c_void,
-                            location: frame.location,
-                            name: frame.function.map(|f| f.name.slice()),
-                        });
-                    }
+    });
+}
+
+/// Looks up just the file and line of each of `frames`, passing them to `cb`
+/// along with the frame's index, and skipping frames for which either isn't
+/// known.
+///
+/// This only runs the line programs, with `find_location`, so the DIEs of
+/// functions, which `find_frames` has to parse to name them and find what was
+/// inlined, are left alone, as is the symbol table. The location is that of
+/// the innermost inlined function, the same as for the first symbol
+/// `resolve_frames` gives.
+#[cfg(feature = "std")]
+pub unsafe fn resolve_lines(frames: &[super::Frame], cb: &mut dyn FnMut(usize, &str, u32)) {
+    Cache::with_global(|cache| {
+        let mut last_lib = None;
+        for (i, frame) in frames.iter().enumerate() {
+            let avma = ResolveWhat::Frame(frame).address_or_ip() as *const u8;
+            let (lib, addr) = match cache.avma_to_svma_from(last_lib, avma) {
+                Some(pair) => pair,
+                None => continue,
+            };
+            last_lib = Some(lib);
+            let (cx, _) = match cache.mapping_for_lib(lib) {
+                Some(pair) => pair,
+                None => continue,
+            };
+            let mut location = cx.dwarf.find_location(addr as u64).ok().flatten();
+            if location.is_none() {
+                if let Some((object_cx, object_addr)) = cx.object.search_object_map(addr as u64) {
+                    location = object_cx.dwarf.find_location(object_addr).ok().flatten();
                 }
             }
+            if let Some(addr2line::Location {
+                file: Some(file),
+                line: Some(line),
+                ..
+            }) = location
+            {
+                cb(i, file, line);
+            }
         }
-        if !any_frames {
-            if let Some(name) = cx.object.search_symtab(addr as u64) {
-                call(Symbol::Symtab {
-                    addr: addr as *mut c_void,
-                    name,
+    });
+}
+
+// Looks up `addr` in whichever library it's in, checking the one at index
+// `hint` before any others, and returns the index of that library.
+fn resolve_in(
+    cache: &mut Cache,
+    hint: Option<usize>,
+    addr: *mut c_void,
+    call: &mut dyn FnMut(Symbol<'_>),
+) -> Option<usize> {
+    let (lib, addr) = cache.avma_to_svma_from(hint, addr as *const u8)?;
+
+    // Finally, get a cached mapping or create a new mapping for this file, and
+    // evaluate the DWARF info to find the file/line/name for this address.
+    let (cx, from_cache) = match cache.mapping_for_lib(lib) {
+        Some(pair) => pair,
+        None => return Some(lib),
+    };
+    resolve_in_context(cx, addr, from_cache, call);
+    Some(lib)
+}
+
+/// Looks up `addr`, a stated virtual memory address of the object `cx` was
+/// built from, passing each symbol found to `call`. `from_cache` is recorded
+/// in the symbols for `Symbol::from_cache`.
+fn resolve_in_context<'a>(
+    cx: &'a mut Context<'a>,
+    addr: *const u8,
+    from_cache: bool,
+    call: &mut dyn FnMut(Symbol<'_>),
+) {
+    let mut any_frames = false;
+    if let Ok(frames) = cx.dwarf.find_frames(addr as u64) {
+        any_frames = for_each_frame(frames, |frame, call_location, inline_depth| {
+            let name = match frame.function {
+                Some(f) => Some(f.name.slice()),
+                None => search_symtab(&cx.object, addr).map(|(name, _)| name),
+            };
+            call(Symbol::Frame {
+                addr: addr as *mut c_void,
+                location: frame.location,
+                call_location,
+                inline_depth,
+                name,
+                from_cache,
+            });
+        });
+    }
+    if !any_frames {
+        if let Some((object_cx, object_addr)) = cx.object.search_object_map(addr as u64) {
+            if let Ok(frames) = object_cx.dwarf.find_frames(object_addr) {
+                any_frames = for_each_frame(frames, |frame, call_location, inline_depth| {
+                    call(Symbol::Frame {
+                        addr: addr as *mut c_void,
+                        location: frame.location,
+                        call_location,
+                        inline_depth,
+                        name: frame.function.map(|f| f.name.slice()),
+                        from_cache,
+                    });
                 });
             }
         }
-    });
+    }
+    if !any_frames {
+        if let Some((name, offset)) = search_symtab(&cx.object, addr) {
+            call(Symbol::Symtab {
+                addr: addr as *mut c_void,
+                name,
+                offset: offset as usize,
+                from_cache,
+            });
+        }
+    }
+}
+
+/// Looks `addr` up in the object's symbol table, unless the `symtab-fallback`
+/// feature is disabled, in which case only debug info is used. libstd always
+/// wants the fallback, and has no say in this crate's features.
+fn search_symtab<'a>(object: &'a Object<'_>, addr: *const u8) -> Option<(&'a [u8], u64)> {
+    if cfg!(any(feature = "symtab-fallback", backtrace_in_libstd)) {
+        object.search_symtab(addr as u64)
+    } else {
+        None
+    }
+}
+
+/// Symbolicates addresses against a single object file, independently of the
+/// libraries loaded into this process.
+#[cfg(feature = "std")]
+pub struct Symbolizer {
+    mapping: Mapping,
+    bias: usize,
+}
+
+#[cfg(feature = "std")]
+impl Symbolizer {
+    pub fn from_file(path: &Path, bias: usize) -> Option<Symbolizer> {
+        Some(Symbolizer {
+            mapping: Mapping::new(path)?,
+            bias,
+        })
+    }
+
+    pub fn set_bias(&mut self, bias: usize) {
+        self.bias = bias;
+    }
+
+    pub fn resolve(&mut self, what: ResolveWhat<'_>, cb: &mut dyn FnMut(&super::Symbol)) {
+        let addr = (what.address_or_ip() as usize).wrapping_sub(self.bias);
+        let cx: &mut Context<'static> = &mut self.mapping.cx;
+        // As in `Cache::mapping_for_lib`, don't leak the `'static` lifetime.
+        let cx = unsafe { mem::transmute::<&mut Context<'static>, &mut Context<'_>>(cx) };
+        resolve_in_context(cx, addr as *const u8, false, &mut |sym| {
+            // See `resolve` for why this is extended to `'static`.
+            let sym = unsafe { mem::transmute::<Symbol<'_>, Symbol<'static>>(sym) };
+            (cb)(&super::Symbol { inner: sym });
+        });
+    }
+}
+
+/// Passes each frame to `f`, innermost first, along with the location it was
+/// inlined at, if it was inlined, and its inline depth, returning whether
+/// there were any frames.
+///
+/// addr2line only gives the call site of an inlined function as the location
+/// of the frame after it, i.e. the function it was inlined into, and the depth
+/// of the innermost frame is only known once the outermost one has been read,
+/// so all the frames are read before any is passed on.
+fn for_each_frame<'ctx, R: gimli::Reader>(
+    mut frames: addr2line::FrameIter<'ctx, R>,
+    mut f: impl FnMut(addr2line::Frame<'ctx, R>, Option<addr2line::Location<'ctx>>, usize),
+) -> bool {
+    let mut all = Vec::new();
+    while let Ok(Some(frame)) = frames.next() {
+        all.push(frame);
+    }
+    let any_frames = !all.is_empty();
+    let mut depth = all.len();
+    let mut all = all.into_iter().peekable();
+    while let Some(frame) = all.next() {
+        depth -= 1;
+        let call_location =
+            all.peek()
+                .and_then(|next| next.location.as_ref())
+                .map(|l| addr2line::Location {
+                    file: l.file,
+                    line: l.line,
+                    column: l.column,
+                });
+        f(frame, call_location, depth);
+    }
+    any_frames
 }
 
 pub enum Symbol<'a> {
@@ -401,11 +725,65 @@ pub enum Symbol<'a> {
     Frame {
         addr: *mut c_void,
         location: Option<addr2line::Location<'a>>,
+        /// Where this function was inlined into its caller, or `None` if it
+        /// wasn't inlined.
+        call_location: Option<addr2line::Location<'a>>,
+        /// How many functions this one was inlined through, 0 for the function
+        /// the code physically belongs to.
+        inline_depth: usize,
         name: Option<&'a [u8]>,
+        /// Whether the library's debug info was already cached.
+        from_cache: bool,
     },
     /// Couldn't find debug information, but we found it in the symbol table of
     /// the elf executable.
-    Symtab { addr: *mut c_void, name: &'a [u8] },
+    Symtab {
+        addr: *mut c_void,
+        name: &'a [u8],
+        /// How far `addr` is past the start of the symbol.
+        offset: usize,
+        /// Whether the library's symbol table was already cached.
+        from_cache: bool,
+    },
+}
+
+impl<'a> Symbol<'a> {
+    // A copy of this symbol, borrowing from the same debug info.
+    fn duplicate(&self) -> Symbol<'a> {
+        let copy = |l: &addr2line::Location<'a>| addr2line::Location {
+            file: l.file,
+            line: l.line,
+            column: l.column,
+        };
+        match *self {
+            Symbol::Frame {
+                addr,
+                ref location,
+                ref call_location,
+                inline_depth,
+                name,
+                from_cache,
+            } => Symbol::Frame {
+                addr,
+                location: location.as_ref().map(copy),
+                call_location: call_location.as_ref().map(copy),
+                inline_depth,
+                name,
+                from_cache,
+            },
+            Symbol::Symtab {
+                addr,
+                name,
+                offset,
+                from_cache,
+            } => Symbol::Symtab {
+                addr,
+                name,
+                offset,
+                from_cache,
+            },
+        }
+    }
 }
 
 impl Symbol<'_> {
@@ -426,6 +804,13 @@ impl Symbol<'_> {
         }
     }
 
+    pub fn symbol_offset(&self) -> Option<usize> {
+        match self {
+            Symbol::Frame { .. } => None,
+            Symbol::Symtab { offset, .. } => Some(*offset),
+        }
+    }
+
     pub fn filename_raw(&self) -> Option<BytesOrWideString<'_>> {
         match self {
             Symbol::Frame { location, .. } => {
@@ -459,4 +844,51 @@ impl Symbol<'_> {
             Symbol::Symtab { .. } => None,
         }
     }
+
+    pub fn call_filename_raw(&self) -> Option<BytesOrWideString<'_>> {
+        match self {
+            Symbol::Frame { call_location, .. } => {
+                let file = call_location.as_ref()?.file?;
+                Some(BytesOrWideString::Bytes(file.as_bytes()))
+            }
+            Symbol::Symtab { .. } => None,
+        }
+    }
+
+    pub fn call_filename(&self) -> Option<&Path> {
+        match self {
+            Symbol::Frame { call_location, .. } => {
+                let file = call_location.as_ref()?.file?;
+                Some(Path::new(file))
+            }
+            Symbol::Symtab { .. } => None,
+        }
+    }
+
+    pub fn call_lineno(&self) -> Option<u32> {
+        match self {
+            Symbol::Frame { call_location, .. } => call_location.as_ref()?.line,
+            Symbol::Symtab { .. } => None,
+        }
+    }
+
+    pub fn call_colno(&self) -> Option<u32> {
+        match self {
+            Symbol::Frame { call_location, .. } => call_location.as_ref()?.column,
+            Symbol::Symtab { .. } => None,
+        }
+    }
+
+    pub fn inline_depth(&self) -> usize {
+        match self {
+            Symbol::Frame { inline_depth, .. } => *inline_depth,
+            Symbol::Symtab { .. } => 0,
+        }
+    }
+
+    pub fn is_from_cache(&self) -> bool {
+        match self {
+            Symbol::Frame { from_cache, .. } | Symbol::Symtab { from_cache, .. } => *from_cache,
+        }
+    }
 }
@@ -84,7 +84,7 @@ impl<'a> Object<'a> {
         )
     }
 
-    pub fn search_symtab<'b>(&'b self, addr: u64) -> Option<&'b [u8]> {
+    pub fn search_symtab<'b>(&'b self, addr: u64) -> Option<(&'b [u8], u64)> {
         // Note that unlike other formats COFF doesn't embed the size of
         // each symbol. As a last ditch effort search for the *closest*
         // symbol to a particular address and return that one. This gets
@@ -99,7 +99,14 @@ impl<'a> Object<'a> {
             // greatest less than `addr`
             Err(i) => i.checked_sub(1)?,
         };
-        self.symbols[i].1.name(self.strings).ok()
+        let (sym_addr, sym) = &self.symbols[i];
+        let name = sym.name(self.strings).ok()?;
+        Some((name, (addr - sym_addr) as u64))
+    }
+
+    pub(super) fn dwarf_endian(&self) -> super::Endian {
+        // PE images are always little-endian.
+        super::Endian::Little
     }
 
     pub(super) fn search_object_map(&self, _addr: u64) -> Option<(&Context<'_>, u64)> {
@@ -0,0 +1,211 @@
+//! Fetching of separate debug info from debuginfod servers.
+//!
+//! When an object has no debug info of its own and none could be found on the
+//! local filesystem, the servers listed in `DEBUGINFOD_URLS` are queried for
+//! `/buildid/<build-id>/debuginfo`. Downloads are stored in the same cache
+//! directory layout used by elfutils' `debuginfod-find`, so files fetched by
+//! either are shared.
+//!
+//! Only plain `http://` servers are supported, since there's no TLS
+//! implementation available to this crate.
+
+use super::mystd::env;
+use super::mystd::ffi::OsString;
+use super::mystd::fs;
+use super::mystd::io::{Read, Write};
+use super::mystd::net::{TcpStream, ToSocketAddrs};
+use super::mystd::path::{Path, PathBuf};
+use super::mystd::sync::{Mutex, Once};
+use super::mystd::time::Duration;
+use super::{Box, String, Vec};
+use core::fmt::Write as _;
+use core::str;
+
+/// How long to wait on a server when `DEBUGINFOD_TIMEOUT` isn't set, in
+/// seconds. This matches elfutils.
+const DEFAULT_TIMEOUT: u64 = 90;
+
+/// Build IDs which no server had debug info for, so they aren't queried again
+/// every time their object is reloaded into the mappings cache.
+fn missing() -> &'static Mutex<Vec<Vec<u8>>> {
+    static mut MISSING: *const Mutex<Vec<Vec<u8>>> = core::ptr::null();
+    static INIT: Once = Once::new();
+    unsafe {
+        INIT.call_once(|| {
+            MISSING = Box::into_raw(Box::new(Mutex::new(Vec::new())));
+        });
+        &*MISSING
+    }
+}
+
+/// Locate a debug file for `build_id`, downloading it into the cache if
+/// needed.
+pub(super) fn locate_build_id(build_id: &[u8]) -> Option<PathBuf> {
+    if build_id.is_empty() {
+        return None;
+    }
+    let mut hex = String::with_capacity(build_id.len() * 2);
+    for byte in build_id {
+        let _ = write!(hex, "{:02x}", byte);
+    }
+
+    let mut path = cache_path()?;
+    path.push(&hex);
+    path.push("debuginfo");
+    if path.is_file() {
+        return Some(path);
+    }
+
+    let urls = env::var("DEBUGINFOD_URLS").ok()?;
+    let mut missing = missing().lock().ok()?;
+    if missing.iter().any(|id| &id[..] == build_id) {
+        return None;
+    }
+    let timeout = env::var("DEBUGINFOD_TIMEOUT")
+        .ok()
+        .and_then(|s| s.parse().ok())
+        .unwrap_or(DEFAULT_TIMEOUT);
+    let timeout = Duration::from_secs(timeout);
+    for url in urls.split_whitespace() {
+        let url = format!("{}/buildid/{}/debuginfo", url.trim_end_matches('/'), hex);
+        if let Some(data) = fetch(&url, timeout) {
+            if store(&path, &data).is_some() {
+                return Some(path);
+            }
+        }
+    }
+    missing.push(build_id.to_vec());
+    None
+}
+
+/// Returns the root of the download cache, following elfutils: either
+/// `$DEBUGINFOD_CACHE_PATH`, `$XDG_CACHE_HOME/debuginfod_client`, or
+/// `$HOME/.cache/debuginfod_client`.
+fn cache_path() -> Option<PathBuf> {
+    if let Some(path) = env::var_os("DEBUGINFOD_CACHE_PATH") {
+        return Some(PathBuf::from(path));
+    }
+    let mut path = match env::var_os("XDG_CACHE_HOME") {
+        Some(path) => PathBuf::from(path),
+        None => {
+            let mut path = PathBuf::from(env::var_os("HOME")?);
+            path.push(".cache");
+            path
+        }
+    };
+    path.push("debuginfod_client");
+    Some(path)
+}
+
+/// Writes `data` to `path` via a temporary file, so that a concurrent reader
+/// (possibly in another process) never sees a partial download.
+fn store(path: &Path, data: &[u8]) -> Option<()> {
+    fs::create_dir_all(path.parent()?).ok()?;
+    let mut tmp = OsString::from(path.as_os_str());
+    tmp.push(format!(".{}.tmp", super::mystd::process::id()));
+    let tmp = PathBuf::from(tmp);
+    if fs::write(&tmp, data)
+        .and_then(|()| fs::rename(&tmp, path))
+        .is_err()
+    {
+        let _ = fs::remove_file(&tmp);
+        return None;
+    }
+    Some(())
+}
+
+/// Performs an HTTP/1.0 `GET` of `url`, returning the body of a `200`
+/// response.
+fn fetch(url: &str, timeout: Duration) -> Option<Vec<u8>> {
+    if !url.starts_with("http://") {
+        return None;
+    }
+    let rest = &url["http://".len()..];
+    let (authority, path) = match rest.find('/') {
+        Some(i) => (&rest[..i], &rest[i..]),
+        None => (rest, "/"),
+    };
+    let addr = if authority.contains(':') {
+        authority.to_socket_addrs()
+    } else {
+        (authority, 80).to_socket_addrs()
+    };
+    let addr = addr.ok()?.next()?;
+
+    let mut stream = TcpStream::connect_timeout(&addr, timeout).ok()?;
+    stream.set_read_timeout(Some(timeout)).ok()?;
+    stream.set_write_timeout(Some(timeout)).ok()?;
+    let request = format!(
+        "GET {} HTTP/1.0\r\nHost: {}\r\nUser-Agent: backtrace-rs\r\nConnection: close\r\n\r\n",
+        path, authority
+    );
+    stream.write_all(request.as_bytes()).ok()?;
+    let mut response = Vec::new();
+    stream.read_to_end(&mut response).ok()?;
+    parse_response(response)
+}
+
+/// Splits a raw HTTP response into its headers and body, returning the body if
+/// the status is `200` and its length matches any `Content-Length` header.
+fn parse_response(mut response: Vec<u8>) -> Option<Vec<u8>> {
+    let end = response.windows(4).position(|w| w == b"\r\n\r\n")?;
+    let head = str::from_utf8(&response[..end]).ok()?;
+    let mut lines = head.split("\r\n");
+    let status = lines.next()?.split(' ').nth(1)?;
+    if status != "200" {
+        return None;
+    }
+    let body_len = response.len() - (end + 4);
+    for line in lines {
+        let (name, value) = match line.find(':') {
+            Some(i) => (&line[..i], line[i + 1..].trim()),
+            None => continue,
+        };
+        if name.eq_ignore_ascii_case("content-length") && value.parse() != Ok(body_len) {
+            return None;
+        }
+    }
+    response.drain(..end + 4);
+    Some(response)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::mystd::net::TcpListener;
+    use super::super::mystd::thread;
+    use super::*;
+
+    #[test]
+    fn parse_ok_response() {
+        let response = b"HTTP/1.0 200 OK\r\nContent-Length: 4\r\n\r\nELF!".to_vec();
+        assert_eq!(parse_response(response).as_deref(), Some(&b"ELF!"[..]));
+
+        let truncated = b"HTTP/1.0 200 OK\r\nContent-Length: 10\r\n\r\nELF!".to_vec();
+        assert_eq!(parse_response(truncated), None);
+
+        let missing = b"HTTP/1.0 404 Not Found\r\n\r\n".to_vec();
+        assert_eq!(parse_response(missing), None);
+    }
+
+    #[test]
+    fn fetch_from_local_server() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        let server = thread::spawn(move || {
+            let (mut stream, _) = listener.accept().unwrap();
+            let mut request = [0; 1024];
+            let n = stream.read(&mut request).unwrap();
+            let request = String::from_utf8_lossy(&request[..n]).into_owned();
+            stream
+                .write_all(b"HTTP/1.0 200 OK\r\nContent-Length: 5\r\n\r\nhello")
+                .unwrap();
+            request
+        });
+
+        let url = format!("http://{}/buildid/abcd/debuginfo", addr);
+        let body = fetch(&url, Duration::from_secs(5));
+        assert_eq!(body.as_deref(), Some(&b"hello"[..]));
+        let request = server.join().unwrap();
+        assert!(request.starts_with("GET /buildid/abcd/debuginfo HTTP/1.0\r\n"));
+    }
+}
@@ -9,12 +9,12 @@ use core::str;
 use object::elf::{ELFCOMPRESS_ZLIB, ELF_NOTE_GNU, NT_GNU_BUILD_ID, SHF_COMPRESSED};
 use object::read::elf::{CompressionHeader, FileHeader, SectionHeader, SectionTable, Sym};
 use object::read::StringTable;
-use object::{BigEndian, Bytes, NativeEndian};
+use object::{BigEndian, Bytes, Endian, Endianness};
 
 #[cfg(target_pointer_width = "32")]
-type Elf = object::elf::FileHeader32<NativeEndian>;
+type Elf = object::elf::FileHeader32<Endianness>;
 #[cfg(target_pointer_width = "64")]
-type Elf = object::elf::FileHeader64<NativeEndian>;
+type Elf = object::elf::FileHeader64<Endianness>;
 
 impl Mapping {
     pub fn new(path: &Path) -> Option<Mapping> {
@@ -36,6 +36,22 @@ impl Mapping {
                 }
             }
 
+            // Try to fetch an external debug file from a debuginfod server,
+            // unless the object already carries its own debug info.
+            #[cfg(feature = "debuginfod")]
+            {
+                if object.section_header(".debug_info").is_none() {
+                    if let Some(path_debug) = object
+                        .build_id()
+                        .and_then(super::debuginfod::locate_build_id)
+                    {
+                        if let Some(mapping) = Mapping::new_debug(path_debug, None) {
+                            return Some(Either::A(mapping));
+                        }
+                    }
+                }
+            }
+
             Context::new(stash, object, None).map(Either::B)
         })
     }
@@ -74,10 +90,9 @@ struct ParsedSym {
 }
 
 pub struct Object<'a> {
-    /// Zero-sized type representing the native endianness.
-    ///
-    /// We could use a literal instead, but this helps ensure correctness.
-    endian: NativeEndian,
+    /// The byte order declared in the file's header, which every header,
+    /// note, and section in it is read with.
+    endian: Endianness,
     /// The entire file data.
     data: &'a [u8],
     sections: SectionTable<'a, Elf>,
@@ -100,6 +115,9 @@ impl<'a> Object<'a> {
                 .ok()?;
         }
         let strings = syms.strings();
+        // The addresses of Thumb functions have bit 0 set to mark the
+        // instruction set, which isn't part of where they start.
+        let is_arm = elf.e_machine(endian) == object::elf::EM_ARM;
 
         let mut syms = syms
             .iter()
@@ -117,7 +135,10 @@ impl<'a> Object<'a> {
             // symbolicating with locally defined functions.
             .filter(|sym| sym.st_shndx(endian) != object::elf::SHN_UNDEF)
             .map(|sym| {
-                let address = sym.st_value(endian).into();
+                let mut address: u64 = sym.st_value(endian).into();
+                if is_arm && sym.st_type() == object::elf::STT_FUNC {
+                    address &= !1;
+                }
                 let size = sym.st_size(endian).into();
                 let name = sym.st_name(endian);
                 ParsedSym {
@@ -196,7 +217,9 @@ impl<'a> Object<'a> {
             .map(|(_index, section)| section)
     }
 
-    pub fn search_symtab<'b>(&'b self, addr: u64) -> Option<&'b [u8]> {
+    /// Finds the symbol `addr` falls in, returning its name and how far into
+    /// it `addr` is.
+    pub fn search_symtab<'b>(&'b self, addr: u64) -> Option<(&'b [u8], u64)> {
         // Same sort of binary search as Windows above
         let i = match self.syms.binary_search_by_key(&addr, |sym| sym.address) {
             Ok(i) => i,
@@ -204,12 +227,21 @@ impl<'a> Object<'a> {
         };
         let sym = self.syms.get(i)?;
         if sym.address <= addr && addr <= sym.address + sym.size {
-            self.strings.get(sym.name).ok()
+            let name = self.strings.get(sym.name).ok()?;
+            Some((name, addr - sym.address))
         } else {
             None
         }
     }
 
+    pub(super) fn dwarf_endian(&self) -> super::Endian {
+        if self.endian.is_big_endian() {
+            super::Endian::Big
+        } else {
+            super::Endian::Little
+        }
+    }
+
     pub(super) fn search_object_map(&self, _addr: u64) -> Option<(&Context<'_>, u64)> {
         None
     }
@@ -238,7 +270,7 @@ impl<'a> Object<'a> {
         let crc_bytes = data
             .get(offset..offset + 4)
             .and_then(|bytes| bytes.try_into().ok())?;
-        let crc = u32::from_ne_bytes(crc_bytes);
+        let crc = self.endian.read_u32_bytes(crc_bytes);
         let path_debug = locate_debuglink(path, filename)?;
         Some((path_debug, crc))
     }
@@ -348,7 +380,8 @@ fn hex(byte: u8) -> u8 {
 ///
 /// gdb also allows the user to customize the debug search path, but we don't.
 ///
-/// gdb also supports debuginfod, but we don't yet.
+/// Like gdb, debuginfod is consulted by build ID when this fails, if the
+/// `debuginfod` feature is enabled.
 fn locate_debuglink(path: &Path, filename: &[u8]) -> Option<PathBuf> {
     let path = fs::canonicalize(path).ok()?;
     let parent = path.parent()?;
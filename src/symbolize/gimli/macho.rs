@@ -236,14 +236,19 @@ impl<'a> Object<'a> {
         Some(section.data(self.endian, self.data).ok()?)
     }
 
-    pub fn search_symtab<'b>(&'b self, addr: u64) -> Option<&'b [u8]> {
+    pub fn search_symtab<'b>(&'b self, addr: u64) -> Option<(&'b [u8], u64)> {
         debug_assert!(!self.syms_sort_by_name);
         let i = match self.syms.binary_search_by_key(&addr, |(_, addr)| *addr) {
             Ok(i) => i,
             Err(i) => i.checked_sub(1)?,
         };
-        let (sym, _addr) = self.syms.get(i)?;
-        Some(sym)
+        let (sym, sym_addr) = self.syms.get(i)?;
+        Some((sym, addr - sym_addr))
+    }
+
+    pub(super) fn dwarf_endian(&self) -> super::Endian {
+        // Only images in the native byte order are parsed, see `parse`.
+        super::Endian::default()
     }
 
     /// Try to load a context for an object file.
@@ -7,7 +7,7 @@ use super::{ResolveWhat, SymbolName};
 
 pub unsafe fn resolve(what: ResolveWhat<'_>, cb: &mut dyn FnMut(&super::Symbol)) {
     let sym = match what {
-        ResolveWhat::Address(addr) => Symbol {
+        ResolveWhat::Address(addr) | ResolveWhat::ExactAddress(addr) => Symbol {
             inner: resolve_addr(addr),
             _unused: PhantomData,
         },
@@ -19,6 +19,40 @@ pub unsafe fn resolve(what: ResolveWhat<'_>, cb: &mut dyn FnMut(&super::Symbol))
     cb(&super::Symbol { inner: sym })
 }
 
+pub unsafe fn resolve_frames(
+    frames: &[super::Frame],
+    cb: &mut dyn FnMut(&super::Frame, Option<&super::Symbol>),
+) {
+    for frame in frames {
+        let mut any = false;
+        resolve(ResolveWhat::Frame(frame), &mut |symbol| {
+            any = true;
+            cb(frame, Some(symbol));
+        });
+        if !any {
+            cb(frame, None);
+        }
+    }
+}
+
+#[cfg(feature = "std")]
+pub unsafe fn resolve_lines(frames: &[super::Frame], cb: &mut dyn FnMut(usize, &str, u32)) {
+    // There's no getting at lines without symbolizing all of the frame, so
+    // the innermost symbol's are used.
+    for (i, frame) in frames.iter().enumerate() {
+        let mut first = true;
+        resolve(ResolveWhat::Frame(frame), &mut |symbol| {
+            if !core::mem::replace(&mut first, false) {
+                return;
+            }
+            let file = symbol.filename().and_then(|file| file.to_str());
+            if let (Some(file), Some(line)) = (file, symbol.lineno()) {
+                cb(i, file, line);
+            }
+        });
+    }
+}
+
 pub struct Symbol<'a> {
     inner: Frame,
     _unused: PhantomData<&'a ()>,
@@ -33,6 +67,10 @@ impl<'a> Symbol<'a> {
         Some(self.inner.addr)
     }
 
+    pub fn symbol_offset(&self) -> Option<usize> {
+        None
+    }
+
     pub fn filename_raw(&self) -> Option<BytesOrWideString<'_>> {
         Some(BytesOrWideString::Bytes(&self.inner.inner.filename))
     }
@@ -51,6 +89,48 @@ impl<'a> Symbol<'a> {
             core::str::from_utf8(&self.inner.inner.filename).unwrap(),
         ))
     }
+
+    pub fn call_filename_raw(&self) -> Option<BytesOrWideString<'_>> {
+        None
+    }
+
+    #[cfg(feature = "std")]
+    pub fn call_filename(&self) -> Option<&std::path::Path> {
+        None
+    }
+
+    pub fn call_lineno(&self) -> Option<u32> {
+        None
+    }
+
+    pub fn call_colno(&self) -> Option<u32> {
+        None
+    }
+
+    pub fn inline_depth(&self) -> usize {
+        0
+    }
+
+    pub fn is_from_cache(&self) -> bool {
+        false
+    }
 }
 
 pub unsafe fn clear_symbol_cache() {}
+
+pub unsafe fn clear_library_cache() {}
+
+// There's no way to symbolicate against an arbitrary file here.
+#[cfg(feature = "std")]
+pub struct Symbolizer(());
+
+#[cfg(feature = "std")]
+impl Symbolizer {
+    pub fn from_file(_path: &::std::path::Path, _bias: usize) -> Option<Symbolizer> {
+        None
+    }
+
+    pub fn set_bias(&mut self, _bias: usize) {}
+
+    pub fn resolve(&mut self, _what: ResolveWhat<'_>, _cb: &mut dyn FnMut(&super::Symbol)) {}
+}
@@ -10,8 +10,19 @@ cfg_if::cfg_if! {
 use super::backtrace::Frame;
 use super::types::BytesOrWideString;
 use core::ffi::c_void;
+use core::sync::atomic::{AtomicUsize, Ordering};
 use rustc_demangle::{try_demangle, Demangle};
 
+mod crate_name;
+#[cfg(feature = "std")]
+mod demangle;
+#[cfg(feature = "std")]
+pub use self::demangle::register_demangler;
+#[cfg(feature = "std")]
+mod snapshot;
+#[cfg(feature = "std")]
+pub use self::snapshot::{snapshot_modules, ModuleSnapshot};
+
 /// Resolve an address to a symbol, passing the symbol to the specified
 /// closure.
 ///
@@ -20,7 +31,10 @@ use rustc_demangle::{try_demangle, Demangle};
 /// activated implementation) to find symbols to yield.
 ///
 /// The closure may not be called if resolution could not be performed, and it
-/// also may be called more than once in the case of inlined functions.
+/// also may be called more than once in the case of inlined functions. Those
+/// symbols are always yielded innermost first, i.e. starting with the function
+/// that was executing and ending with the one whose code it was inlined into,
+/// with `Symbol::inline_depth` counting down to 0.
 ///
 /// Symbols yielded represent the execution at the specified `addr`, returning
 /// file/line pairs for that address (if available).
@@ -60,9 +74,246 @@ use rustc_demangle::{try_demangle, Demangle};
 #[cfg(feature = "std")]
 pub fn resolve<F: FnMut(&Symbol)>(addr: *mut c_void, cb: F) {
     let _guard = crate::lock::lock();
+    crate::modules::check_for_changes();
     unsafe { resolve_unsynchronized(addr, cb) }
 }
 
+/// Same as `resolve`, except that the symbols for `addr` are returned as an
+/// iterator rather than passed to a closure.
+///
+/// This composes with iterator pipelines, e.g. symbolizing a whole stack with
+/// `frames.iter().flat_map(|frame| backtrace::resolve_iter(frame.ip()))`.
+/// As with `resolve` there may be several symbols for one address when
+/// functions have been inlined into each other, innermost first.
+///
+/// The symbols of `addr` are looked up as soon as this is called and copied
+/// into owned `BacktraceSymbol`s, since `Symbol`s can't outlive the lookup;
+/// an iterator chained over several addresses only resolves each one as it's
+/// reached though. Lookups go through the same caches as `resolve`, so
+/// iterating over a whole stack only opens and maps each library once.
+///
+/// # Required features
+///
+/// This function requires the `std` feature of the `backtrace` crate to be
+/// enabled, and the `std` feature is enabled by default.
+///
+/// # Example
+///
+/// ```
+/// let mut ips = Vec::new();
+/// backtrace::trace(|frame| {
+///     ips.push(frame.ip());
+///     true
+/// });
+/// for symbol in ips.into_iter().flat_map(backtrace::resolve_iter) {
+///     println!("{:?}", symbol.name());
+/// }
+/// ```
+#[cfg(feature = "std")]
+pub fn resolve_iter(addr: *mut c_void) -> impl Iterator<Item = crate::BacktraceSymbol> {
+    let mut symbols = std::vec::Vec::new();
+    resolve(addr, |symbol| {
+        symbols.push(crate::BacktraceSymbol::from_symbol(symbol))
+    });
+    symbols.into_iter()
+}
+
+/// The outcome of `resolve_status`, telling apart the reasons an address may
+/// have no symbols.
+///
+/// # Required features
+///
+/// This type requires the `std` feature of the `backtrace` crate to be
+/// enabled, and the `std` feature is enabled by default.
+#[cfg(feature = "std")]
+#[non_exhaustive]
+#[derive(Clone, Debug)]
+pub enum ResolveStatus {
+    /// The address has symbols, innermost first as `resolve` would pass them
+    /// to its closure. There's always at least one.
+    Resolved(std::vec::Vec<crate::BacktraceSymbol>),
+    /// The address is within a loaded module, but neither its debug info nor
+    /// its symbol table covers it, e.g. because the module is stripped. The
+    /// address is likely genuine, just not symbolizable.
+    NoDebugInfo {
+        /// The module the address is in.
+        module: crate::Module,
+    },
+    /// The address isn't within any loaded module, so it's either in code
+    /// generated at runtime, or garbage, e.g. from a corrupted stack.
+    NotInAnyModule,
+    /// The address has no symbols, and which module it's in couldn't be told
+    /// since loaded modules can't be enumerated on this platform.
+    Unknown,
+}
+
+/// Resolves an address like `resolve`, but also says why it couldn't be
+/// resolved if it wasn't.
+///
+/// With plain `resolve`, an address in a stripped library and one which
+/// points at nothing at all both just get no symbols. Profilers and crash
+/// reporters may want to keep the former, which is a real frame they can't
+/// name, while discarding the latter, so when there are no symbols this
+/// consults the list of loaded modules, as returned by `loaded_modules`, to
+/// say which it is.
+///
+/// # Required features
+///
+/// This function requires the `std` feature of the `backtrace` crate to be
+/// enabled, and the `std` feature is enabled by default.
+///
+/// # Example
+///
+/// ```
+/// use backtrace::ResolveStatus;
+///
+/// backtrace::trace(|frame| {
+///     match backtrace::resolve_status(frame.ip()) {
+///         ResolveStatus::Resolved(symbols) => println!("{:?}", symbols[0].name()),
+///         ResolveStatus::NoDebugInfo { module } => println!("somewhere in {:?}", module.name()),
+///         ResolveStatus::NotInAnyModule => println!("bogus frame {:?}", frame.ip()),
+///         _ => println!("unknown frame {:?}", frame.ip()),
+///     }
+///     true
+/// });
+/// ```
+#[cfg(feature = "std")]
+pub fn resolve_status(addr: *mut c_void) -> ResolveStatus {
+    let symbols = resolve_iter(addr).collect::<std::vec::Vec<_>>();
+    if !symbols.is_empty() {
+        return ResolveStatus::Resolved(symbols);
+    }
+    let mut modules = crate::loaded_modules().peekable();
+    if modules.peek().is_none() {
+        return ResolveStatus::Unknown;
+    }
+    match modules.find(|module| module.contains(addr)) {
+        Some(module) => ResolveStatus::NoDebugInfo { module },
+        None => ResolveStatus::NotInAnyModule,
+    }
+}
+
+/// Finds and resolves just the frame which called this function, for cheaply
+/// tagging e.g. an error with where it originated.
+///
+/// This is much cheaper than `Backtrace::new`, since the stack is only walked
+/// as far as that frame, nothing is allocated for the frames, and only that
+/// one frame is resolved. Its innermost symbol is returned, which for a
+/// function that was inlined into its caller is that function's.
+///
+/// With `skip_runtime` set, frames outside of the main executable, such as
+/// libc's and those of other shared libraries, are skipped over until the
+/// first frame within it, as told by `Frame::is_in_main_executable`. Code
+/// from the standard library and other crates linked into the executable
+/// counts as the executable's, so that e.g. a callback from `qsort` resolves
+/// to the callback rather than to libc.
+///
+/// This function's own frame is recognised by its `symbol_address`, which on
+/// platforms where that's just the `ip` (e.g. macOS) means it can't be told
+/// apart, in which case the first frame found is used, which is some frame
+/// within this crate.
+///
+/// Returns `None` if no such frame was found or it has no symbols.
+///
+/// # Required features
+///
+/// This function requires the `std` feature of the `backtrace` crate to be
+/// enabled, and the `std` feature is enabled by default.
+///
+/// # Example
+///
+/// ```
+/// fn fail() -> String {
+///     match backtrace::resolve_leaf(true) {
+///         Some(origin) => format!("failed at {:?}:{:?}", origin.filename(), origin.lineno()),
+///         None => "failed".to_string(),
+///     }
+/// }
+/// println!("{}", fail());
+/// ```
+#[cfg(feature = "std")]
+#[inline(never)] // want to make sure there's a frame here to skip
+pub fn resolve_leaf(skip_runtime: bool) -> Option<crate::BacktraceSymbol> {
+    resolve_caller_of(resolve_leaf as *const () as usize, skip_runtime)
+}
+
+/// Returns the symbol of the function this is called from, e.g. for logging
+/// which function something happened in, or asserting that code runs where
+/// it's expected to.
+///
+/// This is the same as `resolve_leaf(false)`: the stack is walked only as far
+/// as the caller's frame, and only that frame is resolved. Its innermost
+/// symbol is returned, so when called from a function which was inlined into
+/// another, this is the inlined function. The same caveat about platforms
+/// where this function's own frame can't be recognised applies too.
+///
+/// Returns `None` if the caller has no symbols.
+///
+/// # Required features
+///
+/// This function requires the `std` feature of the `backtrace` crate to be
+/// enabled, and the `std` feature is enabled by default.
+///
+/// # Example
+///
+/// ```
+/// fn work() {
+///     let function = backtrace::current_function();
+///     if let Some(name) = function.as_ref().and_then(|f| f.name()) {
+///         println!("in {}", name);
+///     }
+/// }
+/// work();
+/// ```
+#[cfg(feature = "std")]
+#[inline(never)] // want to make sure there's a frame here to skip
+pub fn current_function() -> Option<crate::BacktraceSymbol> {
+    resolve_caller_of(current_function as *const () as usize, false)
+}
+
+// Resolves the frame after that of the function starting at `this`, for
+// `resolve_leaf` and `current_function`. Always inlined, so that the frame
+// looked for is theirs.
+#[cfg(feature = "std")]
+#[inline(always)]
+fn resolve_caller_of(this: usize, skip_runtime: bool) -> Option<crate::BacktraceSymbol> {
+    let _guard = crate::lock::lock();
+    crate::modules::check_for_changes();
+    let mut found_this = false;
+    let mut first = None;
+    let mut leaf = None;
+    unsafe {
+        crate::trace_unsynchronized(|frame| {
+            if frame.symbol_address() as usize == this {
+                found_this = true;
+                return true;
+            }
+            if skip_runtime && !frame.is_in_main_executable() {
+                return true;
+            }
+            if found_this {
+                leaf = Some(frame.clone());
+                return false;
+            }
+            if first.is_none() {
+                first = Some(frame.clone());
+            }
+            true
+        });
+    }
+
+    let frame = leaf.or(first)?;
+    let mut symbol = None;
+    unsafe {
+        resolve_frame_unsynchronized(&frame, |s| {
+            if symbol.is_none() {
+                symbol = Some(crate::BacktraceSymbol::from_symbol(s));
+            }
+        });
+    }
+    symbol
+}
+
 /// Resolve a previously capture frame to a symbol, passing the symbol to the
 /// specified closure.
 ///
@@ -72,6 +323,15 @@ pub fn resolve<F: FnMut(&Symbol)>(addr: *mut c_void, cb: F) {
 /// or information about inline frames for example. It's recommended to use this
 /// if you can.
 ///
+/// Like `resolve`, this looks up the byte before the frame's `ip`, since that
+/// is a return address pointing just past the call the frame is making. This
+/// is what keeps a frame which called a function that never returns, such as
+/// `abort`, from resolving to the function after it, when the call was its
+/// last instruction. The one exception is the frame a signal interrupted, as
+/// passed to the closure of `trace_from_siginfo` or seen when tracing from a
+/// signal handler, whose `ip` is the instruction it was interrupted at and is
+/// looked up as it is, where the unwinder can tell.
+///
 /// # Required features
 ///
 /// This function requires the `std` feature of the `backtrace` crate to be
@@ -102,11 +362,444 @@ pub fn resolve<F: FnMut(&Symbol)>(addr: *mut c_void, cb: F) {
 #[cfg(feature = "std")]
 pub fn resolve_frame<F: FnMut(&Symbol)>(frame: &Frame, cb: F) {
     let _guard = crate::lock::lock();
+    crate::modules::check_for_changes();
     unsafe { resolve_frame_unsynchronized(frame, cb) }
 }
 
+/// Resolve an address to a symbol exactly as given, passing the symbol to the
+/// specified closure.
+///
+/// This is the same as `resolve`, except that `resolve` assumes its address
+/// is a return address taken from a stack frame and so actually looks up the
+/// byte before it, to land on the call instruction. That's wrong for
+/// addresses not taken from a stack, such as function pointers or addresses
+/// of the faulting instruction, which this looks up as-is.
+///
+/// Callers holding a return address can subtract one before calling this to
+/// get the same result as `resolve`.
+///
+/// # Required features
+///
+/// This function requires the `std` feature of the `backtrace` crate to be
+/// enabled, and the `std` feature is enabled by default.
+///
+/// # Panics
+///
+/// This function strives to never panic, but if the `cb` provided panics then
+/// some platforms will force a double panic to abort the process. Some
+/// platforms use a C library which internally uses callbacks which cannot be
+/// unwound through, so panicking from `cb` may trigger a process abort.
+///
+/// # Example
+///
+/// ```
+/// fn callback() {}
+///
+/// backtrace::resolve_addr(callback as usize, |symbol| {
+///     println!("{:?}", symbol.name());
+/// });
+/// ```
+#[cfg(feature = "std")]
+pub fn resolve_addr<F: FnMut(&Symbol)>(addr: usize, cb: F) {
+    let _guard = crate::lock::lock();
+    crate::modules::check_for_changes();
+    unsafe { resolve_addr_unsynchronized(addr, cb) }
+}
+
+/// Resolves all the frames of a stack, in order.
+///
+/// This gives the same symbols as calling `resolve_frame` on each frame in
+/// turn, but is cheaper for deep stacks. Each library's parsed debug info is
+/// cached from its first lookup on either way, but here the global lock is
+/// only taken once for the whole stack, and since consecutive frames are
+/// usually in the same library, each frame's library is looked for first
+/// among the library of the frame before it, before searching all those
+/// loaded. How much that saves grows with the number of libraries loaded;
+/// see the `trace_and_resolve_stack` benchmark. Runs of frames with the same
+/// address, as recursion gives, are only looked up once, with the symbols of
+/// the first frame of the run passed along again for the rest.
+///
+/// `cb` is called with each symbol of each frame, the same as with
+/// `resolve_frame`, and once with `None` for a frame that has no symbols.
+///
+/// # Required features
+///
+/// This function requires the `std` feature of the `backtrace` crate to be
+/// enabled, and the `std` feature is enabled by default.
+///
+/// # Panics
+///
+/// See information on `resolve_frame` for caveats on `cb` panicking.
+///
+/// # Example
+///
+/// ```
+/// let mut frames = Vec::new();
+/// backtrace::trace(|frame| {
+///     frames.push(frame.clone());
+///     true
+/// });
+/// backtrace::resolve_stack(&frames, |frame, symbol| match symbol {
+///     Some(symbol) => println!("{:?}", symbol.name()),
+///     None => println!("{:?}", frame.ip()),
+/// });
+/// ```
+#[cfg(feature = "std")]
+pub fn resolve_stack<F>(frames: &[Frame], mut cb: F)
+where
+    F: FnMut(&Frame, Option<&Symbol>),
+{
+    let _guard = crate::lock::lock();
+    crate::modules::check_for_changes();
+    unsafe { imp::resolve_frames(frames, &mut cb) }
+}
+
+/// Resolves just the file and line of each of the frames of a stack, for
+/// when function names aren't needed.
+///
+/// `cb` is called with the index of each frame in `frames`, in order, along
+/// with its file and line, which are those of the innermost function inlined
+/// at the frame's address, the same as for the first symbol `resolve_frame`
+/// gives it. Frames whose file or line isn't known are left out, and the file
+/// is left out too if it isn't valid UTF-8.
+///
+/// With the `gimli-symbolize` implementation only the line tables of the
+/// debug info are consulted, so the function entries which `resolve` has to
+/// parse to find names and inlined functions are never read, nor is the
+/// symbol table. That makes this cheaper than `resolve_stack` for e.g.
+/// coverage tools, most of all the first time each compilation unit is
+/// looked up, when `resolve` would have to parse all of its functions. Like
+/// `resolve_stack` it takes the global lock once for the whole stack.
+/// Elsewhere the frames are fully resolved, so this is no faster.
+///
+/// # Required features
+///
+/// This function requires the `std` feature of the `backtrace` crate to be
+/// enabled, and the `std` feature is enabled by default.
+///
+/// # Panics
+///
+/// See information on `resolve_frame` for caveats on `cb` panicking.
+///
+/// # Example
+///
+/// ```
+/// let mut frames = Vec::new();
+/// backtrace::trace(|frame| {
+///     frames.push(frame.clone());
+///     true
+/// });
+/// backtrace::resolve_lines(&frames, |i, file, line| {
+///     println!("{:?} at {}:{}", frames[i].ip(), file, line);
+/// });
+/// ```
+#[cfg(feature = "std")]
+pub fn resolve_lines<F>(frames: &[Frame], mut cb: F)
+where
+    F: FnMut(usize, &str, u32),
+{
+    let _guard = crate::lock::lock();
+    crate::modules::check_for_changes();
+    unsafe { imp::resolve_lines(frames, &mut cb) }
+}
+
+/// Resolves the frames of a stack in order until `deadline` passes, and
+/// reports the rest unresolved, for a bounded-time backtrace.
+///
+/// Symbolizing a deep stack with cold caches can take tens of milliseconds,
+/// which is too long for e.g. a request handler that hit an error. This
+/// gives predictable latency instead: frames are resolved innermost first
+/// for as long as the time budget lasts, and `cb` is then called with just
+/// the frame for each of the remaining ones, so that they can be printed as
+/// raw addresses.
+///
+/// `cb` is called with each symbol of each resolved frame, the same as with
+/// `resolve_frame`, and once with `None` for a frame that has no symbols or
+/// that wasn't resolved. The number of leading frames which were resolved is
+/// returned, so that the two cases can be told apart.
+///
+/// The deadline is checked before each frame, and looking up a single frame
+/// can't be cut short, so it may be overrun by the time taken to resolve one
+/// frame, which with cold caches includes loading that library's debug info.
+/// Calling `prewarm` ahead of time makes that less likely.
+///
+/// # Required features
+///
+/// This function requires the `std` feature of the `backtrace` crate to be
+/// enabled, and the `std` feature is enabled by default.
+///
+/// # Panics
+///
+/// See information on `resolve_frame` for caveats on `cb` panicking.
+///
+/// # Example
+///
+/// ```
+/// use std::time::{Duration, Instant};
+///
+/// let mut frames = Vec::new();
+/// backtrace::trace(|frame| {
+///     frames.push(frame.clone());
+///     true
+/// });
+/// let deadline = Instant::now() + Duration::from_millis(5);
+/// backtrace::resolve_stack_deadline(&frames, deadline, |frame, symbol| match symbol {
+///     Some(symbol) => println!("{:?}", symbol.name()),
+///     None => println!("{:?}", frame.ip()),
+/// });
+/// ```
+#[cfg(feature = "std")]
+pub fn resolve_stack_deadline<F>(frames: &[Frame], deadline: std::time::Instant, mut cb: F) -> usize
+where
+    F: FnMut(&Frame, Option<&Symbol>),
+{
+    let _guard = crate::lock::lock();
+    crate::modules::check_for_changes();
+    let mut resolved = 0;
+    for frame in frames {
+        if std::time::Instant::now() >= deadline {
+            break;
+        }
+        let mut any = false;
+        unsafe {
+            resolve_frame_unsynchronized(frame, |symbol| {
+                any = true;
+                cb(frame, Some(symbol));
+            });
+        }
+        if !any {
+            cb(frame, None);
+        }
+        resolved += 1;
+    }
+    for frame in &frames[resolved..] {
+        cb(frame, None);
+    }
+    resolved
+}
+
+/// Resolves each of `addrs` ahead of time so that resolving them again later
+/// is cheap, discarding the symbols.
+///
+/// The first lookup in a library maps it and parses its debug info, and the
+/// first lookup in each compilation unit within it parses that unit's line
+/// table, all of which is cached from then on. Calling this at startup or on
+/// a background thread with the addresses a program expects to symbolize
+/// moves that work off of e.g. a latency-sensitive path which prints a
+/// backtrace. Addresses without a symbol are remembered too.
+///
+/// `addrs` are treated like the instruction pointers given to `resolve`.
+///
+/// Only a few libraries' debug info is kept around at once, so warming
+/// addresses spread across many libraries will evict the earlier ones. With
+/// the `gimli-symbolize` feature that's the four most recently used
+/// libraries; on other implementations this may do little or nothing.
+///
+/// # Required features
+///
+/// This function requires the `std` feature of the `backtrace` crate to be
+/// enabled, and the `std` feature is enabled by default.
+///
+/// # Example
+///
+/// ```
+/// let mut ips = Vec::new();
+/// backtrace::trace(|frame| {
+///     ips.push(frame.ip() as usize);
+///     true
+/// });
+/// backtrace::prewarm(&ips);
+/// ```
+#[cfg(feature = "std")]
+pub fn prewarm(addrs: &[usize]) {
+    let _guard = crate::lock::lock();
+    crate::modules::check_for_changes();
+    for &addr in addrs {
+        unsafe { imp::resolve(ResolveWhat::Address(addr as *mut c_void), &mut |_| {}) }
+    }
+}
+
+/// Resolves addresses to symbols using the debug info of an object file on
+/// disk, rather than the libraries loaded into this process.
+///
+/// This is for symbolicating addresses offline: addresses recorded by some
+/// other process, possibly on another machine, can be looked up as long as
+/// the exact binary they came from is at hand. Separate debug info is found
+/// for the file the same way as for loaded libraries, e.g. through
+/// `.gnu_debuglink` or a build ID.
+///
+/// Each `Symbolizer` keeps its file mapped and its parsed debug info around
+/// for as long as it lives, independently of this crate's global caches, so
+/// it's best to create one per file and use it for all of that file's
+/// addresses.
+///
+/// A `Symbolizer` can also stand in for one of the libraries loaded into this
+/// process, with `for_build_id`, to look up its addresses in e.g. an
+/// unstripped copy of it.
+///
+/// Only the `gimli-symbolize` implementation supports this; elsewhere
+/// `from_file` and `for_build_id` always return `None`.
+///
+/// # Required features
+///
+/// This type requires the `std` feature of the `backtrace` crate to be
+/// enabled, and the `std` feature is enabled by default.
+///
+/// # Example
+///
+/// ```no_run
+/// use backtrace::Symbolizer;
+///
+/// // An address from a profile of `my-server`, which was loaded 0x5555_0000
+/// // bytes past the addresses stated in the file.
+/// let mut symbolizer = Symbolizer::from_file("my-server", 0x5555_0000).unwrap();
+/// symbolizer.resolve(0x5556_1234, |symbol| {
+///     println!("{:?}", symbol.name());
+/// });
+/// ```
+#[cfg(feature = "std")]
+pub struct Symbolizer {
+    inner: imp::Symbolizer,
+    // Set by `for_build_id`, for which only addresses in the module with this
+    // build ID are looked up in `inner`.
+    route: Option<Route>,
+}
+
+// Where the module a `Symbolizer::for_build_id` stands in for is loaded.
+#[cfg(feature = "std")]
+struct Route {
+    build_id: Vec<u8>,
+    // The module's `base`, `len` and `bias`, if it's loaded, as of the load
+    // generation `generation`.
+    module: Option<(usize, usize, usize)>,
+    generation: Option<usize>,
+}
+
+#[cfg(feature = "std")]
+impl Symbolizer {
+    /// Maps the object file at `path` and parses its debug info.
+    ///
+    /// `load_bias` is subtracted from every address before it's looked up in
+    /// the file, so it's the difference between where the file was loaded
+    /// and the addresses it states (e.g. `Module::bias` of the module when it
+    /// was loaded, or the start of the mapping for a position-independent
+    /// executable). Pass zero to look up addresses as stated in the file.
+    ///
+    /// Returns `None` if the file can't be read or isn't an object file this
+    /// crate understands.
+    pub fn from_file<P: AsRef<Path>>(path: P, load_bias: usize) -> Option<Symbolizer> {
+        Some(Symbolizer {
+            inner: imp::Symbolizer::from_file(path.as_ref(), load_bias)?,
+            route: None,
+        })
+    }
+
+    /// Maps the object file at `alternate_path` to look up the addresses of
+    /// the loaded module whose build ID is `build_id` in, rather than in the
+    /// module's own file.
+    ///
+    /// This is for symbolizing live backtraces of a stripped binary, which
+    /// can be deployed without its debug info so long as an unstripped build
+    /// of it, or its separate debug info, is kept somewhere else, matched up
+    /// by build ID. It's also handy when the module's file has been replaced
+    /// on disk since it was loaded, e.g. by a hot reload.
+    ///
+    /// Addresses passed to `resolve` and `resolve_addr` are taken to be ones
+    /// in this process. Those within the module are looked up in the
+    /// alternate file, with the module's load bias applied, while all others
+    /// are resolved like with `resolve` and `resolve_addr` outside of a
+    /// `Symbolizer`. Where the module is loaded is found with
+    /// `loaded_modules` and looked up again whenever libraries are loaded or
+    /// unloaded, so the module can be loaded after the `Symbolizer` is
+    /// created, or be reloaded.
+    ///
+    /// It's up to the caller to make sure the alternate file really is a
+    /// build of the module; its own build ID isn't checked, since separate
+    /// debug info may not have one.
+    ///
+    /// Returns `None` if the file can't be read or isn't an object file this
+    /// crate understands.
+    ///
+    /// # Example
+    ///
+    /// ```no_run
+    /// use backtrace::Symbolizer;
+    ///
+    /// let main = backtrace::loaded_modules().next().unwrap();
+    /// let build_id = main.build_id().unwrap();
+    /// let mut symbolizer =
+    ///     Symbolizer::for_build_id(build_id, "/debug/my-server.unstripped").unwrap();
+    /// backtrace::trace(|frame| {
+    ///     symbolizer.resolve(frame.ip() as usize, |symbol| {
+    ///         println!("{:?}", symbol.name());
+    ///     });
+    ///     true
+    /// });
+    /// ```
+    pub fn for_build_id<P: AsRef<Path>>(build_id: &[u8], alternate_path: P) -> Option<Symbolizer> {
+        Some(Symbolizer {
+            inner: imp::Symbolizer::from_file(alternate_path.as_ref(), 0)?,
+            route: Some(Route {
+                build_id: build_id.to_vec(),
+                module: None,
+                generation: None,
+            }),
+        })
+    }
+
+    /// Resolves `addr`, passing each symbol found to `cb`.
+    ///
+    /// Like with `resolve`, `addr` is taken to be an instruction pointer from
+    /// a stack frame, i.e. a return address, and adjusted to land on the call
+    /// instruction before it's looked up. Symbols report the addresses stated
+    /// in the file, with the load bias removed.
+    pub fn resolve<F: FnMut(&Symbol)>(&mut self, addr: usize, mut cb: F) {
+        if self.in_file(addr) {
+            self.inner
+                .resolve(ResolveWhat::Address(addr as *mut c_void), &mut cb)
+        } else {
+            resolve(addr as *mut c_void, cb)
+        }
+    }
+
+    /// Same as `resolve`, except that `addr` is looked up exactly as given,
+    /// like with `resolve_addr`.
+    pub fn resolve_addr<F: FnMut(&Symbol)>(&mut self, addr: usize, mut cb: F) {
+        if self.in_file(addr) {
+            self.inner
+                .resolve(ResolveWhat::ExactAddress(addr as *mut c_void), &mut cb)
+        } else {
+            resolve_addr(addr, cb)
+        }
+    }
+
+    // Returns whether `addr` is to be looked up in the file, which for a
+    // `for_build_id` one means it's in the module, whose bias is applied.
+    fn in_file(&mut self, addr: usize) -> bool {
+        let route = match &mut self.route {
+            Some(route) => route,
+            None => return true,
+        };
+        let generation = crate::modules::generation();
+        if generation.is_none() || generation != route.generation {
+            route.module = crate::loaded_modules()
+                .find(|module| module.build_id() == Some(&route.build_id[..]))
+                .map(|module| (module.base() as usize, module.len(), module.bias()));
+            route.generation = generation;
+        }
+        match route.module {
+            Some((base, len, bias)) if base <= addr && addr - base < len => {
+                self.inner.set_bias(bias);
+                true
+            }
+            _ => false,
+        }
+    }
+}
+
 pub enum ResolveWhat<'a> {
     Address(*mut c_void),
+    ExactAddress(*mut c_void),
     Frame(&'a Frame),
 }
 
@@ -115,6 +808,8 @@ impl<'a> ResolveWhat<'a> {
     fn address_or_ip(&self) -> *mut c_void {
         match self {
             ResolveWhat::Address(a) => adjust_ip(*a),
+            ResolveWhat::ExactAddress(a) => *a,
+            ResolveWhat::Frame(f) if f.ip_is_exact() => f.ip(),
             ResolveWhat::Frame(f) => adjust_ip(f.ip()),
         }
     }
@@ -138,6 +833,16 @@ impl<'a> ResolveWhat<'a> {
 // For now though this is a pretty niche concern so we just internally always
 // subtract one. Consumers should keep working and getting pretty good results,
 // so we should be good enough.
+//
+// Subtracting one matters most after a call to a function which never returns,
+// like `abort`, which can be the very last instruction of the caller. The
+// return address is then the caller's end, i.e. the start of whatever function
+// follows it, and only one byte back is it the caller again.
+//
+// The exception is a frame interrupted by a signal, whose ip is the
+// instruction it was interrupted at, which may be the first of its function.
+// The default unwinder can tell those apart, so `resolve_frame` uses their ip
+// as-is.
 fn adjust_ip(a: *mut c_void) -> *mut c_void {
     if a.is_null() {
         a
@@ -162,6 +867,26 @@ where
     imp::resolve(ResolveWhat::Address(addr), &mut cb)
 }
 
+/// Same as `resolve_addr`, only unsafe as it's unsynchronized.
+///
+/// This function does not have synchronization guarantees but is available
+/// when the `std` feature of this crate isn't compiled in. See the
+/// `resolve_addr` function for more documentation and examples.
+///
+/// # Safety
+///
+/// The same requirements as `resolve_unsynchronized` apply.
+///
+/// # Panics
+///
+/// See information on `resolve_addr` for caveats on `cb` panicking.
+pub unsafe fn resolve_addr_unsynchronized<F>(addr: usize, mut cb: F)
+where
+    F: FnMut(&Symbol),
+{
+    imp::resolve(ResolveWhat::ExactAddress(addr as *mut c_void), &mut cb)
+}
+
 /// Same as `resolve_frame`, only unsafe as it's unsynchronized.
 ///
 /// This function does not have synchronization guarantees but is available
@@ -208,11 +933,45 @@ impl Symbol {
         self.inner.name()
     }
 
+    /// Returns the name of the crate this function is in, taken from its
+    /// mangled Rust name, e.g. `"core"` for `core::fmt::write`.
+    ///
+    /// Both the legacy and v0 Rust mangling schemes are understood. Methods
+    /// of trait impls, like `<foo::Bar as core::fmt::Debug>::fmt`, belong to
+    /// the crate of the type the trait is implemented for, and generic
+    /// functions to the crate defining them rather than the one they were
+    /// instantiated in. `None` is returned for functions whose names aren't
+    /// mangled Rust names, or whose crate can't be told from the name, such
+    /// as trait methods implemented for primitive types, or crates whose
+    /// names aren't ASCII.
+    ///
+    /// This is meant for grouping frames by crate, e.g. for crate level
+    /// views of a profile.
+    pub fn crate_name(&self) -> Option<&str> {
+        crate_name::crate_name(self.name()?.as_bytes())
+    }
+
     /// Returns the starting address of this function.
     pub fn addr(&self) -> Option<*mut c_void> {
         self.inner.addr().map(|p| p as *mut _)
     }
 
+    /// Returns how many bytes past the start of the symbol the looked-up
+    /// address is, when the symbol was found in the symbol table rather than
+    /// in debug info.
+    ///
+    /// Binaries stripped of their debug info usually still have a symbol
+    /// table, or at least the dynamic symbol table of their exported symbols,
+    /// so this is what lets them be printed as `name+offset`. Such symbols
+    /// have no file or line information. Looking symbols up in the symbol
+    /// table can be turned off with the `symtab-fallback` feature, which is
+    /// enabled by default.
+    ///
+    /// This is only known for the gimli backend, and `None` otherwise.
+    pub fn symbol_offset(&self) -> Option<usize> {
+        self.inner.symbol_offset()
+    }
+
     /// Returns the raw filename as a slice. This is mainly useful for `no_std`
     /// environments.
     pub fn filename_raw(&self) -> Option<BytesOrWideString<'_>> {
@@ -251,6 +1010,83 @@ impl Symbol {
     pub fn filename(&self) -> Option<&Path> {
         self.inner.filename()
     }
+
+    /// Returns the raw filename of the call site this function was inlined
+    /// at, as a slice. This is mainly useful for `no_std` environments.
+    ///
+    /// See `call_filename` for more information.
+    pub fn call_filename_raw(&self) -> Option<BytesOrWideString<'_>> {
+        self.inner.call_filename_raw()
+    }
+
+    /// Returns the column number of the call site this function was inlined
+    /// at.
+    ///
+    /// See `call_filename` for more information.
+    pub fn call_colno(&self) -> Option<u32> {
+        self.inner.call_colno()
+    }
+
+    /// Returns the line number of the call site this function was inlined
+    /// at.
+    ///
+    /// See `call_filename` for more information.
+    pub fn call_lineno(&self) -> Option<u32> {
+        self.inner.call_lineno()
+    }
+
+    /// Returns the file name of the call site this function was inlined at.
+    ///
+    /// When functions are inlined into each other `resolve` yields a symbol
+    /// for each of them, innermost first. The `filename` and `lineno` of each
+    /// symbol are where execution is within that function, so for all but
+    /// the innermost one they're where the next inner function was called.
+    /// This is instead where this function itself was called from, which for
+    /// any symbol is the same as the `filename` of the symbol after it.
+    ///
+    /// This returns `None` for the outermost symbol, which wasn't inlined
+    /// anywhere. It's currently only available with gimli.
+    ///
+    /// # Required features
+    ///
+    /// This function requires the `std` feature of the `backtrace` crate to be
+    /// enabled, and the `std` feature is enabled by default.
+    #[cfg(feature = "std")]
+    pub fn call_filename(&self) -> Option<&Path> {
+        self.inner.call_filename()
+    }
+
+    /// Returns how many functions this one was inlined through, with 0 for
+    /// the function the code at the address physically belongs to.
+    ///
+    /// `resolve` yields symbols innermost first, so when e.g. `c` was inlined
+    /// into `b` which was inlined into `a`, the symbols are `c`, `b` and `a`
+    /// with depths 2, 1 and 0. The first symbol is the one which was
+    /// executing, as is on top of e.g. a flame graph, and the symbol of
+    /// depth 0 is the last one for an address.
+    ///
+    /// This is always 0 for symbols found in the symbol table rather than in
+    /// debug info, and for backends other than gimli, which yield a single
+    /// symbol for an address.
+    pub fn inline_depth(&self) -> usize {
+        self.inner.inline_depth()
+    }
+
+    /// Returns whether the debug info or symbol table this symbol was found
+    /// in was already cached, rather than read afresh for this lookup.
+    ///
+    /// The parsed debug info of recently used libraries is cached, and the
+    /// cache is emptied by `clear_symbol_cache` and whenever libraries are
+    /// loaded or unloaded. This tells whether that happened, e.g. to check
+    /// that a library which was rebuilt and reloaded with `dlopen` is
+    /// actually being looked up in its new file rather than its old one.
+    ///
+    /// This is only known for the gimli backend, and `false` otherwise. It's
+    /// also `false` for symbols found through a `Symbolizer`, which has no
+    /// cache.
+    pub fn from_cache(&self) -> bool {
+        self.inner.is_from_cache()
+    }
 }
 
 impl fmt::Debug for Symbol {
@@ -380,6 +1216,22 @@ fn format_symbol_name(
     Ok(())
 }
 
+// Formats a name which is neither a Rust nor a C++ one, after giving the
+// other demanglers a go at it.
+fn format_foreign_symbol_name(
+    fmt: fn(&str, &mut fmt::Formatter<'_>) -> fmt::Result,
+    bytes: &[u8],
+    f: &mut fmt::Formatter<'_>,
+) -> fmt::Result {
+    #[cfg(feature = "std")]
+    {
+        if let Some(name) = str::from_utf8(bytes).ok().and_then(demangle::demangle) {
+            return fmt(&name, f);
+        }
+    }
+    format_symbol_name(fmt, bytes, f)
+}
+
 cfg_if::cfg_if! {
     if #[cfg(feature = "cpp_demangle")] {
         impl<'a> fmt::Display for SymbolName<'a> {
@@ -389,7 +1241,7 @@ cfg_if::cfg_if! {
                 } else if let Some(ref cpp) = self.cpp_demangled.0 {
                     cpp.fmt(f)
                 } else {
-                    format_symbol_name(fmt::Display::fmt, self.bytes, f)
+                    format_foreign_symbol_name(fmt::Display::fmt, self.bytes, f)
                 }
             }
         }
@@ -399,7 +1251,7 @@ cfg_if::cfg_if! {
                 if let Some(ref s) = self.demangled {
                     s.fmt(f)
                 } else {
-                    format_symbol_name(fmt::Display::fmt, self.bytes, f)
+                    format_foreign_symbol_name(fmt::Display::fmt, self.bytes, f)
                 }
             }
         }
@@ -426,7 +1278,7 @@ cfg_if::cfg_if! {
                     }
                 }
 
-                format_symbol_name(fmt::Debug::fmt, self.bytes, f)
+                format_foreign_symbol_name(fmt::Debug::fmt, self.bytes, f)
             }
         }
     } else {
@@ -435,7 +1287,7 @@ cfg_if::cfg_if! {
                 if let Some(ref s) = self.demangled {
                     s.fmt(f)
                 } else {
-                    format_symbol_name(fmt::Debug::fmt, self.bytes, f)
+                    format_foreign_symbol_name(fmt::Debug::fmt, self.bytes, f)
                 }
             }
         }
@@ -448,6 +1300,10 @@ cfg_if::cfg_if! {
 /// otherwise been cached globally or in the thread which typically represent
 /// parsed DWARF information or similar.
 ///
+/// This also forgets which addresses were found to have no symbol, so call
+/// this after registering debug info for code (such as JIT-compiled code)
+/// that has already been symbolicated.
+///
 /// # Caveats
 ///
 /// While this function is always available it doesn't actually do anything on
@@ -463,18 +1319,142 @@ pub fn clear_symbol_cache() {
     }
 }
 
+// Drops any cached list of loaded libraries, along with the debug info parsed
+// from them, so it's all rebuilt the next time an address is resolved.
+//
+// unsafe because this is required to be externally synchronized
+#[cfg(feature = "std")]
+#[allow(dead_code)]
+pub(crate) unsafe fn clear_library_cache() {
+    imp::clear_library_cache();
+}
+
+/// Statistics about the caches used when symbolicating addresses, as returned
+/// by `cache_stats`.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[non_exhaustive]
+pub struct CacheStats {
+    /// The cached list of libraries loaded into this process, which maps an
+    /// address to the library it belongs to. Each entry is one library.
+    pub modules: CacheCounters,
+    /// The cached debug info parsed from libraries, which maps an address to
+    /// its symbols. Each entry is one library's parsed debug info.
+    pub symbols: CacheCounters,
+}
+
+/// Counters for one of the caches described by `CacheStats`.
+///
+/// All counts other than `entries` accumulate over the life of the process,
+/// wrapping around on overflow.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[non_exhaustive]
+pub struct CacheCounters {
+    /// The number of lookups answered from the cache.
+    pub hits: usize,
+    /// The number of lookups which had to fill the cache first.
+    pub misses: usize,
+    /// The number of entries currently in the cache.
+    pub entries: usize,
+    /// The number of times the cache was emptied, either by
+    /// `clear_symbol_cache` or because libraries were loaded or unloaded.
+    pub invalidations: usize,
+}
+
+/// Returns how effective the symbolication caches have been so far.
+///
+/// The counters are plain atomics updated as addresses are resolved, so this
+/// is cheap to call from any thread and doesn't take this crate's global lock.
+/// The counts are read one at a time though, so a snapshot taken while
+/// another thread is symbolicating may be slightly inconsistent.
+///
+/// The number of libraries whose debug info is kept around at once is fixed,
+/// so a high `symbols.misses` count relative to `symbols.hits` means addresses
+/// are spread across more libraries than fit in the cache.
+///
+/// Only the `gimli-symbolize` implementation keeps these statistics; on other
+/// implementations every counter stays at zero.
+///
+/// # Example
+///
+/// ```
+/// let stats = backtrace::cache_stats();
+/// let lookups = stats.symbols.hits + stats.symbols.misses;
+/// println!("{} of {} lookups hit the cache", stats.symbols.hits, lookups);
+/// ```
+pub fn cache_stats() -> CacheStats {
+    CacheStats {
+        modules: MODULE_CACHE.snapshot(),
+        symbols: SYMBOL_CACHE.snapshot(),
+    }
+}
+
+// Counters backing `cache_stats`, updated by the symbolication implementation.
+#[allow(dead_code)]
+pub(crate) struct Counters {
+    hits: AtomicUsize,
+    misses: AtomicUsize,
+    entries: AtomicUsize,
+    invalidations: AtomicUsize,
+}
+
+#[allow(dead_code)]
+impl Counters {
+    const fn new() -> Counters {
+        Counters {
+            hits: AtomicUsize::new(0),
+            misses: AtomicUsize::new(0),
+            entries: AtomicUsize::new(0),
+            invalidations: AtomicUsize::new(0),
+        }
+    }
+
+    pub(crate) fn hit(&self) {
+        self.hits.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub(crate) fn miss(&self) {
+        self.misses.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub(crate) fn set_entries(&self, entries: usize) {
+        self.entries.store(entries, Ordering::Relaxed);
+    }
+
+    pub(crate) fn invalidate(&self) {
+        self.invalidations.fetch_add(1, Ordering::Relaxed);
+        self.entries.store(0, Ordering::Relaxed);
+    }
+
+    fn snapshot(&self) -> CacheCounters {
+        CacheCounters {
+            hits: self.hits.load(Ordering::Relaxed),
+            misses: self.misses.load(Ordering::Relaxed),
+            entries: self.entries.load(Ordering::Relaxed),
+            invalidations: self.invalidations.load(Ordering::Relaxed),
+        }
+    }
+}
+
+pub(crate) static MODULE_CACHE: Counters = Counters::new();
+pub(crate) static SYMBOL_CACHE: Counters = Counters::new();
+
 cfg_if::cfg_if! {
     if #[cfg(miri)] {
         mod miri;
         use miri as imp;
-    } else if #[cfg(all(windows, target_env = "msvc", not(target_vendor = "uwp")))] {
+    } else if #[cfg(all(
+        windows,
+        target_env = "msvc",
+        not(target_vendor = "uwp"),
+        any(feature = "symbolize", backtrace_in_libstd),
+    ))] {
         mod dbghelp;
         use dbghelp as imp;
     } else if #[cfg(all(
         any(unix, windows),
         not(target_vendor = "uwp"),
         not(target_os = "emscripten"),
-        any(not(backtrace_in_libstd), feature = "backtrace"),
+        any(all(not(backtrace_in_libstd), feature = "symbolize"), feature = "backtrace"),
     ))] {
         mod gimli;
         use gimli as imp;
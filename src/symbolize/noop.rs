@@ -7,6 +7,33 @@ use core::marker;
 
 pub unsafe fn resolve(_addr: ResolveWhat<'_>, _cb: &mut dyn FnMut(&super::Symbol)) {}
 
+pub unsafe fn resolve_frames(
+    frames: &[super::Frame],
+    cb: &mut dyn FnMut(&super::Frame, Option<&super::Symbol>),
+) {
+    for frame in frames {
+        cb(frame, None);
+    }
+}
+
+#[cfg(feature = "std")]
+pub unsafe fn resolve_lines(_frames: &[super::Frame], _cb: &mut dyn FnMut(usize, &str, u32)) {}
+
+// There's no way to symbolicate against an arbitrary file here.
+#[cfg(feature = "std")]
+pub struct Symbolizer(());
+
+#[cfg(feature = "std")]
+impl Symbolizer {
+    pub fn from_file(_path: &::std::path::Path, _bias: usize) -> Option<Symbolizer> {
+        None
+    }
+
+    pub fn set_bias(&mut self, _bias: usize) {}
+
+    pub fn resolve(&mut self, _what: ResolveWhat<'_>, _cb: &mut dyn FnMut(&super::Symbol)) {}
+}
+
 pub struct Symbol<'a> {
     _marker: marker::PhantomData<&'a i32>,
 }
@@ -20,6 +47,10 @@ impl Symbol<'_> {
         None
     }
 
+    pub fn symbol_offset(&self) -> Option<usize> {
+        None
+    }
+
     pub fn filename_raw(&self) -> Option<BytesOrWideString<'_>> {
         None
     }
@@ -36,6 +67,33 @@ impl Symbol<'_> {
     pub fn colno(&self) -> Option<u32> {
         None
     }
+
+    pub fn call_filename_raw(&self) -> Option<BytesOrWideString<'_>> {
+        None
+    }
+
+    #[cfg(feature = "std")]
+    pub fn call_filename(&self) -> Option<&::std::path::Path> {
+        None
+    }
+
+    pub fn call_lineno(&self) -> Option<u32> {
+        None
+    }
+
+    pub fn call_colno(&self) -> Option<u32> {
+        None
+    }
+
+    pub fn inline_depth(&self) -> usize {
+        0
+    }
+
+    pub fn is_from_cache(&self) -> bool {
+        false
+    }
 }
 
 pub unsafe fn clear_symbol_cache() {}
+
+pub unsafe fn clear_library_cache() {}
@@ -0,0 +1,124 @@
+//! Resolving addresses against the modules loaded at some earlier point.
+//!
+//! `resolve` looks an address up in whichever libraries are loaded when it's
+//! called, so an address recorded while a library was loaded can't be
+//! resolved once that library is unloaded, and worse, may be resolved against
+//! a different library loaded at the same address since. A `ModuleSnapshot`
+//! maps the file of every module loaded when it's taken, so that addresses
+//! can be resolved against exactly those modules for as long as it lives.
+
+use super::{Symbol, Symbolizer};
+use crate::Module;
+use std::prelude::v1::*;
+
+/// Records the modules currently loaded into this process and maps each of
+/// their files, so that addresses in them can be resolved later on even if
+/// they've been unloaded by then.
+///
+/// See `ModuleSnapshot` for more information.
+///
+/// # Required features
+///
+/// This function requires the `std` feature of the `backtrace` crate to be
+/// enabled, and the `std` feature is enabled by default.
+pub fn snapshot_modules() -> ModuleSnapshot {
+    let modules = crate::loaded_modules()
+        .map(|module| {
+            let symbolizer = Symbolizer::from_file(module.name(), module.bias());
+            (module, symbolizer)
+        })
+        .collect();
+    ModuleSnapshot { modules }
+}
+
+/// The modules loaded into this process at the time `snapshot_modules` was
+/// called, along with their mapped files.
+///
+/// A profiler which captures backtraces while libraries may be loaded and
+/// unloaded with `dlopen` and `dlclose` can't resolve the addresses it has
+/// recorded later on with `resolve`, since by then the library an address
+/// was in may be gone, or replaced by another one at the same address. Taking
+/// a snapshot when capturing and resolving against it instead removes that
+/// race: each module's file is mapped when the snapshot is taken, and stays
+/// mapped, so even if the library is unloaded, and its file deleted or
+/// replaced on disk, its addresses resolve just as they would have at the
+/// time.
+///
+/// # Memory cost
+///
+/// A snapshot holds on to its own `Symbolizer` for every module, separately
+/// from this crate's global caches and from other snapshots. Each keeps its
+/// module's file, and any separate debug info found for it, mapped into
+/// memory, which costs address space for the whole file but only as much
+/// memory as is actually read of it. On top of that, the debug info parsed by
+/// lookups is kept until the snapshot is dropped, which grows with the number
+/// of compilation units addresses were looked up in. Taking a snapshot maps
+/// every module up front, so is best done once per batch of captures rather
+/// than once per backtrace, e.g. whenever `on_module_change` hooks run, and
+/// snapshots should be dropped as soon as their addresses are resolved.
+///
+/// Only the `gimli-symbolize` implementation can map modules' files; elsewhere
+/// no addresses resolve against a snapshot.
+///
+/// # Required features
+///
+/// This type requires the `std` feature of the `backtrace` crate to be
+/// enabled, and the `std` feature is enabled by default.
+///
+/// # Example
+///
+/// ```
+/// let mut ips = Vec::new();
+/// backtrace::trace(|frame| {
+///     ips.push(frame.ip() as usize);
+///     true
+/// });
+/// let mut snapshot = backtrace::snapshot_modules();
+///
+/// // ... libraries may be unloaded here ...
+///
+/// for ip in ips {
+///     snapshot.resolve(ip, |symbol| {
+///         println!("{:?}", symbol.name());
+///     });
+/// }
+/// ```
+pub struct ModuleSnapshot {
+    modules: Vec<(Module, Option<Symbolizer>)>,
+}
+
+impl ModuleSnapshot {
+    /// Returns the modules which were loaded when this snapshot was taken.
+    pub fn modules(&self) -> impl Iterator<Item = &Module> {
+        self.modules.iter().map(|(module, _)| module)
+    }
+
+    /// Resolves `addr` against the module it was in when this snapshot was
+    /// taken, passing each symbol found to `cb`.
+    ///
+    /// Like with `resolve`, `addr` is taken to be an instruction pointer from
+    /// a stack frame, i.e. a return address, and adjusted to land on the call
+    /// instruction before it's looked up. Addresses which weren't in any
+    /// module, or in one whose file couldn't be mapped, such as the vDSO,
+    /// resolve to no symbols.
+    pub fn resolve<F: FnMut(&Symbol)>(&mut self, addr: usize, cb: F) {
+        if let Some(symbolizer) = self.symbolizer(addr) {
+            symbolizer.resolve(addr, cb);
+        }
+    }
+
+    /// Same as `resolve`, except that `addr` is looked up exactly as given,
+    /// like with `resolve_addr`.
+    pub fn resolve_addr<F: FnMut(&Symbol)>(&mut self, addr: usize, cb: F) {
+        if let Some(symbolizer) = self.symbolizer(addr) {
+            symbolizer.resolve_addr(addr, cb);
+        }
+    }
+
+    fn symbolizer(&mut self, addr: usize) -> Option<&mut Symbolizer> {
+        self.modules
+            .iter_mut()
+            .find(|(module, _)| module.contains(addr as *mut _))
+            .and_then(|(_, symbolizer)| symbolizer.as_mut())
+    }
+}
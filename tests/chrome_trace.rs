@@ -0,0 +1,99 @@
+use backtrace::{Backtrace, ChromeTraceWriter};
+use std::collections::HashMap;
+use std::time::{Duration, Instant};
+
+// Picks the value of `"key":` out of a line of the trace, up to the next
+// comma or closing brace, or for strings, to the closing quote.
+fn field<'a>(line: &'a str, key: &str) -> Option<&'a str> {
+    let start = line.find(&format!("\"{}\":", key))? + key.len() + 3;
+    let rest = &line[start..];
+    let len = if rest.starts_with('"') {
+        let mut escaped = false;
+        rest[1..].find(|c| {
+            let end = c == '"' && !escaped;
+            escaped = c == '\\' && !escaped;
+            end
+        })? + 2
+    } else {
+        rest.find(|c| c == ',' || c == '}')?
+    };
+    Some(&rest[..len])
+}
+
+#[inline(never)]
+fn sampled_function() -> Backtrace {
+    Backtrace::new_unresolved()
+}
+
+#[test]
+fn writes_events_and_stack_frames() {
+    let mut writer = ChromeTraceWriter::new();
+    let start = Instant::now();
+    let mut backtrace = sampled_function();
+    let end = start + Duration::from_micros(1500);
+    writer.add_sample(7, "poll \"task\"\n", start, end, backtrace.frames());
+    writer.add_sample(7, "poll", end, end, backtrace.frames());
+    writer.add_sample(8, "idle", end, end, &[]);
+    let mut trace = Vec::new();
+    writer.finish(&mut trace).unwrap();
+    let trace = String::from_utf8(trace).unwrap();
+
+    assert!(trace.starts_with("{\"traceEvents\":["));
+    assert!(trace.trim_end().ends_with('}'));
+
+    let events = trace
+        .lines()
+        .filter(|line| line.contains("\"ph\":\"X\""))
+        .collect::<Vec<_>>();
+    assert_eq!(events.len(), 3);
+    assert!(events[0].starts_with("{\"name\":\"poll \\\"task\\\"\\n\""));
+    let dur = field(events[0], "dur").unwrap().parse::<f64>().unwrap();
+    assert!((dur - 1500.0).abs() < 0.001, "{}", dur);
+    assert_eq!(field(events[0], "tid"), Some("7"));
+    assert_eq!(
+        field(events[0], "pid"),
+        Some(&*std::process::id().to_string())
+    );
+    // The same stack interns to the same entry, and no stack to none.
+    let sf = field(events[0], "sf").unwrap();
+    assert_eq!(field(events[1], "sf"), Some(sf));
+    assert_eq!(field(events[2], "sf"), None);
+
+    let mut frames = HashMap::new();
+    for line in trace.lines().filter(|line| line.contains("\"category\":")) {
+        let id = line.trim_start_matches(',')[1..].split('"').next().unwrap();
+        let name = field(line, "name").unwrap().to_string();
+        frames.insert(id.to_string(), (name, field(line, "parent")));
+    }
+    let mut names = Vec::new();
+    let mut id = Some(sf);
+    while let Some(next) = id {
+        let (name, parent) = &frames[next];
+        names.push(name.clone());
+        id = *parent;
+    }
+
+    // Which are those of the frames, inlined ones included, innermost first,
+    // with frames which don't resolve standing in for themselves.
+    backtrace.resolve();
+    let mut expected = Vec::new();
+    for frame in backtrace.frames() {
+        if frame.symbols().is_empty() {
+            expected.push(format!("{:#x}", frame.ip() as usize));
+        }
+        for symbol in frame.symbols() {
+            expected.push(symbol.name().map_or(String::new(), |name| name.to_string()));
+        }
+    }
+    assert_eq!(names.len(), expected.len());
+    for (name, expected) in names.iter().zip(&expected) {
+        if !expected.is_empty() {
+            assert_eq!(name[1..name.len() - 1].replace("\\\"", "\""), *expected);
+        }
+    }
+    if cfg!(debug_assertions) {
+        assert!(expected
+            .iter()
+            .any(|name| name.contains("sampled_function")));
+    }
+}
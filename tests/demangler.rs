@@ -0,0 +1,33 @@
+// Demanglers are global, so this gets a test binary of its own where they
+// can't change what other tests see.
+
+use backtrace::SymbolName;
+
+#[test]
+fn demanglers_tried_in_order() {
+    backtrace::register_demangler(|name| name.strip_prefix("first$").map(str::to_uppercase));
+    backtrace::register_demangler(|name| {
+        if name.starts_with("first$") || name.starts_with("second$") {
+            Some("<second>".to_string())
+        } else {
+            None
+        }
+    });
+
+    assert_eq!(SymbolName::new(b"first$abc").to_string(), "ABC");
+    assert_eq!(SymbolName::new(b"second$abc").to_string(), "<second>");
+    assert_eq!(
+        format!("{:?}", SymbolName::new(b"second$abc")),
+        "\"<second>\""
+    );
+    assert_eq!(SymbolName::new(b"unknown").to_string(), "unknown");
+
+    // Go's names are cleaned up before the registered demanglers see them,
+    // and Rust's are demangled as usual.
+    assert_eq!(
+        SymbolName::new(b"runtime.goexit.abi0").to_string(),
+        "runtime.goexit"
+    );
+    let rust = SymbolName::new(b"_ZN4core3fmt5write17h0123456789abcdefE");
+    assert_eq!(format!("{:#}", rust), "core::fmt::write");
+}
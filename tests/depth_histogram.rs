@@ -0,0 +1,40 @@
+// The histogram is registered globally, so this gets a test binary of its own
+// where other tests' backtraces aren't recorded in it.
+
+use backtrace::{Backtrace, DepthHistogram};
+
+static DEPTHS: DepthHistogram = DepthHistogram::new();
+
+#[inline(never)]
+fn recurse(depth: u32) -> Backtrace {
+    if depth == 0 {
+        return Backtrace::new_unresolved();
+    }
+    let backtrace = recurse(depth - 1);
+    // Keeps this from being a tail call, which would leave no frame.
+    assert!(!backtrace.frames().is_empty());
+    backtrace
+}
+
+#[test]
+fn backtraces_record_their_depth() {
+    backtrace::set_depth_histogram(Some(&DEPTHS));
+    let shallow = Backtrace::new_unresolved();
+    let deep = recurse(50);
+    backtrace::set_depth_histogram(None);
+    Backtrace::new_unresolved();
+
+    assert_eq!(DEPTHS.count(), 2);
+    // Depths include the frames `frames` leaves out, and so are at least as
+    // deep as the frames it returns.
+    let deepest = DEPTHS.max();
+    assert!(deepest >= deep.frames().len());
+    assert!(deepest >= shallow.frames().len() + 50);
+    assert_eq!(DEPTHS.percentile(100.0), deepest);
+    let p50 = DEPTHS.percentile(50.0);
+    assert!(p50 >= shallow.frames().len() && p50 < deepest);
+
+    // A buffer of the size given by the top percentile holds a whole stack.
+    let mut buf = vec![std::ptr::null_mut(); DEPTHS.percentile(100.0)];
+    assert!(!backtrace::trace_into(&mut buf).truncated);
+}
@@ -0,0 +1,30 @@
+#![cfg(any(target_os = "linux", target_os = "android"))]
+
+#[inline(never)]
+fn traced_function() -> usize {
+    let mut frames = 0;
+    backtrace::trace(|_| {
+        frames += 1;
+        true
+    });
+    // Keeps this from being a tail call, which would leave no frame.
+    assert!(frames > 0);
+    frames
+}
+
+#[test]
+fn finds_the_loaded_unwinder() {
+    // The standard library already has an unwinder loaded for panics, which
+    // is found rather than another being opened.
+    assert!(backtrace::unwinder_available());
+    assert!(traced_function() > 1);
+    assert_eq!(
+        backtrace::trace_status(|_| true),
+        backtrace::TraceOutcome::Complete
+    );
+
+    let backtrace = backtrace::Backtrace::new();
+    if cfg!(debug_assertions) {
+        assert!(format!("{:?}", backtrace).contains("finds_the_loaded_unwinder"));
+    }
+}
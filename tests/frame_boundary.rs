@@ -0,0 +1,69 @@
+// The predicate is global, so this gets a test binary of its own where it
+// can't change what other tests see.
+
+use backtrace::{Backtrace, BacktraceFmt, BytesOrWideString, PrintFmt};
+use std::fmt;
+
+#[inline(never)]
+fn boundary_function() -> Backtrace {
+    let backtrace = segment_function();
+    // Keeps this from being a tail call, which would leave no frame.
+    assert!(!backtrace.frames().is_empty());
+    backtrace
+}
+
+#[inline(never)]
+fn segment_function() -> Backtrace {
+    Backtrace::new()
+}
+
+struct Separated<'a>(&'a Backtrace);
+
+impl fmt::Display for Separated<'_> {
+    fn fmt(&self, fmt: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut print_path = |fmt: &mut fmt::Formatter<'_>, path: BytesOrWideString<'_>| {
+            fmt::Display::fmt(&path, fmt)
+        };
+        let mut f = BacktraceFmt::new(fmt, PrintFmt::Short, &mut print_path);
+        f.frame_boundary_separator("--- poll ---");
+        for frame in self.0.frames() {
+            f.frame().backtrace_frame(frame)?;
+        }
+        f.finish()
+    }
+}
+
+#[test]
+fn separator_before_boundary_frames() {
+    let backtrace = boundary_function();
+    assert!(!backtrace.frames().iter().any(|f| f.is_frame_boundary()));
+
+    backtrace::set_frame_boundary_predicate(|frame| {
+        let mut boundary = false;
+        backtrace::resolve_frame(frame, |symbol| {
+            if let Some(name) = symbol.name() {
+                boundary |= name.to_string().contains("boundary_function");
+            }
+        });
+        boundary
+    });
+    let boundaries = backtrace
+        .frames()
+        .iter()
+        .filter(|f| f.is_frame_boundary())
+        .count();
+    assert_eq!(boundaries, 1);
+
+    let printed = Separated(&backtrace).to_string();
+    let lines = printed.lines().collect::<Vec<_>>();
+    let separator = lines.iter().position(|l| *l == "--- poll ---").unwrap();
+    assert_eq!(lines.iter().filter(|l| **l == "--- poll ---").count(), 1);
+    assert!(
+        lines[separator + 1].contains("boundary_function"),
+        "{}",
+        printed
+    );
+
+    // The `Debug` output has no separators.
+    assert!(!format!("{:?}", backtrace).contains("--- poll ---"));
+}
@@ -0,0 +1,65 @@
+#![cfg(unix)]
+
+use backtrace::TracingAlloc;
+use std::alloc::System;
+
+#[global_allocator]
+static ALLOC: TracingAlloc<System> = TracingAlloc::with_sampling(System, 0, 16);
+
+#[inline(never)]
+fn allocating_function() -> Vec<u64> {
+    vec![7; 1000]
+}
+
+// The sampling rate is global, so everything is done in one test rather than
+// in several which could run at once.
+#[test]
+fn samples_allocations() {
+    // Clear out anything sampled by the test harness.
+    ALLOC.set_sample_every(1);
+    backtrace::take_allocation_samples(|_| {});
+
+    let v = allocating_function();
+    ALLOC.set_sample_every(0);
+
+    let mut samples = Vec::new();
+    let dropped = backtrace::take_allocation_samples(|sample| samples.push(*sample));
+    assert_eq!(dropped, 0);
+    let sample = samples
+        .iter()
+        .find(|sample| sample.ptr() == v.as_ptr() as *mut u8)
+        .expect("allocation should have been sampled");
+    assert_eq!(sample.size(), 8000);
+    assert!(sample.ips().len() <= 16);
+
+    let mut names = Vec::new();
+    for &ip in sample.ips() {
+        backtrace::resolve(ip, |symbol| {
+            if let Some(name) = symbol.name() {
+                names.push(name.to_string());
+            }
+        });
+    }
+    assert!(
+        names
+            .iter()
+            .any(|name| name.contains("allocating_function")),
+        "{:?}",
+        names
+    );
+
+    // Nothing is left over.
+    assert_eq!(backtrace::take_allocation_samples(|_| panic!()), 0);
+
+    // Sampling more than the ring holds overwrites the oldest samples.
+    ALLOC.set_sample_every(1);
+    let boxes = (0..40).map(Box::new).collect::<Vec<_>>();
+    ALLOC.set_sample_every(0);
+
+    let mut ptrs = Vec::new();
+    let dropped = backtrace::take_allocation_samples(|sample| ptrs.push(sample.ptr()));
+    assert!(dropped > 0);
+    assert_eq!(ptrs.len(), 32);
+    let last = &*boxes[39] as *const i32 as *mut u8;
+    assert!(ptrs.contains(&last));
+}
@@ -0,0 +1,34 @@
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+
+#[test]
+fn hook_runs_after_dlopen() {
+    // Skip musl which is by default statically linked and doesn't support
+    // dynamic libraries, and anything without `dl_iterate_phdr`.
+    if !cfg!(target_os = "linux") || cfg!(target_env = "musl") {
+        return;
+    }
+
+    let calls = Arc::new(AtomicUsize::new(0));
+    let calls2 = calls.clone();
+    backtrace::on_module_change(move || {
+        calls2.fetch_add(1, Ordering::SeqCst);
+    });
+
+    // Observe the initial generation.
+    backtrace::trace(|_| false);
+    assert_eq!(calls.load(Ordering::SeqCst), 0);
+
+    let mut path = std::env::current_exe().unwrap();
+    path.pop();
+    path.push("libdylib_dep.so");
+    let lib = unsafe { libloading::Library::new(&path).unwrap() };
+
+    backtrace::trace(|_| false);
+    assert_eq!(calls.load(Ordering::SeqCst), 1);
+    assert!(backtrace::loaded_modules().any(|m| m.name().ends_with("libdylib_dep.so")));
+
+    drop(lib);
+    backtrace::resolve(hook_runs_after_dlopen as *mut _, |_| {});
+    assert_eq!(calls.load(Ordering::SeqCst), 2);
+}
@@ -0,0 +1,33 @@
+#[test]
+fn snapshot_resolves_after_dlclose() {
+    // Skip musl which is by default statically linked and doesn't support
+    // dynamic libraries, and anything without `dl_iterate_phdr`.
+    if !cfg!(target_os = "linux") || cfg!(target_env = "musl") {
+        return;
+    }
+
+    let mut path = std::env::current_exe().unwrap();
+    path.pop();
+    path.push("libdylib_dep.so");
+    let lib = unsafe { libloading::Library::new(&path).unwrap() };
+    let foo = unsafe { *lib.get::<*mut std::ffi::c_void>(b"foo\0").unwrap() } as usize;
+
+    let mut snapshot = backtrace::snapshot_modules();
+    assert!(snapshot.modules().any(|m| m.contains(foo as *mut _)));
+    let names = |snapshot: &mut backtrace::ModuleSnapshot| {
+        let mut names = Vec::new();
+        snapshot.resolve_addr(foo, |symbol| names.push(symbol.name().unwrap().to_string()));
+        names
+    };
+    let before = names(&mut snapshot);
+    assert!(before.iter().any(|name| name == "foo"), "{:?}", before);
+
+    drop(lib);
+    assert!(!backtrace::loaded_modules().any(|m| m.contains(foo as *mut _)));
+    assert_eq!(names(&mut snapshot), before);
+
+    // Addresses outside of any module resolve to nothing.
+    let mut any = false;
+    snapshot.resolve(1, |_| any = true);
+    assert!(!any);
+}
@@ -0,0 +1,103 @@
+#![cfg(any(target_os = "linux", target_os = "android"))]
+
+use backtrace::PerCpuSampleBuffers;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+
+#[inline(never)]
+fn capturing_function(buffers: &PerCpuSampleBuffers) -> bool {
+    buffers.capture_to_current_cpu()
+}
+
+#[test]
+fn captures_and_drains() {
+    let buffers = PerCpuSampleBuffers::new(4, 64);
+    assert!(buffers.cpus() >= 1);
+    assert!(capturing_function(&buffers));
+
+    let mut samples = Vec::new();
+    let drained = buffers.drain(|sample| {
+        assert!(sample.cpu() < buffers.cpus());
+        samples.push(sample.ips().to_vec());
+    });
+    assert_eq!(drained, 1);
+    assert!(samples[0].len() <= 64);
+
+    let mut names = Vec::new();
+    for &ip in &samples[0] {
+        backtrace::resolve(ip, |symbol| {
+            if let Some(name) = symbol.name() {
+                names.push(name.to_string());
+            }
+        });
+    }
+    assert!(
+        names.iter().any(|name| name.contains("capturing_function")),
+        "{:?}",
+        names
+    );
+
+    // The slot was freed, and nothing's left.
+    assert_eq!(buffers.drain(|_| panic!()), 0);
+    assert_eq!(buffers.dropped(), 0);
+}
+
+#[test]
+fn drops_when_full() {
+    let buffers = PerCpuSampleBuffers::new(0, 64);
+    assert!(!buffers.capture_to_current_cpu());
+    assert!(!buffers.capture_to_current_cpu());
+    assert_eq!(buffers.drain(|_| panic!()), 0);
+    assert_eq!(buffers.dropped(), 2);
+    assert_eq!(buffers.dropped(), 0);
+
+    let buffers = PerCpuSampleBuffers::new(1, 0);
+    assert!(buffers.capture_to_current_cpu());
+    buffers.drain(|sample| assert!(sample.ips().is_empty() && sample.is_truncated()));
+
+    let buffers = PerCpuSampleBuffers::new(1, 2);
+    assert!(buffers.capture_to_current_cpu());
+    buffers.drain(|sample| {
+        assert_eq!(sample.ips().len(), 2);
+        assert!(sample.is_truncated());
+    });
+}
+
+#[test]
+fn captures_while_draining() {
+    const THREADS: usize = 4;
+    const CAPTURES: usize = 500;
+
+    let buffers = Arc::new(PerCpuSampleBuffers::new(8, 32));
+    let done = Arc::new(AtomicBool::new(false));
+    let threads = (0..THREADS)
+        .map(|_| {
+            let buffers = buffers.clone();
+            std::thread::spawn(move || {
+                (0..CAPTURES)
+                    .filter(|_| buffers.capture_to_current_cpu())
+                    .count()
+            })
+        })
+        .collect::<Vec<_>>();
+    let consumer = {
+        let buffers = buffers.clone();
+        let done = done.clone();
+        std::thread::spawn(move || {
+            let mut drained = 0;
+            while !done.load(Ordering::SeqCst) {
+                drained += buffers.drain(|sample| assert!(!sample.ips().is_empty()));
+            }
+            drained + buffers.drain(|_| {})
+        })
+    };
+
+    let captured = threads
+        .into_iter()
+        .map(|t| t.join().unwrap())
+        .sum::<usize>();
+    done.store(true, Ordering::SeqCst);
+    let drained = consumer.join().unwrap();
+    assert_eq!(drained, captured);
+    assert_eq!(captured + buffers.dropped(), THREADS * CAPTURES);
+}
@@ -0,0 +1,72 @@
+#![cfg(any(target_os = "linux", target_os = "android"))]
+
+use backtrace::{Backtrace, PerfDataWriter};
+use std::convert::TryInto;
+
+fn u16_at(data: &[u8], at: usize) -> u16 {
+    u16::from_ne_bytes(data[at..at + 2].try_into().unwrap())
+}
+
+fn u32_at(data: &[u8], at: usize) -> u32 {
+    u32::from_ne_bytes(data[at..at + 4].try_into().unwrap())
+}
+
+fn u64_at(data: &[u8], at: usize) -> u64 {
+    u64::from_ne_bytes(data[at..at + 8].try_into().unwrap())
+}
+
+#[test]
+fn writes_samples_and_mmaps() {
+    let backtrace = Backtrace::new_unresolved();
+    let mut writer = PerfDataWriter::new();
+    writer.add_sample(backtrace.frames(), 3).unwrap();
+    let mut file = Vec::new();
+    writer.finish(&mut file).unwrap();
+
+    assert_eq!(&file[..8], b"PERFILE2");
+    let header_size = u64_at(&file, 8) as usize;
+    assert_eq!(header_size, 104);
+    let attr_size = u64_at(&file, 16) as usize;
+    let (attrs_offset, attrs_size) = (u64_at(&file, 24) as usize, u64_at(&file, 32) as usize);
+    assert_eq!(attrs_offset, header_size);
+    assert_eq!(attrs_size, attr_size);
+    // The size of the attribute itself.
+    assert_eq!(u32_at(&file, attrs_offset + 4) as usize + 16, attr_size);
+    let (data_offset, data_size) = (u64_at(&file, 40) as usize, u64_at(&file, 48) as usize);
+    assert_eq!(data_offset + data_size, file.len());
+
+    let modules = backtrace::loaded_modules().count();
+    let (mut comms, mut mmaps, mut samples) = (0, 0, 0);
+    let mut at = data_offset;
+    while at < file.len() {
+        let ty = u32_at(&file, at);
+        let size = u16_at(&file, at + 6) as usize;
+        assert_eq!(size % 8, 0);
+        match ty {
+            3 => comms += 1,
+            10 => mmaps += 1,
+            9 => {
+                samples += 1;
+                let ips = backtrace
+                    .frames()
+                    .iter()
+                    .map(|f| f.ip() as u64)
+                    .collect::<Vec<_>>();
+                assert_eq!(u64_at(&file, at + 8), ips[0]);
+                assert_eq!(u32_at(&file, at + 16), std::process::id());
+                assert_eq!(u64_at(&file, at + 32), 3);
+                let nr = u64_at(&file, at + 40) as usize;
+                assert_eq!(nr, ips.len() + 1);
+                let chain = (0..ips.len())
+                    .map(|i| u64_at(&file, at + 56 + i * 8))
+                    .collect::<Vec<_>>();
+                assert_eq!(chain, ips);
+                assert_eq!(size, 48 + nr * 8);
+            }
+            _ => panic!("unexpected record type {}", ty),
+        }
+        at += size;
+    }
+    assert_eq!(at, file.len());
+    assert_eq!((comms, mmaps, samples), (1, modules, 1));
+}
@@ -0,0 +1,181 @@
+#![cfg(target_os = "linux")]
+
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::thread;
+
+#[inline(never)]
+fn spin_until(started: &AtomicBool, stop: &AtomicBool) {
+    started.store(true, Ordering::Relaxed);
+    while !stop.load(Ordering::Relaxed) {
+        std::hint::spin_loop();
+    }
+}
+
+fn names(frames: &[backtrace::BacktraceFrame]) -> Vec<String> {
+    frames
+        .iter()
+        .flat_map(|frame| frame.symbols())
+        .filter_map(|symbol| symbol.name().map(|name| name.to_string()))
+        .collect()
+}
+
+#[test]
+fn captures_other_thread() {
+    let started = Arc::new(AtomicBool::new(false));
+    let stop = Arc::new(AtomicBool::new(false));
+    let handle = {
+        let (started, stop) = (started.clone(), stop.clone());
+        thread::spawn(move || spin_until(&started, &stop))
+    };
+    while !started.load(Ordering::Relaxed) {
+        thread::yield_now();
+    }
+
+    let frames = backtrace::thread_backtrace(&handle).expect("thread should respond");
+    stop.store(true, Ordering::Relaxed);
+    handle.join().unwrap();
+
+    let names = names(&frames);
+    assert!(
+        names.iter().any(|name| name.contains("spin_until")),
+        "{:?}",
+        names
+    );
+    // The signal handler's own frames are skipped.
+    assert!(
+        !names
+            .iter()
+            .any(|name| name.contains("trace_unsynchronized_into")),
+        "{:?}",
+        names
+    );
+}
+
+#[test]
+fn blocked_signal_times_out() {
+    let started = Arc::new(AtomicBool::new(false));
+    let stop = Arc::new(AtomicBool::new(false));
+    let handle = {
+        let (started, stop) = (started.clone(), stop.clone());
+        thread::spawn(move || unsafe {
+            let mut set = std::mem::zeroed();
+            libc::sigemptyset(&mut set);
+            libc::sigaddset(&mut set, backtrace::remote_thread_signal());
+            libc::pthread_sigmask(libc::SIG_BLOCK, &set, std::ptr::null_mut());
+            spin_until(&started, &stop);
+        })
+    };
+    while !started.load(Ordering::Relaxed) {
+        thread::yield_now();
+    }
+
+    assert!(backtrace::thread_backtrace(&handle).is_none());
+    stop.store(true, Ordering::Relaxed);
+    handle.join().unwrap();
+
+    // Later requests aren't confused by the one left pending.
+    let handle = thread::spawn(|| thread::sleep(std::time::Duration::from_millis(500)));
+    assert!(backtrace::thread_backtrace(&handle).is_some());
+    handle.join().unwrap();
+}
+
+#[test]
+fn dumps_all_threads() {
+    let started = Arc::new(AtomicBool::new(false));
+    let stop = Arc::new(AtomicBool::new(false));
+    let handle = {
+        let (started, stop) = (started.clone(), stop.clone());
+        thread::Builder::new()
+            .name("dump-target".to_string())
+            .spawn(move || spin_until(&started, &stop))
+            .unwrap()
+    };
+    while !started.load(Ordering::Relaxed) {
+        thread::yield_now();
+    }
+
+    let dump = backtrace::dump_all_threads();
+    stop.store(true, Ordering::Relaxed);
+    handle.join().unwrap();
+
+    let target = dump
+        .iter()
+        .find(|thread| thread.name() == Some("dump-target"))
+        .expect("spawned thread should be dumped");
+    assert!(names(target.frames())
+        .iter()
+        .any(|name| name.contains("spin_until")));
+    assert!(
+        format!("{:?}", target).starts_with(&format!("thread {} (dump-target):\n", target.tid()))
+    );
+
+    // The calling thread is included too.
+    let current = unsafe { libc::syscall(libc::SYS_gettid) as libc::pid_t };
+    assert!(dump.iter().any(|thread| thread.tid() == current));
+}
+
+#[test]
+fn dumps_all_threads_frozen() {
+    let started = Arc::new(AtomicBool::new(false));
+    let blocked_started = Arc::new(AtomicBool::new(false));
+    let stop = Arc::new(AtomicBool::new(false));
+    let handle = {
+        let (started, stop) = (started.clone(), stop.clone());
+        thread::Builder::new()
+            .name("frozen-target".to_string())
+            .spawn(move || spin_until(&started, &stop))
+            .unwrap()
+    };
+    let blocked = {
+        let (started, stop) = (blocked_started.clone(), stop.clone());
+        thread::Builder::new()
+            .name("frozen-blocked".to_string())
+            .spawn(move || unsafe {
+                let mut set = std::mem::zeroed();
+                libc::sigemptyset(&mut set);
+                libc::sigaddset(&mut set, backtrace::remote_thread_signal());
+                libc::pthread_sigmask(libc::SIG_BLOCK, &set, std::ptr::null_mut());
+                spin_until(&started, &stop);
+            })
+            .unwrap()
+    };
+    while !started.load(Ordering::Relaxed) || !blocked_started.load(Ordering::Relaxed) {
+        thread::yield_now();
+    }
+
+    let dump = backtrace::dump_all_threads_frozen();
+    // The threads carry on afterwards.
+    stop.store(true, Ordering::Relaxed);
+    handle.join().unwrap();
+    blocked.join().unwrap();
+
+    let target = dump
+        .iter()
+        .find(|thread| thread.name() == Some("frozen-target"))
+        .expect("spawned thread should be dumped");
+    let names = names(target.frames());
+    assert!(
+        names.iter().any(|name| name.contains("spin_until")),
+        "{:?}",
+        names
+    );
+    assert!(
+        !names
+            .iter()
+            .any(|name| name.contains("trace_unsynchronized_into")),
+        "{:?}",
+        names
+    );
+
+    // A thread blocking the signal is left out, without holding up the rest.
+    assert!(!dump
+        .iter()
+        .any(|thread| thread.name() == Some("frozen-blocked")));
+    let current = unsafe { libc::syscall(libc::SYS_gettid) as libc::pid_t };
+    assert!(dump.iter().any(|thread| thread.tid() == current));
+    assert!(dump.windows(2).all(|w| w[0].tid() < w[1].tid()));
+
+    // Frozen and ordinary dumps don't get in each other's way.
+    assert!(!backtrace::dump_all_threads().is_empty());
+}
@@ -0,0 +1,46 @@
+// In a test binary of its own, since the signal can only be picked once per
+// process.
+#![cfg(target_os = "linux")]
+
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::thread;
+
+#[test]
+fn uses_configured_signal() {
+    let default = backtrace::remote_thread_signal();
+    assert_eq!(default, libc::SIGRTMIN() + 3);
+    assert!(!backtrace::set_sampling_signal(libc::SIGUSR1));
+    assert!(!backtrace::set_sampling_signal(libc::SIGRTMAX() + 1));
+    assert_eq!(backtrace::remote_thread_signal(), default);
+
+    let signal = libc::SIGRTMIN() + 5;
+    assert!(backtrace::set_sampling_signal(signal));
+    assert_eq!(backtrace::remote_thread_signal(), signal);
+    assert!(!backtrace::set_sampling_signal(libc::SIGRTMIN() + 6));
+    assert_eq!(backtrace::remote_thread_signal(), signal);
+
+    // A thread blocking the default signal still answers on the new one.
+    let started = Arc::new(AtomicBool::new(false));
+    let stop = Arc::new(AtomicBool::new(false));
+    let handle = {
+        let (started, stop) = (started.clone(), stop.clone());
+        thread::spawn(move || unsafe {
+            let mut set = std::mem::zeroed();
+            libc::sigemptyset(&mut set);
+            libc::sigaddset(&mut set, default);
+            libc::pthread_sigmask(libc::SIG_BLOCK, &set, std::ptr::null_mut());
+            started.store(true, Ordering::Relaxed);
+            while !stop.load(Ordering::Relaxed) {
+                std::hint::spin_loop();
+            }
+        })
+    };
+    while !started.load(Ordering::Relaxed) {
+        thread::yield_now();
+    }
+    let frames = backtrace::thread_backtrace(&handle);
+    stop.store(true, Ordering::Relaxed);
+    handle.join().unwrap();
+    assert!(!frames.expect("thread should respond").is_empty());
+}
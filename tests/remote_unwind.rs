@@ -0,0 +1,157 @@
+// Unwinds this process's own stack "remotely", reading memory directly and
+// finding unwind tables through `dl_iterate_phdr`, as a core dump analyzer
+// would through the dump instead.
+#![cfg(all(target_os = "linux", target_arch = "x86_64"))]
+
+use backtrace::{RemoteAddressSpace, RemoteUnwindTable};
+use std::ffi::c_void;
+
+// `DW_EH_PE_udata4` and `DW_EH_PE_datarel | DW_EH_PE_sdata4`.
+const FDE_COUNT_ENC: u8 = 0x03;
+const TABLE_ENC: u8 = 0x3b;
+
+// The executable segments of each module along with their unwind tables.
+fn unwind_tables() -> Vec<RemoteUnwindTable> {
+    unsafe extern "C" fn callback(
+        info: *mut libc::dl_phdr_info,
+        _size: libc::size_t,
+        data: *mut c_void,
+    ) -> libc::c_int {
+        let tables = &mut *(data as *mut Vec<RemoteUnwindTable>);
+        let info = &*info;
+        let phdrs = std::slice::from_raw_parts(info.dlpi_phdr, info.dlpi_phnum as usize);
+        let hdr = match phdrs.iter().find(|p| p.p_type == libc::PT_GNU_EH_FRAME) {
+            Some(p) => info.dlpi_addr as usize + p.p_vaddr as usize,
+            None => return 0,
+        };
+        let header = std::slice::from_raw_parts(hdr as *const u8, 4);
+        if header[2] != FDE_COUNT_ENC || header[3] != TABLE_ENC {
+            return 0;
+        }
+        let fde_count = *((hdr + 8) as *const u32) as usize;
+        for p in phdrs {
+            if p.p_type == libc::PT_LOAD && p.p_flags & libc::PF_X != 0 {
+                let start = info.dlpi_addr as usize + p.p_vaddr as usize;
+                tables.push(RemoteUnwindTable {
+                    start_ip: start,
+                    end_ip: start + p.p_memsz as usize,
+                    segbase: hdr,
+                    table_data: hdr + 12,
+                    // Each entry is two 32-bit offsets, i.e. one word.
+                    table_len: fde_count,
+                });
+            }
+        }
+        0
+    }
+
+    let mut tables = Vec::new();
+    unsafe {
+        libc::dl_iterate_phdr(Some(callback), &mut tables as *mut _ as *mut c_void);
+    }
+    tables
+}
+
+// The readable mappings, so that reading anything else fails as it would in a
+// core dump which doesn't have it.
+fn readable_ranges() -> Vec<(usize, usize)> {
+    let maps = std::fs::read_to_string("/proc/self/maps").unwrap();
+    maps.lines()
+        .filter_map(|line| {
+            let mut fields = line.split_whitespace();
+            let range = fields.next()?;
+            if !fields.next()?.starts_with('r') {
+                return None;
+            }
+            let (start, end) = range.split_at(range.find('-')?);
+            let start = usize::from_str_radix(start, 16).ok()?;
+            let end = usize::from_str_radix(&end[1..], 16).ok()?;
+            Some((start, end))
+        })
+        .collect()
+}
+
+#[inline(never)]
+fn capture_and_unwind() -> Vec<usize> {
+    let tables = unwind_tables();
+    let readable = readable_ranges();
+    let space = RemoteAddressSpace::builder()
+        .find_proc_info(move |ip| {
+            tables
+                .iter()
+                .find(|t| t.start_ip <= ip && ip < t.end_ip)
+                .cloned()
+        })
+        .access_mem(move |addr| {
+            readable
+                .iter()
+                .any(|&(start, end)| start <= addr && addr + 8 <= end)
+                .then(|| unsafe { *(addr as *const usize) })
+        })
+        .build()
+        .unwrap();
+
+    // libunwind numbers x86_64's registers as DWARF does.
+    let regs = unsafe {
+        let mut context = std::mem::zeroed::<libc::ucontext_t>();
+        assert_eq!(libc::getcontext(&mut context), 0);
+        let gregs = &context.uc_mcontext.gregs;
+        let mut regs = [0; 17];
+        let order = [
+            libc::REG_RAX,
+            libc::REG_RDX,
+            libc::REG_RCX,
+            libc::REG_RBX,
+            libc::REG_RSI,
+            libc::REG_RDI,
+            libc::REG_RBP,
+            libc::REG_RSP,
+            libc::REG_R8,
+            libc::REG_R9,
+            libc::REG_R10,
+            libc::REG_R11,
+            libc::REG_R12,
+            libc::REG_R13,
+            libc::REG_R14,
+            libc::REG_R15,
+            libc::REG_RIP,
+        ];
+        for (reg, &greg) in regs.iter_mut().zip(order.iter()) {
+            *reg = gregs[greg as usize] as usize;
+        }
+        regs
+    };
+
+    let mut ips = Vec::new();
+    let mut with_info = 0;
+    backtrace::trace_remote(&space, &regs, |frame| {
+        ips.push(frame.ip() as usize);
+        if let Some(info) = frame.proc_info() {
+            assert_eq!(frame.symbol_address() as usize, info.start_ip);
+            with_info += 1;
+        }
+        true
+    })
+    .unwrap();
+    assert!(with_info > 0);
+    ips
+}
+
+#[test]
+fn unwinds_through_callbacks() {
+    let ips = capture_and_unwind();
+    let mut names = Vec::new();
+    for (i, &ip) in ips.iter().enumerate() {
+        // Return addresses point after the call.
+        let addr = if i == 0 { ip } else { ip - 1 };
+        backtrace::resolve(addr as *mut c_void, |symbol| {
+            if let Some(name) = symbol.name() {
+                names.push(name.to_string());
+            }
+        });
+    }
+    let position = |needle: &str| names.iter().position(|name| name.contains(needle));
+    let inner = position("capture_and_unwind").expect("missing the innermost frame");
+    let outer = position("unwinds_through_callbacks").expect("missing the caller");
+    assert!(inner < outer, "{:?}", names);
+}
@@ -0,0 +1,55 @@
+// Kept apart from `module_change`, which registers a hook, since this checks
+// that the caches notice libraries coming and going without any hooks.
+
+use std::ffi::c_void;
+
+// Loads `libdylib_dep.so`, returning it and where its `foo` is.
+fn load_foo(path: &std::path::Path) -> (libloading::Library, *mut c_void) {
+    unsafe {
+        let lib = libloading::Library::new(path).unwrap();
+        let foo = *lib.get::<*mut c_void>(b"foo\0").unwrap();
+        (lib, foo)
+    }
+}
+
+// Returns the names of the symbols the function starting at `addr` resolves
+// to, which `resolve` looks up one byte before the address it's given.
+fn names(addr: *mut c_void) -> Vec<String> {
+    let mut names = Vec::new();
+    backtrace::resolve((addr as usize + 1) as *mut c_void, |symbol| {
+        if let Some(name) = symbol.name() {
+            names.push(name.to_string());
+        }
+    });
+    names
+}
+
+#[test]
+fn resolves_after_dlopen_without_hooks() {
+    // Skip musl which is by default statically linked and doesn't support
+    // dynamic libraries, and anything without `dl_iterate_phdr`.
+    if !cfg!(target_os = "linux") || cfg!(target_env = "musl") {
+        return;
+    }
+
+    // Fill the cache of loaded libraries before the library is loaded.
+    assert!(!names(resolves_after_dlopen_without_hooks as *mut c_void).is_empty());
+
+    let mut path = std::env::current_exe().unwrap();
+    path.pop();
+    path.push("libdylib_dep.so");
+    let (lib, foo) = load_foo(&path);
+    assert_eq!(names(foo), ["foo"]);
+
+    drop(lib);
+    assert!(names(foo).is_empty());
+
+    // An address which resolved to nothing is remembered until libraries
+    // come or go. Loading the library again tends to put it back where it
+    // was the last time, as long as nothing else took its place meanwhile.
+    let (lib, foo) = load_foo(&path);
+    drop(lib);
+    assert!(names(foo).is_empty());
+    let (_lib, foo) = load_foo(&path);
+    assert_eq!(names(foo), ["foo"]);
+}
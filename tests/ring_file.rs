@@ -0,0 +1,109 @@
+#![cfg(unix)]
+
+use backtrace::{read_ring_file, Frame, RingFileSink};
+use std::path::PathBuf;
+
+fn temp_path(name: &str) -> PathBuf {
+    std::env::temp_dir().join(format!("backtrace-{}-{}", name, std::process::id()))
+}
+
+fn current_stack() -> Vec<Frame> {
+    let mut frames = Vec::new();
+    backtrace::trace(|frame| {
+        frames.push(frame.clone());
+        true
+    });
+    frames
+}
+
+#[test]
+fn keeps_the_most_recent_samples() {
+    let path = temp_path("ring");
+    let sink = RingFileSink::create(&path, 4, 4096).unwrap();
+    let frames = current_stack();
+    for _ in 0..6 {
+        assert!(sink.write_stack(&frames));
+    }
+    assert!(sink.capture());
+    assert_eq!(sink.written(), 7);
+    assert_eq!(sink.dropped(), 0);
+    drop(sink);
+
+    let samples = read_ring_file(&path).unwrap();
+    std::fs::remove_file(&path).unwrap();
+    let sequences = samples.iter().map(|s| s.sequence()).collect::<Vec<_>>();
+    assert_eq!(sequences, [4, 5, 6, 7]);
+
+    let mut packed = Vec::new();
+    backtrace::pack_stack(&frames, &mut packed);
+    let expected = backtrace::unpack_stack(&mut &packed[..]).unwrap();
+    for sample in &samples[..3] {
+        assert!(!sample.is_truncated());
+        assert_eq!(*sample.stack(), expected);
+    }
+    // The last one was captured by the sink itself.
+    assert!(samples[3].stack().frames.len() > 1);
+    assert!(!samples[3].is_truncated());
+}
+
+#[test]
+fn truncates_stacks_which_dont_fit() {
+    let path = temp_path("ring-truncated");
+    let frames = current_stack();
+    let mut packed = Vec::new();
+    backtrace::pack_stack(&frames, &mut packed);
+
+    let sink = RingFileSink::create(&path, 2, packed.len() - 1).unwrap();
+    assert!(!sink.write_packed(&packed));
+    assert_eq!(sink.dropped(), 1);
+    assert!(sink.write_stack(&frames));
+    drop(sink);
+
+    let samples = read_ring_file(&path).unwrap();
+    std::fs::remove_file(&path).unwrap();
+    assert_eq!(samples.len(), 1);
+    assert!(samples[0].is_truncated());
+    let stack = samples[0].stack();
+    assert!(stack.frames.len() < frames.len());
+    // The innermost frames are the ones kept.
+    let full = backtrace::unpack_stack(&mut &packed[..]).unwrap();
+    for (kept, frame) in stack.frames.iter().zip(&full.frames) {
+        assert_eq!(kept.address, frame.address);
+    }
+}
+
+#[test]
+fn rejects_other_files() {
+    let path = temp_path("not-a-ring");
+    std::fs::write(&path, vec![0; 256]).unwrap();
+    assert!(read_ring_file(&path).is_err());
+    std::fs::remove_file(&path).unwrap();
+
+    assert!(RingFileSink::create(&path, 0, 16).is_err());
+}
+
+#[test]
+fn survives_the_process_aborting() {
+    // Run again as a child which writes samples and then aborts.
+    if let Some(path) = std::env::var_os("RING_FILE_CHILD") {
+        let sink = RingFileSink::create(path, 8, 4096).unwrap();
+        for _ in 0..3 {
+            sink.capture();
+        }
+        std::process::abort();
+    }
+
+    let path = temp_path("ring-abort");
+    let output = std::process::Command::new(std::env::current_exe().unwrap())
+        .args(&["--exact", "survives_the_process_aborting"])
+        .env("RING_FILE_CHILD", &path)
+        .output()
+        .unwrap();
+    assert!(!output.status.success());
+
+    let samples = read_ring_file(&path).unwrap();
+    std::fs::remove_file(&path).unwrap();
+    let sequences = samples.iter().map(|s| s.sequence()).collect::<Vec<_>>();
+    assert_eq!(sequences, [1, 2, 3]);
+    assert!(samples.iter().all(|s| !s.stack().frames.is_empty()));
+}
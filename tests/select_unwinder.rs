@@ -0,0 +1,25 @@
+// The selection is cached by the first trace of the process, so this lives
+// in its own test binary.
+
+#[test]
+fn frame_pointer_unwinder_from_env() {
+    std::env::set_var("BACKTRACE_UNWINDER", "fp");
+
+    let mut frames = Vec::new();
+    backtrace::trace(|frame| {
+        frames.push((frame.ip(), frame.symbol_address()));
+        true
+    });
+    // Only the frame pointer walker can't find where functions start.
+    for (ip, symbol_address) in frames {
+        assert_eq!(ip, symbol_address);
+    }
+
+    // `Backtrace` goes through the loop-detecting trace, which honours the
+    // selection too.
+    let backtrace = backtrace::Backtrace::new_unresolved();
+    assert!(!backtrace.frames().is_empty());
+    for frame in backtrace.frames() {
+        assert_eq!(frame.ip(), frame.symbol_address());
+    }
+}
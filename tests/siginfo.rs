@@ -0,0 +1,142 @@
+// Crash reporters trace from within the handler of the fatal signal, where
+// the interesting stack is that of the code the signal interrupted.
+#![cfg(all(target_os = "linux", target_env = "gnu", target_arch = "x86_64"))]
+
+use std::ptr;
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+const MAX_FRAMES: usize = 128;
+
+static mut IPS: [usize; MAX_FRAMES] = [0; MAX_FRAMES];
+static LEN: AtomicUsize = AtomicUsize::new(0);
+static PC: AtomicUsize = AtomicUsize::new(0);
+
+extern "C" fn handler(_signum: libc::c_int, info: *mut libc::siginfo_t, ctx: *mut libc::c_void) {
+    unsafe {
+        let ctx = ctx as *mut libc::ucontext_t;
+        PC.store(
+            (*ctx).uc_mcontext.gregs[libc::REG_RIP as usize] as usize,
+            Ordering::SeqCst,
+        );
+        let ips = &mut *ptr::addr_of_mut!(IPS);
+        let mut len = 0;
+        backtrace::trace_from_siginfo(info, ctx, |frame| {
+            ips[len] = frame.ip() as usize;
+            len += 1;
+            len < ips.len()
+        });
+        LEN.store(len, Ordering::SeqCst);
+    }
+}
+
+#[inline(never)]
+fn crashing_function() {
+    unsafe {
+        assert_eq!(libc::raise(libc::SIGSEGV), 0);
+    }
+}
+
+#[test]
+fn trace_starts_at_interrupted_frame() {
+    unsafe {
+        let mut action: libc::sigaction = std::mem::zeroed();
+        action.sa_sigaction = handler as *const () as usize;
+        action.sa_flags = libc::SA_SIGINFO;
+        libc::sigemptyset(&mut action.sa_mask);
+        assert_eq!(libc::sigaction(libc::SIGSEGV, &action, ptr::null_mut()), 0);
+    }
+
+    crashing_function();
+
+    let len = LEN.load(Ordering::SeqCst);
+    let ips = unsafe { &(&*ptr::addr_of!(IPS))[..len] };
+    assert!(len > 1, "{:x?}", ips);
+    assert_eq!(ips[0], PC.load(Ordering::SeqCst));
+
+    let mut names = Vec::new();
+    for ip in ips {
+        backtrace::resolve(*ip as *mut _, |symbol| {
+            if let Some(name) = symbol.name() {
+                names.push(name.to_string());
+            }
+        });
+    }
+    assert!(
+        !names.iter().any(|name| name.contains("handler")),
+        "{:?}",
+        names
+    );
+    assert!(
+        names.iter().any(|name| name.contains("crashing_function")),
+        "{:?}",
+        names
+    );
+}
+
+// A function whose very first instruction faults, so that the interrupted
+// frame's `ip` is the start of the function and looking up the byte before it
+// would land in whatever precedes it.
+#[cfg(feature = "symtab-fallback")]
+std::arch::global_asm!(
+    ".text",
+    ".globl backtrace_test_ud2_at_start",
+    ".type backtrace_test_ud2_at_start,@function",
+    ".p2align 4",
+    "backtrace_test_ud2_at_start:",
+    ".cfi_startproc",
+    "ud2",
+    "ret",
+    ".cfi_endproc",
+    ".size backtrace_test_ud2_at_start, .-backtrace_test_ud2_at_start",
+);
+
+#[cfg(feature = "symtab-fallback")]
+extern "C" {
+    fn backtrace_test_ud2_at_start();
+}
+
+#[cfg(feature = "symtab-fallback")]
+static mut INTERRUPTED: Option<backtrace::Frame> = None;
+
+#[cfg(feature = "symtab-fallback")]
+extern "C" fn sigill_handler(
+    _signum: libc::c_int,
+    info: *mut libc::siginfo_t,
+    ctx: *mut libc::c_void,
+) {
+    unsafe {
+        let ctx = ctx as *mut libc::ucontext_t;
+        backtrace::trace_from_siginfo(info, ctx, |frame| {
+            *ptr::addr_of_mut!(INTERRUPTED) = Some(frame.clone());
+            false
+        });
+        // Carry on past the `ud2`.
+        (*ctx).uc_mcontext.gregs[libc::REG_RIP as usize] += 2;
+    }
+}
+
+// The assembly has no debug info, so only the symbol table can name it.
+#[test]
+#[cfg(feature = "symtab-fallback")]
+fn interrupted_frame_resolves_without_adjustment() {
+    unsafe {
+        let mut action: libc::sigaction = std::mem::zeroed();
+        action.sa_sigaction = sigill_handler as *const () as usize;
+        action.sa_flags = libc::SA_SIGINFO;
+        libc::sigemptyset(&mut action.sa_mask);
+        assert_eq!(libc::sigaction(libc::SIGILL, &action, ptr::null_mut()), 0);
+
+        backtrace_test_ud2_at_start();
+    }
+
+    let frame = unsafe { (*ptr::addr_of!(INTERRUPTED)).clone().unwrap() };
+    assert_eq!(
+        frame.ip() as usize,
+        backtrace_test_ud2_at_start as *const () as usize
+    );
+    let mut names = Vec::new();
+    backtrace::resolve_frame(&frame, |symbol| {
+        names.push(symbol.name().map(|name| name.to_string()));
+    });
+    assert_eq!(names, [Some("backtrace_test_ud2_at_start".to_string())]);
+}
@@ -0,0 +1,105 @@
+// Profilers capture stacks from within a `SIGPROF` handler, where only
+// async-signal-safe code may run: the handler here only writes into
+// preallocated storage, and everything is resolved after it returns.
+#![cfg(unix)]
+
+use std::ptr;
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+const MAX_FRAMES: usize = 128;
+
+static mut IPS: [*mut libc::c_void; MAX_FRAMES] = [ptr::null_mut(); MAX_FRAMES];
+static LEN: AtomicUsize = AtomicUsize::new(0);
+
+#[cfg(any(feature = "llvm-unwind", feature = "nongnu-unwind"))]
+static mut EXTERNAL_IPS: [usize; MAX_FRAMES] = [0; MAX_FRAMES];
+#[cfg(any(feature = "llvm-unwind", feature = "nongnu-unwind"))]
+static EXTERNAL_LEN: AtomicUsize = AtomicUsize::new(0);
+
+extern "C" fn handler(_signum: libc::c_int) {
+    unsafe {
+        let ips = &mut *ptr::addr_of_mut!(IPS);
+        let captured = backtrace::trace_unsynchronized_into(ips);
+        LEN.store(captured.len, Ordering::SeqCst);
+
+        #[cfg(any(feature = "llvm-unwind", feature = "nongnu-unwind"))]
+        {
+            let ips = &mut *ptr::addr_of_mut!(EXTERNAL_IPS);
+            let mut len = 0;
+            let res = backtrace::trace_unsynchronized_external_api(
+                |frame| {
+                    ips[len] = frame.ip() as usize;
+                    len += 1;
+                    len < ips.len()
+                },
+                true,
+            );
+            if res.is_ok() {
+                EXTERNAL_LEN.store(len, Ordering::SeqCst);
+            }
+        }
+    }
+}
+
+fn install_handler() {
+    unsafe {
+        let mut action: libc::sigaction = std::mem::zeroed();
+        action.sa_sigaction = handler as *const () as usize;
+        libc::sigemptyset(&mut action.sa_mask);
+        assert_eq!(libc::sigaction(libc::SIGPROF, &action, ptr::null_mut()), 0);
+    }
+}
+
+fn resolve_names(ips: &[usize]) -> Vec<String> {
+    let mut names = Vec::new();
+    for ip in ips {
+        backtrace::resolve(*ip as *mut _, |symbol| {
+            if let Some(name) = symbol.name() {
+                names.push(name.to_string());
+            }
+        });
+    }
+    names
+}
+
+#[inline(never)]
+fn profiled_function() {
+    unsafe {
+        assert_eq!(libc::raise(libc::SIGPROF), 0);
+    }
+}
+
+#[test]
+fn trace_from_sigprof_handler() {
+    install_handler();
+    // Make sure anything looked up lazily on first use, like the external
+    // unwinder's entry points, is looked up outside of the handler.
+    #[cfg(any(feature = "llvm-unwind", feature = "nongnu-unwind"))]
+    let _ = backtrace::UnwContext::zeroed();
+
+    profiled_function();
+
+    let len = LEN.load(Ordering::SeqCst);
+    assert!(len > 0);
+    let ips = unsafe { &(&*ptr::addr_of!(IPS))[..len] };
+    let ips = ips.iter().map(|ip| *ip as usize).collect::<Vec<_>>();
+    let names = resolve_names(&ips);
+    assert!(
+        names.iter().any(|name| name.contains("profiled_function")),
+        "{:?}",
+        names
+    );
+
+    #[cfg(any(feature = "llvm-unwind", feature = "nongnu-unwind"))]
+    {
+        let len = EXTERNAL_LEN.load(Ordering::SeqCst);
+        assert!(len > 0);
+        let ips = unsafe { &(&*ptr::addr_of!(EXTERNAL_IPS))[..len] };
+        let names = resolve_names(ips);
+        assert!(
+            names.iter().any(|name| name.contains("profiled_function")),
+            "{:?}",
+            names
+        );
+    }
+}
@@ -4,9 +4,9 @@ use backtrace::Backtrace;
 // function for frames which reports the starting address of a symbol. As a
 // result it's only enabled on a few platforms.
 const ENABLED: bool = cfg!(all(
-    // Windows hasn't really been tested, and OSX doesn't support actually
-    // finding an enclosing frame, so disable this
-    target_os = "linux",
+    // Windows hasn't really been tested, so disable this there. On macOS the
+    // enclosing function is found with `LC_FUNCTION_STARTS`.
+    any(target_os = "linux", target_os = "macos"),
     // On ARM finding the enclosing function is simply returning the ip itself.
     not(target_arch = "arm"),
 ));
@@ -321,3 +321,1707 @@ fn sp_smoke_test() {
         }
     }
 }
+
+#[test]
+#[cfg(target_os = "linux")]
+fn loaded_modules_contains_self() {
+    let addr = loaded_modules_contains_self as usize as *mut std::ffi::c_void;
+    let modules = backtrace::loaded_modules().collect::<Vec<_>>();
+    let module = modules
+        .iter()
+        .find(|m| m.contains(addr))
+        .expect("no module contains this function");
+    assert_eq!(
+        module.name().canonicalize().ok(),
+        std::env::current_exe().unwrap().canonicalize().ok()
+    );
+    assert!(modules
+        .iter()
+        .any(|m| m.name().to_string_lossy().contains("libc")));
+}
+
+#[test]
+#[cfg(any(target_arch = "x86_64", target_arch = "aarch64"))]
+fn thread_pointer_is_per_thread() {
+    let here = backtrace::current_thread_pointer();
+    assert_ne!(here, 0);
+    assert_eq!(here, backtrace::current_thread_pointer());
+    let there = thread::spawn(backtrace::current_thread_pointer)
+        .join()
+        .unwrap();
+    assert_ne!(here, there);
+}
+
+#[test]
+fn normal_stack_has_no_loop() {
+    assert!(!backtrace::Backtrace::new_unresolved().loop_detected());
+
+    let mut frames = 0;
+    let looped = backtrace::trace_detect_loops(|_| {
+        frames += 1;
+        true
+    });
+    assert!(!looped);
+    assert!(frames > 0);
+}
+
+#[test]
+fn unknown_address_resolves_repeatedly() {
+    // Somewhere that's certainly not in any loaded object.
+    let bogus = 0x10 as *mut std::ffi::c_void;
+    for _ in 0..2 {
+        let mut symbols = 0;
+        backtrace::resolve(bogus, |_| symbols += 1);
+        assert_eq!(symbols, 0);
+    }
+
+    // A real address still resolves, including after forgetting negatives.
+    let mut ip = std::ptr::null_mut();
+    backtrace::trace(|frame| {
+        ip = frame.ip();
+        false
+    });
+    for _ in 0..2 {
+        let mut symbols = 0;
+        backtrace::resolve(ip, |_| symbols += 1);
+        assert!(symbols > 0);
+        backtrace::clear_symbol_cache();
+    }
+}
+
+#[test]
+fn trace_into_reports_truncation() {
+    let mut buf = [std::ptr::null_mut(); backtrace::MAX_RECOMMENDED_FRAMES];
+    let full = backtrace::trace_into(&mut buf);
+    assert!(full.len > 1);
+    assert!(!full.truncated);
+
+    let mut small = [std::ptr::null_mut(); 1];
+    let result = backtrace::trace_into(&mut small);
+    assert_eq!(result.len, 1);
+    assert!(result.truncated);
+
+    let result = backtrace::trace_into(&mut []);
+    assert_eq!(result.len, 0);
+    assert!(result.truncated);
+}
+
+#[test]
+fn resolve_addr_is_not_adjusted() {
+    #[inline(never)]
+    fn target() {}
+    target();
+
+    let mut name = None;
+    backtrace::resolve_addr(target as usize, |symbol| {
+        if name.is_none() {
+            name = symbol.name().map(|n| n.to_string());
+        }
+    });
+    let name = name.expect("function pointer should resolve");
+    assert!(
+        name.contains("resolve_addr_is_not_adjusted::target"),
+        "{}",
+        name
+    );
+}
+
+#[test]
+fn gdb_format() {
+    use backtrace::{Backtrace, GdbFmt};
+
+    let backtrace = Backtrace::new();
+    let frame = &backtrace.frames()[0];
+    let line = GdbFmt::new(0, frame).to_string();
+    let ip = format!("{:#018x}", frame.ip() as usize);
+    assert!(line.starts_with(&format!("#0  {} in ", ip)), "{}", line);
+    assert!(line.contains(" () at "), "{}", line);
+    let symbol = &frame.symbols()[0];
+    if let (Some(lineno), Some(colno)) = (symbol.lineno(), symbol.colno()) {
+        let location = format!(":{}:{}", lineno, colno);
+        assert!(
+            line.lines().next().unwrap().ends_with(&location),
+            "{}",
+            line
+        );
+    }
+
+    let unresolved = Backtrace::new_unresolved();
+    let line = GdbFmt::new(12, &unresolved.frames()[0]).to_string();
+    assert!(line.starts_with("#12 0x"), "{}", line);
+    assert!(line.ends_with(" in ?? ()"), "{}", line);
+}
+
+#[test]
+fn addr2line_format() {
+    use backtrace::Backtrace;
+
+    let backtrace = Backtrace::new();
+    let frame = &backtrace.frames()[0];
+    let mut out = Vec::new();
+    backtrace::format_addr2line_style(&backtrace.frames()[..1], &mut out).unwrap();
+    let out = String::from_utf8(out).unwrap();
+    let lines = out.lines().collect::<Vec<_>>();
+    assert_eq!(lines.len(), 2, "{}", out);
+    let symbol = &frame.symbols()[0];
+    assert_eq!(lines[0], format!("{:#}", symbol.name().unwrap()));
+    if let (Some(file), Some(lineno)) = (symbol.filename(), symbol.lineno()) {
+        assert_eq!(lines[1], format!("{}:{}", file.display(), lineno));
+    }
+
+    let unresolved = Backtrace::new_unresolved();
+    let mut out = Vec::new();
+    backtrace::format_addr2line_style(&unresolved.frames()[..1], &mut out).unwrap();
+    assert_eq!(out, b"??\n??:0\n");
+}
+
+#[test]
+fn gdb_format_truncates_names() {
+    use backtrace::{Backtrace, GdbFmt};
+
+    let backtrace = Backtrace::new();
+    let frame = &backtrace.frames()[0];
+    let name = format!("{:#}", frame.symbols()[0].name().unwrap());
+    assert!(name.chars().count() > 12, "{}", name);
+    let line = GdbFmt::new(0, frame).max_name_len(12).to_string();
+    let line = line.lines().next().unwrap();
+    let start = line.find(" in ").unwrap() + 4;
+    let end = line.find(" ()").unwrap();
+    let truncated = &line[start..end];
+    assert_eq!(truncated.chars().count(), 12, "{}", line);
+    assert!(truncated.contains("..."), "{}", line);
+    assert!(name.ends_with(&truncated[truncated.find("...").unwrap() + 3..]));
+}
+
+#[test]
+fn trace_ordered_reverses() {
+    use backtrace::FrameOrder;
+
+    let mut inner = Vec::new();
+    backtrace::trace_ordered(FrameOrder::InnermostFirst, |frame| {
+        inner.push(frame.sp() as usize);
+        true
+    });
+    let mut outer = Vec::new();
+    backtrace::trace_ordered(FrameOrder::OutermostFirst, |frame| {
+        outer.push(frame.sp() as usize);
+        true
+    });
+
+    // The innermost frames differ since they're in different calls to
+    // `trace_ordered`, but everything further out is the same.
+    outer.reverse();
+    let n = 3;
+    assert!(inner.len() > n && outer.len() > n);
+    assert_eq!(inner[inner.len() - n..], outer[outer.len() - n..]);
+}
+
+#[test]
+fn trace_status_reports_outcome() {
+    use backtrace::TraceOutcome;
+
+    let mut frames = 0;
+    let outcome = backtrace::trace_status(|_| {
+        frames += 1;
+        frames < 2
+    });
+    assert_eq!(outcome, TraceOutcome::StoppedByCallback);
+    assert_eq!(frames, 2);
+
+    let outcome = backtrace::trace_status(|_| true);
+    assert_ne!(outcome, TraceOutcome::StoppedByCallback);
+}
+
+#[test]
+fn trace_debug_reports_reason_codes() {
+    use backtrace::TraceOutcome;
+
+    const NO_REASON: i32 = 0;
+    const FAILURE: i32 = 9;
+    const END_OF_STACK: i32 = 5;
+
+    let mut frames = Vec::new();
+    let mut steps = Vec::new();
+    let mut ends = Vec::new();
+    let outcome = backtrace::trace_debug(
+        |frame| {
+            frames.push(frame.ip() as usize);
+            true
+        },
+        |frame, code| match frame {
+            Some(frame) => steps.push((frame.ip() as usize, code)),
+            None => ends.push(code),
+        },
+    );
+    assert_ne!(outcome, TraceOutcome::StoppedByCallback);
+    if cfg!(any(windows, miri)) {
+        assert!(steps.is_empty() && ends.is_empty());
+        return;
+    }
+
+    // Every frame is reported with the code to carry on.
+    assert_eq!(ends.len(), 1);
+    assert!(
+        steps.iter().all(|&(_, code)| code == NO_REASON),
+        "{:?}",
+        steps
+    );
+    let reported = steps.iter().map(|&(ip, _)| ip).collect::<Vec<_>>();
+    assert_eq!(reported, frames);
+    match outcome {
+        TraceOutcome::Complete => assert_eq!(ends[0], END_OF_STACK),
+        TraceOutcome::UnwinderError(code) => assert_eq!(ends[0], code),
+        outcome => panic!("{:?}", outcome),
+    }
+
+    // Asking to stop is handed back as a failure.
+    let mut codes = Vec::new();
+    let outcome = backtrace::trace_debug(|_| false, |_, code| codes.push(code));
+    assert_eq!(outcome, TraceOutcome::StoppedByCallback);
+    assert_eq!(codes.len(), 2);
+    assert_eq!(codes[0], FAILURE);
+}
+
+#[test]
+fn trace_with_end_marks_end_of_stack() {
+    let mut frames = 0;
+    let mut ends = 0;
+    backtrace::trace_with_end(|frame| {
+        match frame {
+            Some(_) => {
+                assert_eq!(ends, 0);
+                frames += 1;
+            }
+            None => ends += 1,
+        }
+        true
+    });
+    assert!(frames > 0);
+    assert_eq!(ends, 1);
+
+    // Stopping early isn't the end of the stack.
+    let mut calls = Vec::new();
+    backtrace::trace_with_end(|frame| {
+        calls.push(frame.is_some());
+        false
+    });
+    assert_eq!(calls, [true]);
+}
+
+#[test]
+fn trace_options_combine() {
+    use backtrace::{FrameOrder, TraceOptions};
+
+    fn sps(options: TraceOptions) -> Vec<usize> {
+        let mut sps = Vec::new();
+        options.capture(|frame| {
+            sps.push(frame.sp() as usize);
+            true
+        });
+        sps
+    }
+
+    // All the captures are from the same function, so they only differ in
+    // the frames left out.
+    let all = sps(TraceOptions::new().validate(true));
+    assert!(all.len() > 4);
+    let some = sps(TraceOptions::new().validate(true).skip(1).max_frames(2));
+    assert_eq!(some, all[1..3]);
+    assert!(sps(TraceOptions::new().max_frames(0)).is_empty());
+    let mut outer = sps(TraceOptions::new()
+        .validate(true)
+        .skip(1)
+        .order(FrameOrder::OutermostFirst));
+    outer.reverse();
+    assert_eq!(outer, all[1..]);
+}
+
+#[test]
+#[cfg(all(
+    any(target_os = "linux", target_os = "android"),
+    any(target_arch = "x86_64", target_arch = "aarch64"),
+))]
+fn trace_with_timeout_gives_up() {
+    use backtrace::TraceOutcome;
+    use std::time::Duration;
+
+    let mut frames = 0;
+    let outcome = backtrace::trace_with_timeout(Duration::from_secs(60), |_| {
+        frames += 1;
+        true
+    });
+    assert_ne!(outcome, TraceOutcome::TimedOut);
+    assert!(frames > 1);
+
+    // The timeout expires while the first frame is being handled, so the
+    // trace ends before the second.
+    let mut frames = 0;
+    let outcome = backtrace::trace_with_timeout(Duration::from_millis(10), |_| {
+        frames += 1;
+        thread::sleep(Duration::from_millis(100));
+        true
+    });
+    assert_eq!(outcome, TraceOutcome::TimedOut);
+    assert_eq!(frames, 1);
+}
+
+#[test]
+fn ordinary_frames_are_not_thunks() {
+    let mut frames = 0;
+    backtrace::trace(|frame| {
+        assert!(!frame.is_thunk(), "{:?}", frame);
+        frames += 1;
+        true
+    });
+    assert!(frames > 0);
+}
+
+#[test]
+fn trace_catch_returns_panic() {
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    let frames = AtomicUsize::new(0);
+    let result = backtrace::trace_catch(|_| {
+        frames.fetch_add(1, Ordering::Relaxed);
+        true
+    });
+    assert!(result.is_ok());
+    assert!(frames.load(Ordering::Relaxed) > 1);
+
+    let frames = AtomicUsize::new(0);
+    let result = backtrace::trace_catch(|_| {
+        if frames.fetch_add(1, Ordering::Relaxed) == 1 {
+            panic!("second frame");
+        }
+        true
+    });
+    assert_eq!(frames.load(Ordering::Relaxed), 2);
+    let payload = result.unwrap_err();
+    assert_eq!(payload.downcast_ref::<&str>(), Some(&"second frame"));
+}
+
+#[test]
+fn sample_aggregator_merges_same_functions() {
+    use backtrace::{Backtrace, SampleAggregator};
+
+    let mut aggregator = SampleAggregator::new();
+    for _ in 0..2 {
+        aggregator.add(Backtrace::new_unresolved());
+    }
+    // Captured from a different ip within this same function.
+    aggregator.add(Backtrace::new_unresolved());
+    aggregator.add_weighted(Vec::new(), 4);
+
+    assert_eq!(aggregator.total(), 7);
+    let weighted = aggregator.into_weighted();
+    let counts = weighted.iter().map(|(_, count)| *count).collect::<Vec<_>>();
+    assert_eq!(counts, [3, 4]);
+    assert!(weighted[1].0.is_empty());
+}
+
+#[test]
+fn sample_aggregator_flushes_windows() {
+    use backtrace::{Backtrace, SampleAggregator};
+
+    let mut aggregator = SampleAggregator::new();
+    aggregator.add_weighted(Backtrace::new_unresolved(), 2);
+    aggregator.add_weighted(Vec::new(), 3);
+    let first = aggregator.flush_partial();
+    assert_eq!(first.iter().map(|(_, count)| *count).sum::<u64>(), 5);
+    assert!(aggregator.is_empty());
+    assert_eq!(aggregator.total(), 0);
+
+    aggregator.add(Vec::new());
+    let second = aggregator.flush_partial();
+    assert_eq!(second.len(), 1);
+    assert!(second[0].0.is_empty());
+    assert_eq!(second[0].1, 1);
+}
+
+#[test]
+fn trace_timed_delivers_frames() {
+    let mut frames = 0;
+    let elapsed = backtrace::trace_timed(|_| {
+        frames += 1;
+        true
+    });
+    assert!(frames > 0);
+    assert!(elapsed < std::time::Duration::from_secs(60));
+}
+
+#[test]
+fn raw_unwind_context_only_while_tracing() {
+    let mut cloned = None;
+    backtrace::trace(|frame| {
+        #[cfg(all(unix, not(target_os = "emscripten")))]
+        assert!(unsafe { frame.raw_unwind_context() }.is_some());
+        cloned = Some(frame.clone());
+        false
+    });
+    assert!(unsafe { cloned.unwrap().raw_unwind_context() }.is_none());
+}
+
+#[test]
+fn module_relative_address() {
+    backtrace::trace(|frame| {
+        match frame.module_base_address() {
+            Some(base) => {
+                let offset = frame.module_relative_address().unwrap();
+                assert_eq!(base as usize + offset, frame.ip() as usize);
+            }
+            None => assert_eq!(frame.module_relative_address(), None),
+        }
+        true
+    });
+}
+
+#[test]
+#[cfg(feature = "fallback-unwind")]
+fn trace_with_fallback_complete_stack() {
+    use backtrace::{TraceOutcome, UnwindBackend};
+
+    let mut frames = 0;
+    let trace = backtrace::trace_with_fallback(|_| {
+        frames += 1;
+        true
+    });
+    assert!(frames > 0);
+    if trace.backend == UnwindBackend::Default {
+        assert_ne!(trace.outcome, TraceOutcome::StoppedByCallback);
+    }
+}
+
+#[test]
+fn frames_in_main_executable() {
+    let mut in_main = Vec::new();
+    backtrace::trace(|frame| {
+        in_main.push(frame.is_in_main_executable());
+        true
+    });
+    // This test is linked into the test executable itself, and runs on a
+    // thread started by libc.
+    if cfg!(all(target_os = "linux", target_env = "gnu")) {
+        assert!(in_main.iter().any(|x| *x), "{:?}", in_main);
+        assert!(in_main.iter().any(|x| !*x), "{:?}", in_main);
+    }
+}
+
+#[test]
+fn resolve_iter_matches_resolve() {
+    let mut ip = None;
+    backtrace::trace(|frame| {
+        ip = Some(frame.ip());
+        false
+    });
+    let ip = ip.unwrap();
+
+    let mut names = Vec::new();
+    backtrace::resolve(ip, |symbol| {
+        names.push(symbol.name().map(|name| name.to_string()))
+    });
+    let iter_names = backtrace::resolve_iter(ip)
+        .map(|symbol| symbol.name().map(|name| name.to_string()))
+        .collect::<Vec<_>>();
+    assert_eq!(names, iter_names);
+}
+
+#[test]
+fn clone_keeps_symbol_address() {
+    let mut frames = Vec::new();
+    backtrace::trace(|frame| {
+        frames.push((frame.clone(), frame.ip(), frame.symbol_address()));
+        true
+    });
+    assert!(!frames.is_empty());
+    for (clone, ip, symbol_address) in frames {
+        assert_eq!(clone.ip(), ip);
+        assert_eq!(clone.symbol_address(), symbol_address);
+        assert_eq!(clone.clone().symbol_address(), symbol_address);
+    }
+}
+
+#[test]
+#[cfg(all(
+    any(target_os = "linux", target_os = "android"),
+    any(target_arch = "x86_64", target_arch = "aarch64"),
+))]
+fn trace_on_altstack_reaches_caller() {
+    let mut expected = Vec::new();
+    backtrace::trace(|frame| {
+        expected.push(frame.symbol_address() as usize);
+        true
+    });
+
+    let mut stack = vec![0u8; 256 * 1024];
+    let range = stack.as_ptr() as usize..stack.as_ptr() as usize + stack.len();
+    let mut on_altstack = false;
+    let mut actual = Vec::new();
+    unsafe {
+        backtrace::trace_on_altstack(&mut stack, |frame| {
+            on_altstack |= range.contains(&(frame.sp() as usize));
+            actual.push(frame.symbol_address() as usize);
+            true
+        });
+    }
+    assert!(on_altstack);
+    // Past the frames of the unwinding machinery, which differ, the stacks are
+    // the same all the way out.
+    let this_fn = expected[..].iter().position(|x| actual.contains(x)).unwrap();
+    let tail = &expected[this_fn..];
+    assert!(tail.len() > 1);
+    assert!(actual.ends_with(tail), "{:x?}\n{:x?}", expected, actual);
+}
+
+#[test]
+#[cfg_attr(any(windows, miri), ignore)]
+#[rustfmt::skip] // we care about line numbers here
+fn inlined_call_location() {
+    #[inline(always)]
+    fn inlined() -> Vec<*mut std::ffi::c_void> {
+        let mut ips = Vec::new();
+        backtrace::trace(|frame| {
+            ips.push(frame.ip());
+            true
+        });
+        ips
+    }
+    #[inline(never)]
+    fn caller() -> (Vec<*mut std::ffi::c_void>, u32) {
+        let line = line!(); let ips = inlined();
+        (ips, line)
+    }
+
+    let (ips, line) = caller();
+    let mut found = None;
+    for ip in ips {
+        let mut symbols = Vec::new();
+        backtrace::resolve(ip, |symbol| {
+            symbols.push((
+                symbol.name().map(|name| name.to_string()),
+                symbol.lineno(),
+                symbol.call_lineno(),
+                symbol.call_filename().map(|path| path.to_owned()),
+                symbol.inline_depth(),
+            ));
+        });
+        let i = symbols.iter().position(|s| {
+            s.0.as_deref()
+                .map_or(false, |n| n.contains("inlined_call_location::inlined"))
+        });
+        if let Some(i) = i {
+            found = Some((symbols, i));
+            break;
+        }
+    }
+    let (symbols, i) = found.unwrap();
+    assert_eq!(symbols[i].2, Some(line), "{:?}", symbols);
+    assert!(symbols[i].3.as_ref().unwrap().ends_with("smoke.rs"));
+    assert_eq!(symbols[i + 1].1, Some(line));
+    for pair in symbols.windows(2) {
+        assert_eq!(pair[0].2, pair[1].1);
+    }
+    assert_eq!(symbols.last().unwrap().2, None);
+    // Innermost first, counting down to the function the code is in.
+    for (depth, symbol) in symbols.iter().rev().enumerate() {
+        assert_eq!(symbol.4, depth, "{:?}", symbols);
+    }
+    assert!(symbols[i].4 > 0);
+}
+
+#[test]
+#[cfg_attr(any(windows, miri), ignore)]
+fn inlined_backtrace_symbols_are_distinct() {
+    use std::collections::HashSet;
+
+    #[inline(always)]
+    fn inlined() -> backtrace::Backtrace {
+        backtrace::Backtrace::new()
+    }
+    #[inline(never)]
+    fn caller() -> backtrace::Backtrace {
+        let backtrace = inlined();
+        // Keeps this from being a tail call, which would leave no frame.
+        assert!(!backtrace.frames().is_empty());
+        backtrace
+    }
+
+    let backtrace = caller();
+    let frame = backtrace.frames().iter().find(|frame| {
+        frame.symbols().iter().any(|symbol| {
+            symbol.name().map_or(false, |name| {
+                name.to_string()
+                    .contains("inlined_backtrace_symbols_are_distinct::inlined")
+            })
+        })
+    });
+    // Without debug info there's nothing to tell inlined calls by.
+    let frame = match frame {
+        Some(frame) => frame,
+        None if !cfg!(debug_assertions) => return,
+        None => panic!("{:?}", backtrace),
+    };
+    let symbols = frame.symbols();
+    assert!(symbols.len() > 1, "{:?}", symbols);
+    for (depth, symbol) in symbols.iter().rev().enumerate() {
+        assert_eq!(symbol.inline_depth(), depth, "{:?}", symbols);
+        assert_eq!(symbol.addr(), symbols[0].addr());
+        assert_eq!(symbol, &symbol.clone());
+    }
+    // Sharing an address doesn't make them the same.
+    let distinct = symbols.iter().collect::<HashSet<_>>();
+    assert_eq!(distinct.len(), symbols.len());
+}
+
+#[test]
+fn prewarm_then_resolve() {
+    let mut ips = Vec::new();
+    backtrace::trace(|frame| {
+        ips.push(frame.ip() as usize);
+        true
+    });
+    backtrace::prewarm(&ips);
+    backtrace::prewarm(&[0, 1, usize::max_value()]);
+
+    let mut names = 0;
+    for ip in ips {
+        backtrace::resolve(ip as *mut _, |symbol| {
+            if symbol.name().is_some() {
+                names += 1;
+            }
+        });
+    }
+    assert!(names > 0);
+}
+
+#[test]
+fn thread_entry_frame() {
+    std::thread::spawn(|| {
+        let mut all = Vec::new();
+        let status = backtrace::trace_status(|frame| {
+            all.push(frame.ip() as usize);
+            true
+        });
+        let mut trimmed = Vec::new();
+        backtrace::trace_without_thread_entry(|frame| {
+            trimmed.push(frame.ip() as usize);
+            true
+        });
+
+        // `trace` passes on the null frame libgcc reports past the base of
+        // the stack, which is left out along with the outermost frame.
+        all.retain(|ip| *ip != 0);
+        if status == backtrace::TraceOutcome::Complete {
+            // The frames this thread was started with are the same, other
+            // than the very last one.
+            assert_eq!(trimmed.len() + 1, all.len());
+            let n = trimmed.len();
+            assert_eq!(trimmed[n - 3..], all[n - 3..n], "{:x?} {:x?}", all, trimmed);
+        } else {
+            assert_eq!(trimmed.len(), all.len());
+        }
+    })
+    .join()
+    .unwrap();
+}
+
+#[test]
+fn lsda_of_frames_with_cleanups() {
+    #[inline(never)]
+    fn with_cleanup(cb: &mut dyn FnMut()) {
+        // Dropping this while unwinding needs a landing pad.
+        let _guard = String::from("cleanup");
+        cb();
+    }
+
+    let mut default = Vec::new();
+    with_cleanup(&mut || {
+        backtrace::trace(|frame| {
+            default.push(frame.lsda());
+            true
+        });
+    });
+    assert!(default.iter().all(|lsda| lsda.is_none()));
+
+    #[cfg(any(feature = "llvm-unwind", feature = "nongnu-unwind"))]
+    {
+        let mut external = Vec::new();
+        with_cleanup(&mut || unsafe {
+            let res = backtrace::trace_unsynchronized_external_api(
+                |frame| {
+                    external.push(frame.lsda());
+                    true
+                },
+                false,
+            );
+            assert!(res.is_ok());
+        });
+        assert!(external.iter().any(|lsda| lsda.is_some()));
+    }
+}
+
+#[test]
+#[cfg_attr(any(windows, miri), ignore)]
+fn cache_stats_count_lookups() {
+    let before = backtrace::cache_stats();
+    backtrace::resolve_addr(cache_stats_count_lookups as usize, |_| {});
+    let after = backtrace::cache_stats();
+    let lookups = |c: backtrace::CacheCounters| c.hits + c.misses;
+    assert!(lookups(after.modules) > lookups(before.modules));
+    assert!(lookups(after.symbols) > lookups(before.symbols));
+
+    backtrace::clear_symbol_cache();
+    assert!(backtrace::cache_stats().symbols.invalidations > after.symbols.invalidations);
+}
+
+#[test]
+fn caller_ip_is_next_frames_ip() {
+    backtrace::trace(|frame| {
+        assert_eq!(frame.caller_ip(), None);
+        true
+    });
+
+    #[cfg(any(feature = "llvm-unwind", feature = "nongnu-unwind"))]
+    {
+        let mut frames = Vec::new();
+        unsafe {
+            let res = backtrace::trace_unsynchronized_external_api(
+                |frame| {
+                    frames.push((frame.ip() as usize, frame.caller_ip()));
+                    true
+                },
+                false,
+            );
+            assert!(res.is_ok());
+        }
+        assert!(frames.len() > 1);
+        for pair in frames.windows(2) {
+            assert_eq!(pair[0].1, Some(pair[1].0));
+        }
+    }
+}
+
+#[test]
+fn frame_size_is_distance_to_callers_sp() {
+    backtrace::trace(|frame| {
+        assert_eq!(frame.frame_size(), None);
+        true
+    });
+
+    #[cfg(any(feature = "llvm-unwind", feature = "nongnu-unwind"))]
+    {
+        let mut frames = Vec::new();
+        unsafe {
+            let res = backtrace::trace_unsynchronized_external_api(
+                |frame| {
+                    frames.push((frame.sp() as usize, frame.frame_size()));
+                    true
+                },
+                false,
+            );
+            assert!(res.is_ok());
+        }
+        assert!(frames.len() > 1);
+        assert_eq!(frames.last().unwrap().1, None);
+        for pair in frames.windows(2) {
+            assert_eq!(pair[0].1, Some(pair[1].0 - pair[0].0));
+        }
+    }
+}
+
+#[test]
+#[cfg(target_os = "linux")]
+fn symbolizer_from_own_executable() {
+    #[inline(never)]
+    fn target() {}
+
+    let addr = target as usize;
+    let module = backtrace::loaded_modules()
+        .find(|m| m.contains(addr as *mut _))
+        .expect("no module contains this function");
+    let mut symbolizer = backtrace::Symbolizer::from_file(module.name(), module.bias())
+        .expect("failed to parse own executable");
+
+    let mut live = Vec::new();
+    backtrace::resolve_addr(addr, |symbol| {
+        live.push(symbol.name().map(|n| n.to_string()))
+    });
+    let mut offline = Vec::new();
+    symbolizer.resolve_addr(addr, |symbol| {
+        offline.push(symbol.name().map(|n| n.to_string()))
+    });
+    assert!(!offline.is_empty());
+    assert_eq!(live, offline);
+    assert!(offline[0].as_ref().unwrap().contains("target"));
+
+    // Stated addresses are looked up with a zero bias.
+    let mut stated = Vec::new();
+    let mut symbolizer = backtrace::Symbolizer::from_file(module.name(), 0).unwrap();
+    symbolizer.resolve_addr(addr - module.bias(), |symbol| {
+        stated.push(symbol.name().map(|n| n.to_string()))
+    });
+    assert_eq!(stated, offline);
+
+    assert!(backtrace::Symbolizer::from_file("/nonexistent", 0).is_none());
+}
+
+#[test]
+#[cfg(target_os = "linux")]
+fn symbolizer_for_build_id_routes_one_module() {
+    #[inline(never)]
+    fn target() {}
+
+    let names = |symbolizer: &mut backtrace::Symbolizer, addr: usize| {
+        let mut names = Vec::new();
+        symbolizer.resolve_addr(addr, |symbol| {
+            names.push(symbol.name().map(|n| n.to_string()))
+        });
+        names
+    };
+    let live = |addr: usize| {
+        let mut names = Vec::new();
+        backtrace::resolve_addr(addr, |symbol| {
+            names.push(symbol.name().map(|n| n.to_string()))
+        });
+        names
+    };
+    let module = |addr: usize| {
+        backtrace::loaded_modules()
+            .find(|m| m.contains(addr as *mut _))
+            .unwrap()
+    };
+    let addr = target as usize;
+    let exe = module(addr);
+    let malloc = libc::malloc as usize;
+    let libc = module(malloc);
+
+    // Standing in for the executable with its own file changes nothing, and
+    // addresses elsewhere are resolved as usual.
+    let mut symbolizer =
+        backtrace::Symbolizer::for_build_id(exe.build_id().unwrap(), exe.name()).unwrap();
+    assert_eq!(names(&mut symbolizer, addr), live(addr));
+    assert_eq!(names(&mut symbolizer, malloc), live(malloc));
+
+    // Addresses in libc are looked up in whatever file stands in for it. libc
+    // usually has no debug info, so `malloc` is only named, and the two files
+    // only told apart, by its symbol table.
+    let mut symbolizer =
+        backtrace::Symbolizer::for_build_id(libc.build_id().unwrap(), exe.name()).unwrap();
+    assert_eq!(names(&mut symbolizer, addr), live(addr));
+    if cfg!(feature = "symtab-fallback") {
+        assert_ne!(names(&mut symbolizer, malloc), live(malloc));
+    }
+
+    assert!(backtrace::Symbolizer::for_build_id(&[1, 2, 3], "/nonexistent").is_none());
+}
+
+#[test]
+#[cfg(unix)]
+fn trace_filtered_skips_frames() {
+    // Traces from within a `qsort` callback, so that there are frames in
+    // libc between frames in this executable.
+    extern "C" fn compare(a: *const libc::c_void, b: *const libc::c_void) -> libc::c_int {
+        let mut all = Vec::new();
+        backtrace::trace(|frame| {
+            all.push((frame.ip() as usize, frame.is_in_main_executable()));
+            true
+        });
+        let mut filtered = Vec::new();
+        backtrace::trace_filtered(
+            |frame| frame.is_in_main_executable(),
+            |frame| {
+                assert!(frame.is_in_main_executable());
+                filtered.push(frame.ip() as usize);
+                true
+            },
+        );
+        RESULT.with(|r| *r.borrow_mut() = Some((all, filtered)));
+        unsafe { (*(a as *const u32)).cmp(&*(b as *const u32)) as libc::c_int }
+    }
+
+    thread_local!(static RESULT: std::cell::RefCell<Option<(Vec<(usize, bool)>, Vec<usize>)>> =
+        std::cell::RefCell::new(None));
+
+    let mut values = [2u32, 1];
+    unsafe {
+        libc::qsort(values.as_mut_ptr().cast(), 2, 4, Some(compare));
+    }
+    let (all, filtered) = RESULT.with(|r| r.borrow_mut().take()).unwrap();
+
+    if cfg!(all(target_os = "linux", target_env = "gnu")) {
+        // The first frame in this executable past qsort's frames in libc.
+        let libc = all
+            .iter()
+            .position(|&(_, main)| !main)
+            .expect("no frames in libc");
+        let (outer, _) = all[libc..].iter().find(|&&(_, main)| main).unwrap();
+        assert!(filtered.contains(outer));
+    }
+
+    let mut first = 0;
+    backtrace::trace_filtered(
+        |_| true,
+        |_| {
+            first += 1;
+            false
+        },
+    );
+    assert_eq!(first, 1);
+}
+
+#[test]
+#[cfg(all(target_arch = "arm", not(target_os = "ios")))]
+fn arm_addresses_have_no_thumb_bit() {
+    #[inline(never)]
+    fn thumb_target() -> Vec<(usize, usize)> {
+        let mut addrs = Vec::new();
+        backtrace::trace(|frame| {
+            addrs.push((frame.ip() as usize, frame.symbol_address() as usize));
+            true
+        });
+        addrs
+    }
+
+    let addrs = thumb_target();
+    assert!(!addrs.is_empty());
+    for (ip, symbol_address) in addrs {
+        assert_eq!(ip & 1, 0, "{:#x}", ip);
+        assert_eq!(symbol_address & 1, 0, "{:#x}", symbol_address);
+    }
+
+    // Functions start where the symbol table says, once the Thumb bit of the
+    // function pointer is dropped.
+    let start = thumb_target as *const () as usize & !1;
+    let mut name = None;
+    backtrace::resolve_addr(start, |symbol| {
+        name = symbol.name().map(|name| name.to_string());
+    });
+    let name = name.expect("function start should resolve");
+    assert!(name.contains("thumb_target"), "{}", name);
+}
+
+#[test]
+#[cfg(all(target_os = "linux", target_env = "gnu"))]
+fn symtab_fallback_gives_offset() {
+    // libc is rarely shipped with debug info, but exports `qsort`.
+    let addr = libc::qsort as *const () as usize + 4;
+    let mut found = None;
+    backtrace::resolve_addr(addr, |symbol| {
+        if symbol.filename().is_none() {
+            found = Some((
+                symbol.name().map(|name| name.to_string()),
+                symbol.symbol_offset(),
+            ));
+        }
+    });
+    let (name, offset) = match found {
+        Some(found) => found,
+        // Debug info was found for libc after all.
+        None => return,
+    };
+    if cfg!(feature = "symtab-fallback") {
+        assert!(name.unwrap().contains("qsort"));
+        assert_eq!(offset, Some(4));
+    } else {
+        assert_eq!(offset, None);
+    }
+}
+
+#[test]
+fn resolve_stack_deadline_stops_at_deadline() {
+    use std::time::{Duration, Instant};
+
+    let mut frames = Vec::new();
+    backtrace::trace(|frame| {
+        frames.push(frame.clone());
+        true
+    });
+    assert!(!frames.is_empty());
+
+    // With the deadline already passed, every frame is reported unresolved.
+    let mut unresolved = Vec::new();
+    let resolved = backtrace::resolve_stack_deadline(&frames, Instant::now(), |frame, symbol| {
+        assert!(symbol.is_none());
+        unresolved.push(frame.ip());
+    });
+    assert_eq!(resolved, 0);
+    assert_eq!(
+        unresolved,
+        frames.iter().map(|frame| frame.ip()).collect::<Vec<_>>()
+    );
+
+    // With plenty of time, the symbols are the same as from `resolve_frame`.
+    let deadline = Instant::now() + Duration::from_secs(3600);
+    let mut names = Vec::new();
+    let resolved = backtrace::resolve_stack_deadline(&frames, deadline, |_, symbol| {
+        names.push(symbol.and_then(|s| s.name()).map(|name| name.to_string()));
+    });
+    assert_eq!(resolved, frames.len());
+    let mut expected = Vec::new();
+    for frame in &frames {
+        let mut any = false;
+        backtrace::resolve_frame(frame, |symbol| {
+            any = true;
+            expected.push(symbol.name().map(|name| name.to_string()));
+        });
+        if !any {
+            expected.push(None);
+        }
+    }
+    assert_eq!(names, expected);
+}
+
+#[test]
+#[cfg(unix)]
+fn resolve_stack_matches_resolve_frame() {
+    // Traces from within a `qsort` callback, so that the stack goes from this
+    // executable to libc and back.
+    extern "C" fn compare(a: *const libc::c_void, b: *const libc::c_void) -> libc::c_int {
+        backtrace::trace(|frame| {
+            FRAMES.with(|f| f.borrow_mut().push(frame.clone()));
+            true
+        });
+        unsafe { (*(a as *const u32)).cmp(&*(b as *const u32)) as libc::c_int }
+    }
+
+    thread_local!(static FRAMES: std::cell::RefCell<Vec<backtrace::Frame>> =
+        std::cell::RefCell::new(Vec::new()));
+
+    let mut values = [2u32, 1];
+    unsafe {
+        libc::qsort(values.as_mut_ptr().cast(), 2, 4, Some(compare));
+    }
+    let frames = FRAMES.with(|f| f.borrow_mut().split_off(0));
+    assert!(!frames.is_empty());
+
+    let mut symbols = Vec::new();
+    backtrace::resolve_stack(&frames, |frame, symbol| {
+        let name = symbol.and_then(|s| s.name()).map(|name| name.to_string());
+        symbols.push((frame.ip(), name));
+    });
+    let mut expected = Vec::new();
+    for frame in &frames {
+        let mut any = false;
+        backtrace::resolve_frame(frame, |symbol| {
+            any = true;
+            expected.push((frame.ip(), symbol.name().map(|name| name.to_string())));
+        });
+        if !any {
+            expected.push((frame.ip(), None));
+        }
+    }
+    assert_eq!(symbols, expected);
+}
+
+#[test]
+fn resolve_stack_matches_resolve_frame_for_recursion() {
+    #[inline(never)]
+    fn recurse(depth: u32, frames: &mut Vec<backtrace::Frame>) -> u32 {
+        if depth == 0 {
+            backtrace::trace(|frame| {
+                frames.push(frame.clone());
+                true
+            });
+            return 0;
+        }
+        // Not a tail call, so that each level keeps its frame.
+        recurse(depth - 1, frames) + 1
+    }
+
+    let mut frames = Vec::new();
+    assert_eq!(recurse(8, &mut frames), 8);
+
+    // Runs of frames returning to the same call get their symbols reused,
+    // which should make no difference to them.
+    let mut symbols = Vec::new();
+    backtrace::resolve_stack(&frames, |frame, symbol| {
+        let symbol = symbol.map(|s| (s.name().map(|n| n.to_string()), s.lineno()));
+        symbols.push((frame.ip(), symbol));
+    });
+    let mut expected = Vec::new();
+    for frame in &frames {
+        let mut any = false;
+        backtrace::resolve_frame(frame, |symbol| {
+            any = true;
+            let name = symbol.name().map(|name| name.to_string());
+            expected.push((frame.ip(), Some((name, symbol.lineno()))));
+        });
+        if !any {
+            expected.push((frame.ip(), None));
+        }
+    }
+    assert_eq!(symbols, expected);
+
+    if cfg!(debug_assertions) {
+        let recursions = symbols
+            .iter()
+            .filter_map(|(_, symbol)| symbol.as_ref()?.0.as_ref())
+            .filter(|name| name.contains("recurse"))
+            .count();
+        assert!(recursions >= 8, "{:?}", symbols);
+    }
+}
+
+#[test]
+fn source_context_reads_around_line() {
+    use std::path::Path;
+
+    let mut context = backtrace::SourceContext::new(2);
+    let file = Path::new(file!());
+    let line = line!(); // the line of the snippet
+    let snippet = context.snippet_at(file, line).unwrap();
+    assert_eq!(snippet.line(), line);
+    let numbers = snippet.lines().map(|(n, _)| n).collect::<Vec<_>>();
+    assert_eq!(numbers, (line - 2..=line + 2).collect::<Vec<_>>());
+    let printed = snippet.to_string();
+    let lines = printed.lines().collect::<Vec<_>>();
+    assert_eq!(lines.len(), 5, "{}", printed);
+    assert!(lines[1].starts_with("  "), "{}", printed);
+    assert!(lines[2].starts_with("> "), "{}", printed);
+    assert!(lines[2].ends_with("// the line of the snippet"));
+
+    // Snippets are cut short at the ends of the file.
+    let snippet = context.snippet_at(file, 1).unwrap();
+    assert_eq!(snippet.lines().count(), 3);
+    assert!(context.snippet_at(file, 0).is_none());
+    assert!(context.snippet_at(file, u32::MAX).is_none());
+    assert!(context
+        .snippet_at(Path::new("/nonexistent.rs"), 1)
+        .is_none());
+
+    // Symbols give the snippet of their file and line.
+    let mut found = false;
+    backtrace::trace(|frame| {
+        backtrace::resolve_frame(frame, |symbol| {
+            if let Some(snippet) = context.snippet(symbol) {
+                found = true;
+                assert_eq!(Some(snippet.line()), symbol.lineno());
+            }
+        });
+        true
+    });
+    if cfg!(all(debug_assertions, target_os = "linux")) {
+        assert!(found);
+    }
+}
+
+#[test]
+fn resolve_lines_matches_resolve_frame() {
+    let mut frames = Vec::new();
+    backtrace::trace(|frame| {
+        frames.push(frame.clone());
+        true
+    });
+
+    let mut lines = Vec::new();
+    backtrace::resolve_lines(&frames, |i, file, line| {
+        lines.push((i, file.to_string(), line));
+    });
+    let mut expected = Vec::new();
+    for (i, frame) in frames.iter().enumerate() {
+        let mut first = None;
+        backtrace::resolve_frame(frame, |symbol| {
+            if first.is_none() {
+                first = Some((symbol.filename().map(|f| f.to_path_buf()), symbol.lineno()));
+            }
+        });
+        if let Some((Some(file), Some(line))) = first {
+            expected.push((i, file.to_str().unwrap().to_string(), line));
+        }
+    }
+    assert_eq!(lines, expected);
+    if cfg!(target_os = "linux") {
+        assert!(lines.iter().any(|(_, file, _)| file.ends_with("smoke.rs")));
+    }
+}
+
+#[test]
+#[cfg(target_os = "linux")]
+fn frames_are_in_executable_segments() {
+    let mut flags = Vec::new();
+    backtrace::trace(|frame| {
+        flags.push(frame.segment_flags());
+        // Only this crate's frames and the test harness's are of interest.
+        flags.len() < 4
+    });
+    assert!(!flags.is_empty());
+    for flags in flags {
+        let flags = flags.expect("frames should be in a loaded module");
+        assert!(flags.is_executable(), "{:?}", flags);
+        assert!(!flags.is_writable(), "{:?}", flags);
+        assert_eq!(format!("{:?}", flags), "SegmentFlags(r-x)");
+    }
+}
+
+#[cfg(target_os = "linux")]
+#[inline(never)]
+fn fingerprint_here() -> backtrace::StackFingerprint {
+    let mut frames = Vec::new();
+    backtrace::trace(|frame| {
+        frames.push(frame.clone());
+        true
+    });
+    backtrace::stack_fingerprint(&frames)
+}
+
+#[test]
+#[cfg(target_os = "linux")]
+fn stack_fingerprint_is_stable_across_runs() {
+    const CHILD: &str = "BACKTRACE_FINGERPRINT_CHILD";
+    if std::env::var_os(CHILD).is_some() {
+        let fingerprint = fingerprint_here();
+        assert!(fingerprint.is_stable(), "{:?}", fingerprint);
+        println!("fingerprint={:x}", fingerprint.hash);
+        return;
+    }
+
+    // Each run of the test binary is loaded at different addresses.
+    let run = || {
+        let output = std::process::Command::new(std::env::current_exe().unwrap())
+            .args(&[
+                "stack_fingerprint_is_stable_across_runs",
+                "--exact",
+                "--nocapture",
+                "--test-threads=1",
+            ])
+            .env(CHILD, "1")
+            .output()
+            .unwrap();
+        assert!(output.status.success(), "{:?}", output);
+        let stdout = String::from_utf8(output.stdout).unwrap();
+        // The harness's progress shares the line the fingerprint is printed on.
+        stdout
+            .split("fingerprint=")
+            .nth(1)
+            .and_then(|rest| rest.split_whitespace().next())
+            .map(|hash| hash.to_string())
+            .expect("child should print its fingerprint")
+    };
+    assert_eq!(run(), run());
+
+    // Different stacks get different fingerprints.
+    let here = fingerprint_here();
+    let nested = (|| fingerprint_here())();
+    assert_ne!(here.hash, nested.hash);
+}
+
+#[test]
+#[cfg_attr(any(target_os = "macos", target_os = "ios", miri), ignore)]
+fn resolve_leaf_finds_caller() {
+    #[inline(never)]
+    fn origin(skip_runtime: bool) -> Option<backtrace::BacktraceSymbol> {
+        backtrace::resolve_leaf(skip_runtime)
+    }
+
+    for &skip_runtime in &[false, true] {
+        let symbol = origin(skip_runtime).expect("caller should resolve");
+        let name = symbol.name().unwrap().to_string();
+        assert!(name.contains("origin"), "{}", name);
+        if cfg!(not(windows)) {
+            assert!(symbol.filename().unwrap().ends_with("smoke.rs"));
+        }
+    }
+}
+
+#[test]
+#[cfg_attr(any(target_os = "macos", target_os = "ios", miri), ignore)]
+fn current_function_is_caller() {
+    #[inline(never)]
+    fn whereabouts() -> Option<backtrace::BacktraceSymbol> {
+        let function = backtrace::current_function();
+        // Keeps this from being a tail call, which would leave no frame.
+        assert!(function.is_some());
+        function
+    }
+
+    #[inline(never)]
+    fn both() -> (backtrace::BacktraceSymbol, backtrace::BacktraceSymbol) {
+        let pair = (
+            backtrace::current_function(),
+            backtrace::resolve_leaf(false),
+        );
+        (pair.0.unwrap(), pair.1.unwrap())
+    }
+
+    let symbol = whereabouts().unwrap();
+    let name = symbol.name().unwrap().to_string();
+    assert!(name.contains("whereabouts"), "{}", name);
+    if cfg!(all(not(windows), debug_assertions)) {
+        assert!(symbol.filename().unwrap().ends_with("smoke.rs"));
+    }
+
+    // The same as `resolve_leaf(false)`, other than the line it's called on.
+    let (function, leaf) = both();
+    assert_eq!(
+        function.name().unwrap().to_string(),
+        leaf.name().unwrap().to_string()
+    );
+    assert!(function.name().unwrap().to_string().contains("both"));
+}
+
+#[test]
+#[cfg_attr(any(target_os = "macos", target_os = "ios", miri), ignore)]
+fn resolved_backtrace_starts_at_caller() {
+    #[inline(never)]
+    fn capture() -> backtrace::ResolvedBacktrace {
+        backtrace::resolved_backtrace()
+    }
+
+    let backtrace = capture();
+    let frame = &backtrace.frames()[0];
+    let name = frame.symbols()[0].name().unwrap().to_string();
+    assert!(name.contains("capture"), "{}", name);
+
+    let display = backtrace.to_string();
+    assert_eq!(display, format!("{:?}", backtrace));
+    assert!(display.contains("resolved_backtrace_starts_at_caller"));
+    assert!(!display.contains("backtrace::capture::resolved_backtrace"));
+}
+
+#[test]
+#[cfg(target_os = "linux")]
+fn stack_memory_includes_callers_locals() {
+    #[inline(never)]
+    fn capture(marker: &[u8; 16]) -> backtrace::BacktraceWithStackMemory {
+        let capture = backtrace::capture_with_stack_memory(4096);
+        unsafe { std::ptr::read_volatile(marker) };
+        capture
+    }
+
+    let marker = *b"stack-memory-ok!";
+    let capture = capture(&marker);
+    assert_ne!(capture.stack_memory_address(), 0);
+    assert!(capture.stack_memory().len() <= 4096);
+    assert!(capture
+        .stack_memory()
+        .windows(marker.len())
+        .any(|window| window == marker));
+
+    let mut backtrace = capture.into_backtrace();
+    backtrace.resolve();
+    let frame = &backtrace.frames()[0];
+    let name = frame.symbols()[0].name().unwrap().to_string();
+    assert!(name.contains("capture"), "{}", name);
+
+    let empty = backtrace::capture_with_stack_memory(0);
+    assert!(empty.stack_memory().is_empty());
+}
+
+#[test]
+#[cfg(target_os = "linux")]
+fn resolve_status_tells_apart_failures() {
+    use backtrace::ResolveStatus;
+
+    // Addresses are looked up like return addresses, one byte back.
+    let addr = (resolve_status_tells_apart_failures as usize + 1) as *mut std::ffi::c_void;
+    match backtrace::resolve_status(addr) {
+        ResolveStatus::Resolved(symbols) => {
+            let name = symbols[0].name().unwrap().to_string();
+            assert!(name.contains("resolve_status_tells_apart"), "{}", name);
+        }
+        status => panic!("unexpected status {:?}", status),
+    }
+
+    // The ELF header at the start of the executable isn't in any function.
+    let exe = backtrace::loaded_modules()
+        .find(|m| m.contains(addr))
+        .expect("no module contains this function");
+    match backtrace::resolve_status(exe.base()) {
+        ResolveStatus::NoDebugInfo { module } => assert_eq!(module.base(), exe.base()),
+        status => panic!("unexpected status {:?}", status),
+    }
+
+    let heap = Box::new(0u64);
+    let heap = &*heap as *const u64 as *mut std::ffi::c_void;
+    match backtrace::resolve_status(heap) {
+        ResolveStatus::NotInAnyModule => {}
+        status => panic!("unexpected status {:?}", status),
+    }
+}
+
+#[test]
+fn current_arch_matches_target() {
+    use backtrace::Arch;
+
+    let arch = backtrace::current_arch();
+    assert_eq!(Arch::current(), Some(arch).filter(|a| *a != Arch::Unknown));
+    if cfg!(target_arch = "x86_64") {
+        assert_eq!(arch, Arch::X86_64);
+    }
+    assert_eq!(
+        backtrace::pointer_width() as usize,
+        std::mem::size_of::<usize>() * 8
+    );
+}
+
+#[test]
+#[cfg(target_os = "linux")]
+fn pack_stack_round_trips() {
+    let mut frames = Vec::new();
+    backtrace::trace(|frame| {
+        frames.push(frame.clone());
+        true
+    });
+    let mut packed = Vec::new();
+    backtrace::pack_stack(&frames, &mut packed);
+    backtrace::pack_stack(&frames[..1], &mut packed);
+
+    let mut input = &packed[..];
+    let stack = backtrace::unpack_stack(&mut input).unwrap();
+    assert_eq!(stack.frames.len(), frames.len());
+    let modules = backtrace::loaded_modules().collect::<Vec<_>>();
+    for (frame, packed) in frames.iter().zip(&stack.frames) {
+        let ip = frame.ip() as usize;
+        match packed.module {
+            Some(index) => {
+                let module = modules.iter().find(|m| m.contains(ip as *mut _)).unwrap();
+                assert_eq!(packed.address, ip.wrapping_sub(module.bias()));
+                if let Some(id) = module.build_id() {
+                    let expected = backtrace::PackedModuleId::BuildId(id.to_vec());
+                    assert_eq!(stack.modules[index], expected);
+                }
+            }
+            None => assert_eq!(packed.address, ip),
+        }
+    }
+
+    let second = backtrace::unpack_stack(&mut input).unwrap();
+    assert_eq!(second.frames.len(), 1);
+    assert!(input.is_empty());
+    assert!(backtrace::unpack_stack(&mut input).is_none());
+}
+
+#[test]
+#[cfg(all(
+    target_os = "linux",
+    any(target_arch = "x86_64", target_arch = "aarch64")
+))]
+fn no_frame_has_null_sp() {
+    let mut frames = 0;
+    backtrace::trace(|frame| {
+        assert!(!frame.sp().is_null(), "null sp at {:?}", frame.ip());
+        frames += 1;
+        true
+    });
+    assert!(frames > 1);
+}
+
+#[test]
+#[cfg(target_os = "linux")]
+fn symbol_crate_name() {
+    let mut crates = Vec::new();
+    backtrace::resolve(
+        (symbol_crate_name as usize + 1) as *mut std::ffi::c_void,
+        |symbol| crates.push(symbol.crate_name().map(|s| s.to_string())),
+    );
+    assert_eq!(crates, [Some("smoke".to_string())]);
+
+    let mut crates = Vec::new();
+    backtrace::resolve((libc::malloc as usize + 1) as *mut _, |symbol| {
+        crates.push(symbol.crate_name().map(|s| s.to_string()))
+    });
+    assert!(crates.iter().all(|c| c.is_none()), "{:?}", crates);
+}
+
+// `backtrace_test_ends_in_call` ends in a call, as a call to a function which
+// never returns may, so that the return address is the start of the function
+// placed after it. The callee does return, to tidy up the stack and return
+// from the caller on its behalf.
+#[cfg(all(
+    target_os = "linux",
+    target_arch = "x86_64",
+    feature = "symtab-fallback"
+))]
+std::arch::global_asm!(
+    ".text",
+    ".globl backtrace_test_ends_in_call",
+    ".type backtrace_test_ends_in_call,@function",
+    ".p2align 4",
+    "backtrace_test_ends_in_call:",
+    ".cfi_startproc",
+    "sub rsp, 8",
+    ".cfi_def_cfa_offset 16",
+    "call backtrace_test_noreturn",
+    ".cfi_endproc",
+    ".size backtrace_test_ends_in_call, .-backtrace_test_ends_in_call",
+    ".globl backtrace_test_after_call",
+    ".type backtrace_test_after_call,@function",
+    "backtrace_test_after_call:",
+    "add rsp, 8",
+    "ret",
+    ".size backtrace_test_after_call, .-backtrace_test_after_call",
+);
+
+#[cfg(all(
+    target_os = "linux",
+    target_arch = "x86_64",
+    feature = "symtab-fallback"
+))]
+thread_local!(static NORETURN_FRAMES: std::cell::RefCell<Vec<backtrace::Frame>> =
+    std::cell::RefCell::new(Vec::new()));
+
+#[cfg(all(
+    target_os = "linux",
+    target_arch = "x86_64",
+    feature = "symtab-fallback"
+))]
+#[no_mangle]
+extern "C" fn backtrace_test_noreturn() {
+    let mut frames = Vec::new();
+    backtrace::trace(|frame| {
+        frames.push(frame.clone());
+        true
+    });
+    NORETURN_FRAMES.with(|f| *f.borrow_mut() = frames);
+}
+
+// The assembly has no debug info, so only the symbol table can name it.
+#[test]
+#[cfg(all(
+    target_os = "linux",
+    target_arch = "x86_64",
+    feature = "symtab-fallback"
+))]
+fn frame_ending_in_noreturn_call_resolves_to_caller() {
+    extern "C" {
+        fn backtrace_test_ends_in_call();
+        fn backtrace_test_after_call();
+    }
+
+    unsafe { backtrace_test_ends_in_call() };
+    let frames = NORETURN_FRAMES.with(|f| f.borrow_mut().split_off(0));
+    let after_call = backtrace_test_after_call as *const () as usize;
+    let frame = frames
+        .iter()
+        .find(|frame| frame.ip() as usize == after_call)
+        .expect("no frame returns to the end of the caller");
+
+    let ends_in_call = backtrace_test_ends_in_call as *const () as usize;
+    assert_eq!(frame.symbol_address() as usize, ends_in_call);
+    let mut names = Vec::new();
+    backtrace::resolve_frame(frame, |symbol| {
+        names.push(symbol.name().map(|name| name.to_string()));
+    });
+    assert_eq!(names, [Some("backtrace_test_ends_in_call".to_string())]);
+}
+
+#[test]
+#[cfg(any(feature = "llvm-unwind", feature = "nongnu-unwind"))]
+fn self_test_sees_helper_frames() {
+    let frames = backtrace::self_test().unwrap();
+    assert!(frames > backtrace::SELF_TEST_DEPTH, "{}", frames);
+}
+
+#[test]
+#[cfg(any(target_arch = "x86_64", target_arch = "aarch64"))]
+fn capture_registers_matches_frame() {
+    use backtrace::RegisterContext;
+
+    fn check(frame: &backtrace::Frame, registers: RegisterContext) {
+        match registers {
+            RegisterContext::X86_64(regs) => {
+                assert_eq!(regs.rip, frame.ip() as u64);
+                assert_eq!(regs.rsp, frame.sp() as u64);
+                // rbx, rbp and r12 to r15 are always known.
+                assert_eq!(regs.valid & 0xf048, 0xf048, "{:#x}", regs.valid);
+            }
+            RegisterContext::Aarch64(regs) => {
+                assert_eq!(regs.pc, frame.ip() as u64);
+                assert_eq!(regs.sp, frame.sp() as u64);
+                // x19 to x29 are always known.
+                assert_eq!(regs.valid & 0x3ff8_0000, 0x3ff8_0000);
+            }
+            _ => panic!("wrong architecture"),
+        }
+    }
+
+    let mut frames = 0;
+    backtrace::trace(|frame| {
+        check(frame, frame.capture_registers().unwrap());
+        assert!(frame.clone().capture_registers().is_none());
+        frames += 1;
+        frames < 3
+    });
+    assert_eq!(frames, 3);
+
+    #[cfg(any(feature = "llvm-unwind", feature = "nongnu-unwind"))]
+    {
+        let mut frames = 0;
+        let res = unsafe {
+            backtrace::trace_unsynchronized_external_api_with_registers(
+                |frame| {
+                    let registers = frame.capture_registers().unwrap();
+                    check(frame, registers);
+                    if let RegisterContext::X86_64(regs) = registers {
+                        // Everything is known in the innermost frame.
+                        if frames == 0 {
+                            assert_eq!(regs.valid, 0x1ffff);
+                        }
+                    }
+                    assert!(frame.clone().capture_registers().is_none());
+                    frames += 1;
+                    frames < 3
+                },
+                false,
+            )
+        };
+        assert!(res.is_ok());
+        assert_eq!(frames, 3);
+
+        // Other external traces don't read them.
+        let res = unsafe {
+            backtrace::trace_unsynchronized_external_api(
+                |frame| {
+                    assert!(frame.capture_registers().is_none());
+                    false
+                },
+                false,
+            )
+        };
+        assert!(res.is_ok());
+    }
+}
+
+#[test]
+#[cfg_attr(any(target_os = "windows", miri), ignore)]
+fn offset_in_function_is_from_symbol_address() {
+    let mut frames = 0;
+    backtrace::trace(|frame| {
+        let offset = frame.offset_in_function().unwrap();
+        assert_eq!(
+            frame.symbol_address() as usize + offset,
+            frame.ip() as usize
+        );
+        assert_eq!(frame.clone().offset_in_function(), Some(offset));
+        frames += 1;
+        frames < 3
+    });
+    assert_eq!(frames, 3);
+
+    #[cfg(all(unix, any(target_arch = "x86_64", target_arch = "aarch64")))]
+    unsafe {
+        backtrace::trace_frame_pointers(
+            |frame| {
+                assert_eq!(frame.offset_in_function(), None);
+                true
+            },
+            None,
+        );
+    }
+
+    #[cfg(any(feature = "llvm-unwind", feature = "nongnu-unwind"))]
+    {
+        let res = unsafe {
+            backtrace::trace_unsynchronized_external_api(
+                |frame| {
+                    if let Some(info) = frame.proc_info() {
+                        let offset = frame.ip() as usize - info.start_ip;
+                        assert_eq!(frame.offset_in_function(), Some(offset));
+                    }
+                    true
+                },
+                false,
+            )
+        };
+        assert!(res.is_ok());
+    }
+}
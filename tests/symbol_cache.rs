@@ -0,0 +1,25 @@
+// Clearing the symbol cache affects every thread, so this lives in its own
+// test binary where other tests can't fill or clear it in the meantime.
+
+#[inline(never)]
+fn cached_function() {}
+
+fn from_cache() -> bool {
+    let mut from_cache = None;
+    backtrace::resolve_addr(cached_function as *const () as usize, |symbol| {
+        from_cache = Some(symbol.from_cache());
+    });
+    from_cache.unwrap()
+}
+
+#[test]
+fn lookups_after_clearing_are_fresh() {
+    backtrace::clear_symbol_cache();
+    assert!(!from_cache());
+    assert!(from_cache());
+    assert!(from_cache());
+
+    backtrace::clear_symbol_cache();
+    assert!(!from_cache());
+    assert!(from_cache());
+}
@@ -0,0 +1,44 @@
+// The filter is global, so this gets a test binary of its own where it can't
+// change what other tests see.
+
+use backtrace::{Backtrace, GdbFmt};
+use std::borrow::Cow;
+
+#[inline(never)]
+fn filtered_function() -> Backtrace {
+    Backtrace::new()
+}
+
+#[test]
+fn filter_applies_to_formatting() {
+    backtrace::set_symbol_filter(|name| {
+        if name.contains("filtered_function") {
+            Cow::Borrowed("<filtered>")
+        } else {
+            Cow::Borrowed(name)
+        }
+    });
+    let backtrace = filtered_function();
+
+    let debug = format!("{:?}", backtrace);
+    assert!(debug.contains("<filtered>"), "{}", debug);
+    assert!(!debug.contains("filtered_function"), "{}", debug);
+
+    let gdb = backtrace
+        .frames()
+        .iter()
+        .enumerate()
+        .map(|(i, frame)| GdbFmt::new(i, frame).to_string())
+        .collect::<Vec<_>>()
+        .join("\n");
+    assert!(gdb.contains("in <filtered> ()"), "{}", gdb);
+
+    // The names themselves are left alone.
+    let names = backtrace
+        .frames()
+        .iter()
+        .flat_map(|frame| frame.symbols())
+        .filter_map(|symbol| symbol.name().map(|name| name.to_string()))
+        .collect::<Vec<_>>();
+    assert!(names.iter().any(|name| name.contains("filtered_function")));
+}